@@ -55,12 +55,15 @@ fn main() {
         );
     }
 
-    // Load account of the creator so it can transfer value
+    // Fund the creator so it can transfer value
     context
         .eth_context
         .journal()
-        .load_account(context.address)
-        .unwrap();
+        .load_account(context.current_caller)
+        .unwrap()
+        .data
+        .info
+        .balance = U256::from(value);
 
     // Going ahead to excecute this ecall
     let result = process_ecall(&mut vm, &mut context).unwrap();
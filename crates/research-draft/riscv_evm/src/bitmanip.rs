@@ -0,0 +1,56 @@
+//! # Bit manipulation
+//! Semantics of the Zba/Zbb/Zbc/Zbkb instructions that have no direct Rust equivalent.
+//! Each extension is decoded only when its cargo feature (`zba`, `zbb`, `zbc`, `zbkb`, or
+//! `bitmanip` for all of them) is enabled, contracts built for plain RV32IM are not affected.
+//! Rotates, byte reversal and bit counts shorten keccak, 256-bit byte swapping and ABI encoding
+//! considerably.
+
+/// Full 64-bit carry-less product of `a` and `b`
+fn clmul_wide(a: u32, b: u32) -> u64 {
+    (0..32)
+        .filter(|i| (b >> i) & 1 == 1)
+        .fold(0, |product, i| product ^ (u64::from(a) << i))
+}
+
+/// `clmul`: low half of the carry-less product
+pub fn clmul(a: u32, b: u32) -> u32 {
+    clmul_wide(a, b) as u32
+}
+
+/// `clmulh`: high half of the carry-less product
+pub fn clmulh(a: u32, b: u32) -> u32 {
+    (clmul_wide(a, b) >> 32) as u32
+}
+
+/// `clmulr`: bits 62 to 31 of the carry-less product (the reversed product of the reversed inputs)
+pub fn clmulr(a: u32, b: u32) -> u32 {
+    (clmul_wide(a, b) >> 31) as u32
+}
+
+/// `orc.b`: every non-zero byte becomes `0xff`
+pub fn orc_b(value: u32) -> u32 {
+    u32::from_le_bytes(
+        value
+            .to_le_bytes()
+            .map(|byte| if byte != 0 { 0xff } else { 0 }),
+    )
+}
+
+/// `brev8`: reverses the bits of every byte
+pub fn brev8(value: u32) -> u32 {
+    u32::from_le_bytes(value.to_le_bytes().map(u8::reverse_bits))
+}
+
+/// `zip`: interleaves the low half (even bits) with the high half (odd bits)
+pub fn zip(value: u32) -> u32 {
+    (0..16).fold(0, |zipped, i| {
+        zipped | ((value >> i) & 1) << (2 * i) | ((value >> (i + 16)) & 1) << (2 * i + 1)
+    })
+}
+
+/// `unzip`: the inverse of [zip], even bits go to the low half and odd bits to the high half
+pub fn unzip(value: u32) -> u32 {
+    (0..16).fold(0, |unzipped, i| {
+        unzipped | ((value >> (2 * i)) & 1) << i | ((value >> (2 * i + 1)) & 1) << (i + 16)
+    })
+}
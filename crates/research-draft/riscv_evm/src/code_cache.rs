@@ -0,0 +1,426 @@
+//! # Code cache
+//! Contract code is decoded once into a [DecodedProgram] (every instruction validated, split into
+//! basic blocks with a jump table) and kept in a [CodeCache] keyed by the code hash, so calling the
+//! same contract again, from another frame or another transaction, skips decoding entirely.
+//! The cache is bounded by the total number of instruction words it holds and evicts the least
+//! recently used programs first.
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use hashbrown::HashMap;
+use revm::primitives::B256;
+use riscv_evm_core::WORD_SIZE;
+
+use crate::{
+    container::{Profile, split_container},
+    fusion::{FusedOp, fuse},
+    instructions::{COMPRESSED_INSTRUCTION_SIZE, Instruction, fetch_instruction, instruction_size},
+    utils::bytes_to_u32_vec,
+    vm::VMErrors,
+};
+
+/// Default capacity of a [CodeCache], in instruction words (16 MiB of code)
+pub const DEFAULT_CODE_CACHE_CAPACITY: usize = 1 << 22;
+
+/// A code cache shared by every frame of a transaction, and by transactions that are handed the same cache
+pub type SharedCodeCache = Arc<Mutex<CodeCache>>;
+
+/// A straight-line run of instructions, only the last one can change the control flow
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BasicBlock {
+    /// Address of the first instruction
+    pub start: u32,
+    /// Address right after the last instruction
+    pub end: u32,
+}
+
+/// Contract code decoded ahead of execution
+#[derive(Debug, Clone)]
+pub struct DecodedProgram {
+    /// Address the code is loaded at
+    pub base: u32,
+    /// The profile the code was decoded for, see [crate::container]
+    pub profile: Profile,
+    /// The raw instruction words
+    pub code: Vec<u32>,
+    /// The decoded form of the instruction starting at every 16-bit parcel (compressed code can
+    /// start an instruction at any of them), parcels that do not start a valid instruction (e.g.
+    /// data) keep their decode error so it is raised only if they are ever executed
+    pub instructions: Vec<Result<Instruction, VMErrors>>,
+    /// Basic blocks in address order
+    pub blocks: Vec<BasicBlock>,
+    /// Maps the start address of every basic block to its index in `blocks`
+    pub jump_table: HashMap<u32, usize>,
+    /// The [FusedOp] starting at every parcel, when it begins a fusable pair of 32-bit instructions
+    pub fused: Vec<Option<FusedOp>>,
+    /// Hot blocks compiled to native code
+    #[cfg(feature = "jit")]
+    pub jit: crate::jit::JitBlocks,
+}
+
+impl DecodedProgram {
+    /// Decodes `code` as if it was loaded at `base`
+    pub fn new(code: Vec<u32>, base: u32) -> Self {
+        Self::new_with_profile(code, base, Profile::Rv32I)
+    }
+
+    /// Decodes `code` for `profile` as if it was loaded at `base`
+    pub fn new_with_profile(code: Vec<u32>, base: u32, profile: Profile) -> Self {
+        let size = (code.len() * WORD_SIZE) as u32;
+        let end = base.wrapping_add(size);
+        let raw: Vec<_> = (0..size)
+            .step_by(COMPRESSED_INSTRUCTION_SIZE as usize)
+            .map(|offset| fetch_instruction(&code, offset))
+            .collect();
+        let instructions: Vec<_> = raw
+            .iter()
+            .map(|raw| {
+                raw.map_or(Err(VMErrors::InvalidMemoryAccess), |raw| {
+                    Instruction::decode_for(raw, profile)
+                })
+            })
+            .collect();
+
+        // a block starts at the entry, at every branch/jump target and after every instruction that
+        // can leave the straight line (ecalls included, they may halt or re-enter the host).
+        // Blocks follow the code from the start, skipping the parcels in the middle of 32-bit
+        // instructions.
+        let mut leaders = vec![base];
+        let mut offset = 0;
+        while offset < size {
+            let index = (offset / COMPRESSED_INSTRUCTION_SIZE) as usize;
+            let address = base.wrapping_add(offset);
+            let next = offset + raw[index].map_or(WORD_SIZE as u32, instruction_size);
+            offset = next;
+            let target = match &instructions[index] {
+                Ok(
+                    Instruction::Beq { imm, .. }
+                    | Instruction::Bne { imm, .. }
+                    | Instruction::Blt { imm, .. }
+                    | Instruction::Bge { imm, .. }
+                    | Instruction::Bltu { imm, .. }
+                    | Instruction::Bgeu { imm, .. }
+                    | Instruction::Jal { imm, .. },
+                ) => Some(address.wrapping_add(*imm as u32)),
+                Ok(Instruction::Jalr { .. } | Instruction::Ecall) => None,
+                _ => continue,
+            };
+
+            if let Some(target) = target
+                && target >= base
+                && target < end
+                && target.is_multiple_of(COMPRESSED_INSTRUCTION_SIZE)
+            {
+                leaders.push(target);
+            }
+            leaders.push(base.wrapping_add(next));
+        }
+        leaders.retain(|&leader| leader < end);
+        leaders.sort_unstable();
+        leaders.dedup();
+
+        let blocks: Vec<_> = leaders
+            .iter()
+            .enumerate()
+            .map(|(i, &start)| BasicBlock {
+                start,
+                end: leaders.get(i + 1).copied().unwrap_or(end),
+            })
+            .collect();
+        let jump_table = blocks
+            .iter()
+            .enumerate()
+            .map(|(i, block)| (block.start, i))
+            .collect();
+
+        // fused ops always cover two 32-bit instructions
+        let step = (WORD_SIZE as u32 / COMPRESSED_INSTRUCTION_SIZE) as usize;
+        let fused = (0..instructions.len())
+            .map(|i| match (raw[i], raw.get(i + step).copied().flatten()) {
+                (Some(first), Some(second))
+                    if instruction_size(first) == WORD_SIZE as u32
+                        && instruction_size(second) == WORD_SIZE as u32 =>
+                {
+                    match (&instructions[i], &instructions[i + step]) {
+                        (Ok(first), Ok(second)) => fuse(first, second),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            })
+            .collect();
+
+        Self {
+            base,
+            profile,
+            code,
+            instructions,
+            blocks,
+            jump_table,
+            fused,
+            #[cfg(feature = "jit")]
+            jit: Default::default(),
+        }
+    }
+
+    /// Decodes contract bytes the same way [crate::vm::Vm::from_bin_u8] loads them, honouring
+    /// their container header
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let (profile, code) = split_container(bytes);
+        Self::new_with_profile(bytes_to_u32_vec(code), 0, profile)
+    }
+
+    /// Size of the code in words
+    pub fn len(&self) -> usize {
+        self.code.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.code.is_empty()
+    }
+
+    /// Whether `[address, address + size)` overlaps the code
+    pub fn overlaps(&self, address: u32, size: u32) -> bool {
+        let end = self.base as u64 + (self.code.len() * WORD_SIZE) as u64;
+        (address as u64) < end && address as u64 + size as u64 > self.base as u64
+    }
+
+    /// The raw and decoded instruction at `pc`, `None` when `pc` is outside the code, unaligned or
+    /// when the instruction does not fit in the code
+    #[inline]
+    pub fn fetch(&self, pc: u32) -> Option<(u32, &Result<Instruction, VMErrors>)> {
+        let offset = pc.wrapping_sub(self.base);
+        let raw = fetch_instruction(&self.code, offset)?;
+        Some((
+            raw,
+            &self.instructions[(offset / COMPRESSED_INSTRUCTION_SIZE) as usize],
+        ))
+    }
+
+    /// The fused op starting at `pc`, if any
+    #[inline]
+    pub fn fused_at(&self, pc: u32) -> Option<FusedOp> {
+        let offset = pc.wrapping_sub(self.base);
+        if !offset.is_multiple_of(COMPRESSED_INSTRUCTION_SIZE) {
+            return None;
+        }
+
+        *self
+            .fused
+            .get((offset / COMPRESSED_INSTRUCTION_SIZE) as usize)?
+    }
+
+    /// The basic block starting at `pc`
+    pub fn block_at(&self, pc: u32) -> Option<&BasicBlock> {
+        self.jump_table.get(&pc).map(|&i| &self.blocks[i])
+    }
+}
+
+/// Bounded cache of decoded programs keyed by code hash, evicting the least recently used first
+#[derive(Debug)]
+pub struct CodeCache {
+    /// Maximum number of instruction words held
+    capacity: usize,
+    /// Number of instruction words currently held
+    size: usize,
+    programs: HashMap<B256, Arc<DecodedProgram>>,
+    /// Code hashes from least to most recently used
+    recently_used: VecDeque<B256>,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl Default for CodeCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CODE_CACHE_CAPACITY)
+    }
+}
+
+impl CodeCache {
+    /// Create a new cache holding at most `capacity` instruction words
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            size: 0,
+            programs: HashMap::new(),
+            recently_used: VecDeque::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Wraps the cache so it can be shared between contexts
+    pub fn shared(self) -> SharedCodeCache {
+        Arc::new(Mutex::new(self))
+    }
+
+    /// Looks up the program of `code_hash`, marking it as recently used
+    pub fn get(&mut self, code_hash: &B256) -> Option<Arc<DecodedProgram>> {
+        let Some(program) = self.programs.get(code_hash) else {
+            self.misses += 1;
+            return None;
+        };
+
+        self.hits += 1;
+        if let Some(position) = self.recently_used.iter().position(|hash| hash == code_hash) {
+            self.recently_used.remove(position);
+        }
+        self.recently_used.push_back(*code_hash);
+
+        Some(program.clone())
+    }
+
+    /// Adds a program, evicting the least recently used ones until it fits.
+    /// Programs larger than the whole cache are returned without being cached.
+    pub fn insert(&mut self, code_hash: B256, program: DecodedProgram) -> Arc<DecodedProgram> {
+        let program = Arc::new(program);
+        if program.len() > self.capacity {
+            return program;
+        }
+
+        if let Some(old) = self.programs.remove(&code_hash) {
+            self.size -= old.len();
+            self.recently_used.retain(|hash| *hash != code_hash);
+        }
+
+        while self.size + program.len() > self.capacity {
+            let Some(evicted) = self.recently_used.pop_front() else {
+                break;
+            };
+            if let Some(old) = self.programs.remove(&evicted) {
+                self.size -= old.len();
+            }
+        }
+
+        self.size += program.len();
+        self.programs.insert(code_hash, program.clone());
+        self.recently_used.push_back(code_hash);
+
+        program
+    }
+
+    /// Returns the program of `code_hash`, decoding `code` only on a miss
+    pub fn get_or_decode(&mut self, code_hash: B256, code: &[u8]) -> Arc<DecodedProgram> {
+        match self.get(&code_hash) {
+            Some(program) => program,
+            None => self.insert(code_hash, DecodedProgram::from_bytes(code)),
+        }
+    }
+
+    pub fn contains(&self, code_hash: &B256) -> bool {
+        self.programs.contains_key(code_hash)
+    }
+
+    /// Number of programs held
+    pub fn len(&self) -> usize {
+        self.programs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.programs.is_empty()
+    }
+
+    /// Number of instruction words held
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn clear(&mut self) {
+        self.programs.clear();
+        self.recently_used.clear();
+        self.size = 0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_basic_blocks() {
+        let program = DecodedProgram::new(
+            vec![
+                0x00150513, // 0x0: addi a0, a0, 1
+                0x00b50463, // 0x4: beq a0, a1, 8
+                0xff9ff06f, // 0x8: jal zero, -8
+                0x00000073, // 0xc: ecall
+                0x00008067, // 0x10: jalr zero, 0(ra)
+            ],
+            0,
+        );
+
+        assert_eq!(
+            program.blocks,
+            vec![
+                BasicBlock {
+                    start: 0x0,
+                    end: 0x8
+                },
+                BasicBlock {
+                    start: 0x8,
+                    end: 0xc
+                },
+                BasicBlock {
+                    start: 0xc,
+                    end: 0x10
+                },
+                BasicBlock {
+                    start: 0x10,
+                    end: 0x14
+                },
+            ]
+        );
+        assert_eq!(program.block_at(0xc), Some(&program.blocks[2]));
+        assert_eq!(program.block_at(0x4), None);
+        assert!(matches!(
+            program.fetch(0x4),
+            Some((0x00b50463, Ok(Instruction::Beq { .. })))
+        ));
+        assert!(program.fetch(0x1).is_none());
+        assert!(program.fetch(0x14).is_none());
+    }
+
+    #[test]
+    fn test_invalid_words_fail_only_when_fetched() {
+        let program = DecodedProgram::new(vec![0x00150513, 0xffffffff], 0);
+
+        assert_eq!(program.blocks.len(), 1);
+        assert!(matches!(program.fetch(0x4), Some((_, Err(_)))));
+    }
+
+    #[test]
+    fn test_code_cache_eviction() {
+        let mut cache = CodeCache::new(4);
+        let (a, b, c) = (
+            B256::with_last_byte(1),
+            B256::with_last_byte(2),
+            B256::with_last_byte(3),
+        );
+        let two_words = [0u8; 8];
+
+        cache.get_or_decode(a, &two_words);
+        cache.get_or_decode(b, &two_words);
+        assert_eq!((cache.len(), cache.size(), cache.misses), (2, 4, 2));
+
+        // touching `a` makes `b` the least recently used
+        cache.get_or_decode(a, &two_words);
+        assert_eq!(cache.hits, 1);
+        cache.get_or_decode(c, &two_words);
+
+        assert!(cache.contains(&a));
+        assert!(!cache.contains(&b));
+        assert!(cache.contains(&c));
+        assert_eq!(cache.size(), 4);
+
+        // too large to ever fit
+        let program = cache.get_or_decode(B256::with_last_byte(4), &[0u8; 20]);
+        assert_eq!(program.len(), 5);
+        assert_eq!(cache.len(), 2);
+    }
+}
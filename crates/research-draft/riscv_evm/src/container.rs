@@ -0,0 +1,131 @@
+//! # Contract container
+//! Contract code may start with a one-word container header selecting the [Profile] it is decoded
+//! and run with: the magic [CONTAINER_MAGIC] followed by the profile byte.
+//! Code without a valid header is plain RV32I code, the header itself is never loaded.
+//! The code is a sequence of big-endian 32-bit words (see [crate::utils::u32_vec_to_bytes]),
+//! compressed instructions are packed two per word, the one at the lower address in the low half
+//! (see [crate::instructions::read_parcel]).
+//!
+//! RV32E code only has the registers `x0`-`x15`, so its ecalls use an ABI of their own within them:
+//! - the ecall code goes in `t0` ([RV32E_ECALL_CODE_REG]) instead of `t6`
+//! - the ecall registers `a0`-`a5` are the registers themselves, as for RV32I
+//! - every other ecall register `xN` is the word at `t1 + 4 * N`, in the ecall block `t1`
+//!   ([riscv_evm_core::e_constants::RV32E_ECALL_BLOCK_REG]) points at. The block is laid out like the register file, so an
+//!   ecall only reads and writes the words of the registers it uses, and `t1` is not read at all
+//!   by the ecalls that only use `a0`-`a5` (e.g. `Sbrk`).
+
+use riscv_evm_core::e_constants::{ECALL_CODE_REG, RV32E_ECALL_CODE_REG};
+use serde::{Deserialize, Serialize};
+
+/// Magic bytes at the start of a contract container
+pub const CONTAINER_MAGIC: [u8; 3] = [0xef, b'R', b'V'];
+/// Size of the container header in bytes, one word so the code after it stays word-aligned
+pub const CONTAINER_HEADER_SIZE: usize = 4;
+
+/// The base integer instruction set a contract is decoded and run with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Profile {
+    /// The full base ISA, 32 registers
+    #[default]
+    Rv32I,
+    /// The embedded base ISA, 16 registers
+    Rv32E,
+}
+
+impl Profile {
+    /// Parses the profile byte of a container header
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Self::Rv32I),
+            1 => Some(Self::Rv32E),
+            _ => None,
+        }
+    }
+
+    /// The profile byte of a container header
+    pub fn to_byte(self) -> u8 {
+        match self {
+            Self::Rv32I => 0,
+            Self::Rv32E => 1,
+        }
+    }
+
+    /// Number of integer registers instructions can name
+    pub fn register_count(self) -> u32 {
+        match self {
+            Self::Rv32I => 32,
+            Self::Rv32E => 16,
+        }
+    }
+
+    /// The register holding the ecall code
+    pub fn ecall_code_register(self) -> u32 {
+        match self {
+            Self::Rv32I => ECALL_CODE_REG,
+            Self::Rv32E => RV32E_ECALL_CODE_REG,
+        }
+    }
+
+    /// Where the ecall register `register` of the RV32I ecall layout is passed, see the
+    /// [module docs](self)
+    pub fn ecall_location(self, register: u32) -> EcallLocation {
+        match (self, register) {
+            (Self::Rv32I, _) | (Self::Rv32E, 10..=15) => EcallLocation::Register(register),
+            (Self::Rv32E, ECALL_CODE_REG) => EcallLocation::Register(RV32E_ECALL_CODE_REG),
+            (Self::Rv32E, _) => EcallLocation::Block(4 * register),
+        }
+    }
+}
+
+/// Where an ecall register is passed, see [Profile::ecall_location]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EcallLocation {
+    /// In this register
+    Register(u32),
+    /// In the word at this offset of the ecall block, see the [module docs](self)
+    Block(u32),
+}
+
+/// Splits contract bytes into the profile of their container header and the code after it
+pub fn split_container(bytes: &[u8]) -> (Profile, &[u8]) {
+    match bytes {
+        [a, b, c, profile, code @ ..] if [*a, *b, *c] == CONTAINER_MAGIC => {
+            match Profile::from_byte(*profile) {
+                Some(profile) => (profile, code),
+                None => (Profile::Rv32I, bytes),
+            }
+        }
+        _ => (Profile::Rv32I, bytes),
+    }
+}
+
+/// Prefixes `code` with the container header selecting `profile`
+pub fn with_container_header(profile: Profile, code: &[u8]) -> Vec<u8> {
+    let mut container = Vec::with_capacity(CONTAINER_HEADER_SIZE + code.len());
+    container.extend(CONTAINER_MAGIC);
+    container.push(profile.to_byte());
+    container.extend(code);
+    container
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_split_container() {
+        let code = [0x00, 0x00, 0x00, 0x73];
+        assert_eq!(
+            split_container(&with_container_header(Profile::Rv32E, &code)),
+            (Profile::Rv32E, &code[..])
+        );
+        assert_eq!(
+            split_container(&with_container_header(Profile::Rv32I, &code)),
+            (Profile::Rv32I, &code[..])
+        );
+        // no header, or a header with an unknown profile, is plain code
+        assert_eq!(split_container(&code), (Profile::Rv32I, &code[..]));
+        let unknown = [0xef, b'R', b'V', 7, 0x00, 0x00, 0x00, 0x73];
+        assert_eq!(split_container(&unknown), (Profile::Rv32I, &unknown[..]));
+    }
+}
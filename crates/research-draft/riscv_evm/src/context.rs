@@ -1,10 +1,22 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    sync::{Arc, PoisonError},
+};
+
+use crate::{
+    code_cache::{CodeCache, DecodedProgram, SharedCodeCache},
+    debug_console::DebugConsole,
+    inspector::SharedInspector,
+    vm::VMErrors,
+};
 
 use revm::{
     Context as RevmEthContext,
-    context::{BlockEnv, CfgEnv, TxEnv},
+    context::{BlockEnv, CfgEnv, JournalOutput, TxEnv},
     database::{CacheDB, EmptyDB},
-    primitives::{Address, Bytes},
+    interpreter::{Gas, Host},
+    primitives::{Address, B256, Bytes, Log, hardfork::SpecId},
+    state::EvmState,
 };
 
 pub type StorageType = [u8; 32];
@@ -15,6 +27,61 @@ pub struct Storage {
     pub mapping: HashMap<StorageType, StorageType>,
 }
 
+/// The journal outputs of the ecalls run inside frames, in the order they were finalized, see
+/// [Context::with_state_changes]
+#[derive(Debug, Clone, Default)]
+pub struct StateChanges {
+    pub states: Vec<EvmState>,
+    pub logs: Vec<Log>,
+}
+
+impl StateChanges {
+    /// Adds the outputs of an ecall
+    pub fn extend(&mut self, outputs: impl IntoIterator<Item = JournalOutput>) {
+        for output in outputs {
+            self.states.push(output.state);
+            self.logs.extend(output.logs);
+        }
+    }
+
+    /// Drops what was added after `position` (see [StateChanges::position]), for a frame that
+    /// failed
+    pub fn truncate(&mut self, position: (usize, usize)) {
+        self.states.truncate(position.0);
+        self.logs.truncate(position.1);
+    }
+
+    /// How many states and logs there are so far
+    pub fn position(&self) -> (usize, usize) {
+        (self.states.len(), self.logs.len())
+    }
+
+    /// Applies the changes to the journal of `eth_context` the way `CacheDB::commit` would apply
+    /// them to a database: the touched accounts of every state in order, their storage slot by
+    /// slot, then the logs.
+    pub fn apply(self, eth_context: &mut EthContext) {
+        let journal = &mut eth_context.journaled_state;
+        for state in self.states {
+            for (address, account) in state {
+                if !account.is_touched() {
+                    continue;
+                }
+                match journal.state.get_mut(&address) {
+                    Some(present) => {
+                        present.info = account.info;
+                        present.status |= account.status;
+                        present.storage.extend(account.storage);
+                    }
+                    None => {
+                        journal.state.insert(address, account);
+                    }
+                }
+            }
+        }
+        journal.logs.extend(self.logs);
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Context {
     pub eth_context: EthContext,
@@ -23,15 +90,118 @@ pub struct Context {
     pub address: Address,        // address(this)
     pub current_caller: Address, // msg.sender
     pub return_data: Bytes,
+
+    // gas meter for the ecalls, starts at the tx gas limit (SSTORE refunds are accumulated here too)
+    pub gas: Gas,
+    // gas charged for every executed instruction, 0 (unmetered) until instruction pricing is settled
+    pub instruction_cost: u64,
+
+    // collector for the `DebugLog` ecall, `None` in production so the ecall costs nothing
+    pub debug_console: Option<DebugConsole>,
+
+    // journal outputs of the ecalls run inside frames, which `Vm::run` drops otherwise, `None` unless tracing or run by the handler
+    pub state_changes: Option<StateChanges>,
+
+    // revm inspector following the frames, logs and (optionally) steps, `None` unless inspecting
+    pub inspector: Option<SharedInspector>,
+
+    // pre-decoded programs by code hash, shared by every frame (and by other transactions given the same cache)
+    pub code_cache: SharedCodeCache,
+
+    // run hot blocks as native code, on by default when built with the `jit` feature
+    #[cfg(feature = "jit")]
+    pub jit: bool,
 }
 
 impl Context {
     pub fn new(eth_context: EthContext) -> Self {
+        let gas = Gas::new(eth_context.tx.gas_limit);
         Self {
             eth_context,
             address: Default::default(),
             current_caller: Default::default(),
             return_data: Default::default(),
+            gas,
+            instruction_cost: 0,
+            debug_console: None,
+            state_changes: None,
+            inspector: None,
+            code_cache: CodeCache::default().shared(),
+            #[cfg(feature = "jit")]
+            jit: true,
+        }
+    }
+
+    /// The hardfork the ecalls are priced against
+    pub fn spec(&self) -> SpecId {
+        self.eth_context.cfg.spec
+    }
+
+    /// Records `cost` against the gas meter, failing once the gas limit is exceeded
+    pub fn charge_gas(&mut self, cost: u64) -> Result<(), VMErrors> {
+        if self.gas.record_cost(cost) {
+            Ok(())
+        } else {
+            Err(VMErrors::OutOfGas)
         }
     }
+
+    /// Attaches a debug console so `DebugLog` ecalls are collected instead of ignored
+    pub fn with_debug_console(mut self) -> Self {
+        self.debug_console = Some(DebugConsole::new());
+        self
+    }
+
+    /// Collects the state changes of every frame, see [crate::geth_trace::prestate_trace]
+    pub fn with_state_changes(mut self) -> Self {
+        self.state_changes = Some(StateChanges::default());
+        self
+    }
+
+    /// Attaches an inspector, see [crate::inspector]
+    pub fn with_inspector(mut self, inspector: SharedInspector) -> Self {
+        self.inspector = Some(inspector);
+        self
+    }
+
+    /// Shares `code_cache` with this context, e.g. to reuse decoded programs across transactions
+    pub fn with_code_cache(mut self, code_cache: SharedCodeCache) -> Self {
+        self.code_cache = code_cache;
+        self
+    }
+
+    /// Returns the decoded program of `code`, only decoding it when the code cache misses
+    pub fn decoded_program(&self, code_hash: B256, code: &[u8]) -> Arc<DecodedProgram> {
+        self.code_cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_or_decode(code_hash, code)
+    }
+
+    /// Returns the decoded program of the code deployed at `address`.
+    /// The code itself is only loaded when the code cache misses.
+    pub fn load_program(&mut self, address: Address) -> Arc<DecodedProgram> {
+        let code_hash = self
+            .eth_context
+            .load_account_code_hash(address)
+            .map(|code_hash| code_hash.data)
+            .unwrap_or_default();
+
+        let cached = self
+            .code_cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&code_hash);
+        cached.unwrap_or_else(|| {
+            let code = self
+                .eth_context
+                .load_account_code(address)
+                .unwrap_or_default()
+                .data;
+            self.code_cache
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(code_hash, DecodedProgram::from_bytes(&code))
+        })
+    }
 }
@@ -0,0 +1,75 @@
+use std::fmt;
+
+use revm::primitives::Address;
+
+/// A single message written by a contract through the `DebugLog` ecall
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DebugLogEntry {
+    /// The contract that wrote the message
+    pub address: Address,
+    /// The message, invalid UTF-8 sequences are replaced
+    pub message: String,
+    /// Snapshot of the registers (x0 - x31) when the message was written
+    pub registers: Option<[u32; 32]>,
+    /// Start address and bytes of the requested memory range
+    pub memory: Option<(u32, Vec<u8>)>,
+}
+
+/// Host-side collector for the `DebugLog` ecall.
+/// When this is not attached to the context the ecall is a no-op, so production runs pay nothing for it.
+#[derive(Debug, Clone, Default)]
+pub struct DebugConsole {
+    pub entries: Vec<DebugLogEntry>,
+}
+
+impl DebugConsole {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, entry: DebugLogEntry) {
+        self.entries.push(entry);
+    }
+
+    /// Returns only the messages, in the order they were written
+    pub fn messages(&self) -> Vec<&str> {
+        self.entries.iter().map(|e| e.message.as_str()).collect()
+    }
+}
+
+impl fmt::Display for DebugLogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "[{}] {}", self.address, self.message)?;
+
+        if let Some(registers) = &self.registers {
+            for (i, row) in registers.chunks(4).enumerate() {
+                for (j, value) in row.iter().enumerate() {
+                    write!(f, "  x{:<2} = {:#010x}", i * 4 + j, value)?;
+                }
+                writeln!(f)?;
+            }
+        }
+
+        if let Some((offset, bytes)) = &self.memory {
+            for (i, row) in bytes.chunks(16).enumerate() {
+                write!(f, "  {:08x}:", *offset as usize + i * 16)?;
+                for byte in row {
+                    write!(f, " {:02x}", byte)?;
+                }
+                writeln!(f)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for DebugConsole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.entries {
+            write!(f, "{}", entry)?;
+        }
+
+        Ok(())
+    }
+}
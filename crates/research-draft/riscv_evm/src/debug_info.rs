@@ -0,0 +1,649 @@
+//! # Debug info
+//! Source-level debugging of ELF contracts. [DebugInfo] keeps the functions of the symbol table
+//! (`.symtab`) and the line table (`.debug_line`, DWARF 2 to 5) of an ELF file, so a pc maps to a
+//! function name and a `file:line`.
+//!
+//! Load a Vm with [Vm::from_bin_elf_with_debug_info] and [Vm::run] reports traps and reverts with a
+//! backtrace. [crate::disassembler::disassemble_elf] annotates its listing with the source lines.
+//!
+//! The backtrace follows the frame pointer chain (`s0`, the return address being saved at `s0 - 4`
+//! and the caller's frame pointer at `s0 - 8`). Build the contract with frame pointers
+//! (`-C force-frame-pointers=yes`, `-fno-omit-frame-pointer`) to see more than the frame that
+//! trapped. Rust symbols are demangled, legacy and `v0` ones alike.
+//!
+//! The line table is read with [gimli] and symbols are demangled with [rustc_demangle], so a
+//! malformed or hostile ELF makes [DebugInfo::parse] fail instead of overflowing.
+use std::fmt;
+
+use elf::{
+    ElfBytes,
+    abi::{SHF_COMPRESSED, SHF_EXECINSTR, STT_FUNC, STT_NOTYPE},
+    endian::LittleEndian,
+    file::Class,
+};
+use gimli::{
+    AttributeValue, DebugLine, DebugLineOffset, DebugLineStr, DebugStr, EndianSlice,
+    IncompleteLineProgram,
+};
+use hashbrown::HashMap;
+use riscv_evm_core::{MemoryChuckSize, interfaces::MemoryInterface};
+
+use crate::{
+    instructions::{COMPRESSED_INSTRUCTION_SIZE, fetch_instruction, instruction_size},
+    vm::Vm,
+};
+
+/// Frames a backtrace stops at
+pub const MAX_BACKTRACE_FRAMES: usize = 64;
+
+/// A section as gimli reads it
+type Slice<'a> = EndianSlice<'a, gimli::LittleEndian>;
+
+/// A function of the symbol table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    pub name: String,
+    pub start: u32,
+    /// First address after the function, the next symbol when the symbol table has no size for it
+    pub end: u32,
+}
+
+/// Where a pc is in the source, as far as the debug info knows
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub pc: u32,
+    pub function: Option<String>,
+    /// Offset of `pc` in `function`
+    pub offset: u32,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+}
+
+impl fmt::Display for SourceLocation {
+    /// `0x00001008 transfer+0x8 at src/lib.rs:42`, like `addr2line -f`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#010x} ", self.pc)?;
+        match &self.function {
+            Some(function) => write!(f, "{function}+{:#x}", self.offset)?,
+            None => f.write_str("??")?,
+        }
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => write!(f, " at {file}:{line}"),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The instructions from `address` on come from `file:line`
+#[derive(Debug, Clone, Copy)]
+struct Row {
+    address: u32,
+    /// Index in [DebugInfo::files]
+    file: Option<usize>,
+    line: u32,
+}
+
+/// Rows of contiguous instructions, up to `end`
+#[derive(Debug, Clone)]
+struct Sequence {
+    start: u32,
+    end: u32,
+    rows: Vec<Row>,
+}
+
+/// Function names and source lines of an ELF file, see the module documentation
+#[derive(Debug, Clone, Default)]
+pub struct DebugInfo {
+    /// By start address
+    functions: Vec<Function>,
+    files: Vec<String>,
+    /// By start address
+    sequences: Vec<Sequence>,
+}
+
+impl DebugInfo {
+    /// Reads the symbol table and line table of an ELF file, either may be missing (a stripped ELF
+    /// has neither).
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error if the ELF or its line table is not valid, or the line
+    /// table is compressed.
+    pub fn parse(input: &[u8]) -> anyhow::Result<Self> {
+        let elf = ElfBytes::<LittleEndian>::minimal_parse(input)?;
+        let mut debug_info = Self {
+            functions: functions(&elf)?,
+            ..Default::default()
+        };
+
+        let section = |name: &str| -> anyhow::Result<&[u8]> {
+            let Some(header) = elf.section_header_by_name(name)? else {
+                return Ok(&[]);
+            };
+            if header.sh_flags & u64::from(SHF_COMPRESSED) != 0 {
+                anyhow::bail!("{name} is compressed, link with --compress-debug-sections=none");
+            }
+            Ok(elf.section_data(&header)?.0)
+        };
+        let strings = StringSections {
+            line_str: DebugLineStr::new(section(".debug_line_str")?, gimli::LittleEndian),
+            str: DebugStr::new(section(".debug_str")?, gimli::LittleEndian),
+        };
+        let debug_line_data = section(".debug_line")?;
+        let debug_line = DebugLine::new(debug_line_data, gimli::LittleEndian);
+        let address_size = if elf.ehdr.class == Class::ELF64 { 8 } else { 4 };
+        let mut file_ids = HashMap::new();
+        let mut offset = 0usize;
+        while offset < debug_line_data.len() {
+            let program = debug_line.program(DebugLineOffset(offset), address_size, None, None)?;
+            let header = program.header();
+            offset = offset
+                .saturating_add(usize::from(header.format().initial_length_size()))
+                .saturating_add(header.unit_length());
+            debug_info.add_program(program, &strings, &mut file_ids)?;
+        }
+        debug_info
+            .sequences
+            .sort_by_key(|sequence| (sequence.start, sequence.end));
+
+        Ok(debug_info)
+    }
+
+    /// Whether there is neither a symbol table nor a line table
+    pub fn is_empty(&self) -> bool {
+        self.functions.is_empty() && self.sequences.is_empty()
+    }
+
+    /// The functions of the symbol table by start address
+    pub fn functions(&self) -> &[Function] {
+        &self.functions
+    }
+
+    /// The function names by start address, in the shape of [crate::elf_parser::Elf::symbols]
+    pub fn symbols(&self) -> HashMap<u32, String> {
+        self.functions
+            .iter()
+            .map(|function| (function.start, function.name.clone()))
+            .collect()
+    }
+
+    /// The function `pc` is in
+    pub fn function(&self, pc: u32) -> Option<&Function> {
+        let index = self
+            .functions
+            .partition_point(|function| function.start <= pc);
+        let function = self.functions.get(index.checked_sub(1)?)?;
+        (pc < function.end).then_some(function)
+    }
+
+    /// The `file:line` `pc` comes from
+    pub fn line(&self, pc: u32) -> Option<(&str, u32)> {
+        let index = self
+            .sequences
+            .partition_point(|sequence| sequence.start <= pc);
+        let sequence = self.sequences.get(index.checked_sub(1)?)?;
+        if pc >= sequence.end {
+            return None;
+        }
+        let row = sequence.rows[sequence.rows.partition_point(|row| row.address <= pc) - 1];
+        let file = row.file.map_or("??", |file| self.files[file].as_str());
+        Some((file, row.line))
+    }
+
+    /// Everything known about `pc`
+    pub fn locate(&self, pc: u32) -> SourceLocation {
+        let function = self.function(pc);
+        let line = self.line(pc);
+        SourceLocation {
+            pc,
+            function: function.map(|function| function.name.clone()),
+            offset: function.map_or(0, |function| pc - function.start),
+            file: line.map(|(file, _)| file.to_string()),
+            line: line.map(|(_, line)| line),
+        }
+    }
+
+    /// The frames of `vm` stopped at `pc`, innermost first: `pc`, then the calls found following
+    /// the frame pointer chain. The walk stops at the first return address outside of a known
+    /// function.
+    pub fn backtrace(&self, vm: &Vm, pc: u32) -> Vec<SourceLocation> {
+        let mut frames = vec![self.locate(pc)];
+        let mut frame_pointer = vm.registers.read_reg(8);
+        while frames.len() < MAX_BACKTRACE_FRAMES && frame_pointer.is_multiple_of(4) {
+            let read = |address: u32| vm.memory.read_mem(address, MemoryChuckSize::WordSize);
+            let (Some(return_address), Some(caller_frame_pointer)) = (
+                read(frame_pointer.wrapping_sub(4)),
+                read(frame_pointer.wrapping_sub(8)),
+            ) else {
+                break;
+            };
+            // the call is right before the return address, compressed or not
+            let call = match fetch_instruction(&vm.memory.memory, return_address.wrapping_sub(4)) {
+                Some(raw) if instruction_size(raw) == 4 => return_address.wrapping_sub(4),
+                _ => return_address.wrapping_sub(COMPRESSED_INSTRUCTION_SIZE),
+            };
+            if return_address == 0 || self.function(call).is_none() {
+                break;
+            }
+            frames.push(self.locate(call));
+            // the stack grows down, callers' frames are above
+            if caller_frame_pointer <= frame_pointer {
+                break;
+            }
+            frame_pointer = caller_frame_pointer;
+        }
+
+        frames
+    }
+
+    /// Runs the line number program of one unit
+    fn add_program(
+        &mut self,
+        program: IncompleteLineProgram<Slice>,
+        strings: &StringSections,
+        file_ids: &mut HashMap<String, usize>,
+    ) -> anyhow::Result<()> {
+        // file index of the unit -> index in `self.files`
+        let mut unit_files: HashMap<u64, Option<usize>> = HashMap::new();
+        let mut rows: Vec<Row> = Vec::new();
+        let mut program_rows = program.rows();
+        while let Some((header, row)) = program_rows.next_row()? {
+            if row.end_sequence() {
+                if let (Some(first), Ok(end)) = (rows.first(), u32::try_from(row.address())) {
+                    let start = first.address;
+                    self.sequences.push(Sequence {
+                        start,
+                        end,
+                        rows: std::mem::take(&mut rows),
+                    });
+                }
+                // rows past 4 GiB are dropped
+                rows.clear();
+                continue;
+            }
+            let Ok(address) = u32::try_from(row.address()) else {
+                continue;
+            };
+
+            let file = match unit_files.get(&row.file_index()) {
+                Some(&file) => file,
+                None => {
+                    let file = match row.file(header) {
+                        Some(entry) => {
+                            let directory = match entry.directory(header) {
+                                Some(directory) => attribute_string(directory, strings)?,
+                                None => String::new(),
+                            };
+                            let name = attribute_string(entry.path_name(), strings)?;
+                            Some(intern(&mut self.files, file_ids, join(&directory, &name)))
+                        }
+                        None => None,
+                    };
+                    unit_files.insert(row.file_index(), file);
+                    file
+                }
+            };
+            rows.push(Row {
+                address,
+                file,
+                line: row
+                    .line()
+                    .map_or(0, |line| u32::try_from(line.get()).unwrap_or(u32::MAX)),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// The string sections DWARF 5 file tables point into
+struct StringSections<'a> {
+    line_str: DebugLineStr<Slice<'a>>,
+    str: DebugStr<Slice<'a>>,
+}
+
+/// The executable symbols of the symbol table, sorted by address
+fn functions(elf: &ElfBytes<LittleEndian>) -> anyhow::Result<Vec<Function>> {
+    let (Some((symtab, strtab)), Some(sections)) = (elf.symbol_table()?, elf.section_headers())
+    else {
+        return Ok(Vec::new());
+    };
+
+    // (function, is an STT_FUNC, has a size)
+    let mut functions = Vec::new();
+    for symbol in symtab.iter() {
+        let kind = symbol.st_symtype();
+        if !matches!(kind, STT_FUNC | STT_NOTYPE) || symbol.st_name == 0 || symbol.is_undefined() {
+            continue;
+        }
+        let Ok(section) = sections.get(usize::from(symbol.st_shndx)) else {
+            continue;
+        };
+        if section.sh_flags & u64::from(SHF_EXECINSTR) == 0 {
+            continue;
+        }
+        let name = strtab.get(symbol.st_name as usize)?;
+        // local labels ($x, $d, .L...) are not functions
+        if name.is_empty() || name.starts_with('$') || name.starts_with(".L") {
+            continue;
+        }
+
+        let start: u32 = symbol.st_value.try_into()?;
+        let end = match symbol.st_size {
+            0 => section.sh_addr.saturating_add(section.sh_size),
+            size => symbol.st_value.saturating_add(size),
+        };
+        let function = Function {
+            name: demangle(name),
+            start,
+            end: u32::try_from(end).unwrap_or(u32::MAX),
+        };
+        functions.push((function, kind == STT_FUNC, symbol.st_size != 0));
+    }
+
+    functions.sort_by_key(|(function, is_function, _)| (function.start, !is_function));
+    functions.dedup_by_key(|(function, _, _)| function.start);
+    // a symbol without a size ends where the next one starts
+    for i in 1..functions.len() {
+        let next = functions[i].0.start;
+        let (function, _, sized) = &mut functions[i - 1];
+        if !*sized {
+            function.end = function.end.min(next);
+        }
+    }
+
+    Ok(functions
+        .into_iter()
+        .map(|(function, _, _)| function)
+        .collect())
+}
+
+/// Demangles a Rust symbol without its hash, other names are returned as they are
+pub fn demangle(name: &str) -> String {
+    format!("{:#}", rustc_demangle::demangle(name))
+}
+
+/// `name` in `directory`, unless it is absolute
+fn join(directory: &str, name: &str) -> String {
+    if directory.is_empty() || name.starts_with('/') {
+        name.to_string()
+    } else {
+        format!("{}/{name}", directory.trim_end_matches('/'))
+    }
+}
+
+/// The index of `path` in `files`, adding it if it is new
+fn intern(files: &mut Vec<String>, ids: &mut HashMap<String, usize>, path: String) -> usize {
+    *ids.entry(path).or_insert_with_key(|path| {
+        files.push(path.clone());
+        files.len() - 1
+    })
+}
+
+/// A file or directory name of a line table header
+fn attribute_string(
+    value: AttributeValue<Slice>,
+    strings: &StringSections,
+) -> anyhow::Result<String> {
+    let string = match value {
+        AttributeValue::String(string) => string,
+        AttributeValue::DebugLineStrRef(offset) => strings.line_str.get_str(offset)?,
+        AttributeValue::DebugStrRef(offset) => strings.str.get_str(offset)?,
+        _ => anyhow::bail!("unsupported .debug_line string form"),
+    };
+    Ok(string.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use gimli::constants::{
+        DW_LNE_end_sequence, DW_LNE_set_address, DW_LNS_advance_line, DW_LNS_advance_pc,
+        DW_LNS_copy,
+    };
+    use revm::{Context as EthContext, MainContext, database::CacheDB};
+
+    use super::*;
+    use crate::{
+        context::Context,
+        disassembler::{disassemble_elf, format_listing},
+        elf_parser::Elf,
+        utils::u32_vec_to_bytes,
+    };
+
+    const CODE: [u32; 13] = [
+        // main
+        0xff010113, // addi sp, sp, -16
+        0x00112623, // sw ra, 12(sp)
+        0x00812423, // sw s0, 8(sp)
+        0x01010413, // addi s0, sp, 16
+        0x010000ef, // jal ra, fail
+        0x00100073, // ebreak
+        0x00000013, // nop
+        0x00000013, // nop
+        // fail
+        0xff010113, // addi sp, sp, -16
+        0x00112623, // sw ra, 12(sp)
+        0x00812423, // sw s0, 8(sp)
+        0x01010413, // addi s0, sp, 16
+        0x00100073, // ebreak
+    ];
+
+    /// The DWARF 4 line table of [CODE]: main at src/lib.rs:10 and 11, fail at 20 and 22
+    fn debug_line() -> Vec<u8> {
+        debug_line_unit(&[
+            &[0, 5, DW_LNE_set_address.0, 0, 0, 0, 0][..],
+            &[DW_LNS_advance_line.0, 9, DW_LNS_copy.0],
+            &[
+                DW_LNS_advance_pc.0,
+                0x10,
+                DW_LNS_advance_line.0,
+                1,
+                DW_LNS_copy.0,
+            ],
+            &[
+                DW_LNS_advance_pc.0,
+                0x10,
+                DW_LNS_advance_line.0,
+                9,
+                DW_LNS_copy.0,
+            ],
+            // special opcode: 0x10 bytes and 2 lines further
+            &[13 + 16 * 14 + (2 + 5)],
+            &[DW_LNS_advance_pc.0, 4, 0, 1, DW_LNE_end_sequence.0],
+        ])
+    }
+
+    /// A DWARF 4 `.debug_line` with a single unit running `program`
+    fn debug_line_unit(program: &[&[u8]]) -> Vec<u8> {
+        let mut header = vec![1, 1, 1, 0xfb, 14, 13, 0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1];
+        header.extend(b"src\0\0lib.rs\0\x01\0\0\0");
+        let program = program.concat();
+
+        let mut unit = 4u16.to_le_bytes().to_vec();
+        unit.extend((header.len() as u32).to_le_bytes());
+        unit.extend(header);
+        unit.extend(program);
+        let mut debug_line = (unit.len() as u32).to_le_bytes().to_vec();
+        debug_line.extend(unit);
+        debug_line
+    }
+
+    /// A minimal ELF32 RISC-V executable of [CODE] loaded at 0, with a symbol table and a line table
+    fn elf32() -> Vec<u8> {
+        elf32_with(debug_line())
+    }
+
+    /// [elf32] with another line table
+    fn elf32_with(debug_line: Vec<u8>) -> Vec<u8> {
+        let text: Vec<u8> = CODE.iter().flat_map(|word| word.to_le_bytes()).collect();
+        let strtab = b"\0$x\0main\0_ZN8contract4fail17h0123456789abcdefE\0".to_vec();
+        let mut symtab = vec![0; 16];
+        // (name, value, size, info), the mapping symbol is local and untyped
+        for (name, value, size, info) in [
+            (1u32, 0u32, 0u32, 0u8),
+            (4, 0, 0x20, 0x12),
+            (9, 0x20, 0x14, 0x12),
+        ] {
+            for field in [name, value, size] {
+                symtab.extend(field.to_le_bytes());
+            }
+            symtab.extend([info, 0]);
+            symtab.extend(1u16.to_le_bytes()); // .text
+        }
+        let shstrtab = b"\0.text\0.symtab\0.strtab\0.debug_line\0.shstrtab\0".to_vec();
+
+        // (name, type, flags, link, info, entry size, data)
+        let sections = [
+            (1u32, 1u32, 6u32, 0u32, 0u32, 0u32, text),
+            (7, 2, 0, 3, 2, 16, symtab),
+            (15, 3, 0, 0, 0, 0, strtab),
+            (23, 1, 0, 0, 0, 0, debug_line),
+            (35, 3, 0, 0, 0, 0, shstrtab),
+        ];
+        let mut data = Vec::new();
+        let mut headers = vec![0; 40];
+        for (name, kind, flags, link, info, entry_size, bytes) in sections {
+            let offset = 84 + data.len() as u32;
+            for field in [
+                name,
+                kind,
+                flags,
+                0,
+                offset,
+                bytes.len() as u32,
+                link,
+                info,
+                1,
+                entry_size,
+            ] {
+                headers.extend(field.to_le_bytes());
+            }
+            data.extend(bytes);
+            data.resize(data.len().next_multiple_of(4), 0);
+        }
+
+        let mut elf = vec![0x7f, b'E', b'L', b'F', 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        elf.extend(2u16.to_le_bytes()); // ET_EXEC
+        elf.extend(243u16.to_le_bytes()); // EM_RISCV
+        for field in [1u32, 0, 52, 84 + data.len() as u32, 0] {
+            elf.extend(field.to_le_bytes());
+        }
+        for half in [52u16, 32, 1, 40, 6, 5] {
+            elf.extend(half.to_le_bytes());
+        }
+        let size = (CODE.len() * 4) as u32;
+        for field in [1, 84, 0, 0, size, size, 5, 4] {
+            elf.extend(u32::to_le_bytes(field));
+        }
+        elf.extend(data);
+        elf.extend(headers);
+        elf
+    }
+
+    #[test]
+    fn test_debug_info() {
+        let elf = elf32();
+        let debug_info = DebugInfo::parse(&elf).unwrap();
+        assert!(!debug_info.is_empty());
+        assert_eq!(
+            debug_info.functions(),
+            [
+                Function {
+                    name: "main".to_string(),
+                    start: 0,
+                    end: 0x20
+                },
+                Function {
+                    name: "contract::fail".to_string(),
+                    start: 0x20,
+                    end: 0x34
+                }
+            ]
+        );
+        assert_eq!(debug_info.line(0x0c), Some(("src/lib.rs", 10)));
+        assert_eq!(debug_info.line(0x18), Some(("src/lib.rs", 11)));
+        assert_eq!(debug_info.line(0x30), Some(("src/lib.rs", 22)));
+        assert_eq!(debug_info.line(0x34), None);
+        assert_eq!(
+            debug_info.locate(0x24).to_string(),
+            "0x00000024 contract::fail+0x4 at src/lib.rs:20"
+        );
+        assert_eq!(debug_info.locate(0x40).to_string(), "0x00000040 ??");
+        assert!(
+            Elf::decode_with_debug_info(&elf)
+                .unwrap()
+                .debug_info
+                .is_some()
+        );
+        assert!(Elf::decode(&elf).unwrap().debug_info.is_none());
+
+        let listing = format_listing(&disassemble_elf(&elf).unwrap());
+        assert!(listing.contains(
+            "\n00000020 <_ZN8contract4fail17h0123456789abcdefE>:\nsrc/lib.rs:20\n      20:\t"
+        ));
+        assert_eq!(listing.matches("src/lib.rs:").count(), 4);
+
+        let mut context = Context::new(EthContext::mainnet().with_db(CacheDB::default()));
+        let mut vm = Vm::from_bin_u8(u32_vec_to_bytes(&CODE, CODE.len() * 4)).unwrap();
+        vm.debug_info = Some(Arc::new(debug_info));
+        while vm.step(false, &mut context).is_ok() {}
+        let backtrace: Vec<String> = vm
+            .backtrace()
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            backtrace,
+            [
+                "0x00000030 contract::fail+0x10 at src/lib.rs:22",
+                "0x00000010 main+0x10 at src/lib.rs:11"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_demangle() {
+        assert_eq!(
+            demangle("_ZN4core9panicking5panic17h0123456789abcdefE"),
+            "core::panicking::panic"
+        );
+        assert_eq!(
+            demangle(
+                "_ZN52_$LT$contract..Token$u20$as$u20$core..fmt..Debug$GT$3fmt17h0123456789abcdefE"
+            ),
+            "<contract::Token as core::fmt::Debug>::fmt"
+        );
+        assert_eq!(demangle("_RNvC6_123foo3bar"), "123foo::bar");
+        assert_eq!(demangle("transfer"), "transfer");
+        assert_eq!(demangle("_ZN3foo"), "_ZN3foo");
+    }
+
+    #[test]
+    fn test_debug_info_overflowing_line_table() {
+        let max_uleb = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+        let min_sleb = [0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x7f];
+        let elf = elf32_with(debug_line_unit(&[
+            &[0, 5, DW_LNE_set_address.0, 0, 0, 0, 0][..],
+            &[DW_LNS_copy.0, DW_LNS_advance_pc.0],
+            &max_uleb,
+            &[DW_LNS_advance_pc.0],
+            &max_uleb,
+            &[DW_LNS_advance_line.0],
+            &min_sleb,
+            &[DW_LNS_advance_line.0],
+            &min_sleb,
+            &[DW_LNS_copy.0, 0xff, 0, 1, DW_LNE_end_sequence.0],
+        ]));
+
+        // a hostile line table is either read or rejected, never a panic
+        if let Ok(debug_info) = DebugInfo::parse(&elf) {
+            debug_info.locate(0);
+        }
+        let mut truncated = elf32();
+        truncated.truncate(truncated.len() - 1);
+        assert!(DebugInfo::parse(&truncated).is_err());
+    }
+}
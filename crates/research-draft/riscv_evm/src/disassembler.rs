@@ -0,0 +1,371 @@
+//! # Disassembler
+//! Renders RV32IMC machine code as standard assembly, using the ABI register names, absolute
+//! branch/jump targets (with symbol names when they are known) and the name of the ecall being made.
+//! Compressed instructions are shown as the instruction they expand to.
+//! Works on a [Vm]'s loaded code, on raw contract bytes and on ELF files. When there is debug info
+//! (see [crate::debug_info]), instructions are annotated with the `file:line` they come from.
+use hashbrown::HashMap;
+use riscv_evm_core::{
+    WORD_SIZE,
+    e_constants::{ECALL_CODE_REG, RiscvEVMECalls},
+};
+
+use crate::{
+    container::split_container,
+    debug_info::DebugInfo,
+    elf_parser::Elf,
+    instructions::{
+        COMPRESSED_INSTRUCTION_SIZE, CSR_CYCLE, CSR_CYCLEH, CSR_INSTRET, CSR_INSTRETH, CSR_TIME,
+        CSR_TIMEH, Instruction, fetch_instruction, instruction_size, read_parcel,
+    },
+    utils::bytes_to_u32_vec,
+    vm::Vm,
+};
+
+/// ABI names of the 32 integer registers
+pub const ABI_REGISTER_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6",
+];
+
+/// Returns the ABI name of register `x{register}`
+pub fn abi_name(register: u32) -> &'static str {
+    ABI_REGISTER_NAMES[(register & 0x1f) as usize]
+}
+
+/// Returns the name of the counter CSR `csr`, or its number for any other CSR
+pub fn csr_name(csr: u32) -> String {
+    match csr {
+        CSR_CYCLE => "cycle".to_string(),
+        CSR_TIME => "time".to_string(),
+        CSR_INSTRET => "instret".to_string(),
+        CSR_CYCLEH => "cycleh".to_string(),
+        CSR_TIMEH => "timeh".to_string(),
+        CSR_INSTRETH => "instreth".to_string(),
+        _ => format!("{:#x}", csr),
+    }
+}
+
+/// One disassembled instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisassembledInstruction {
+    pub address: u32,
+    /// The instruction bits, only the low 16 for a compressed instruction
+    pub raw: u32,
+    /// The assembly text, e.g. `add a0, a1, a2`
+    pub text: String,
+    /// Symbol defined at this address, if any
+    pub label: Option<String>,
+    /// The `file:line` this instruction comes from, if there is a line table
+    pub source: Option<String>,
+}
+
+/// Disassembles `code` as if it was loaded at `base`.
+/// `symbols` maps addresses to names, they are used as labels and to name branch/jump targets.
+pub fn disassemble(
+    code: &[u32],
+    base: u32,
+    symbols: &HashMap<u32, String>,
+) -> Vec<DisassembledInstruction> {
+    let size = (code.len() * WORD_SIZE) as u32;
+    let mut ecall_code = None;
+    let mut instructions = vec![];
+    let mut offset = 0;
+
+    while offset < size {
+        let address = base.wrapping_add(offset);
+        let instruction = match fetch_instruction(code, offset) {
+            Some(raw) => disassemble_at(raw, address, symbols, &mut ecall_code),
+            // the first half of a 32-bit instruction cut off by the end of the code
+            None => {
+                let raw = read_parcel(code, offset).unwrap_or_default() as u32;
+                DisassembledInstruction {
+                    address,
+                    raw,
+                    text: format!(".half {:#06x}", raw),
+                    label: symbols.get(&address).cloned(),
+                    source: None,
+                }
+            }
+        };
+
+        offset += instruction_size(instruction.raw);
+        instructions.push(instruction);
+    }
+
+    instructions
+}
+
+/// Disassembles the instruction `raw` found at `address`.
+/// `ecall_code` is the value of t6 (the ecall code register) when it was last set to a constant.
+fn disassemble_at(
+    raw: u32,
+    address: u32,
+    symbols: &HashMap<u32, String>,
+    ecall_code: &mut Option<u32>,
+) -> DisassembledInstruction {
+    DisassembledInstruction {
+        address,
+        raw,
+        text: disassemble_instruction(raw, address, symbols, ecall_code),
+        label: symbols.get(&address).cloned(),
+        source: None,
+    }
+}
+
+/// Disassembles raw contract bytes the same way [Vm::from_bin_u8] loads them, without their
+/// container header
+pub fn disassemble_bytes(bytes: &[u8]) -> Vec<DisassembledInstruction> {
+    let (_, code) = split_container(bytes);
+    disassemble(&bytes_to_u32_vec(code), 0, &HashMap::new())
+}
+
+/// Disassembles `count` instructions of a [Vm]'s memory starting at `start`, labelled and
+/// annotated with its debug info if it has some
+pub fn disassemble_vm(vm: &Vm, start: u32, count: usize) -> Vec<DisassembledInstruction> {
+    let symbols = vm
+        .debug_info
+        .as_deref()
+        .map(DebugInfo::symbols)
+        .unwrap_or_default();
+    let mut ecall_code = None;
+    let mut address = start;
+
+    let mut instructions: Vec<_> = (0..count)
+        .map(|_| {
+            let raw = fetch_instruction(&vm.memory.memory, address).unwrap_or_default();
+            let instruction = disassemble_at(raw, address, &symbols, &mut ecall_code);
+            address = address.wrapping_add(instruction_size(raw));
+            instruction
+        })
+        .collect();
+    if let Some(debug_info) = &vm.debug_info {
+        annotate(&mut instructions, debug_info);
+    }
+
+    instructions
+}
+
+/// Disassembles the executable segments of an ELF file, labelled with its symbols
+///
+/// # Errors
+///
+/// This function may return an error if the ELF is not valid.
+pub fn disassemble_elf(input: &[u8]) -> anyhow::Result<Vec<DisassembledInstruction>> {
+    let elf = Elf::decode(input)?;
+    if elf.is_64bit {
+        anyhow::bail!("must be a 32-bit elf");
+    }
+    let symbols = Elf::symbols(input)?;
+    let mut instructions = disassemble(&elf.instructions, elf.pc_base, &symbols);
+    annotate(&mut instructions, &DebugInfo::parse(input)?);
+
+    Ok(instructions)
+}
+
+/// Fills in the source line of each instruction from `debug_info`
+pub fn annotate(instructions: &mut [DisassembledInstruction], debug_info: &DebugInfo) {
+    for instruction in instructions {
+        instruction.source = debug_info
+            .line(instruction.address)
+            .map(|(file, line)| format!("{file}:{line}"));
+    }
+}
+
+/// Renders a listing in the `objdump -d` style, with the source line before the instructions it
+/// starts like `objdump -dl`
+pub fn format_listing(instructions: &[DisassembledInstruction]) -> String {
+    let mut listing = String::new();
+    let mut source = None;
+    for instruction in instructions {
+        if let Some(label) = &instruction.label {
+            listing.push_str(&format!("\n{:08x} <{}>:\n", instruction.address, label));
+        }
+        if instruction.source.is_some() && instruction.source != source {
+            source = instruction.source.clone();
+            listing.push_str(&format!(
+                "{}\n",
+                instruction.source.as_deref().unwrap_or_default()
+            ));
+        }
+        let raw = match instruction_size(instruction.raw) {
+            COMPRESSED_INSTRUCTION_SIZE => format!("{:04x}    ", instruction.raw),
+            _ => format!("{:08x}", instruction.raw),
+        };
+        listing.push_str(&format!(
+            "{:8x}:\t{}\t{}\n",
+            instruction.address, raw, instruction.text
+        ));
+    }
+
+    listing
+}
+
+fn target(address: u32, symbols: &HashMap<u32, String>) -> String {
+    match symbols.get(&address) {
+        Some(name) => format!("{:#x} <{}>", address, name),
+        None => format!("{:#x}", address),
+    }
+}
+
+fn disassemble_instruction(
+    raw: u32,
+    address: u32,
+    symbols: &HashMap<u32, String>,
+    ecall_code: &mut Option<u32>,
+) -> String {
+    let Ok(instruction) = Instruction::decode_any(raw) else {
+        return match instruction_size(raw) {
+            COMPRESSED_INSTRUCTION_SIZE => format!(".half {:#06x}", raw),
+            _ => format!(".word {:#010x}", raw),
+        };
+    };
+
+    let text = match instruction {
+        Instruction::Beq { rs1, rs2, imm }
+        | Instruction::Bne { rs1, rs2, imm }
+        | Instruction::Blt { rs1, rs2, imm }
+        | Instruction::Bge { rs1, rs2, imm }
+        | Instruction::Bltu { rs1, rs2, imm }
+        | Instruction::Bgeu { rs1, rs2, imm } => format!(
+            "{} {}, {}, {}",
+            instruction.mnemonic(),
+            abi_name(rs1),
+            abi_name(rs2),
+            target(address.wrapping_add(imm as u32), symbols)
+        ),
+        Instruction::Jal { rd, imm } => format!(
+            "jal {}, {}",
+            abi_name(rd),
+            target(address.wrapping_add(imm as u32), symbols)
+        ),
+        Instruction::Ecall => match ecall_code.and_then(RiscvEVMECalls::from_u32) {
+            Some(ecall) => format!("ecall\t# {:?}", ecall),
+            None => String::from("ecall"),
+        },
+        _ => instruction.to_string(),
+    };
+
+    // track `addi t6, zero, <code>` so the following ecall can be named, anything else that
+    // writes t6 leaves the ecall code unknown
+    if instruction.rd() == Some(ECALL_CODE_REG) {
+        *ecall_code = match instruction {
+            Instruction::Addi { rs1: 0, imm, .. } => Some(imm as u32),
+            _ => None,
+        };
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_disassemble_rv32im() {
+        let code = vec![
+            0x00c58533, // add a0, a1, a2
+            0x40c58533, // sub a0, a1, a2
+            0x02c58533, // mul a0, a1, a2
+            0xff010113, // addi sp, sp, -16
+            0x00351513, // slli a0, a0, 3
+            0x40355513, // srai a0, a0, 3
+            0x00c12503, // lw a0, 12(sp)
+            0x00a12623, // sw a0, 12(sp)
+            0x123455b7, // lui a1, 0x12345
+            0xfeb50ee3, // beq a0, a1, -4
+            0x008000ef, // jal ra, 8
+            0x00008067, // jalr zero, 0(ra)
+            0xffffffff, // not an instruction
+        ];
+
+        let listing: Vec<String> = disassemble(&code, 0x100, &HashMap::new())
+            .into_iter()
+            .map(|i| i.text)
+            .collect();
+
+        assert_eq!(
+            listing,
+            vec![
+                "add a0, a1, a2",
+                "sub a0, a1, a2",
+                "mul a0, a1, a2",
+                "addi sp, sp, -16",
+                "slli a0, a0, 3",
+                "srai a0, a0, 3",
+                "lw a0, 12(sp)",
+                "sw a0, 12(sp)",
+                "lui a1, 0x12345",
+                "beq a0, a1, 0x120",
+                "jal ra, 0x130",
+                "jalr zero, 0(ra)",
+                ".word 0xffffffff",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_disassemble_compressed() {
+        let code = vec![
+            0x05934515, // c.li a0, 5 ; addi a1, zero, 7 (spans two words)
+            0x952e0070, // c.add a0, a1
+            0x0000c501, // c.beqz a0, 8 ; not an instruction
+        ];
+
+        let instructions = disassemble(&code, 0x100, &HashMap::new());
+        let listing: Vec<_> = instructions
+            .iter()
+            .map(|i| (i.address, i.text.as_str()))
+            .collect();
+
+        assert_eq!(
+            listing,
+            vec![
+                (0x100, "addi a0, zero, 5"),
+                (0x102, "addi a1, zero, 7"),
+                (0x106, "add a0, a0, a1"),
+                (0x108, "beq a0, zero, 0x110"),
+                (0x10a, ".half 0x0000"),
+            ]
+        );
+        assert!(format_listing(&instructions).contains("     100:\t4515    \taddi a0, zero, 5"));
+    }
+
+    #[test]
+    fn test_disassemble_ecall_and_symbols() {
+        let code = vec![
+            0x02000f93, // addi t6, zero, 0x20
+            0x00000073, // ecall
+            0x00100f93, // addi t6, zero, 1
+            0x00000073, // ecall (unknown code)
+            0xff9ff06f, // jal zero, -8
+        ];
+        let mut symbols = HashMap::new();
+        symbols.insert(0x0, String::from("main"));
+        symbols.insert(0x8, String::from("again"));
+
+        let instructions = disassemble(&code, 0, &symbols);
+        assert_eq!(instructions[1].text, "ecall\t# Keccak256");
+        assert_eq!(instructions[3].text, "ecall");
+        assert_eq!(instructions[4].text, "jal zero, 0x8 <again>");
+        assert_eq!(instructions[0].label.as_deref(), Some("main"));
+
+        let listing = format_listing(&instructions);
+        assert!(listing.contains("00000000 <main>:"));
+        assert!(listing.contains("       4:\t00000073\tecall\t# Keccak256"));
+    }
+
+    #[test]
+    fn test_disassemble_bytes_and_vm() {
+        let code = vec![0x00c58533, 0x00000073];
+        let bytes = crate::utils::u32_vec_to_bytes(&code, code.len() * 4);
+
+        let from_bytes = disassemble_bytes(&bytes);
+        assert_eq!(from_bytes[0].text, "add a0, a1, a2");
+
+        let vm = Vm::from_bin(code).unwrap();
+        assert_eq!(disassemble_vm(&vm, 0, 2), from_bytes);
+    }
+}
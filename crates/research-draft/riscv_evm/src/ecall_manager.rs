@@ -1,1572 +1,1053 @@
 #![allow(unused)]
 
 use crate::{
-    context::Context,
-    utils::{
-        address_to_u32_vec, bytes_to_u32, combine_u32_to_u64, split_u64_to_u32, u32_vec_to_address,
-        u32_vec_to_u256,
-    },
+    code_cache::DecodedProgram,
+    context::{Context, StateChanges},
+    gas::{balance_cost, ext_code_hash_cost, ext_code_size_cost},
+    host::{EcallArgs, EcallHost, EcallRequest, EcallResponse},
+    inspector::{SharedInspector, frame_result},
+    utils::{copy_padded, split_u64_to_u32},
     vm::{VMErrors, Vm},
 };
 use revm::{
     Context as EthContext, MainContext,
-    context::{ContextTr, JournalOutput, JournalTr},
+    context::{
+        ContextTr, CreateScheme, JournalOutput, JournalTr, journaled_state::JournalCheckpoint,
+    },
     database::CacheDB,
-    interpreter::Host,
-    primitives::{Address, B256, Log, LogData, U256, keccak256},
+    interpreter::{
+        CallInputs, CallOutcome, CallScheme, CallValue, CreateInputs, CreateOutcome, Gas, Host,
+        InstructionResult, InterpreterResult,
+        gas::{
+            CALL_STIPEND, SELFDESTRUCT, call_cost, extcodecopy_cost, selfdestruct_cost, sload_cost,
+            sstore_cost, sstore_refund,
+        },
+    },
+    primitives::{Address, Bytes, Log, LogData, U256, hardfork::SpecId, keccak256},
     state::Bytecode,
 };
 use riscv_evm_core::{MemoryChuckSize, e_constants::*, interfaces::MemoryInterface};
+use std::sync::Arc;
+
+/// The synchronous host: ecalls are processed against the journal of the context as soon as the
+/// Vm reaches them, charging the meter of the context as they go
+impl EcallHost for Context {
+    fn fulfil(&mut self, request: &EcallRequest) -> Result<EcallResponse, VMErrors> {
+        let (response, outputs) = serve_ecall(self, request)?;
+        if let Some(changes) = self.state_changes.as_mut() {
+            changes.extend(outputs);
+        }
+        Ok(response)
+    }
+}
 
+/// Processes the ecall `vm` is on with `context` as the host and applies its outputs to `vm`,
+/// returning the journal outputs instead of collecting them
 pub fn process_ecall(vm: &mut Vm, context: &mut Context) -> Result<Vec<JournalOutput>, VMErrors> {
-    let e_call_code = vm.registers.read_reg(ECALL_CODE_REG);
-
-    match RiscvEVMECalls::from_u32(e_call_code) {
-        Some(rv_ec) => match rv_ec {
-            RiscvEVMECalls::Keccak256 => {
-                // This would load from memory data from `offset` and `size`,
-                // the `offest` is an address in memroy where the read should start from
-                // `size` is the number of bytes has the data that is to be hashed.
-                // after the hashing is done, it would be stored in 8 registers
-                let offset = vm.registers.read_reg(KECCAK256_OFFSET_REGISTER);
-                let size = vm.registers.read_reg(KECCAK256_SIZE_REGISTER);
-                let mut data = vec![0u8; size as usize];
-                for i in 0..size {
-                    data[i as usize] = vm
-                        .memory
-                        .read_mem(offset + i, MemoryChuckSize::BYTE)
-                        .unwrap() as u8;
-                }
-
-                let hash = keccak256(&data);
-
-                // writing 256 bits to 8 regiters
-                vm.registers
-                    .write_reg(KECCAK256_OUTPUT_REGITER_1, bytes_to_u32(&hash.0[0..4]));
-                vm.registers
-                    .write_reg(KECCAK256_OUTPUT_REGITER_2, bytes_to_u32(&hash.0[4..8]));
-                vm.registers
-                    .write_reg(KECCAK256_OUTPUT_REGITER_3, bytes_to_u32(&hash.0[8..12]));
-                vm.registers
-                    .write_reg(KECCAK256_OUTPUT_REGITER_4, bytes_to_u32(&hash.0[12..16]));
-                vm.registers
-                    .write_reg(KECCAK256_OUTPUT_REGITER_5, bytes_to_u32(&hash.0[16..20]));
-                vm.registers
-                    .write_reg(KECCAK256_OUTPUT_REGITER_6, bytes_to_u32(&hash.0[20..24]));
-                vm.registers
-                    .write_reg(KECCAK256_OUTPUT_REGITER_7, bytes_to_u32(&hash.0[24..28]));
-                vm.registers
-                    .write_reg(KECCAK256_OUTPUT_REGITER_8, bytes_to_u32(&hash.0[28..32]));
-
-                Ok(vec![])
-            }
-            RiscvEVMECalls::Address => {
-                // This branch would load the address of this current running contract from context
-                // to 5 regiters
-                let address = context.address.0.0;
-
-                // writing 160 bits (20 bytes) to register
-                vm.registers
-                    .write_reg(ADDRESS_REGISTER_1, bytes_to_u32(&address[0..4]));
-                vm.registers
-                    .write_reg(ADDRESS_REGISTER_2, bytes_to_u32(&address[4..8]));
-                vm.registers
-                    .write_reg(ADDRESS_REGISTER_3, bytes_to_u32(&address[8..12]));
-                vm.registers
-                    .write_reg(ADDRESS_REGISTER_4, bytes_to_u32(&address[12..16]));
-                vm.registers
-                    .write_reg(ADDRESS_REGISTER_5, bytes_to_u32(&address[16..20]));
-
-                Ok(vec![])
-            }
-            RiscvEVMECalls::Balance => {
-                // Construct the address that is to be read by reading 5 registers, reconstruct the address
-                // query the balance from context, write this balance to 8 new registers
-                let address_u32_1 = vm.registers.read_reg(BALANCE_INPUT_REGISTER_1);
-                let address_u32_2 = vm.registers.read_reg(BALANCE_INPUT_REGISTER_2);
-                let address_u32_3 = vm.registers.read_reg(BALANCE_INPUT_REGISTER_3);
-                let address_u32_4 = vm.registers.read_reg(BALANCE_INPUT_REGISTER_4);
-                let address_u32_5 = vm.registers.read_reg(BALANCE_INPUT_REGISTER_5);
-
-                let address = u32_vec_to_address(&vec![
-                    address_u32_1,
-                    address_u32_2,
-                    address_u32_3,
-                    address_u32_4,
-                    address_u32_5,
-                ]);
-
-                let balance: [u8; 32] = context
-                    .eth_context
-                    .balance(Address::new(address))
-                    .unwrap_or_default()
-                    .data
-                    .to_be_bytes();
-
-                // writing 256 bits to 8 regiters
-                vm.registers
-                    .write_reg(BALANCE_OUTPUT_REGISTER_1, bytes_to_u32(&balance[0..4]));
-                vm.registers
-                    .write_reg(BALANCE_OUTPUT_REGISTER_2, bytes_to_u32(&balance[4..8]));
-                vm.registers
-                    .write_reg(BALANCE_OUTPUT_REGISTER_3, bytes_to_u32(&balance[8..12]));
-                vm.registers
-                    .write_reg(BALANCE_OUTPUT_REGISTER_4, bytes_to_u32(&balance[12..16]));
-                vm.registers
-                    .write_reg(BALANCE_OUTPUT_REGISTER_5, bytes_to_u32(&balance[16..20]));
-                vm.registers
-                    .write_reg(BALANCE_OUTPUT_REGISTER_6, bytes_to_u32(&balance[20..24]));
-                vm.registers
-                    .write_reg(BALANCE_OUTPUT_REGISTER_7, bytes_to_u32(&balance[24..28]));
-                vm.registers
-                    .write_reg(BALANCE_OUTPUT_REGISTER_8, bytes_to_u32(&balance[28..32]));
-
-                Ok(vec![])
-            }
-            RiscvEVMECalls::Origin => {
-                let origin = context.eth_context.tx.caller.0;
-
-                // Writing this origin to five registers
-                vm.registers
-                    .write_reg(ORIGIN_OUTPUT_REGISTER_1, bytes_to_u32(&origin[0..4]));
-                vm.registers
-                    .write_reg(ORIGIN_OUTPUT_REGISTER_2, bytes_to_u32(&origin[4..8]));
-                vm.registers
-                    .write_reg(ORIGIN_OUTPUT_REGISTER_3, bytes_to_u32(&origin[8..12]));
-                vm.registers
-                    .write_reg(ORIGIN_OUTPUT_REGISTER_4, bytes_to_u32(&origin[12..16]));
-                vm.registers
-                    .write_reg(ORIGIN_OUTPUT_REGISTER_5, bytes_to_u32(&origin[16..20]));
-
-                Ok(vec![])
-            }
-            RiscvEVMECalls::Caller => {
-                let origin = context.current_caller.0;
-
-                // Writing this origin to five registers
-                vm.registers
-                    .write_reg(CALLER_OUTPUT_REGISTER_1, bytes_to_u32(&origin[0..4]));
-                vm.registers
-                    .write_reg(CALLER_OUTPUT_REGISTER_2, bytes_to_u32(&origin[4..8]));
-                vm.registers
-                    .write_reg(CALLER_OUTPUT_REGISTER_3, bytes_to_u32(&origin[8..12]));
-                vm.registers
-                    .write_reg(CALLER_OUTPUT_REGISTER_4, bytes_to_u32(&origin[12..16]));
-                vm.registers
-                    .write_reg(CALLER_OUTPUT_REGISTER_5, bytes_to_u32(&origin[16..20]));
-
-                Ok(vec![])
-            }
-            RiscvEVMECalls::CallValue => {
-                // Load the vaule from context into a 8 registers
-                let value: [u8; 32] = context.eth_context.tx.value.to_be_bytes();
-
-                // writing 256 bits to 8 regiters
-                vm.registers
-                    .write_reg(CALL_VALUE_OUTPUT_REGISTER_1, bytes_to_u32(&value[0..4]));
-                vm.registers
-                    .write_reg(CALL_VALUE_OUTPUT_REGISTER_2, bytes_to_u32(&value[4..8]));
-                vm.registers
-                    .write_reg(CALL_VALUE_OUTPUT_REGISTER_3, bytes_to_u32(&value[8..12]));
-                vm.registers
-                    .write_reg(CALL_VALUE_OUTPUT_REGISTER_4, bytes_to_u32(&value[12..16]));
-                vm.registers
-                    .write_reg(CALL_VALUE_OUTPUT_REGISTER_5, bytes_to_u32(&value[16..20]));
-                vm.registers
-                    .write_reg(CALL_VALUE_OUTPUT_REGISTER_6, bytes_to_u32(&value[20..24]));
-                vm.registers
-                    .write_reg(CALL_VALUE_OUTPUT_REGISTER_7, bytes_to_u32(&value[24..28]));
-                vm.registers
-                    .write_reg(CALL_VALUE_OUTPUT_REGISTER_8, bytes_to_u32(&value[28..32]));
-
-                Ok(vec![])
-            }
-            RiscvEVMECalls::CallDataLoad => {
-                // This would load 32bytes of the call data to 8 registers
-                // The offset this 32bytes should come from is gotten from a register.
-                let offset = vm.registers.read_reg(CALL_DATA_LOAD_INPUT_REGISTER);
-                let mut data = Vec::new();
-                for i in offset as usize..(offset + 32) as usize {
-                    data.push(*context.eth_context.tx.data.get(i).unwrap_or(&0u8));
-                }
-                // writing 256 bits to 8 regiters
-                vm.registers
-                    .write_reg(CALL_DATA_LOAD_OUTPUT_REGISTER_1, bytes_to_u32(&data[0..4]));
-                vm.registers
-                    .write_reg(CALL_DATA_LOAD_OUTPUT_REGISTER_2, bytes_to_u32(&data[4..8]));
-                vm.registers
-                    .write_reg(CALL_DATA_LOAD_OUTPUT_REGISTER_3, bytes_to_u32(&data[8..12]));
-                vm.registers.write_reg(
-                    CALL_DATA_LOAD_OUTPUT_REGISTER_4,
-                    bytes_to_u32(&data[12..16]),
-                );
-                vm.registers.write_reg(
-                    CALL_DATA_LOAD_OUTPUT_REGISTER_5,
-                    bytes_to_u32(&data[16..20]),
-                );
-                vm.registers.write_reg(
-                    CALL_DATA_LOAD_OUTPUT_REGISTER_6,
-                    bytes_to_u32(&data[20..24]),
-                );
-                vm.registers.write_reg(
-                    CALL_DATA_LOAD_OUTPUT_REGISTER_7,
-                    bytes_to_u32(&data[24..28]),
-                );
-                vm.registers.write_reg(
-                    CALL_DATA_LOAD_OUTPUT_REGISTER_8,
-                    bytes_to_u32(&data[28..32]),
-                );
-
-                Ok(vec![])
-            }
-            RiscvEVMECalls::CallDataSize => {
-                // This load to a register the number of bytes present in the calldata
-                // into a register
-                let size = context.eth_context.tx.data.len() as u32;
-
-                vm.registers.write_reg(CALL_DATA_SIZE_OUTPUT_REGISTER, size);
-
-                Ok(vec![])
-            }
-            RiscvEVMECalls::CallDataCopy => {
-                // Loads the dest_offset, offset and size from registers
-                let dest_offset = vm.registers.read_reg(CALL_DATA_COPY_INPUT_REGISTER_1);
-                let offset = vm.registers.read_reg(CALL_DATA_COPY_INPUT_REGISTER_2);
-                let size = vm.registers.read_reg(CALL_DATA_COPY_INPUT_REGISTER_3);
-
-                let mut data = Vec::new();
-                for i in offset as usize..(offset + size) as usize {
-                    data.push(*context.eth_context.tx.data.get(i).unwrap_or(&0u8));
-                }
-
-                // writing to memory
-                for (i, byte) in data.iter().enumerate() {
-                    let byte_addr = dest_offset + i as u32;
-                    vm.memory
-                        .write_mem(byte_addr as u32, MemoryChuckSize::BYTE, *byte as u32);
-                }
-
-                Ok(vec![])
-            }
-            RiscvEVMECalls::CodeSize => {
-                // This function retruns the code of the currently excecuting contract
-                let code_len = context
-                    .eth_context
-                    .load_account_code(context.address)
-                    .unwrap_or_default()
-                    .len() as u32;
-
-                vm.registers.write_reg(CODE_SIZE_OUT_REGISTER, code_len);
+    let request = EcallRequest::decode(vm)?;
+    if let Some(response) = vm.local_ecall(&request, context) {
+        return response?.apply(vm, context).map(|()| vec![]);
+    }
+    let (response, outputs) = serve_ecall(context, &request)?;
+    response.apply(vm, context)?;
+    Ok(outputs)
+}
 
-                Ok(vec![])
+/// Serves `request` against the journal of `context`, returning the writes to make to the Vm
+/// along with the journal outputs
+fn serve_ecall(
+    context: &mut Context,
+    request: &EcallRequest,
+) -> Result<(EcallResponse, Vec<JournalOutput>), VMErrors> {
+    let mut response = EcallResponse::default();
+    let outputs = match (request.ecall, &request.args) {
+        (RiscvEVMECalls::Keccak256, EcallArgs::Bytes(data)) => {
+            // The data is read from memory from `offset` and `size`,
+            // after the hashing is done, it would be stored in 8 registers
+            let hash = keccak256(data);
+
+            // writing 256 bits to 8 regiters
+            response.write_word256(KECCAK256_OUTPUT_REGITER_1, &hash.0);
+
+            vec![]
+        }
+        (RiscvEVMECalls::Address, _) => {
+            // This branch would load the address of this current running contract from context
+            // to 5 regiters
+            let address = context.address.0.0;
+
+            // writing 160 bits (20 bytes) to register
+            response.write_address(ADDRESS_REGISTER_1, &address);
+
+            vec![]
+        }
+        (RiscvEVMECalls::Balance, &EcallArgs::Address(address)) => {
+            // The address is read from 5 registers,
+            // query the balance from context, write this balance to 8 new registers
+            let balance = context.eth_context.balance(address).unwrap_or_default();
+            context.charge_gas(balance_cost(context.spec(), balance.is_cold))?;
+
+            let balance: [u8; 32] = balance.data.to_be_bytes();
+
+            // writing 256 bits to 8 regiters
+            response.write_word256(BALANCE_OUTPUT_REGISTER_1, &balance);
+
+            vec![]
+        }
+        (RiscvEVMECalls::Origin, _) => {
+            let origin = context.eth_context.tx.caller.0;
+
+            // Writing this origin to five registers
+            response.write_address(ORIGIN_OUTPUT_REGISTER_1, &origin);
+
+            vec![]
+        }
+        (RiscvEVMECalls::Caller, _) => {
+            let origin = context.current_caller.0;
+
+            // Writing this origin to five registers
+            response.write_address(CALLER_OUTPUT_REGISTER_1, &origin);
+
+            vec![]
+        }
+        (RiscvEVMECalls::CallValue, _) => {
+            // Load the vaule from context into a 8 registers
+            let value: [u8; 32] = context.eth_context.tx.value.to_be_bytes();
+
+            // writing 256 bits to 8 regiters
+            response.write_word256(CALL_VALUE_OUTPUT_REGISTER_1, &value);
+
+            vec![]
+        }
+        (RiscvEVMECalls::CallDataLoad, &EcallArgs::Index(offset)) => {
+            // This would load 32bytes of the call data to 8 registers
+            // The offset this 32bytes should come from is gotten from a register.
+            let mut data = [0u8; 32];
+            data.copy_from_slice(&copy_padded(&context.eth_context.tx.data, offset, 32)?);
+
+            // writing 256 bits to 8 regiters
+            response.write_word256(CALL_DATA_LOAD_OUTPUT_REGISTER_1, &data);
+
+            vec![]
+        }
+        (RiscvEVMECalls::CallDataSize, _) => {
+            // This load to a register the number of bytes present in the calldata
+            // into a register
+            let size = context.eth_context.tx.data.len() as u32;
+
+            response.write_reg(CALL_DATA_SIZE_OUTPUT_REGISTER, size);
+
+            vec![]
+        }
+        (
+            RiscvEVMECalls::CallDataCopy,
+            &EcallArgs::Copy {
+                dest_offset,
+                offset,
+                size,
+                ..
+            },
+        ) => {
+            let data = copy_padded(&context.eth_context.tx.data, offset, size)?;
+
+            // writing to memory
+            response.write_memory(dest_offset, &data);
+
+            vec![]
+        }
+        (RiscvEVMECalls::CodeSize, _) => {
+            // This function retruns the code of the currently excecuting contract
+            let code_len = context
+                .eth_context
+                .load_account_code(context.address)
+                .unwrap_or_default()
+                .len() as u32;
+
+            response.write_reg(CODE_SIZE_OUT_REGISTER, code_len);
+
+            vec![]
+        }
+        (
+            RiscvEVMECalls::CodeCopy,
+            &EcallArgs::Copy {
+                dest_offset,
+                offset,
+                size,
+                ..
+            },
+        ) => {
+            // This copies the code of the current running contract to memory
+            // the dest_offest, offset and size is gotten from the register
+
+            let code = context
+                .eth_context
+                .journal()
+                .code(context.address)
+                .map_err(|e| VMErrors::CodeLoadError(e.to_string()))?
+                .data;
+
+            let data = copy_padded(&code, offset, size)?;
+
+            // writing to memory
+            response.write_memory(dest_offset, &data);
+
+            vec![]
+        }
+        (RiscvEVMECalls::GasPrice, _) => {
+            // This returns the gas price in the current enviroment
+            let gas_price: [u8; 32] = context.eth_context.effective_gas_price().to_be_bytes();
+
+            // writing 256 bits to 8 regiters
+            response.write_word256(GAS_PRICE_OUTPUT_REGISTER_1, &gas_price);
+
+            vec![]
+        }
+        (RiscvEVMECalls::ExtCodeSize, &EcallArgs::Address(address)) => {
+            // This returns the size of the code of a given address
+            let code = context
+                .eth_context
+                .journal()
+                .code(address)
+                .map_err(|e| VMErrors::CodeLoadError(e.to_string()))?;
+            context.charge_gas(ext_code_size_cost(context.spec(), code.is_cold))?;
+
+            let code_len = code.data.len() as u32;
+
+            response.write_reg(EXT_CODE_SIZE_INPUT_REGISTER_6, code_len);
+
+            vec![]
+        }
+        (
+            RiscvEVMECalls::ExtCodeCopy,
+            &EcallArgs::Copy {
+                address: Some(address),
+                dest_offset,
+                offset,
+                size,
+            },
+        ) => {
+            let code = context
+                .eth_context
+                .journal()
+                .code(address)
+                .map_err(|e| VMErrors::CodeLoadError(e.to_string()))?;
+            let cost = extcodecopy_cost(context.spec(), size as usize, code.is_cold)
+                .ok_or(VMErrors::OutOfGas)?;
+            context.charge_gas(cost)?;
+
+            let data = copy_padded(&code.data, offset, size)?;
+
+            // writing to memory
+            response.write_memory(dest_offset, &data);
+
+            vec![]
+        }
+        (RiscvEVMECalls::ReturnDataSize, _) => {
+            // This returns the size of the return data from the last call/frame
+            // This request would be copied to a register
+            let data_len = context.return_data.len() as u32;
+
+            response.write_reg(RETURN_DATA_SIZE_OUTPUT_REGISTER, data_len);
+
+            vec![]
+        }
+        (
+            RiscvEVMECalls::ReturnDataCopy,
+            &EcallArgs::Copy {
+                dest_offset,
+                offset,
+                size,
+                ..
+            },
+        ) => {
+            let data = copy_padded(&context.return_data, offset, size)?;
+
+            // writing to memory
+            response.write_memory(dest_offset, &data);
+
+            vec![]
+        }
+        (RiscvEVMECalls::ExtCodeHash, &EcallArgs::Address(address)) => {
+            let code_hash = context
+                .eth_context
+                .load_account_code_hash(address)
+                .unwrap_or_default();
+            context.charge_gas(ext_code_hash_cost(context.spec(), code_hash.is_cold))?;
+
+            let code_hash = code_hash.data.0;
+
+            // writing 256 bits to 8 regiters
+            response.write_word256(EXT_CODE_HASH_OUTPUT_REGISTER_1, &code_hash);
+
+            vec![]
+        }
+        (RiscvEVMECalls::BlockHash, &EcallArgs::Number(block_number)) => {
+            // The block_number is loaded from two register
+            let bloch_hash = context
+                .eth_context
+                .block_hash(block_number)
+                .unwrap_or_default()
+                .0;
+
+            // writing 256 bits to 8 regiters
+            response.write_word256(BLOCK_HASH_OUTPUT_REGISTER_1, &bloch_hash);
+
+            vec![]
+        }
+        (RiscvEVMECalls::Coinbase, _) => {
+            let address = context.eth_context.block.beneficiary.0.0;
+
+            // Writing this origin to five registers
+            response.write_address(COINBASE_OUTPUT_REGISTER_1, &address);
+
+            vec![]
+        }
+        (RiscvEVMECalls::Timestamp, _) => {
+            let timestamp = context.eth_context.block.timestamp;
+            let (timestamp_high, timestamp_low) = split_u64_to_u32(timestamp);
+
+            response.write_reg(TIMESTAMP_OUTPUT_REGISTER_1, timestamp_high);
+            response.write_reg(TIMESTAMP_OUTPUT_REGISTER_2, timestamp_low);
+
+            vec![]
+        }
+        (RiscvEVMECalls::Number, _) => {
+            let number = context.eth_context.block.number;
+            let (number_high, number_low) = split_u64_to_u32(number);
+
+            response.write_reg(NUMBER_OUTPUT_REGISTER_1, number_high);
+            response.write_reg(NUMBER_OUTPUT_REGISTER_2, number_low);
+
+            vec![]
+        }
+        (RiscvEVMECalls::PrevRandao, _) => {
+            // This allows a smart contract to access randomness (pseduo randomness), using an accumualation of a Randomness DAO for validators
+            let prev_randao: [u8; 32] = context
+                .eth_context
+                .prevrandao()
+                .unwrap_or_default()
+                .to_be_bytes();
+
+            // writing 256 bits to 8 regiters
+            response.write_word256(PREV_RANDAO_OUTPUT_REGISTER_1, &prev_randao);
+
+            vec![]
+        }
+        (RiscvEVMECalls::GasLimit, _) => {
+            // This obtains the blocks gas limit and writes it to register
+            let gas_limit: [u8; 32] = context.eth_context.gas_limit().to_be_bytes();
+
+            // writing 256 bits to 8 regiters
+            response.write_word256(GAS_LIMIT_OUTPUT_REGISTER_1, &gas_limit);
+
+            vec![]
+        }
+        (RiscvEVMECalls::ChainId, _) => {
+            // Loading the chain ID into registers
+            let chain_id = context.eth_context.cfg.chain_id;
+            let (chain_id_high, chain_id_low) = split_u64_to_u32(chain_id);
+
+            response.write_reg(CHAIN_ID_OUTPUT_REGISTER_1, chain_id_high);
+            response.write_reg(CHAIN_ID_OUTPUT_REGISTER_2, chain_id_low);
+
+            vec![]
+        }
+        (RiscvEVMECalls::SelfBalance, _) => {
+            // This gets the balance of the current contract
+            let contract_address = context.address;
+            let balance: [u8; 32] = context
+                .eth_context
+                .balance(contract_address)
+                .unwrap_or_default()
+                .data
+                .to_be_bytes();
+
+            // writing 256 bits to 8 regiters
+            response.write_word256(SELF_BALANCE_OUTPUT_REGISTER_1, &balance);
+
+            vec![]
+        }
+        (RiscvEVMECalls::BaseFee, _) => {
+            let base_fee: [u8; 32] = context.eth_context.basefee().to_be_bytes();
+
+            // writing 256 bits to 8 regiters
+            response.write_word256(BASE_FEE_OUTPUT_REGISTER_1, &base_fee);
+
+            vec![]
+        }
+        (RiscvEVMECalls::BlobHash, &EcallArgs::Index(index)) => {
+            let base_fee: [u8; 32] = context
+                .eth_context
+                .blob_hash(index as usize)
+                .unwrap_or_default()
+                .to_be_bytes();
+
+            // writing 256 bits to 8 regiters
+            response.write_word256(BLOB_HASH_OUTPUT_REGISTER_2, &base_fee);
+
+            vec![]
+        }
+        (RiscvEVMECalls::BlobBaseFee, _) => {
+            let base_fee: [u8; 32] = context.eth_context.blob_gasprice().to_be_bytes();
+
+            // writing 256 bits to 8 regiters
+            response.write_word256(BLOB_BASE_FEE_OUTPUT_REGISTER_1, &base_fee);
+
+            vec![]
+        }
+        (RiscvEVMECalls::Gas, _) => {
+            // Return the gas left on the meter to 8 registers
+            let gas_left: [u8; 32] = U256::from(context.gas.remaining()).to_be_bytes();
+
+            // writing 256 bits to 8 regiters
+            response.write_word256(GAS_OUTPUT_REGISTER_1, &gas_left);
+
+            vec![]
+        }
+        (RiscvEVMECalls::Log0 | RiscvEVMECalls::Log1, EcallArgs::Log { topics, data }) => {
+            let log_data = LogData::new_unchecked(topics.clone(), data.clone());
+            let log = Log {
+                address: context.address,
+                data: log_data,
+            };
+            if let Some(inspector) = context.inspector.clone() {
+                inspector.log(context, log.clone());
             }
-            RiscvEVMECalls::CodeCopy => {
-                // This copies the code of the current running contract to memory
-                // the dest_offest, offset and size is gotten from the register
-                // Loads the dest_offset, offset and size from registers
-                let dest_offset = vm.registers.read_reg(CODE_COPY_INPUT_REGISTER_1);
-                let offset = vm.registers.read_reg(CODE_COPY_INPUT_REGISTER_2);
-                let size = vm.registers.read_reg(CODE_COPY_INPUT_REGISTER_3);
-
-                let code = context
-                    .eth_context
-                    .journal()
-                    .code(context.address)
-                    .map_err(|e| VMErrors::CodeLoadError(e.to_string()))?
-                    .data;
-
-                let mut data = Vec::new();
-                for i in offset as usize..(offset + size) as usize {
-                    data.push(*code.get(i).unwrap_or(&0u8));
-                }
-
-                // writing to memory
-                for (i, byte) in data.iter().enumerate() {
-                    let byte_addr = dest_offset + i as u32;
-                    vm.memory
-                        .write_mem(byte_addr as u32, MemoryChuckSize::BYTE, *byte as u32);
-                }
-
-                Ok(vec![])
+            context.eth_context.log(log);
+
+            finalize(context)
+        }
+        (RiscvEVMECalls::Log2, _) => {
+            // TODO: Implement Log2 (would not be implementing this, it would consume to much registers, a better way to go around this would to store the topic in memory not in a register (or stack in the case of the native evm))
+            vec![]
+        }
+        (RiscvEVMECalls::Log3, _) => {
+            // TODO: Implement Log3 (would not be implementing this, it would consume to much registers, a better way to go around this would to store the topic in memory not in a register (or stack in the case of the native evm))
+            vec![]
+        }
+        (RiscvEVMECalls::Log4, _) => {
+            // TODO: Implement Log4 (would not be implementing this, it would consume to much registers, a better way to go around this would to store the topic in memory not in a register (or stack in the case of the native evm))
+            vec![]
+        }
+        (
+            RiscvEVMECalls::Create,
+            &EcallArgs::Create {
+                value,
+                ref init_code,
+                ..
+            },
+        ) => {
+            // The init code runs as a frame at the address derived from the creator and its nonce,
+            // the runtime code it returns is deployed there
+            // a create has no gas argument, it is given all it may be
+            let gas_limit = forward_gas(context, U256::MAX, false)?;
+            let inputs = CreateInputs {
+                caller: context.current_caller,
+                scheme: CreateScheme::Create,
+                value,
+                init_code: init_code.clone(),
+                gas_limit,
+            };
+            let created = create_frame(context, inputs)?;
+
+            // storing the created address in a resigter
+            response.write_address(CREATE_OUTPUT_REGISTER_1, &created.unwrap_or_default().0);
+
+            finalize(context)
+        }
+        (
+            RiscvEVMECalls::Call,
+            &EcallArgs::Call {
+                gas,
+                address,
+                value,
+                ref input,
+                return_offset,
+                return_size,
+                ..
+            },
+        ) => {
+            // This would run the code of the contract that is being called in a frame of its own
+            // account access (cold/warm), value transfer and new account costs
+            let account_load = context
+                .eth_context
+                .load_account_delegated(address)
+                .ok_or(VMErrors::VMCallError(1))?;
+            context.charge_gas(call_cost(context.spec(), !value.is_zero(), account_load))?;
+
+            let gas_limit = forward_gas(context, gas, !value.is_zero())?;
+            let inputs = CallInputs {
+                input: input.clone(),
+                return_memory_offset: return_offset as usize
+                    ..return_offset as usize + return_size as usize,
+                gas_limit,
+                bytecode_address: address,
+                target_address: address,
+                caller: context.address,
+                value: CallValue::Transfer(value),
+                scheme: CallScheme::Call,
+                is_static: false,
+                is_eof: false,
+            };
+            let output = call_frame(context, inputs)?;
+            write_call_output(&mut response, return_offset, return_size, output);
+
+            finalize(context)
+        }
+        (
+            RiscvEVMECalls::CallCode,
+            &EcallArgs::Call {
+                gas,
+                address,
+                value,
+                ref input,
+                return_offset,
+                return_size,
+                ..
+            },
+        ) => {
+            // Similar to Call but uses code from specified address while keeping context of current contract
+            // {The Opcode is deprecated}
+            // account access (cold/warm) and value transfer costs, no new account is created by CALLCODE
+            let mut account_load = context
+                .eth_context
+                .load_account_delegated(address)
+                .ok_or(VMErrors::VMCallError(1))?;
+            account_load.data.is_empty = false;
+            context.charge_gas(call_cost(context.spec(), !value.is_zero(), account_load))?;
+
+            // In CallCode, address stays the same (current contract)
+            // but we use code from the target address
+            let gas_limit = forward_gas(context, gas, !value.is_zero())?;
+            let inputs = CallInputs {
+                input: input.clone(),
+                return_memory_offset: return_offset as usize
+                    ..return_offset as usize + return_size as usize,
+                gas_limit,
+                bytecode_address: address,
+                target_address: context.address,
+                caller: context.current_caller,
+                value: CallValue::Transfer(value),
+                scheme: CallScheme::CallCode,
+                is_static: false,
+                is_eof: false,
+            };
+            let output = call_frame(context, inputs)?;
+            write_call_output(&mut response, return_offset, return_size, output);
+
+            finalize(context)
+        }
+        (RiscvEVMECalls::Return, EcallArgs::Bytes(data)) => {
+            // This ECALL Halts the vm returning the output
+            response.halt = true;
+
+            context.return_data = data.clone();
+
+            let _ = context.eth_context.journal().checkpoint();
+            context.eth_context.journal().checkpoint_commit();
+
+            vec![]
+        }
+        (
+            RiscvEVMECalls::DelegateCall,
+            &EcallArgs::Call {
+                gas,
+                address,
+                ref input,
+                return_offset,
+                return_size,
+                ..
+            },
+        ) => {
+            // Similar to CallCode but also keeps sender and value from original call
+            // No value registers read because DelegateCall preserves the value from the original call
+
+            // account access (cold/warm) cost
+            let mut account_load = context
+                .eth_context
+                .load_account_delegated(address)
+                .ok_or(VMErrors::VMCallError(1))?;
+            account_load.data.is_empty = false;
+            context.charge_gas(call_cost(context.spec(), false, account_load))?;
+
+            // Keep the same address (this contract) and the original caller, no value transfer
+            let gas_limit = forward_gas(context, gas, false)?;
+            let inputs = CallInputs {
+                input: input.clone(),
+                return_memory_offset: return_offset as usize
+                    ..return_offset as usize + return_size as usize,
+                gas_limit,
+                bytecode_address: address,
+                target_address: context.address,
+                caller: context.current_caller,
+                value: CallValue::Apparent(context.eth_context.tx.value),
+                scheme: CallScheme::DelegateCall,
+                is_static: false,
+                is_eof: false,
+            };
+            let output = call_frame(context, inputs)?;
+            write_call_output(&mut response, return_offset, return_size, output);
+
+            finalize(context)
+        }
+        (
+            RiscvEVMECalls::Create2,
+            &EcallArgs::Create {
+                value,
+                ref init_code,
+                salt: Some(salt),
+            },
+        ) => {
+            // Like Create, the address is derived from the creator, the salt and the init code hash
+            // a create has no gas argument, it is given all it may be
+            let gas_limit = forward_gas(context, U256::MAX, false)?;
+            let inputs = CreateInputs {
+                caller: context.current_caller,
+                scheme: CreateScheme::Create2 { salt },
+                value,
+                init_code: init_code.clone(),
+                gas_limit,
+            };
+            let created = create_frame(context, inputs)?;
+
+            // storing the created address in a resigter
+            response.write_address(CREATE_2_OUTPUT_REGISTER_1, &created.unwrap_or_default().0);
+
+            finalize(context)
+        }
+        (
+            RiscvEVMECalls::StaticCall,
+            &EcallArgs::Call {
+                gas,
+                address,
+                ref input,
+                return_offset,
+                return_size,
+                ..
+            },
+        ) => {
+            // Similar to Call but in static mode - cannot modify state
+            // StaticCall doesn't transfer value, so we don't read the value registers
+
+            // account access (cold/warm) cost
+            let mut account_load = context
+                .eth_context
+                .load_account_delegated(address)
+                .ok_or(VMErrors::VMCallError(1))?;
+            account_load.data.is_empty = false;
+            context.charge_gas(call_cost(context.spec(), false, account_load))?;
+
+            // TODO: Configure to be static
+            let gas_limit = forward_gas(context, gas, false)?;
+            let inputs = CallInputs {
+                input: input.clone(),
+                return_memory_offset: return_offset as usize
+                    ..return_offset as usize + return_size as usize,
+                gas_limit,
+                bytecode_address: address,
+                target_address: address,
+                caller: context.address,
+                value: CallValue::Transfer(U256::ZERO),
+                scheme: CallScheme::StaticCall,
+                is_static: true,
+                is_eof: false,
+            };
+            let output = call_frame(context, inputs)?;
+            write_call_output(&mut response, return_offset, return_size, output);
+
+            finalize(context)
+        }
+        (RiscvEVMECalls::Revert, EcallArgs::Bytes(data)) => {
+            // This ECALL Halts the vm returning the output, reverting state changes using the journal
+            response.halt = true;
+            response.revert = true;
+
+            context.return_data = data.clone();
+
+            let check_point = context.eth_context.journal().checkpoint();
+            context.eth_context.journal().checkpoint_revert(check_point);
+
+            finalize(context)
+        }
+        (RiscvEVMECalls::SelfDestruct, &EcallArgs::Address(target)) => {
+            // This ECALL Halts the vm, the balance of this contract goes to the target
+
+            let value = context
+                .eth_context
+                .journal()
+                .load_account(context.address)
+                .map_err(|_| VMErrors::VMAccountLoadFailed)?
+                .data
+                .info
+                .balance;
+
+            // the price depends on the target account, so the transfer is undone when it can
+            // not be paid for
+            let checkpoint = context.eth_context.journal().checkpoint();
+            let result = context
+                .eth_context
+                .journal()
+                .selfdestruct(context.address, target)
+                .map_err(|_| VMErrors::VMAccountLoadFailed)?;
+            // EIP-3529: no refund from London on
+            if !context.spec().is_enabled_in(SpecId::LONDON) && !result.previously_destroyed {
+                context.gas.record_refund(SELFDESTRUCT);
             }
-            RiscvEVMECalls::GasPrice => {
-                // This returns the gas price in the current enviroment
-                let gas_price: [u8; 32] = context.eth_context.effective_gas_price().to_be_bytes();
-
-                // writing 256 bits to 8 regiters
-                vm.registers
-                    .write_reg(GAS_PRICE_OUTPUT_REGISTER_1, bytes_to_u32(&gas_price[0..4]));
-                vm.registers
-                    .write_reg(GAS_PRICE_OUTPUT_REGISTER_2, bytes_to_u32(&gas_price[4..8]));
-                vm.registers
-                    .write_reg(GAS_PRICE_OUTPUT_REGISTER_3, bytes_to_u32(&gas_price[8..12]));
-                vm.registers.write_reg(
-                    GAS_PRICE_OUTPUT_REGISTER_4,
-                    bytes_to_u32(&gas_price[12..16]),
-                );
-                vm.registers.write_reg(
-                    GAS_PRICE_OUTPUT_REGISTER_5,
-                    bytes_to_u32(&gas_price[16..20]),
-                );
-                vm.registers.write_reg(
-                    GAS_PRICE_OUTPUT_REGISTER_6,
-                    bytes_to_u32(&gas_price[20..24]),
-                );
-                vm.registers.write_reg(
-                    GAS_PRICE_OUTPUT_REGISTER_7,
-                    bytes_to_u32(&gas_price[24..28]),
-                );
-                vm.registers.write_reg(
-                    GAS_PRICE_OUTPUT_REGISTER_8,
-                    bytes_to_u32(&gas_price[28..32]),
-                );
-
-                Ok(vec![])
+            if let Err(error) = context.charge_gas(selfdestruct_cost(context.spec(), result)) {
+                context.eth_context.journal().checkpoint_revert(checkpoint);
+                return Err(error);
             }
-            RiscvEVMECalls::ExtCodeSize => {
-                // This would copy the code of a given address to memory
-                // This function retruns the code of the currently excecuting contract
-                let address_u32_1 = vm.registers.read_reg(EXT_CODE_SIZE_INPUT_REGISTER_1);
-                let address_u32_2 = vm.registers.read_reg(EXT_CODE_SIZE_INPUT_REGISTER_2);
-                let address_u32_3 = vm.registers.read_reg(EXT_CODE_SIZE_INPUT_REGISTER_3);
-                let address_u32_4 = vm.registers.read_reg(EXT_CODE_SIZE_INPUT_REGISTER_4);
-                let address_u32_5 = vm.registers.read_reg(EXT_CODE_SIZE_INPUT_REGISTER_5);
-
-                let address = u32_vec_to_address(&vec![
-                    address_u32_1,
-                    address_u32_2,
-                    address_u32_3,
-                    address_u32_4,
-                    address_u32_5,
-                ]);
-
-                let code_len = context
-                    .eth_context
-                    .journal()
-                    .code(Address::from(address))
-                    .map_err(|e| VMErrors::CodeLoadError(e.to_string()))?
-                    .len() as u32;
-
-                vm.registers
-                    .write_reg(EXT_CODE_SIZE_INPUT_REGISTER_6, code_len);
-
-                Ok(vec![])
-            }
-            RiscvEVMECalls::ExtCodeCopy => {
-                let address_u32_1 = vm.registers.read_reg(EXT_CODE_COPY_INPUT_REGISTER_1);
-                let address_u32_2 = vm.registers.read_reg(EXT_CODE_COPY_INPUT_REGISTER_2);
-                let address_u32_3 = vm.registers.read_reg(EXT_CODE_COPY_INPUT_REGISTER_3);
-                let address_u32_4 = vm.registers.read_reg(EXT_CODE_COPY_INPUT_REGISTER_4);
-                let address_u32_5 = vm.registers.read_reg(EXT_CODE_COPY_INPUT_REGISTER_5);
-
-                let dest_offset = vm.registers.read_reg(EXT_CODE_COPY_INPUT_REGISTER_6);
-                let offset = vm.registers.read_reg(EXT_CODE_COPY_INPUT_REGISTER_7);
-                let size = vm.registers.read_reg(EXT_CODE_COPY_INPUT_REGISTER_8);
-
-                let address = u32_vec_to_address(&vec![
-                    address_u32_1,
-                    address_u32_2,
-                    address_u32_3,
-                    address_u32_4,
-                    address_u32_5,
-                ]);
-
-                let code = context
-                    .eth_context
-                    .journal()
-                    .code(Address::from(address))
-                    .map_err(|e| VMErrors::CodeLoadError(e.to_string()))?
-                    .data;
-
-                let mut data = Vec::new();
-                for i in offset as usize..(offset + size) as usize {
-                    data.push(*code.get(i).unwrap_or(&0u8));
-                }
-
-                // writing to memory
-                for (i, byte) in data.iter().enumerate() {
-                    let byte_addr = dest_offset + i as u32;
-                    vm.memory
-                        .write_mem(byte_addr as u32, MemoryChuckSize::BYTE, *byte as u32);
-                }
-
-                Ok(vec![])
-            }
-            RiscvEVMECalls::ReturnDataSize => {
-                // This returns the size of the return data from the last call/frame
-                // This request would be copied to a register
-                let data_len = context.return_data.to_vec().len() as u32;
-
-                vm.registers
-                    .write_reg(RETURN_DATA_SIZE_OUTPUT_REGISTER, data_len);
+            context.eth_context.journal().checkpoint_commit();
 
-                Ok(vec![])
-            }
-            RiscvEVMECalls::ReturnDataCopy => {
-                let dest_offset = vm.registers.read_reg(RETURN_DATA_COPY_INPUT_REGISTER_1);
-                let offset = vm.registers.read_reg(RETURN_DATA_COPY_INPUT_REGISTER_2);
-                let size = vm.registers.read_reg(RETURN_DATA_COPY_INPUT_REGISTER_3);
-
-                let return_data = context.return_data.to_vec();
-
-                let mut data = Vec::new();
-                for i in offset as usize..(offset + size) as usize {
-                    data.push(*return_data.get(i).unwrap_or(&0u8));
-                }
-
-                // writing to memory
-                for (i, byte) in data.iter().enumerate() {
-                    let byte_addr = dest_offset + i as u32;
-                    vm.memory
-                        .write_mem(byte_addr as u32, MemoryChuckSize::BYTE, *byte as u32);
-                }
-
-                Ok(vec![])
-            }
-            RiscvEVMECalls::ExtCodeHash => {
-                let address_u32_1 = vm.registers.read_reg(EXT_CODE_HASH_INPUT_REGISTER_1);
-                let address_u32_2 = vm.registers.read_reg(EXT_CODE_SIZE_INPUT_REGISTER_2);
-                let address_u32_3 = vm.registers.read_reg(EXT_CODE_SIZE_INPUT_REGISTER_3);
-                let address_u32_4 = vm.registers.read_reg(EXT_CODE_SIZE_INPUT_REGISTER_4);
-                let address_u32_5 = vm.registers.read_reg(EXT_CODE_SIZE_INPUT_REGISTER_5);
-
-                let address = u32_vec_to_address(&vec![
-                    address_u32_1,
-                    address_u32_2,
-                    address_u32_3,
-                    address_u32_4,
-                    address_u32_5,
-                ]);
-
-                let code_hash = context
-                    .eth_context
-                    .load_account_code_hash(Address::from(address))
-                    .unwrap_or_default()
-                    .0;
-
-                // writing 256 bits to 8 regiters
-                vm.registers.write_reg(
-                    EXT_CODE_HASH_OUTPUT_REGISTER_1,
-                    bytes_to_u32(&code_hash[0..4]),
-                );
-                vm.registers.write_reg(
-                    EXT_CODE_HASH_OUTPUT_REGISTER_2,
-                    bytes_to_u32(&code_hash[4..8]),
-                );
-                vm.registers.write_reg(
-                    EXT_CODE_HASH_OUTPUT_REGISTER_3,
-                    bytes_to_u32(&code_hash[8..12]),
-                );
-                vm.registers.write_reg(
-                    EXT_CODE_HASH_OUTPUT_REGISTER_4,
-                    bytes_to_u32(&code_hash[12..16]),
-                );
-                vm.registers.write_reg(
-                    EXT_CODE_HASH_OUTPUT_REGISTER_5,
-                    bytes_to_u32(&code_hash[16..20]),
-                );
-                vm.registers.write_reg(
-                    EXT_CODE_HASH_OUTPUT_REGISTER_6,
-                    bytes_to_u32(&code_hash[20..24]),
-                );
-                vm.registers.write_reg(
-                    EXT_CODE_HASH_OUTPUT_REGISTER_7,
-                    bytes_to_u32(&code_hash[24..28]),
-                );
-                vm.registers.write_reg(
-                    EXT_CODE_HASH_OUTPUT_REGISTER_8,
-                    bytes_to_u32(&code_hash[28..32]),
-                );
-
-                Ok(vec![])
+            if let Some(inspector) = context.inspector.clone() {
+                inspector.with(|i| i.selfdestruct(context.address, target, value));
             }
-            RiscvEVMECalls::BlockHash => {
-                // This would load the block_number from two register
-                let block_number_high = vm.registers.read_reg(BLOCK_HASH_INPUT_REGISTER_1);
-                let block_number_low = vm.registers.read_reg(BLOCK_HASH_INPUT_REGISTER_2);
-
-                let block_number: u64 = combine_u32_to_u64(block_number_high, block_number_low);
-                let bloch_hash = context
-                    .eth_context
-                    .block_hash(block_number)
-                    .unwrap_or_default()
-                    .0;
-
-                // writing 256 bits to 8 regiters
-                vm.registers.write_reg(
-                    EXT_CODE_HASH_OUTPUT_REGISTER_1,
-                    bytes_to_u32(&bloch_hash[0..4]),
-                );
-                vm.registers.write_reg(
-                    EXT_CODE_HASH_OUTPUT_REGISTER_2,
-                    bytes_to_u32(&bloch_hash[4..8]),
-                );
-                vm.registers.write_reg(
-                    EXT_CODE_HASH_OUTPUT_REGISTER_3,
-                    bytes_to_u32(&bloch_hash[8..12]),
-                );
-                vm.registers.write_reg(
-                    EXT_CODE_HASH_OUTPUT_REGISTER_4,
-                    bytes_to_u32(&bloch_hash[12..16]),
-                );
-                vm.registers.write_reg(
-                    EXT_CODE_HASH_OUTPUT_REGISTER_5,
-                    bytes_to_u32(&bloch_hash[16..20]),
-                );
-                vm.registers.write_reg(
-                    EXT_CODE_HASH_OUTPUT_REGISTER_6,
-                    bytes_to_u32(&bloch_hash[20..24]),
-                );
-                vm.registers.write_reg(
-                    EXT_CODE_HASH_OUTPUT_REGISTER_7,
-                    bytes_to_u32(&bloch_hash[24..28]),
-                );
-                vm.registers.write_reg(
-                    EXT_CODE_HASH_OUTPUT_REGISTER_8,
-                    bytes_to_u32(&bloch_hash[28..32]),
-                );
-
-                Ok(vec![])
-            }
-            RiscvEVMECalls::Coinbase => {
-                let address = context.eth_context.block.beneficiary.0.0;
-
-                // Writing this origin to five registers
-                vm.registers
-                    .write_reg(COINBASE_OUTPUT_REGISTER_1, bytes_to_u32(&address[0..4]));
-                vm.registers
-                    .write_reg(COINBASE_OUTPUT_REGISTER_2, bytes_to_u32(&address[4..8]));
-                vm.registers
-                    .write_reg(COINBASE_OUTPUT_REGISTER_3, bytes_to_u32(&address[8..12]));
-                vm.registers
-                    .write_reg(COINBASE_OUTPUT_REGISTER_4, bytes_to_u32(&address[12..16]));
-                vm.registers
-                    .write_reg(COINBASE_OUTPUT_REGISTER_5, bytes_to_u32(&address[16..20]));
-
-                Ok(vec![])
-            }
-            RiscvEVMECalls::Timestamp => {
-                let timestamp = context.eth_context.block.timestamp;
-                let (timestamp_high, timestamp_low) = split_u64_to_u32(timestamp);
-
-                vm.registers
-                    .write_reg(TIMESTAMP_OUTPUT_REGISTER_1, timestamp_high);
-                vm.registers
-                    .write_reg(TIMESTAMP_OUTPUT_REGISTER_2, timestamp_low);
 
-                Ok(vec![])
+            response.halt = true;
+            context.return_data = Default::default();
+
+            vec![]
+        }
+        (RiscvEVMECalls::SLoad, &EcallArgs::Slot(slot)) => {
+            // the journal expects the account to be loaded before its storage is read
+            context
+                .eth_context
+                .journal()
+                .load_account(context.address)
+                .map_err(|e| VMErrors::SLoadError(e.to_string()))?;
+
+            let value = context
+                .eth_context
+                .journal()
+                .sload(context.address, slot)
+                .map_err(|e| VMErrors::SLoadError(e.to_string()))?;
+            context.charge_gas(sload_cost(context.spec(), value.is_cold))?;
+
+            let value: [u8; 32] = value.data.to_be_bytes();
+
+            // writing 256 bits to 8 regiters
+            response.write_word256(SLOAD_OUTPUT_REGISTER_1, &value);
+
+            vec![]
+        }
+        (RiscvEVMECalls::SStore, &EcallArgs::Store { slot, value }) => {
+            context
+                .eth_context
+                .journal()
+                .load_account(context.address)
+                .map_err(|e| VMErrors::SStoreError(e.to_string()))?;
+
+            // EIP-2200: SSTORE fails if the gas left is not more than the call stipend
+            if context.spec().is_enabled_in(SpecId::ISTANBUL)
+                && context.gas.remaining() <= CALL_STIPEND
+            {
+                return Err(VMErrors::OutOfGas);
             }
-            RiscvEVMECalls::Number => {
-                let number = context.eth_context.block.number;
-                let (number_high, number_low) = split_u64_to_u32(number);
 
-                vm.registers
-                    .write_reg(NUMBER_OUTPUT_REGISTER_1, number_high);
-                vm.registers.write_reg(NUMBER_OUTPUT_REGISTER_2, number_low);
-
-                Ok(vec![])
-            }
-            RiscvEVMECalls::PrevRandao => {
-                // This allows a smart contract to access randomness (pseduo randomness), using an accumualation of a Randomness DAO for validators
-                let prev_randao: [u8; 32] = context
-                    .eth_context
-                    .prevrandao()
-                    .unwrap_or_default()
-                    .to_be_bytes();
-
-                // writing 256 bits to 8 regiters
-                vm.registers.write_reg(
-                    PREV_RANDAO_OUTPUT_REGISTER_1,
-                    bytes_to_u32(&prev_randao[0..4]),
-                );
-                vm.registers.write_reg(
-                    PREV_RANDAO_OUTPUT_REGISTER_2,
-                    bytes_to_u32(&prev_randao[4..8]),
-                );
-                vm.registers.write_reg(
-                    PREV_RANDAO_OUTPUT_REGISTER_3,
-                    bytes_to_u32(&prev_randao[8..12]),
-                );
-                vm.registers.write_reg(
-                    PREV_RANDAO_OUTPUT_REGISTER_4,
-                    bytes_to_u32(&prev_randao[12..16]),
-                );
-                vm.registers.write_reg(
-                    PREV_RANDAO_OUTPUT_REGISTER_5,
-                    bytes_to_u32(&prev_randao[16..20]),
-                );
-                vm.registers.write_reg(
-                    PREV_RANDAO_OUTPUT_REGISTER_6,
-                    bytes_to_u32(&prev_randao[20..24]),
-                );
-                vm.registers.write_reg(
-                    PREV_RANDAO_OUTPUT_REGISTER_7,
-                    bytes_to_u32(&prev_randao[24..28]),
-                );
-                vm.registers.write_reg(
-                    PREV_RANDAO_OUTPUT_REGISTER_8,
-                    bytes_to_u32(&prev_randao[28..32]),
-                );
-
-                Ok(vec![])
+            // the price depends on the values the write sees, so the write is undone when it
+            // can not be paid for
+            let checkpoint = context.eth_context.journal().checkpoint();
+            let Some(result) = context.eth_context.sstore(context.address, slot, value) else {
+                context.eth_context.journal().checkpoint_revert(checkpoint);
+                return Err(VMErrors::SStoreError(String::from("storage write failed")));
+            };
+
+            // EIP-2200/3529 net metering, the refund counter is settled by post_execution::refund
+            let cost = sstore_cost(context.spec(), &result.data, result.is_cold);
+            if let Err(error) = context.charge_gas(cost) {
+                context.eth_context.journal().checkpoint_revert(checkpoint);
+                return Err(error);
             }
-            RiscvEVMECalls::GasLimit => {
-                // This obtains the blocks gas limit and writes it to register
-                let gas_limit: [u8; 32] = context.eth_context.gas_limit().to_be_bytes();
-
-                // writing 256 bits to 8 regiters
-                vm.registers
-                    .write_reg(GAS_LIMIT_OUTPUT_REGISTER_1, bytes_to_u32(&gas_limit[0..4]));
-                vm.registers
-                    .write_reg(GAS_LIMIT_OUTPUT_REGISTER_2, bytes_to_u32(&gas_limit[4..8]));
-                vm.registers
-                    .write_reg(GAS_LIMIT_OUTPUT_REGISTER_3, bytes_to_u32(&gas_limit[8..12]));
-                vm.registers.write_reg(
-                    GAS_LIMIT_OUTPUT_REGISTER_4,
-                    bytes_to_u32(&gas_limit[12..16]),
-                );
-                vm.registers.write_reg(
-                    GAS_LIMIT_OUTPUT_REGISTER_5,
-                    bytes_to_u32(&gas_limit[16..20]),
-                );
-                vm.registers.write_reg(
-                    GAS_LIMIT_OUTPUT_REGISTER_6,
-                    bytes_to_u32(&gas_limit[20..24]),
-                );
-                vm.registers.write_reg(
-                    GAS_LIMIT_OUTPUT_REGISTER_7,
-                    bytes_to_u32(&gas_limit[24..28]),
-                );
-                vm.registers.write_reg(
-                    GAS_LIMIT_OUTPUT_REGISTER_8,
-                    bytes_to_u32(&gas_limit[28..32]),
-                );
-
-                Ok(vec![])
-            }
-            RiscvEVMECalls::ChainId => {
-                // Loading the chain ID into registers
-                let chain_id = context.eth_context.cfg.chain_id;
-                let (chain_id_high, chain_id_low) = split_u64_to_u32(chain_id);
-
-                vm.registers
-                    .write_reg(CHAIN_ID_OUTPUT_REGISTER_1, chain_id_high);
-                vm.registers
-                    .write_reg(CHAIN_ID_OUTPUT_REGISTER_2, chain_id_low);
+            context.eth_context.journal().checkpoint_commit();
+            context
+                .gas
+                .record_refund(sstore_refund(context.spec(), &result.data));
+
+            vec![]
+        }
+        // `Sbrk` and `DebugLog` are served by the Vm, see [Vm::local_ecall]
+        _ => return Err(VMErrors::EnvironmentError),
+    };
+
+    Ok((response, outputs))
+}
 
-                Ok(vec![])
-            }
-            RiscvEVMECalls::SelfBalance => {
-                // This gets the balance of the current contract
-                let contract_address = context.address;
-                let balance: [u8; 32] = context
-                    .eth_context
-                    .balance(contract_address)
-                    .unwrap_or_default()
-                    .data
-                    .to_be_bytes();
-
-                // writing 256 bits to 8 regiters
-                vm.registers
-                    .write_reg(SELF_BALANCE_OUTPUT_REGISTER_1, bytes_to_u32(&balance[0..4]));
-                vm.registers
-                    .write_reg(SELF_BALANCE_OUTPUT_REGISTER_2, bytes_to_u32(&balance[4..8]));
-                vm.registers.write_reg(
-                    SELF_BALANCE_OUTPUT_REGISTER_3,
-                    bytes_to_u32(&balance[8..12]),
-                );
-                vm.registers.write_reg(
-                    SELF_BALANCE_OUTPUT_REGISTER_4,
-                    bytes_to_u32(&balance[12..16]),
-                );
-                vm.registers.write_reg(
-                    SELF_BALANCE_OUTPUT_REGISTER_5,
-                    bytes_to_u32(&balance[16..20]),
-                );
-                vm.registers.write_reg(
-                    SELF_BALANCE_OUTPUT_REGISTER_6,
-                    bytes_to_u32(&balance[20..24]),
-                );
-                vm.registers.write_reg(
-                    SELF_BALANCE_OUTPUT_REGISTER_7,
-                    bytes_to_u32(&balance[24..28]),
-                );
-                vm.registers.write_reg(
-                    SELF_BALANCE_OUTPUT_REGISTER_8,
-                    bytes_to_u32(&balance[28..32]),
-                );
-
-                Ok(vec![])
-            }
-            RiscvEVMECalls::BaseFee => {
-                let base_fee: [u8; 32] = context.eth_context.basefee().to_be_bytes();
-
-                // writing 256 bits to 8 regiters
-                vm.registers
-                    .write_reg(BASE_FEE_OUTPUT_REGISTER_1, bytes_to_u32(&base_fee[0..4]));
-                vm.registers
-                    .write_reg(BASE_FEE_OUTPUT_REGISTER_2, bytes_to_u32(&base_fee[4..8]));
-                vm.registers
-                    .write_reg(BASE_FEE_OUTPUT_REGISTER_3, bytes_to_u32(&base_fee[8..12]));
-                vm.registers
-                    .write_reg(BASE_FEE_OUTPUT_REGISTER_4, bytes_to_u32(&base_fee[12..16]));
-                vm.registers
-                    .write_reg(BASE_FEE_OUTPUT_REGISTER_5, bytes_to_u32(&base_fee[16..20]));
-                vm.registers
-                    .write_reg(BASE_FEE_OUTPUT_REGISTER_6, bytes_to_u32(&base_fee[20..24]));
-                vm.registers
-                    .write_reg(BASE_FEE_OUTPUT_REGISTER_7, bytes_to_u32(&base_fee[24..28]));
-                vm.registers
-                    .write_reg(BASE_FEE_OUTPUT_REGISTER_8, bytes_to_u32(&base_fee[28..32]));
-
-                Ok(vec![])
-            }
-            RiscvEVMECalls::BlobHash => {
-                let index = vm.registers.read_reg(BLOB_HASH_OUTPUT_REGISTER_1);
-                let base_fee: [u8; 32] = context
-                    .eth_context
-                    .blob_hash(index as usize)
-                    .unwrap_or_default()
-                    .to_be_bytes();
-
-                // writing 256 bits to 8 regiters
-                vm.registers
-                    .write_reg(BLOB_HASH_OUTPUT_REGISTER_2, bytes_to_u32(&base_fee[0..4]));
-                vm.registers
-                    .write_reg(BLOB_HASH_OUTPUT_REGISTER_3, bytes_to_u32(&base_fee[4..8]));
-                vm.registers
-                    .write_reg(BLOB_HASH_OUTPUT_REGISTER_4, bytes_to_u32(&base_fee[8..12]));
-                vm.registers
-                    .write_reg(BLOB_HASH_OUTPUT_REGISTER_5, bytes_to_u32(&base_fee[12..16]));
-                vm.registers
-                    .write_reg(BLOB_HASH_OUTPUT_REGISTER_6, bytes_to_u32(&base_fee[16..20]));
-                vm.registers
-                    .write_reg(BLOB_HASH_OUTPUT_REGISTER_7, bytes_to_u32(&base_fee[20..24]));
-                vm.registers
-                    .write_reg(BLOB_HASH_OUTPUT_REGISTER_8, bytes_to_u32(&base_fee[24..28]));
-                vm.registers
-                    .write_reg(BLOB_HASH_OUTPUT_REGISTER_9, bytes_to_u32(&base_fee[28..32]));
-
-                Ok(vec![])
-            }
-            RiscvEVMECalls::BlobBaseFee => {
-                let base_fee: [u8; 32] = context.eth_context.blob_gasprice().to_be_bytes();
-
-                // writing 256 bits to 8 regiters
-                vm.registers.write_reg(
-                    BLOB_BASE_FEE_OUTPUT_REGISTER_1,
-                    bytes_to_u32(&base_fee[0..4]),
-                );
-                vm.registers.write_reg(
-                    BLOB_BASE_FEE_OUTPUT_REGISTER_2,
-                    bytes_to_u32(&base_fee[4..8]),
-                );
-                vm.registers.write_reg(
-                    BLOB_BASE_FEE_OUTPUT_REGISTER_3,
-                    bytes_to_u32(&base_fee[8..12]),
-                );
-                vm.registers.write_reg(
-                    BLOB_BASE_FEE_OUTPUT_REGISTER_4,
-                    bytes_to_u32(&base_fee[12..16]),
-                );
-                vm.registers.write_reg(
-                    BLOB_BASE_FEE_OUTPUT_REGISTER_5,
-                    bytes_to_u32(&base_fee[16..20]),
-                );
-                vm.registers.write_reg(
-                    BLOB_BASE_FEE_OUTPUT_REGISTER_6,
-                    bytes_to_u32(&base_fee[20..24]),
-                );
-                vm.registers.write_reg(
-                    BLOB_BASE_FEE_OUTPUT_REGISTER_7,
-                    bytes_to_u32(&base_fee[24..28]),
-                );
-                vm.registers.write_reg(
-                    BLOB_BASE_FEE_OUTPUT_REGISTER_8,
-                    bytes_to_u32(&base_fee[28..32]),
-                );
-
-                Ok(vec![])
-            }
-            RiscvEVMECalls::Gas => {
-                // Return the amount of gas left to 8 registers (this vm is not gas metered yet... :())
-                let gas_left: [u8; 32] = U256::ZERO.to_be_bytes();
-
-                // writing 256 bits to 8 regiters
-                vm.registers
-                    .write_reg(GAS_OUTPUT_REGISTER_1, bytes_to_u32(&gas_left[0..4]));
-                vm.registers
-                    .write_reg(GAS_OUTPUT_REGISTER_2, bytes_to_u32(&gas_left[4..8]));
-                vm.registers
-                    .write_reg(GAS_OUTPUT_REGISTER_3, bytes_to_u32(&gas_left[8..12]));
-                vm.registers
-                    .write_reg(GAS_OUTPUT_REGISTER_4, bytes_to_u32(&gas_left[12..16]));
-                vm.registers
-                    .write_reg(GAS_OUTPUT_REGISTER_5, bytes_to_u32(&gas_left[16..20]));
-                vm.registers
-                    .write_reg(GAS_OUTPUT_REGISTER_6, bytes_to_u32(&gas_left[20..24]));
-                vm.registers
-                    .write_reg(GAS_OUTPUT_REGISTER_7, bytes_to_u32(&gas_left[24..28]));
-                vm.registers
-                    .write_reg(GAS_OUTPUT_REGISTER_8, bytes_to_u32(&gas_left[28..32]));
-
-                Ok(vec![])
-            }
-            RiscvEVMECalls::Log0 => {
-                let offset = vm.registers.read_reg(LOG0_INPUT_REGISTER_1);
-                let size = vm.registers.read_reg(LOG0_INPUT_REGISTER_2);
+/// Runs `program` in a new frame of `context`, the callee's context, with the transaction data of
+/// `context` as its calldata
+pub fn run_frame(program: Arc<DecodedProgram>, context: &mut Context) -> Result<Vm, VMErrors> {
+    let mut vm = Vm::from_program(program);
+    vm.hook = context
+        .inspector
+        .as_ref()
+        .and_then(SharedInspector::step_hook);
+    vm.enter_frame(&context.eth_context.tx.data)?;
+    let changes_before = context.state_changes.as_ref().map(StateChanges::position);
+    vm.run(false, context);
+    // a frame that reverted or trapped leaves no state changes
+    if vm.exit_code != 0
+        && let (Some(changes), Some(position)) = (context.state_changes.as_mut(), changes_before)
+    {
+        changes.truncate(position);
+    }
+    Ok(vm)
+}
 
-                let mut data = vec![0u8; size as usize];
+/// The journal outputs of an ecall that changed the state.
+/// Outside of any frame the journal is finalized after every such ecall and the host commits the
+/// outputs. Inside a frame the changes stay in the journal the frames share, under the checkpoint
+/// of the frame, until the ecall that started the outermost frame returns.
+fn finalize(context: &mut Context) -> Vec<JournalOutput> {
+    let journal = context.eth_context.journal();
+    if journal.depth() == 0 {
+        vec![journal.finalize()]
+    } else {
+        vec![]
+    }
+}
 
-                for i in offset..(offset + size) {
-                    data.push(vm.memory.read_mem(i, MemoryChuckSize::BYTE).unwrap() as u8);
-                }
+/// What a frame replaces in the context it runs on, put back by [FrameEnv::leave]
+struct FrameEnv {
+    address: Address,
+    current_caller: Address,
+    data: Bytes,
+    value: U256,
+    return_data: Bytes,
+}
 
-                let log_data = LogData::new_unchecked(vec![], data.into());
-                let log = Log {
-                    address: context.address,
-                    data: log_data,
-                };
-                context.eth_context.log(log);
+impl FrameEnv {
+    /// Sets `context` up for a frame of `address` called by `caller` with `data` and `value`
+    fn enter(
+        context: &mut Context,
+        address: Address,
+        caller: Address,
+        data: Bytes,
+        value: U256,
+    ) -> Self {
+        let tx = &mut context.eth_context.tx;
+        Self {
+            address: std::mem::replace(&mut context.address, address),
+            current_caller: std::mem::replace(&mut context.current_caller, caller),
+            data: std::mem::replace(&mut tx.data, data),
+            value: std::mem::replace(&mut tx.value, value),
+            return_data: std::mem::take(&mut context.return_data),
+        }
+    }
 
-                Ok(vec![context.eth_context.journal().finalize()])
-            }
-            RiscvEVMECalls::Log1 => {
-                let offset = vm.registers.read_reg(LOG1_INPUT_REGISTER_1);
-                let size = vm.registers.read_reg(LOG1_INPUT_REGISTER_2);
-
-                let mut data = vec![0u8; size as usize];
-
-                for i in offset..(offset + size) {
-                    data.push(vm.memory.read_mem(i, MemoryChuckSize::BYTE).unwrap() as u8);
-                }
-
-                let topic_1 = vm.registers.read_reg(LOG1_INPUT_REGISTER_3);
-                let topic_2 = vm.registers.read_reg(LOG1_INPUT_REGISTER_4);
-                let topic_3 = vm.registers.read_reg(LOG1_INPUT_REGISTER_5);
-                let topic_4 = vm.registers.read_reg(LOG1_INPUT_REGISTER_6);
-                let topic_5 = vm.registers.read_reg(LOG1_INPUT_REGISTER_7);
-                let topic_6 = vm.registers.read_reg(LOG1_INPUT_REGISTER_8);
-                let topic_7 = vm.registers.read_reg(LOG1_INPUT_REGISTER_9);
-                let topic_8 = vm.registers.read_reg(LOG1_INPUT_REGISTER_10);
-
-                let topic = u32_vec_to_u256(&vec![
-                    topic_1, topic_2, topic_3, topic_4, topic_5, topic_6, topic_7, topic_8,
-                ]);
-                let log_data = LogData::new_unchecked(vec![B256::new(topic)], data.into());
-                let log = Log {
-                    address: context.address,
-                    data: log_data,
-                };
-                context.eth_context.log(log);
-
-                Ok(vec![context.eth_context.journal().finalize()])
-            }
-            RiscvEVMECalls::Log2 => {
-                // TODO: Implement Log2 (would not be implementing this, it would consume to much registers, a better way to go around this would to store the topic in memory not in a register (or stack in the case of the native evm))
-                Ok(vec![])
-            }
-            RiscvEVMECalls::Log3 => {
-                // TODO: Implement Log3 (would not be implementing this, it would consume to much registers, a better way to go around this would to store the topic in memory not in a register (or stack in the case of the native evm))
-                Ok(vec![])
-            }
-            RiscvEVMECalls::Log4 => {
-                // TODO: Implement Log4 (would not be implementing this, it would consume to much registers, a better way to go around this would to store the topic in memory not in a register (or stack in the case of the native evm))
-                Ok(vec![])
-            }
-            RiscvEVMECalls::Create => {
-                // First the initcode is obtained from the tx.data
-                // Then the address is calculated using the tx.sender and nonce
-                // Finally the contract is created using the initcode and address
-                // This process returns the runtime code, which is then stored in the account's code section
-                let offset = vm.registers.read_reg(CREATE_INPUT_REGISTER_1);
-                let size = vm.registers.read_reg(CREATE_INPUT_REGISTER_2);
-
-                let mut init_code = Vec::with_capacity(size as usize);
-
-                for i in offset..(offset + size) {
-                    init_code.push(vm.memory.read_mem(i, MemoryChuckSize::BYTE).unwrap() as u8);
-                }
-
-                let value_1 = vm.registers.read_reg(CREATE_INPUT_REGISTER_3);
-                let value_2 = vm.registers.read_reg(CREATE_INPUT_REGISTER_4);
-                let value_3 = vm.registers.read_reg(CREATE_INPUT_REGISTER_5);
-                let value_4 = vm.registers.read_reg(CREATE_INPUT_REGISTER_6);
-                let value_5 = vm.registers.read_reg(CREATE_INPUT_REGISTER_7);
-                let value_6 = vm.registers.read_reg(CREATE_INPUT_REGISTER_8);
-                let value_7 = vm.registers.read_reg(CREATE_INPUT_REGISTER_9);
-                let value_8 = vm.registers.read_reg(CREATE_INPUT_REGISTER_10);
-
-                let value = u32_vec_to_u256(&vec![
-                    value_1, value_2, value_3, value_4, value_5, value_6, value_7, value_8,
-                ]);
-
-                context
-                    .eth_context
-                    .journal()
-                    .load_account(context.address)
-                    .map_err(|_| VMErrors::VMCreateError(2))?;
-                let contract_creator = context.current_caller;
-                context
-                    .eth_context
-                    .journal()
-                    .load_account(contract_creator)
-                    .map_err(|_| VMErrors::VMCreateError(2))?;
-                let old_nonce;
-                if let Some(nonce) = context
-                    .eth_context
-                    .journal()
-                    .inc_account_nonce(contract_creator)
-                    .map_err(|_| VMErrors::VMCreateError(0))?
-                {
-                    old_nonce = nonce - 1;
-                } else {
-                    return Err(VMErrors::VMCreateError(1));
-                }
-                let new_contract_address = contract_creator.create(old_nonce);
-
-                context
-                    .eth_context
-                    .journal()
-                    .transfer(
-                        contract_creator,
-                        new_contract_address,
-                        U256::from_be_bytes(value),
-                    )
-                    .map_err(|_| VMErrors::VMCreateError(3))?;
-
-                // Next up is to run the init-code against this new address, this would perform the initialization of the smart contract
-                // This would do the storage setup and initialization, and returns the runtime code
-                let mut new_context = context.clone();
-                new_context.address = new_contract_address;
-                new_context.current_caller = contract_creator;
-                let mut new_vm =
-                    Vm::from_bin_u8(init_code).map_err(|_| VMErrors::VMCreateError(2))?;
-                new_vm.run(false, &mut new_context);
-
-                let _ = new_context.eth_context.journal().checkpoint();
-                new_context.eth_context.journal().checkpoint_commit();
-
-                let runtime_code = new_context.return_data;
-
-                context
-                    .eth_context
-                    .journal()
-                    .set_code(new_contract_address, Bytecode::new_legacy(runtime_code));
-
-                context
-                    .eth_context
-                    .journal()
-                    .inc_account_nonce(new_contract_address)
-                    .map_err(|_| VMErrors::VMCreateError(0))?;
-
-                let _ = context.eth_context.journal().checkpoint();
-                context.eth_context.journal().checkpoint_commit();
-
-                // storing the created address in a resigter
-                let nc_address_u32s = address_to_u32_vec(&new_contract_address.0);
-
-                vm.registers
-                    .write_reg(CREATE_OUTPUT_REGISTER_1, nc_address_u32s[0]);
-                vm.registers
-                    .write_reg(CREATE_OUTPUT_REGISTER_2, nc_address_u32s[1]);
-                vm.registers
-                    .write_reg(CREATE_OUTPUT_REGISTER_3, nc_address_u32s[2]);
-                vm.registers
-                    .write_reg(CREATE_OUTPUT_REGISTER_4, nc_address_u32s[3]);
-                vm.registers
-                    .write_reg(CREATE_OUTPUT_REGISTER_5, nc_address_u32s[4]);
-
-                Ok(vec![
-                    context.eth_context.journal().finalize(),
-                    new_context.eth_context.journal().finalize(),
-                ])
-            }
-            RiscvEVMECalls::Call => {
-                // This would create a sub context, execute the code of the contract that is being called
-                let gas_1 = vm.registers.read_reg(CALL_INPUT_REGISTER_1);
-                let gas_2 = vm.registers.read_reg(CALL_INPUT_REGISTER_2);
-                let gas_3 = vm.registers.read_reg(CALL_INPUT_REGISTER_3);
-                let gas_4 = vm.registers.read_reg(CALL_INPUT_REGISTER_4);
-                let gas_5 = vm.registers.read_reg(CALL_INPUT_REGISTER_5);
-                let gas_6 = vm.registers.read_reg(CALL_INPUT_REGISTER_6);
-                let gas_7 = vm.registers.read_reg(CALL_INPUT_REGISTER_7);
-                let gas_8 = vm.registers.read_reg(CALL_INPUT_REGISTER_8);
-
-                let _gas = u32_vec_to_u256(&vec![
-                    gas_1, gas_2, gas_3, gas_4, gas_5, gas_6, gas_7, gas_8,
-                ]);
-
-                let address_1 = vm.registers.read_reg(CALL_INPUT_REGISTER_9);
-                let address_2 = vm.registers.read_reg(CALL_INPUT_REGISTER_10);
-                let address_3 = vm.registers.read_reg(CALL_INPUT_REGISTER_11);
-                let address_4 = vm.registers.read_reg(CALL_INPUT_REGISTER_12);
-                let address_5 = vm.registers.read_reg(CALL_INPUT_REGISTER_13);
-
-                let address = u32_vec_to_address(&vec![
-                    address_1, address_2, address_3, address_4, address_5,
-                ]);
-
-                let value_1 = vm.registers.read_reg(CALL_INPUT_REGISTER_14);
-                let value_2 = vm.registers.read_reg(CALL_INPUT_REGISTER_15);
-                let value_3 = vm.registers.read_reg(CALL_INPUT_REGISTER_16);
-                let value_4 = vm.registers.read_reg(CALL_INPUT_REGISTER_17);
-                let value_5 = vm.registers.read_reg(CALL_INPUT_REGISTER_18);
-                let value_6 = vm.registers.read_reg(CALL_INPUT_REGISTER_19);
-                let value_7 = vm.registers.read_reg(CALL_INPUT_REGISTER_20);
-                let value_8 = vm.registers.read_reg(CALL_INPUT_REGISTER_21);
-
-                let value = u32_vec_to_u256(&vec![
-                    value_1, value_2, value_3, value_4, value_5, value_6, value_7, value_8,
-                ]);
-
-                let args_offset = vm.registers.read_reg(CALL_INPUT_REGISTER_22);
-                let args_size = vm.registers.read_reg(CALL_INPUT_REGISTER_23);
-
-                let return_offset = vm.registers.read_reg(CALL_INPUT_REGISTER_24);
-                let _return_size = vm.registers.read_reg(CALL_INPUT_REGISTER_25);
-
-                let mut call_data = Vec::new();
-                for i in args_offset..(args_offset + args_size) {
-                    call_data.push(vm.memory.read_mem(i, MemoryChuckSize::BYTE).unwrap() as u8);
-                }
-
-                let mut new_context = context.clone();
-                new_context.address = Address::from(address);
-                new_context.current_caller = context.address;
-                new_context.eth_context.modify_tx(|tx| {
-                    tx.data = call_data.into();
-                });
-                new_context
-                    .eth_context
-                    .journal()
-                    .load_account(new_context.address)
-                    .map_err(|_| VMErrors::VMCallError(1))?;
-                new_context
-                    .eth_context
-                    .journal()
-                    .load_account(new_context.current_caller)
-                    .map_err(|_| VMErrors::VMCallError(1))?;
-                let code = new_context
-                    .eth_context
-                    .load_account_code(new_context.address)
-                    .unwrap_or_default()
-                    .data;
-
-                context
-                    .eth_context
-                    .journal()
-                    .transfer(
-                        new_context.current_caller,
-                        new_context.address,
-                        U256::from_be_bytes(value),
-                    )
-                    .map_err(|_| VMErrors::VMCallError(0))?;
-
-                let mut new_vm =
-                    Vm::from_bin_u8(code.0.to_vec()).map_err(|_| VMErrors::VMCallError(1))?;
-                new_vm.run(false, &mut new_context);
-
-                // Storing the sub-context return data to memory
-                // writing to memory
-                for (i, byte) in new_context.return_data.iter().enumerate() {
-                    let byte_addr = return_offset + i as u32;
-                    vm.memory
-                        .write_mem(byte_addr as u32, MemoryChuckSize::BYTE, *byte as u32);
-                }
-
-                Ok(vec![
-                    context.eth_context.journal().finalize(),
-                    new_context.eth_context.journal().finalize(),
-                ])
-            }
-            RiscvEVMECalls::CallCode => {
-                // Similar to Call but uses code from specified address while keeping context of current contract
-                // {The Opcode is deprecated}
-                let gas_1 = vm.registers.read_reg(CALL_INPUT_REGISTER_1);
-                let gas_2 = vm.registers.read_reg(CALL_INPUT_REGISTER_2);
-                let gas_3 = vm.registers.read_reg(CALL_INPUT_REGISTER_3);
-                let gas_4 = vm.registers.read_reg(CALL_INPUT_REGISTER_4);
-                let gas_5 = vm.registers.read_reg(CALL_INPUT_REGISTER_5);
-                let gas_6 = vm.registers.read_reg(CALL_INPUT_REGISTER_6);
-                let gas_7 = vm.registers.read_reg(CALL_INPUT_REGISTER_7);
-                let gas_8 = vm.registers.read_reg(CALL_INPUT_REGISTER_8);
-
-                let _gas = u32_vec_to_u256(&vec![
-                    gas_1, gas_2, gas_3, gas_4, gas_5, gas_6, gas_7, gas_8,
-                ]);
-
-                let address_1 = vm.registers.read_reg(CALL_INPUT_REGISTER_9);
-                let address_2 = vm.registers.read_reg(CALL_INPUT_REGISTER_10);
-                let address_3 = vm.registers.read_reg(CALL_INPUT_REGISTER_11);
-                let address_4 = vm.registers.read_reg(CALL_INPUT_REGISTER_12);
-                let address_5 = vm.registers.read_reg(CALL_INPUT_REGISTER_13);
-
-                let address = u32_vec_to_address(&vec![
-                    address_1, address_2, address_3, address_4, address_5,
-                ]);
-
-                let value_1 = vm.registers.read_reg(CALL_INPUT_REGISTER_14);
-                let value_2 = vm.registers.read_reg(CALL_INPUT_REGISTER_15);
-                let value_3 = vm.registers.read_reg(CALL_INPUT_REGISTER_16);
-                let value_4 = vm.registers.read_reg(CALL_INPUT_REGISTER_17);
-                let value_5 = vm.registers.read_reg(CALL_INPUT_REGISTER_18);
-                let value_6 = vm.registers.read_reg(CALL_INPUT_REGISTER_19);
-                let value_7 = vm.registers.read_reg(CALL_INPUT_REGISTER_20);
-                let value_8 = vm.registers.read_reg(CALL_INPUT_REGISTER_21);
-
-                let value = u32_vec_to_u256(&vec![
-                    value_1, value_2, value_3, value_4, value_5, value_6, value_7, value_8,
-                ]);
-
-                let args_offset = vm.registers.read_reg(CALL_INPUT_REGISTER_22);
-                let args_size = vm.registers.read_reg(CALL_INPUT_REGISTER_23);
-
-                let return_offset = vm.registers.read_reg(CALL_INPUT_REGISTER_24);
-                let _return_size = vm.registers.read_reg(CALL_INPUT_REGISTER_25);
-
-                let mut call_data = vec![0u8; args_size as usize];
-                for i in args_offset..(args_offset + args_size) {
-                    call_data.push(vm.memory.read_mem(i, MemoryChuckSize::BYTE).unwrap() as u8);
-                }
-
-                let mut new_context = context.clone();
-                // In CallCode, address stays the same (current contract)
-                // but we use code from the target address
-                // new_context.address remains the same as the current address
-                new_context.current_caller = context.current_caller;
-                new_context.eth_context.modify_tx(|tx| {
-                    tx.data = call_data.into();
-                });
-
-                // Get code from target address
-                let code = new_context
-                    .eth_context
-                    .load_account_code(Address::from(address))
-                    .unwrap_or_default()
-                    .data;
-
-                // Transfer value if needed (from current contract to current contract)
-                if !U256::from_be_bytes(value).is_zero() {
-                    context
-                        .eth_context
-                        .journal()
-                        .transfer(
-                            new_context.current_caller,
-                            new_context.address,
-                            U256::from_be_bytes(value),
-                        )
-                        .map_err(|_| VMErrors::VMCallError(0))?;
-                }
-
-                let mut new_vm =
-                    Vm::from_bin_u8(code.0.to_vec()).map_err(|_| VMErrors::VMCallError(1))?;
-                new_vm.run(false, &mut new_context);
-
-                // Storing the sub-context return data to memory
-                for (i, byte) in new_context.return_data.iter().enumerate() {
-                    let byte_addr = return_offset + i as u32;
-                    vm.memory
-                        .write_mem(byte_addr as u32, MemoryChuckSize::BYTE, *byte as u32);
-                }
-
-                Ok(vec![
-                    context.eth_context.journal().finalize(),
-                    new_context.eth_context.journal().finalize(),
-                ])
-            }
-            RiscvEVMECalls::Return => {
-                // This ECALL Halts the vm returning the output
-                vm.running = false;
+    /// Puts back the frame the context ran before [FrameEnv::enter]
+    fn leave(self, context: &mut Context) {
+        context.address = self.address;
+        context.current_caller = self.current_caller;
+        context.eth_context.tx.data = self.data;
+        context.eth_context.tx.value = self.value;
+        context.return_data = self.return_data;
+    }
+}
 
-                let offset = vm.registers.read_reg(RETURN_INPUT_REGISTER_1);
-                let size = vm.registers.read_reg(RETURN_INPUT_REGISTER_2);
+/// Runs `program` in `context` on a meter of its own holding `gas_limit`. The meter `context`
+/// had, the caller's, is put back for [settle] to charge.
+fn run_metered(
+    program: Arc<DecodedProgram>,
+    context: &mut Context,
+    gas_limit: u64,
+) -> Result<InterpreterResult, VMErrors> {
+    let meter = std::mem::replace(&mut context.gas, Gas::new(gas_limit));
+    let vm = run_frame(program, context);
+    let result = vm.map(|vm| frame_result(&vm, context, gas_limit));
+    context.gas = meter;
+    result
+}
 
-                let mut data = Vec::new();
+/// The gas limit of a nested frame (EIP-150): what the guest asked for, at most all but one 64th
+/// of the gas left after the cost of the call. It is charged to the caller up front, [settle]
+/// gives back what the frame did not use. A frame that is sent value gets the call stipend on
+/// top, for free.
+fn forward_gas(
+    context: &mut Context,
+    requested: U256,
+    transfers_value: bool,
+) -> Result<u64, VMErrors> {
+    let remaining = context.gas.remaining();
+    let cap = remaining - remaining / 64;
+    let gas_limit = u64::try_from(requested).map_or(cap, |requested| requested.min(cap));
+    context.charge_gas(gas_limit)?;
+    Ok(if transfers_value {
+        gas_limit + CALL_STIPEND
+    } else {
+        gas_limit
+    })
+}
 
-                for i in offset..(offset + size) {
-                    data.push(vm.memory.read_mem(i, MemoryChuckSize::BYTE).unwrap() as u8);
-                }
+/// Ends the frame started at `checkpoint`: the changes of a frame that succeeded are kept and its
+/// refunds recorded, those of a frame that failed are reverted. The gas it did not use goes back
+/// to the caller's meter either way.
+fn settle(context: &mut Context, result: &InterpreterResult, checkpoint: JournalCheckpoint) {
+    if result.is_ok() {
+        context.eth_context.journal().checkpoint_commit();
+        context.gas.record_refund(result.gas.refunded());
+    } else {
+        context.eth_context.journal().checkpoint_revert(checkpoint);
+    }
+    context.gas.erase_cost(result.gas.remaining());
+}
 
-                context.return_data = data.into();
+/// A frame that failed before running any code, with all of its gas left
+fn failed_frame(result: InstructionResult, gas_limit: u64) -> InterpreterResult {
+    InterpreterResult::new(result, Bytes::new(), Gas::new(gas_limit))
+}
 
-                let _ = context.eth_context.journal().checkpoint();
-                context.eth_context.journal().checkpoint_commit();
+/// Copies at most `return_size` bytes of the output of a call to `return_offset` and maps all of
+/// it at RETURN_DATA_ADDRESS
+fn write_call_output(
+    response: &mut EcallResponse,
+    return_offset: u32,
+    return_size: u32,
+    output: Bytes,
+) {
+    let return_len = std::cmp::min(return_size as usize, output.len());
+    response.write_memory(return_offset, &output[..return_len]);
+    response.return_data = Some(output);
+}
 
-                Ok(vec![])
-            }
-            RiscvEVMECalls::DelegateCall => {
-                // Similar to CallCode but also keeps sender and value from original call
-                let gas_1 = vm.registers.read_reg(CALL_INPUT_REGISTER_1);
-                let gas_2 = vm.registers.read_reg(CALL_INPUT_REGISTER_2);
-                let gas_3 = vm.registers.read_reg(CALL_INPUT_REGISTER_3);
-                let gas_4 = vm.registers.read_reg(CALL_INPUT_REGISTER_4);
-                let gas_5 = vm.registers.read_reg(CALL_INPUT_REGISTER_5);
-                let gas_6 = vm.registers.read_reg(CALL_INPUT_REGISTER_6);
-                let gas_7 = vm.registers.read_reg(CALL_INPUT_REGISTER_7);
-                let gas_8 = vm.registers.read_reg(CALL_INPUT_REGISTER_8);
-
-                let _gas = u32_vec_to_u256(&vec![
-                    gas_1, gas_2, gas_3, gas_4, gas_5, gas_6, gas_7, gas_8,
-                ]);
-
-                let address_1 = vm.registers.read_reg(CALL_INPUT_REGISTER_9);
-                let address_2 = vm.registers.read_reg(CALL_INPUT_REGISTER_10);
-                let address_3 = vm.registers.read_reg(CALL_INPUT_REGISTER_11);
-                let address_4 = vm.registers.read_reg(CALL_INPUT_REGISTER_12);
-                let address_5 = vm.registers.read_reg(CALL_INPUT_REGISTER_13);
-
-                let address = u32_vec_to_address(&vec![
-                    address_1, address_2, address_3, address_4, address_5,
-                ]);
-
-                // No value registers read because DelegateCall preserves the value from the original call
-
-                let args_offset = vm.registers.read_reg(CALL_INPUT_REGISTER_22);
-                let args_size = vm.registers.read_reg(CALL_INPUT_REGISTER_23);
-
-                let return_offset = vm.registers.read_reg(CALL_INPUT_REGISTER_24);
-                let _return_size = vm.registers.read_reg(CALL_INPUT_REGISTER_25);
-
-                let mut call_data = vec![0u8; args_size as usize];
-                for i in args_offset..(args_offset + args_size) {
-                    call_data.push(vm.memory.read_mem(i, MemoryChuckSize::BYTE).unwrap() as u8);
-                }
-
-                let mut new_context = context.clone();
-                // Keep the same address (this contract)
-                // Keep the original caller
-                new_context.eth_context.modify_tx(|tx| {
-                    tx.data = call_data.into();
-                    // Keep the same value from original call
-                    tx.value = context.eth_context.tx.value;
-                });
-
-                // Get code from target address
-                let code = new_context
-                    .eth_context
-                    .load_account_code(Address::from(address))
-                    .unwrap_or_default()
-                    .data;
-
-                // No value transfer in DelegateCall
-
-                let mut new_vm =
-                    Vm::from_bin_u8(code.0.to_vec()).map_err(|_| VMErrors::VMCallError(1))?;
-                new_vm.run(false, &mut new_context);
-
-                // Storing the sub-context return data to memory
-                for (i, byte) in new_context.return_data.iter().enumerate() {
-                    let byte_addr = return_offset + i as u32;
-                    vm.memory
-                        .write_mem(byte_addr as u32, MemoryChuckSize::BYTE, *byte as u32);
-                }
-
-                Ok(vec![
-                    context.eth_context.journal().finalize(),
-                    new_context.eth_context.journal().finalize(),
-                ])
-            }
-            RiscvEVMECalls::Create2 => {
-                // First the initcode is obtained from the tx.data
-                // Then the address is calculated using the tx.sender and nonce
-                // Finally the contract is created using the initcode and address
-                // This process returns the runtime code, which is then stored in the account's code section
-                let offset = vm.registers.read_reg(CREATE_2_INPUT_REGISTER_1);
-                let size = vm.registers.read_reg(CREATE_2_INPUT_REGISTER_2);
-
-                let mut init_code = Vec::with_capacity(size as usize);
-
-                for i in offset..(offset + size) {
-                    init_code.push(vm.memory.read_mem(i, MemoryChuckSize::BYTE).unwrap() as u8);
-                }
-
-                let value_1 = vm.registers.read_reg(CREATE_2_INPUT_REGISTER_3);
-                let value_2 = vm.registers.read_reg(CREATE_2_INPUT_REGISTER_4);
-                let value_3 = vm.registers.read_reg(CREATE_2_INPUT_REGISTER_5);
-                let value_4 = vm.registers.read_reg(CREATE_2_INPUT_REGISTER_6);
-                let value_5 = vm.registers.read_reg(CREATE_2_INPUT_REGISTER_7);
-                let value_6 = vm.registers.read_reg(CREATE_2_INPUT_REGISTER_8);
-                let value_7 = vm.registers.read_reg(CREATE_2_INPUT_REGISTER_9);
-                let value_8 = vm.registers.read_reg(CREATE_2_INPUT_REGISTER_10);
-
-                let value = u32_vec_to_u256(&vec![
-                    value_1, value_2, value_3, value_4, value_5, value_6, value_7, value_8,
-                ]);
-
-                let salt_1 = vm.registers.read_reg(CREATE_2_INPUT_REGISTER_11);
-                let salt_2 = vm.registers.read_reg(CREATE_2_INPUT_REGISTER_12);
-                let salt_3 = vm.registers.read_reg(CREATE_2_INPUT_REGISTER_13);
-                let salt_4 = vm.registers.read_reg(CREATE_2_INPUT_REGISTER_14);
-                let salt_5 = vm.registers.read_reg(CREATE_2_INPUT_REGISTER_15);
-                let salt_6 = vm.registers.read_reg(CREATE_2_INPUT_REGISTER_16);
-                let salt_7 = vm.registers.read_reg(CREATE_2_INPUT_REGISTER_17);
-                let salt_8 = vm.registers.read_reg(CREATE_2_INPUT_REGISTER_18);
-
-                let salt = u32_vec_to_u256(&vec![
-                    salt_1, salt_2, salt_3, salt_4, salt_5, salt_6, salt_7, salt_8,
-                ]);
-
-                context
-                    .eth_context
-                    .journal()
-                    .load_account(context.address)
-                    .map_err(|_| VMErrors::VMCreateError(2))?;
-                let contract_creator = context.current_caller;
-                context
-                    .eth_context
-                    .journal()
-                    .load_account(contract_creator)
-                    .map_err(|_| VMErrors::VMCreateError(2))?;
-                let old_nonce;
-                if let Some(nonce) = context
-                    .eth_context
-                    .journal()
-                    .inc_account_nonce(contract_creator)
-                    .map_err(|_| VMErrors::VMCreateError(0))?
-                {
-                    old_nonce = nonce - 1;
-                } else {
-                    return Err(VMErrors::VMCreateError(1));
-                }
-                let init_code_hash = keccak256(&init_code);
-                let new_contract_address = contract_creator.create2(salt, init_code_hash);
-
-                context
-                    .eth_context
-                    .journal()
-                    .transfer(
-                        contract_creator,
-                        new_contract_address,
-                        U256::from_be_bytes(value),
-                    )
-                    .map_err(|_| VMErrors::VMCreateError(3))?;
-
-                // Next up is to run the init-code against this new address, this would perform the initialization of the smart contract
-                // This would do the storage setup and initialization, and returns the runtime code
-                let mut new_context = context.clone();
-                new_context.address = new_contract_address;
-                new_context.current_caller = contract_creator;
-
-                let mut new_vm =
-                    Vm::from_bin_u8(init_code).map_err(|_| VMErrors::VMCreateError(2))?;
-                new_vm.run(false, &mut new_context);
-
-                let runtime_code = new_context.return_data;
-                context.eth_context.journal().set_code(
-                    new_contract_address,
-                    Bytecode::new_legacy(runtime_code.clone()),
-                );
-
-                context
-                    .eth_context
-                    .journal()
-                    .inc_account_nonce(new_contract_address)
-                    .map_err(|_| VMErrors::VMCreateError(0))?;
-
-                let _ = context.eth_context.journal().checkpoint();
-                context.eth_context.journal().checkpoint_commit();
-
-                // storing the created address in a resigter
-                let nc_address_u32s = address_to_u32_vec(&new_contract_address.0);
-
-                vm.registers
-                    .write_reg(CREATE_2_OUTPUT_REGISTER_1, nc_address_u32s[0]);
-                vm.registers
-                    .write_reg(CREATE_2_OUTPUT_REGISTER_2, nc_address_u32s[1]);
-                vm.registers
-                    .write_reg(CREATE_2_OUTPUT_REGISTER_3, nc_address_u32s[2]);
-                vm.registers
-                    .write_reg(CREATE_2_OUTPUT_REGISTER_4, nc_address_u32s[3]);
-                vm.registers
-                    .write_reg(CREATE_2_OUTPUT_REGISTER_5, nc_address_u32s[4]);
-
-                Ok(vec![
-                    context.eth_context.journal().finalize(),
-                    new_context.eth_context.journal().finalize(),
-                ])
+/// Runs a call frame on the journal of `context`, under a checkpoint, between the `call` and
+/// `call_end` callbacks of the inspector, if any. Returns what the callee returned (or the
+/// inspector answered).
+/// Like in revm, the frame runs with the inputs left by `call`, and the outcome left by
+/// `call_end` is what the caller gets: its gas is charged, and a failure reverts the changes of
+/// the callee.
+fn call_frame(context: &mut Context, mut inputs: CallInputs) -> Result<Bytes, VMErrors> {
+    let inspector = context.inspector.clone();
+    let answer = inspector
+        .as_ref()
+        .and_then(|inspector| inspector.with(|i| i.call(&mut context.eth_context, &mut inputs)));
+
+    let checkpoint = context.eth_context.journal().checkpoint();
+    let mut outcome = match answer {
+        Some(outcome) => outcome,
+        None => match run_call(context, &inputs) {
+            Ok(result) => CallOutcome::new(result, inputs.return_memory_offset.clone()),
+            Err(error) => {
+                context.eth_context.journal().checkpoint_revert(checkpoint);
+                return Err(error);
             }
-            RiscvEVMECalls::StaticCall => {
-                // Similar to Call but in static mode - cannot modify state
-                let gas_1 = vm.registers.read_reg(CALL_INPUT_REGISTER_1);
-                let gas_2 = vm.registers.read_reg(CALL_INPUT_REGISTER_2);
-                let gas_3 = vm.registers.read_reg(CALL_INPUT_REGISTER_3);
-                let gas_4 = vm.registers.read_reg(CALL_INPUT_REGISTER_4);
-                let gas_5 = vm.registers.read_reg(CALL_INPUT_REGISTER_5);
-                let gas_6 = vm.registers.read_reg(CALL_INPUT_REGISTER_6);
-                let gas_7 = vm.registers.read_reg(CALL_INPUT_REGISTER_7);
-                let gas_8 = vm.registers.read_reg(CALL_INPUT_REGISTER_8);
-
-                let _gas = u32_vec_to_u256(&vec![
-                    gas_1, gas_2, gas_3, gas_4, gas_5, gas_6, gas_7, gas_8,
-                ]);
-
-                let address_1 = vm.registers.read_reg(CALL_INPUT_REGISTER_9);
-                let address_2 = vm.registers.read_reg(CALL_INPUT_REGISTER_10);
-                let address_3 = vm.registers.read_reg(CALL_INPUT_REGISTER_11);
-                let address_4 = vm.registers.read_reg(CALL_INPUT_REGISTER_12);
-                let address_5 = vm.registers.read_reg(CALL_INPUT_REGISTER_13);
-
-                let address = u32_vec_to_address(&vec![
-                    address_1, address_2, address_3, address_4, address_5,
-                ]);
-
-                // StaticCall doesn't transfer value, so we don't read the value registers
-
-                let args_offset = vm.registers.read_reg(CALL_INPUT_REGISTER_22);
-                let args_size = vm.registers.read_reg(CALL_INPUT_REGISTER_23);
-
-                let return_offset = vm.registers.read_reg(CALL_INPUT_REGISTER_24);
-                let _return_size = vm.registers.read_reg(CALL_INPUT_REGISTER_25);
-
-                let mut call_data = vec![0u8; args_size as usize];
-                for i in args_offset..(args_offset + args_size) {
-                    call_data.push(vm.memory.read_mem(i, MemoryChuckSize::BYTE).unwrap() as u8);
-                }
-
-                let mut new_context = context.clone();
-                new_context.address = Address::from(address);
-                new_context.current_caller = context.address;
-                // Use a new context that's marked as static
-                // TODO: Configure to be static
-
-                new_context.eth_context.modify_tx(|tx| {
-                    tx.data = call_data.into();
-                });
-
-                let code = new_context
-                    .eth_context
-                    .load_account_code(new_context.address)
-                    .unwrap_or_default()
-                    .data;
-
-                // No value transfer in StaticCall
-
-                let mut new_vm =
-                    Vm::from_bin_u8(code.0.to_vec()).map_err(|_| VMErrors::VMCallError(1))?;
-                new_vm.run(false, &mut new_context);
-
-                // Storing the sub-context return data to memory
-                for (i, byte) in new_context.return_data.iter().enumerate() {
-                    let byte_addr = return_offset + i as u32;
-                    vm.memory
-                        .write_mem(byte_addr as u32, MemoryChuckSize::BYTE, *byte as u32);
-                }
-
-                Ok(vec![
-                    context.eth_context.journal().finalize(),
-                    new_context.eth_context.journal().finalize(),
-                ])
-            }
-            RiscvEVMECalls::Revert => {
-                // This ECALL Halts the vm returning the output, reverting state changes using the journal
-                vm.running = false;
+        },
+    };
+    if let Some(inspector) = &inspector {
+        inspector.with(|i| i.call_end(&mut context.eth_context, &inputs, &mut outcome));
+    }
 
-                let offset = vm.registers.read_reg(REVERT_INPUT_REGISTER_1);
-                let size = vm.registers.read_reg(REVERT_INPUT_REGISTER_2);
+    settle(context, &outcome.result, checkpoint);
+    Ok(outcome.result.output)
+}
 
-                let mut data = Vec::new();
+/// Moves the value of a call and runs the code of its bytecode address
+fn run_call(context: &mut Context, inputs: &CallInputs) -> Result<InterpreterResult, VMErrors> {
+    let journal = context.eth_context.journal();
+    journal
+        .load_account(inputs.caller)
+        .map_err(|_| VMErrors::VMCallError(1))?;
+    journal
+        .load_account(inputs.target_address)
+        .map_err(|_| VMErrors::VMCallError(1))?;
+    // a delegate call only passes the apparent value on, nothing is moved
+    if let Some(value) = inputs.transfer_value()
+        && let Some(error) = journal
+            .transfer(inputs.caller, inputs.target_address, value)
+            .map_err(|_| VMErrors::VMCallError(0))?
+    {
+        return Ok(failed_frame(error.into(), inputs.gas_limit));
+    }
 
-                for i in offset..(offset + size) {
-                    data.push(vm.memory.read_mem(i, MemoryChuckSize::BYTE).unwrap() as u8);
-                }
+    let program = context.load_program(inputs.bytecode_address);
+    let frame = FrameEnv::enter(
+        context,
+        inputs.target_address,
+        inputs.caller,
+        inputs.input.clone(),
+        inputs.call_value(),
+    );
+    let result = run_metered(program, context, inputs.gas_limit);
+    frame.leave(context);
+    result
+}
 
-                context.return_data = data.into();
+/// Runs the init code of a create frame the same way [call_frame] runs calls, then deploys the
+/// runtime code it returned. Returns the address of the new contract unless the creation failed.
+fn create_frame(
+    context: &mut Context,
+    mut inputs: CreateInputs,
+) -> Result<Option<Address>, VMErrors> {
+    let inspector = context.inspector.clone();
+    let answer = inspector
+        .as_ref()
+        .and_then(|inspector| inspector.with(|i| i.create(&mut context.eth_context, &mut inputs)));
+
+    let (checkpoint, mut outcome) = match answer {
+        Some(outcome) => (context.eth_context.journal().checkpoint(), outcome),
+        None => run_create(context, &inputs)?,
+    };
+    if let Some(inspector) = &inspector {
+        inspector.with(|i| i.create_end(&mut context.eth_context, &inputs, &mut outcome));
+    }
 
-                let check_point = context.eth_context.journal().checkpoint();
-                context.eth_context.journal().checkpoint_revert(check_point);
+    let created = outcome.address.filter(|_| outcome.result.is_ok());
+    if let Some(address) = created {
+        let journal = context.eth_context.journal();
+        journal.set_code(address, Bytecode::new_legacy(outcome.result.output.clone()));
+        journal
+            .inc_account_nonce(address)
+            .map_err(|_| VMErrors::VMCreateError(0))?;
+    }
+    settle(context, &outcome.result, checkpoint);
+    Ok(created)
+}
 
-                Ok(vec![context.eth_context.journal().finalize()])
-            }
-            RiscvEVMECalls::SLoad => {
-                let slot_1 = vm.registers.read_reg(SLOAD_INPUT_REGISTER_1);
-                let slot_2 = vm.registers.read_reg(SLOAD_INPUT_REGISTER_2);
-                let slot_3 = vm.registers.read_reg(SLOAD_INPUT_REGISTER_3);
-                let slot_4 = vm.registers.read_reg(SLOAD_INPUT_REGISTER_4);
-                let slot_5 = vm.registers.read_reg(SLOAD_INPUT_REGISTER_5);
-                let slot_6 = vm.registers.read_reg(SLOAD_INPUT_REGISTER_6);
-                let slot_7 = vm.registers.read_reg(SLOAD_INPUT_REGISTER_7);
-                let slot_8 = vm.registers.read_reg(SLOAD_INPUT_REGISTER_8);
-
-                let slot = u32_vec_to_u256(&vec![
-                    slot_1, slot_2, slot_3, slot_4, slot_5, slot_6, slot_7, slot_8,
-                ]);
-
-                let value: [u8; 32] = context
-                    .eth_context
-                    .journal()
-                    .sload(context.address, U256::from_be_bytes(slot))
-                    .map_err(|e| VMErrors::SLoadError(e.to_string()))?
-                    .data
-                    .to_be_bytes();
-
-                context
-                    .eth_context
-                    .journal()
-                    .load_account(context.address)
-                    .map_err(|e| VMErrors::SStoreError(e.to_string()))?;
-
-                // writing 256 bits to 8 regiters
-                vm.registers
-                    .write_reg(SLOAD_OUTPUT_REGISTER_1, bytes_to_u32(&value[0..4]));
-                vm.registers
-                    .write_reg(SLOAD_OUTPUT_REGISTER_2, bytes_to_u32(&value[4..8]));
-                vm.registers
-                    .write_reg(SLOAD_OUTPUT_REGISTER_3, bytes_to_u32(&value[8..12]));
-                vm.registers
-                    .write_reg(SLOAD_OUTPUT_REGISTER_4, bytes_to_u32(&value[12..16]));
-                vm.registers
-                    .write_reg(SLOAD_OUTPUT_REGISTER_5, bytes_to_u32(&value[16..20]));
-                vm.registers
-                    .write_reg(SLOAD_OUTPUT_REGISTER_6, bytes_to_u32(&value[20..24]));
-                vm.registers
-                    .write_reg(SLOAD_OUTPUT_REGISTER_7, bytes_to_u32(&value[24..28]));
-                vm.registers
-                    .write_reg(SLOAD_OUTPUT_REGISTER_8, bytes_to_u32(&value[28..32]));
-
-                Ok(vec![])
-            }
-            RiscvEVMECalls::SStore => {
-                let slot_1 = vm.registers.read_reg(SSTORE_INPUT_REGISTER_1);
-                let slot_2 = vm.registers.read_reg(SSTORE_INPUT_REGISTER_2);
-                let slot_3 = vm.registers.read_reg(SSTORE_INPUT_REGISTER_3);
-                let slot_4 = vm.registers.read_reg(SSTORE_INPUT_REGISTER_4);
-                let slot_5 = vm.registers.read_reg(SSTORE_INPUT_REGISTER_5);
-                let slot_6 = vm.registers.read_reg(SSTORE_INPUT_REGISTER_6);
-                let slot_7 = vm.registers.read_reg(SSTORE_INPUT_REGISTER_7);
-                let slot_8 = vm.registers.read_reg(SSTORE_INPUT_REGISTER_8);
-
-                let value_1 = vm.registers.read_reg(SSTORE_INPUT_REGISTER_9);
-                let value_2 = vm.registers.read_reg(SSTORE_INPUT_REGISTER_10);
-                let value_3 = vm.registers.read_reg(SSTORE_INPUT_REGISTER_11);
-                let value_4 = vm.registers.read_reg(SSTORE_INPUT_REGISTER_12);
-                let value_5 = vm.registers.read_reg(SSTORE_INPUT_REGISTER_13);
-                let value_6 = vm.registers.read_reg(SSTORE_INPUT_REGISTER_14);
-                let value_7 = vm.registers.read_reg(SSTORE_INPUT_REGISTER_15);
-                let value_8 = vm.registers.read_reg(SSTORE_INPUT_REGISTER_16);
-
-                let slot = u32_vec_to_u256(&vec![
-                    slot_1, slot_2, slot_3, slot_4, slot_5, slot_6, slot_7, slot_8,
-                ]);
-                let value = u32_vec_to_u256(&vec![
-                    value_1, value_2, value_3, value_4, value_5, value_6, value_7, value_8,
-                ]);
-
-                context
-                    .eth_context
-                    .journal()
-                    .load_account(context.address)
-                    .map_err(|e| VMErrors::SStoreError(e.to_string()))?;
-
-                context.eth_context.sstore(
-                    context.address,
-                    U256::from_be_bytes(slot),
-                    U256::from_be_bytes(value),
-                );
-
-                Ok(vec![])
-            }
-        },
-        None => Err(VMErrors::EnvironmentError),
+/// Bumps the nonce of the creator, which stays bumped whether or not the init code succeeds,
+/// then moves the value and runs the init code under a checkpoint
+fn run_create(
+    context: &mut Context,
+    inputs: &CreateInputs,
+) -> Result<(JournalCheckpoint, CreateOutcome), VMErrors> {
+    let journal = context.eth_context.journal();
+    journal
+        .load_account(inputs.caller)
+        .map_err(|_| VMErrors::VMCreateError(2))?;
+    let nonce = journal
+        .inc_account_nonce(inputs.caller)
+        .map_err(|_| VMErrors::VMCreateError(0))?
+        .ok_or(VMErrors::VMCreateError(1))?;
+    let checkpoint = journal.checkpoint();
+    let address = inputs.created_address(nonce - 1);
+
+    let result = match run_init_code(context, inputs, address) {
+        Ok(result) => result,
+        Err(error) => {
+            context.eth_context.journal().checkpoint_revert(checkpoint);
+            return Err(error);
+        }
+    };
+    Ok((checkpoint, CreateOutcome::new(result, Some(address))))
+}
+
+/// Moves the value of a create to `address` and runs the init code there, without calldata
+fn run_init_code(
+    context: &mut Context,
+    inputs: &CreateInputs,
+    address: Address,
+) -> Result<InterpreterResult, VMErrors> {
+    let journal = context.eth_context.journal();
+    journal
+        .load_account(address)
+        .map_err(|_| VMErrors::VMCreateError(2))?;
+    if let Some(error) = journal
+        .transfer(inputs.caller, address, inputs.value)
+        .map_err(|_| VMErrors::VMCreateError(3))?
+    {
+        return Ok(failed_frame(error.into(), inputs.gas_limit));
     }
+
+    let program = context.decoded_program(keccak256(&inputs.init_code), &inputs.init_code);
+    let frame = FrameEnv::enter(context, address, inputs.caller, Bytes::new(), inputs.value);
+    let result = run_metered(program, context, inputs.gas_limit);
+    frame.leave(context);
+    result
 }
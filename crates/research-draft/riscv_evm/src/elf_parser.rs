@@ -4,28 +4,37 @@
 //! this code was copied from SP1 codebase's implementation of the ELF parser.
 //! see: [code](https://github.com/succinctlabs/sp1/blob/dev/crates/core/executor/src/disassembler/elf.rs)
 
+use crate::{
+    debug_info::DebugInfo,
+    instructions::COMPRESSED_INSTRUCTION_SIZE,
+    segments::{Permissions, Segment},
+};
 use elf::{
     ElfBytes,
-    abi::{EM_RISCV, ET_EXEC, PF_X, PT_LOAD},
+    abi::{EM_RISCV, ET_EXEC, PF_R, PF_W, PF_X, PT_LOAD, STT_FUNC, STT_NOTYPE, STT_OBJECT},
     endian::LittleEndian,
     file::Class,
 };
 use hashbrown::HashMap;
-use riscv_evm_core::{MAXIMUM_MEMORY_SIZE, WORD_SIZE};
-use std::cmp::min;
+use riscv_evm_core::{MAXIMUM_MEMORY_SIZE, Memory, WORD_SIZE};
+use std::{cmp::min, sync::Arc};
 
-/// RISC-V 32IM ELF (Executable and Linkable Format) File.
+/// RISC-V 32IMC ELF (Executable and Linkable Format) File.
 ///
-/// This file represents a binary in the ELF format, specifically the RISC-V 32IM architecture
+/// This file represents a binary in the ELF format, specifically the RISC-V 32IMC architecture
 /// with the following extensions:
 ///
 /// - Base Integer Instruction Set (I)
 /// - Integer Multiplication and Division (M)
+/// - Compressed Instructions (C)
 ///
 /// This format is commonly used in embedded systems and is supported by many compilers.
+/// 64-bit (RV64IM) executables are accepted too, for [crate::vm64::Vm64], as long as they are
+/// linked below 4 GiB.
 #[derive(Debug, Clone)]
 pub struct Elf {
-    /// The instructions of the program encoded as 32-bits.
+    /// The instructions of the program as 32-bit words, compressed instructions are packed two per
+    /// word (see [crate::instructions::read_parcel]).
     pub instructions: Vec<u32>,
     /// The start address of the program.
     pub pc_start: u32,
@@ -33,6 +42,12 @@ pub struct Elf {
     pub pc_base: u32,
     /// The initial memory image, useful for global constants.
     pub memory_image: HashMap<u32, u32>,
+    /// Whether this is an ELF64 (RV64) executable rather than an ELF32 (RV32) one.
+    pub is_64bit: bool,
+    /// The loadable segments with the permissions of their program header.
+    pub segments: Vec<Segment>,
+    /// Function names and source lines, only kept by [Elf::decode_with_debug_info].
+    pub debug_info: Option<Arc<DebugInfo>>,
 }
 
 impl Elf {
@@ -42,12 +57,17 @@ impl Elf {
         pc_start: u32,
         pc_base: u32,
         memory_image: HashMap<u32, u32>,
+        is_64bit: bool,
+        segments: Vec<Segment>,
     ) -> Self {
         Self {
             instructions,
             pc_start,
             pc_base,
             memory_image,
+            is_64bit,
+            segments,
+            debug_info: None,
         }
     }
 
@@ -64,9 +84,7 @@ impl Elf {
         // Parse the ELF file assuming that it is little-endian..
         let elf = ElfBytes::<LittleEndian>::minimal_parse(input)?;
         // Some sanity checks to make sure that the ELF file is valid.
-        if elf.ehdr.class != Class::ELF32 {
-            anyhow::bail!("must be a 32-bit elf");
-        } else if elf.ehdr.e_machine != EM_RISCV {
+        if elf.ehdr.e_machine != EM_RISCV {
             anyhow::bail!("must be a riscv machine");
         } else if elf.ehdr.e_type != ET_EXEC {
             anyhow::bail!("must be executable");
@@ -75,8 +93,8 @@ impl Elf {
         // Get the entrypoint of the ELF file as an u32.
         let entry: u32 = elf.ehdr.e_entry.try_into()?;
 
-        // Make sure the entrypoint is valid.
-        if entry == MAXIMUM_MEMORY_SIZE || entry % WORD_SIZE as u32 != 0 {
+        // Make sure the entrypoint is valid, compressed code only aligns it to 16 bits.
+        if entry == MAXIMUM_MEMORY_SIZE || !entry.is_multiple_of(COMPRESSED_INSTRUCTION_SIZE) {
             anyhow::bail!("invalid entrypoint");
         }
 
@@ -90,6 +108,7 @@ impl Elf {
 
         let mut instructions: Vec<u32> = Vec::new();
         let mut base_address = u32::MAX;
+        let mut loaded_segments = Vec::new();
 
         // Only read segments that are executable instructions that are also PT_LOAD.
         for segment in segments.iter().filter(|x| x.p_type == PT_LOAD) {
@@ -111,6 +130,16 @@ impl Elf {
                 anyhow::bail!("vaddr {vaddr:08x} is unaligned");
             }
 
+            loaded_segments.push(Segment {
+                start: vaddr,
+                size: mem_size,
+                permissions: Permissions {
+                    read: (segment.p_flags & PF_R) != 0,
+                    write: (segment.p_flags & PF_W) != 0,
+                    execute: (segment.p_flags & PF_X) != 0,
+                },
+            });
+
             // If the virtual address is less than the first memory address, then update the first
             // memory address.
             if (segment.p_flags & PF_X) != 0 && base_address > vaddr {
@@ -154,6 +183,72 @@ impl Elf {
             }
         }
 
-        Ok(Elf::new(instructions, entry, base_address, image))
+        Ok(Elf::new(
+            instructions,
+            entry,
+            base_address,
+            image,
+            elf.ehdr.class == Class::ELF64,
+            loaded_segments,
+        ))
+    }
+
+    /// The memory of a program starting to run: every loadable segment (code, rodata, data and the
+    /// zeroed bss) at its link address.
+    pub fn memory(&self) -> Memory {
+        let mut memory = Memory::new();
+        for (&address, &word) in &self.memory_image {
+            memory.set_word((address >> 2) as usize, word);
+        }
+
+        memory
+    }
+
+    /// Parse the ELF file like [Elf::decode], keeping its symbol table and `.debug_line` (see
+    /// [crate::debug_info]).
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error if the ELF or its debug info is not valid.
+    pub fn decode_with_debug_info(input: &[u8]) -> anyhow::Result<Self> {
+        let mut elf = Self::decode(input)?;
+        elf.debug_info = Some(Arc::new(DebugInfo::parse(input)?));
+        Ok(elf)
+    }
+
+    /// Read the symbol table of the ELF file, mapping addresses to symbol names.
+    /// Only function, object and untyped symbols with a name are returned, ELF files without a
+    /// symbol table (stripped) return an empty map.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error if the ELF is not valid.
+    pub fn symbols(input: &[u8]) -> anyhow::Result<HashMap<u32, String>> {
+        let elf = ElfBytes::<LittleEndian>::minimal_parse(input)?;
+        let mut symbols = HashMap::new();
+
+        let Some((symtab, strtab)) = elf.symbol_table()? else {
+            return Ok(symbols);
+        };
+
+        for symbol in symtab.iter() {
+            if !matches!(symbol.st_symtype(), STT_FUNC | STT_OBJECT | STT_NOTYPE)
+                || symbol.st_name == 0
+            {
+                continue;
+            }
+
+            let name = strtab.get(symbol.st_name as usize)?;
+            // local labels ($x, $d, .L...) are noise in a listing
+            if name.is_empty() || name.starts_with('$') || name.starts_with(".L") {
+                continue;
+            }
+
+            symbols
+                .entry(symbol.st_value.try_into()?)
+                .or_insert_with(|| name.to_string());
+        }
+
+        Ok(symbols)
     }
 }
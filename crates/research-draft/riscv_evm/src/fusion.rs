@@ -0,0 +1,154 @@
+//! # Macro-op fusion
+//! Common compiler idioms span two instructions, e.g. `lui`+`addi` to build a constant. When a
+//! [crate::code_cache::DecodedProgram] is built, such pairs are recognized and fused into a single
+//! [FusedOp] so the Vm dispatches once instead of twice.
+//! A fused op has exactly the effect of running its two instructions one after the other (same
+//! registers, same pc, same retired instruction count), it only saves the second fetch and dispatch.
+use crate::instructions::Instruction;
+
+/// Two instructions executed by a single dispatch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FusedOp {
+    /// `lui rd, hi` ; `addi rd, rd, lo`
+    LoadImmediate { rd: u32, value: u32 },
+    /// `auipc rd, hi` ; `jalr link, lo(rd)`, the usual far call/jump
+    FarJump {
+        rd: u32,
+        hi: i32,
+        link: u32,
+        lo: i32,
+    },
+    /// `slli tmp, rs1, shamt` ; `add rd, add_rs1, add_rs2`, with `tmp` being one of the add operands,
+    /// the usual address calculation for indexing an array
+    ShiftAdd {
+        tmp: u32,
+        rs1: u32,
+        shamt: u32,
+        rd: u32,
+        add_rs1: u32,
+        add_rs2: u32,
+    },
+    /// `slt`/`sltu`/`slti`/`sltiu` into `rd` ; `beq`/`bne rd, zero`, `imm` is relative to the branch
+    CompareBranch {
+        compare: Instruction,
+        rd: u32,
+        branch_if_set: bool,
+        imm: i32,
+    },
+}
+
+/// Fuses `first` and the instruction right after it, when they form a known idiom
+pub fn fuse(first: &Instruction, second: &Instruction) -> Option<FusedOp> {
+    match (*first, *second) {
+        (
+            Instruction::Lui { rd, imm: hi },
+            Instruction::Addi {
+                rd: rd2,
+                rs1,
+                imm: lo,
+            },
+        ) if rd2 == rd && rs1 == rd => Some(FusedOp::LoadImmediate {
+            rd,
+            value: (hi as u32).wrapping_add(lo as u32),
+        }),
+        (
+            Instruction::Auipc { rd, imm: hi },
+            Instruction::Jalr {
+                rd: link,
+                rs1,
+                imm: lo,
+            },
+        ) if rs1 == rd => Some(FusedOp::FarJump { rd, hi, link, lo }),
+        (
+            Instruction::Slli {
+                rd: tmp,
+                rs1,
+                shamt,
+            },
+            Instruction::Add { rd, rs1: a, rs2: b },
+        ) if a == tmp || b == tmp => Some(FusedOp::ShiftAdd {
+            tmp,
+            rs1,
+            shamt,
+            rd,
+            add_rs1: a,
+            add_rs2: b,
+        }),
+        (
+            Instruction::Slt { rd, .. }
+            | Instruction::Sltu { rd, .. }
+            | Instruction::Slti { rd, .. }
+            | Instruction::Sltiu { rd, .. },
+            Instruction::Beq { rs1, rs2: 0, imm } | Instruction::Bne { rs1, rs2: 0, imm },
+        ) if rs1 == rd => Some(FusedOp::CompareBranch {
+            compare: *first,
+            rd,
+            branch_if_set: matches!(second, Instruction::Bne { .. }),
+            imm,
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn decode(raw: u32) -> Instruction {
+        Instruction::decode(raw).unwrap()
+    }
+
+    #[test]
+    fn test_fuse_idioms() {
+        // lui a0, 0x12345 ; addi a0, a0, -1
+        assert_eq!(
+            fuse(&decode(0x12345537), &decode(0xfff50513)),
+            Some(FusedOp::LoadImmediate {
+                rd: 10,
+                value: 0x12344fff
+            })
+        );
+        // auipc ra, 0 ; jalr ra, 16(ra)
+        assert_eq!(
+            fuse(&decode(0x00000097), &decode(0x010080e7)),
+            Some(FusedOp::FarJump {
+                rd: 1,
+                hi: 0,
+                link: 1,
+                lo: 16
+            })
+        );
+        // slli a0, a0, 2 ; add a0, a1, a0
+        assert_eq!(
+            fuse(&decode(0x00251513), &decode(0x00a58533)),
+            Some(FusedOp::ShiftAdd {
+                tmp: 10,
+                rs1: 10,
+                shamt: 2,
+                rd: 10,
+                add_rs1: 11,
+                add_rs2: 10
+            })
+        );
+        // sltu a0, a1, a2 ; bne a0, zero, 8
+        assert_eq!(
+            fuse(&decode(0x00c5b533), &decode(0x00051463)),
+            Some(FusedOp::CompareBranch {
+                compare: decode(0x00c5b533),
+                rd: 10,
+                branch_if_set: true,
+                imm: 8
+            })
+        );
+    }
+
+    #[test]
+    fn test_unrelated_pairs_are_not_fused() {
+        // lui a0, 0x12345 ; addi a1, a1, -1
+        assert_eq!(fuse(&decode(0x12345537), &decode(0xfff58593)), None);
+        // slli a0, a0, 2 ; add a0, a1, a2
+        assert_eq!(fuse(&decode(0x00251513), &decode(0x00c58533)), None);
+        // sltu a0, a1, a2 ; bne a1, zero, 8
+        assert_eq!(fuse(&decode(0x00c5b533), &decode(0x00059463)), None);
+    }
+}
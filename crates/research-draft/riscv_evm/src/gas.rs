@@ -0,0 +1,44 @@
+//! Gas costs of the ecalls that touch state.
+//! The RISC-V instructions themselves are not metered yet, only the environment access is priced here
+//! following the same rules revm uses for the matching EVM opcodes.
+use revm::interpreter::gas::warm_cold_cost;
+use revm::primitives::hardfork::SpecId;
+
+/// Cost of the `Balance` ecall (BALANCE opcode)
+pub const fn balance_cost(spec: SpecId, is_cold: bool) -> u64 {
+    if spec.is_enabled_in(SpecId::BERLIN) {
+        warm_cold_cost(is_cold)
+    } else if spec.is_enabled_in(SpecId::ISTANBUL) {
+        700
+    } else if spec.is_enabled_in(SpecId::TANGERINE) {
+        400
+    } else {
+        20
+    }
+}
+
+/// Cost of the `ExtCodeSize` ecall (EXTCODESIZE opcode)
+pub const fn ext_code_size_cost(spec: SpecId, is_cold: bool) -> u64 {
+    if spec.is_enabled_in(SpecId::BERLIN) {
+        warm_cold_cost(is_cold)
+    } else if spec.is_enabled_in(SpecId::TANGERINE) {
+        700
+    } else {
+        20
+    }
+}
+
+/// Cost of the `ExtCodeHash` ecall (EXTCODEHASH opcode)
+pub const fn ext_code_hash_cost(spec: SpecId, is_cold: bool) -> u64 {
+    if spec.is_enabled_in(SpecId::BERLIN) {
+        warm_cold_cost(is_cold)
+    } else if spec.is_enabled_in(SpecId::ISTANBUL) {
+        700
+    } else {
+        400
+    }
+}
+
+/// Cost of every heap page the `Sbrk` ecall reaches for the first time, the linear part of EVM
+/// memory expansion (3 gas per 32-byte word) for a 4 KiB page
+pub const HEAP_PAGE_COST: u64 = 3 * (crate::memory_map::PAGE_SIZE as u64 / 32);
@@ -0,0 +1,605 @@
+//! # Geth traces
+//! The `callTracer` and `prestateTracer` of geth's `debug_traceTransaction`, for transactions run
+//! by the RISC-V frame machinery, so existing trace viewers work on them unchanged.
+//!
+//! [CallTracer] is a revm [Inspector], attach it with [crate::context::Context::with_inspector]
+//! and it builds the call tree of the frames it sees. The first frame is the root of the tree, the
+//! host usually starts the transaction with a `Call` or `Create` ecall so that frame is the
+//! transaction itself.
+//!
+//! [prestate_trace] needs no inspector, it reads the state the transaction touched from the state
+//! of its `JournalOutput`s, before they are committed to the database. The ecalls of the frames run
+//! by the Vm finalize the journal too, attach [crate::context::Context::with_state_changes] to
+//! collect theirs, then add the outputs [crate::ecall_manager::process_ecall] returned to the host
+//! and pass the [crate::context::StateChanges::states].
+//!
+//! Both serialize with serde to the JSON geth produces (`0x` quantities, lowercase hex).
+//! RISC-V traps have no geth error of their own, they show as the invalid opcode error revm
+//! reports them with.
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::LowerHex,
+};
+
+use alloy_sol_types::{Revert, SolError};
+use revm::{
+    Inspector,
+    context::CreateScheme,
+    database::DatabaseRef,
+    interpreter::{
+        CallInputs, CallOutcome, CallScheme, CreateInputs, CreateOutcome, InstructionResult,
+        Interpreter, InterpreterResult,
+    },
+    primitives::{Address, B256, Bytes, KECCAK_EMPTY, Log, U256},
+    state::{AccountInfo, EvmState, EvmStorageSlot},
+};
+use serde::{Serialize, Serializer};
+
+use crate::context::EthContext;
+
+/// Options of the `callTracer`, named like geth's
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CallTracerConfig {
+    /// Only trace the root frame
+    pub only_top_call: bool,
+    /// Record the logs of every frame, logs of failed frames are dropped like geth does
+    pub with_log: bool,
+}
+
+/// A frame of the call tree, in geth's `callTracer` format
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallFrame {
+    /// `CALL`, `STATICCALL`, `DELEGATECALL`, `CALLCODE`, `CREATE` or `CREATE2`
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    #[serde(serialize_with = "hex")]
+    pub from: Address,
+    #[serde(serialize_with = "hex")]
+    pub gas: u64,
+    #[serde(serialize_with = "hex")]
+    pub gas_used: u64,
+    /// The callee, or the created contract once the create succeeded
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "hex_option")]
+    pub to: Option<Address>,
+    #[serde(serialize_with = "hex")]
+    pub input: Bytes,
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "hex_option")]
+    pub output: Option<Bytes>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// The `Error(string)` message of a revert
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revert_reason: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub calls: Vec<CallFrame>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub logs: Vec<CallLog>,
+    /// Not set for `STATICCALL`
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "hex_option")]
+    pub value: Option<U256>,
+}
+
+/// A log of a frame, `position` is the number of calls the frame made before emitting it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CallLog {
+    #[serde(serialize_with = "hex")]
+    pub address: Address,
+    #[serde(serialize_with = "hex_vec")]
+    pub topics: Vec<B256>,
+    #[serde(serialize_with = "hex")]
+    pub data: Bytes,
+    #[serde(serialize_with = "hex")]
+    pub position: u64,
+}
+
+/// The `callTracer`, see the module documentation
+#[derive(Debug, Default)]
+pub struct CallTracer {
+    config: CallTracerConfig,
+    /// Frames entered and not exited yet, innermost last
+    stack: Vec<CallFrame>,
+    /// Frames entered, including the ones `only_top_call` does not record
+    depth: usize,
+    root: Option<CallFrame>,
+}
+
+impl CallTracer {
+    pub fn new(config: CallTracerConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    /// The call tree, `None` until the root frame exited
+    pub fn frame(&self) -> Option<&CallFrame> {
+        self.root.as_ref()
+    }
+
+    /// The call tree as geth's JSON, `null` until the root frame exited
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::to_value(&self.root).expect("call frames only hold plain data")
+    }
+
+    fn enter(&mut self, frame: CallFrame) {
+        self.depth += 1;
+        if self.depth == 1 || !self.config.only_top_call {
+            self.stack.push(frame);
+        }
+    }
+
+    fn exit(&mut self, result: &InterpreterResult, created: Option<Address>) {
+        let recorded = self.depth == 1 || !self.config.only_top_call;
+        self.depth -= 1;
+        if !recorded {
+            return;
+        }
+        let Some(mut frame) = self.stack.pop() else {
+            return;
+        };
+
+        frame.gas_used = result.gas.spent();
+        if created.is_some() {
+            frame.to = created;
+        }
+        match result.result {
+            // a create outputs the runtime code
+            ok if ok.is_ok() => frame.output = Some(result.output.clone()),
+            InstructionResult::Revert => {
+                frame.error = Some("execution reverted".into());
+                frame.revert_reason = Revert::abi_decode(&result.output, true)
+                    .ok()
+                    .map(|revert| revert.reason);
+                frame.output = Some(result.output.clone());
+            }
+            other => frame.error = Some(error_message(other)),
+        }
+        if frame.error.is_some() {
+            clear_logs(&mut frame);
+        }
+
+        match self.stack.last_mut() {
+            Some(parent) => parent.calls.push(frame),
+            None => self.root = Some(frame),
+        }
+    }
+}
+
+/// geth's message for a failed frame
+fn error_message(result: InstructionResult) -> String {
+    match result {
+        InstructionResult::OutOfGas
+        | InstructionResult::MemoryOOG
+        | InstructionResult::MemoryLimitOOG
+        | InstructionResult::PrecompileOOG
+        | InstructionResult::InvalidOperandOOG => "out of gas".into(),
+        InstructionResult::InvalidFEOpcode => "invalid opcode: INVALID".into(),
+        other => format!("{other:?}"),
+    }
+}
+
+/// The logs of a failed frame and its subcalls were reverted
+fn clear_logs(frame: &mut CallFrame) {
+    frame.logs.clear();
+    frame.calls.iter_mut().for_each(clear_logs);
+}
+
+impl Inspector<EthContext> for CallTracer {
+    fn call(&mut self, _context: &mut EthContext, inputs: &mut CallInputs) -> Option<CallOutcome> {
+        let (kind, value) = match inputs.scheme {
+            CallScheme::Call => ("CALL", Some(inputs.value.get())),
+            CallScheme::CallCode => ("CALLCODE", Some(inputs.value.get())),
+            CallScheme::DelegateCall => ("DELEGATECALL", Some(inputs.value.get())),
+            CallScheme::StaticCall => ("STATICCALL", None),
+            // only produced by EOF code, which the RISC-V frames never run
+            CallScheme::ExtCall => ("EXTCALL", Some(inputs.value.get())),
+            CallScheme::ExtStaticCall => ("EXTSTATICCALL", None),
+            CallScheme::ExtDelegateCall => ("EXTDELEGATECALL", Some(inputs.value.get())),
+        };
+        self.enter(CallFrame {
+            kind,
+            from: inputs.caller,
+            gas: inputs.gas_limit,
+            gas_used: 0,
+            to: Some(inputs.target_address),
+            input: inputs.input.clone(),
+            output: None,
+            error: None,
+            revert_reason: None,
+            calls: Vec::new(),
+            logs: Vec::new(),
+            value,
+        });
+        None
+    }
+
+    fn call_end(
+        &mut self,
+        _context: &mut EthContext,
+        _inputs: &CallInputs,
+        outcome: &mut CallOutcome,
+    ) {
+        self.exit(&outcome.result, None);
+    }
+
+    fn create(
+        &mut self,
+        _context: &mut EthContext,
+        inputs: &mut CreateInputs,
+    ) -> Option<CreateOutcome> {
+        let kind = match inputs.scheme {
+            CreateScheme::Create => "CREATE",
+            CreateScheme::Create2 { .. } => "CREATE2",
+        };
+        self.enter(CallFrame {
+            kind,
+            from: inputs.caller,
+            gas: inputs.gas_limit,
+            gas_used: 0,
+            to: None,
+            input: inputs.init_code.clone(),
+            output: None,
+            error: None,
+            revert_reason: None,
+            calls: Vec::new(),
+            logs: Vec::new(),
+            value: Some(inputs.value),
+        });
+        None
+    }
+
+    fn create_end(
+        &mut self,
+        _context: &mut EthContext,
+        _inputs: &CreateInputs,
+        outcome: &mut CreateOutcome,
+    ) {
+        let created = outcome.address.filter(|_| outcome.result.is_ok());
+        self.exit(&outcome.result, created);
+    }
+
+    fn log(&mut self, _interp: &mut Interpreter, _context: &mut EthContext, log: Log) {
+        if !self.config.with_log {
+            return;
+        }
+        // frames not recorded (`only_top_call`, or a root frame the host ran itself) drop their logs
+        if self.stack.len() != self.depth {
+            return;
+        }
+        let Some(frame) = self.stack.last_mut() else {
+            return;
+        };
+        frame.logs.push(CallLog {
+            address: log.address,
+            topics: log.data.topics().to_vec(),
+            data: log.data.data,
+            position: frame.calls.len() as u64,
+        });
+    }
+}
+
+/// An account in geth's `prestateTracer` format, fields that are not set are left out
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct AccountState {
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "hex_option")]
+    pub balance: Option<U256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "hex_option")]
+    pub code: Option<Bytes>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", serialize_with = "hex_map")]
+    pub storage: BTreeMap<B256, B256>,
+}
+
+/// The output of the `prestateTracer`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum PrestateTrace {
+    /// Every account the transaction touched as it was before, with the slots it accessed
+    Prestate(#[serde(serialize_with = "hex_keys")] BTreeMap<Address, AccountState>),
+    /// `diffMode`: the accounts the transaction changed, before and after. `post` only holds the
+    /// fields that changed, created accounts are not in `pre` and cleared slots are not in `post`.
+    Diff {
+        #[serde(serialize_with = "hex_keys")]
+        pre: BTreeMap<Address, AccountState>,
+        #[serde(serialize_with = "hex_keys")]
+        post: BTreeMap<Address, AccountState>,
+    },
+}
+
+/// The `prestateTracer` of a transaction that made `changes`, `db` being the state they apply to.
+/// The changes apply in order, the way [revm::DatabaseCommit] commits them: accounts that were only
+/// loaded are accessed, not changed.
+/// # Errors
+/// When `db` fails to load an account or code.
+pub fn prestate_trace<DB: DatabaseRef>(
+    changes: &[EvmState],
+    db: &DB,
+    diff_mode: bool,
+) -> Result<PrestateTrace, DB::Error> {
+    // the accounts accessed, with their info once changed and the slots accessed
+    let mut accessed: HashMap<Address, (Option<AccountInfo>, HashMap<U256, EvmStorageSlot>)> =
+        HashMap::new();
+    for state in changes {
+        for (address, account) in state {
+            let (info, storage) = accessed.entry(*address).or_default();
+            let changed = account.is_touched();
+            if changed {
+                *info = Some(account.info.clone());
+            }
+            for (slot, value) in &account.storage {
+                storage
+                    .entry(*slot)
+                    .and_modify(|known| {
+                        if changed {
+                            known.present_value = value.present_value;
+                        }
+                    })
+                    .or_insert_with(|| value.clone());
+            }
+        }
+    }
+
+    let mut pre = BTreeMap::new();
+    let mut post = BTreeMap::new();
+    for (address, (info, storage)) in accessed {
+        let existed = db.basic_ref(address)?;
+        let before = existed.clone().unwrap_or_default();
+        let info = info.unwrap_or_else(|| before.clone());
+        let code_before = code(db, &before)?;
+        let mut account = AccountState {
+            balance: Some(before.balance),
+            nonce: (before.nonce != 0).then_some(before.nonce),
+            code: (!code_before.is_empty()).then(|| code_before.clone()),
+            storage: BTreeMap::new(),
+        };
+
+        if !diff_mode {
+            account.storage = storage
+                .iter()
+                .map(|(slot, value)| (B256::from(*slot), B256::from(value.original_value)))
+                .collect();
+            pre.insert(address, account);
+            continue;
+        }
+
+        let code_after = code(db, &info)?;
+        let mut changed = AccountState {
+            balance: (info.balance != before.balance).then_some(info.balance),
+            nonce: (info.nonce != before.nonce).then_some(info.nonce),
+            code: (code_after != code_before).then_some(code_after),
+            storage: BTreeMap::new(),
+        };
+        for (slot, value) in storage
+            .iter()
+            .filter(|(_, value)| value.present_value != value.original_value)
+        {
+            if !value.original_value.is_zero() {
+                account
+                    .storage
+                    .insert(B256::from(*slot), B256::from(value.original_value));
+            }
+            if !value.present_value.is_zero() {
+                changed
+                    .storage
+                    .insert(B256::from(*slot), B256::from(value.present_value));
+            }
+        }
+
+        if changed == AccountState::default() && account.storage.is_empty() {
+            continue;
+        }
+        if existed.is_some() {
+            pre.insert(address, account);
+        }
+        post.insert(address, changed);
+    }
+
+    Ok(if diff_mode {
+        PrestateTrace::Diff { pre, post }
+    } else {
+        PrestateTrace::Prestate(pre)
+    })
+}
+
+/// The code of `info`, loaded from `db` when the account only holds its hash
+fn code<DB: DatabaseRef>(db: &DB, info: &AccountInfo) -> Result<Bytes, DB::Error> {
+    Ok(match &info.code {
+        Some(code) => code.original_bytes(),
+        None if info.code_hash != KECCAK_EMPTY => {
+            db.code_by_hash_ref(info.code_hash)?.original_bytes()
+        }
+        None => Bytes::new(),
+    })
+}
+
+/// `0x` prefixed lowercase hex, the way geth writes quantities, addresses and data
+fn hex<T: LowerHex, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(&format_args!("{value:#x}"))
+}
+
+fn hex_option<T: LowerHex, S: Serializer>(
+    value: &Option<T>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(value) => hex(value, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn hex_vec<T: LowerHex, S: Serializer>(values: &[T], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(values.iter().map(|value| format!("{value:#x}")))
+}
+
+fn hex_map<K: LowerHex, V: LowerHex, S: Serializer>(
+    map: &BTreeMap<K, V>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(
+        map.iter()
+            .map(|(key, value)| (format!("{key:#x}"), format!("{value:#x}"))),
+    )
+}
+
+fn hex_keys<K: LowerHex, V: Serialize, S: Serializer>(
+    map: &BTreeMap<K, V>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(map.iter().map(|(key, value)| (format!("{key:#x}"), value)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        context::Context, ecall_manager::process_ecall, inspector::SharedInspector,
+        utils::u32_vec_to_bytes, vm::Vm,
+    };
+    use revm::bytecode::Bytecode;
+    use revm::{
+        Context as RevmEthContext, MainContext,
+        context::{ContextTr, JournalTr},
+        database::CacheDB,
+    };
+    use riscv_evm_core::e_constants::ECALL_CODE_REG;
+    use std::sync::{Arc, Mutex};
+
+    /// `addi rd, rs1, imm`
+    fn addi(rd: u32, rs1: u32, imm: u32) -> u32 {
+        (imm << 20) | (rs1 << 15) | (rd << 7) | 0x13
+    }
+
+    const ECALL: u32 = 0x00000073;
+
+    #[test]
+    fn test_geth_traces() {
+        let sender = Address::from([0xAA; 20]);
+        let a = Address::with_last_byte(0x41);
+        let b = Address::with_last_byte(0x42);
+
+        // `a` calls `b`, stores 7 at slot 1, logs its first code word and returns.
+        // Ecalls clobber registers, the inputs of each one are all written.
+        let a_code: Vec<u32> = (9..=25)
+            .map(|register| addi(register, 0, if register == 13 { 0x42 } else { 0 }))
+            .chain([addi(31, 0, 0xF1), ECALL]) // Call `b`
+            .chain((1..=16).map(|register| match register {
+                8 => addi(8, 0, 1),   // slot
+                16 => addi(16, 0, 7), // value
+                _ => addi(register, 0, 0),
+            }))
+            .chain([
+                addi(31, 0, 0x55), // SStore
+                ECALL,
+                addi(1, 0, 0),
+                addi(2, 0, 4),
+                addi(31, 0, 0xA0), // Log0
+                ECALL,
+                addi(1, 0, 0),
+                addi(2, 0, 0),
+                addi(31, 0, 0xF3), // Return
+                ECALL,
+            ])
+            .collect();
+        // `b` reverts with `Error("nope")`, laid out after its code
+        let reason = Revert::from("nope").abi_encode();
+        let b_code = [
+            addi(1, 0, 16),
+            addi(2, 0, reason.len() as u32),
+            addi(31, 0, 0xFD), // Revert
+            ECALL,
+        ];
+        let mut b_code = u32_vec_to_bytes(&b_code, 16);
+        b_code.extend_from_slice(&reason);
+
+        let mut db = CacheDB::default();
+        for (address, code) in [
+            (a, u32_vec_to_bytes(&a_code, a_code.len() * 4)),
+            (b, b_code),
+        ] {
+            db.insert_account_info(
+                address,
+                AccountInfo {
+                    code: Some(Bytecode::new_legacy(code.into())),
+                    ..Default::default()
+                },
+            );
+        }
+        db.insert_account_info(
+            sender,
+            AccountInfo {
+                balance: U256::from(100),
+                ..Default::default()
+            },
+        );
+
+        let tracer = Arc::new(Mutex::new(CallTracer::new(CallTracerConfig {
+            only_top_call: false,
+            with_log: true,
+        })));
+        let mut context = Context::new(RevmEthContext::mainnet().with_db(db.clone()))
+            .with_inspector(SharedInspector::new(tracer.clone()))
+            .with_state_changes();
+        context.address = sender;
+        // like any host, load the sender so the transaction can transfer value
+        context.eth_context.journal().load_account(sender).unwrap();
+
+        // the transaction, a call from `sender` to `a`
+        let mut vm = Vm::new();
+        vm.registers.write_reg(ECALL_CODE_REG, 0xF1);
+        vm.registers.write_reg(13, 0x41);
+        let outputs = process_ecall(&mut vm, &mut context).unwrap();
+        let mut changes = context.state_changes.take().unwrap();
+        changes.extend(outputs);
+
+        let json = tracer.lock().unwrap().json();
+        assert_eq!(json["type"], "CALL");
+        assert_eq!(json["from"], format!("{sender:#x}"));
+        assert_eq!(json["to"], format!("{a:#x}"));
+        assert_eq!(json["value"], "0x0");
+        assert_eq!(json["output"], "0x");
+        assert!(json.get("error").is_none());
+        assert_eq!(
+            json["logs"],
+            serde_json::json!([{
+                "address": format!("{a:#x}"),
+                "topics": [],
+                "data": format!("{:#010x}", a_code[0]),
+                "position": "0x1",
+            }])
+        );
+        let call = &json["calls"][0];
+        assert_eq!(call["type"], "CALL");
+        assert_eq!(call["from"], format!("{a:#x}"));
+        assert_eq!(call["to"], format!("{b:#x}"));
+        assert_eq!(call["error"], "execution reverted");
+        assert_eq!(call["revertReason"], "nope");
+        assert_eq!(call["output"], format!("0x{}", hex::encode(&reason)));
+        assert!(call.get("calls").is_none());
+
+        let PrestateTrace::Prestate(prestate) =
+            prestate_trace(&changes.states, &db, false).unwrap()
+        else {
+            panic!("expected the default mode");
+        };
+        assert_eq!(prestate[&sender].balance, Some(U256::from(100)));
+        assert_eq!(
+            prestate[&a].storage,
+            BTreeMap::from([(B256::from(U256::from(1)), B256::ZERO)])
+        );
+        assert!(prestate[&b].code.is_some());
+
+        let diff = prestate_trace(&changes.states, &db, true).unwrap();
+        let slot = format!("{:#x}", B256::from(U256::from(1)));
+        let json = serde_json::to_value(&diff).unwrap();
+        assert_eq!(
+            json["post"],
+            serde_json::json!({ format!("{a:#x}"): { "storage": { slot: format!("{:#x}", B256::from(U256::from(7))) } } })
+        );
+        // the slot was empty, `a` is in `pre` without it
+        assert_eq!(json["pre"][format!("{a:#x}")]["balance"], "0x0");
+        assert!(json["pre"][format!("{a:#x}")].get("storage").is_none());
+        assert!(json["pre"].get(format!("{b:#x}")).is_none());
+    }
+}
@@ -0,0 +1,238 @@
+//! # Hooks
+//! Instrumentation of the interpreter, for profilers, coverage and custom tracers.
+//! Install a [VmHook] on [Vm::hook](crate::vm::Vm) and the Vm calls it around every instruction,
+//! load, store and ecall, and on every trap. Every callback does nothing by default.
+//!
+//! The memory callbacks report the loads and stores the program executes, not the memory traffic
+//! of ecalls: the guest buffers an ecall reads, the memory and return data window it writes and
+//! the RV32E ecall block are reported only through [VmHook::on_ecall_enter] (the decoded
+//! [EcallRequest], buffers included) and [VmHook::on_ecall_exit] (the Vm with the outputs in).
+//!
+//! Like the [crate::trace::Tracer], a hook sees every instruction on its own, native blocks and
+//! fused ops are skipped while one is installed. Without a hook, each call site is a single
+//! `None` check. With one, a callback costs taking the hook out of the Vm and putting it back plus
+//! a virtual call, two or three per instruction (the step callbacks and one per load or store).
+//! The Vm owns its hook and no lock is taken, read the results with [Hook::get] once the Vm is
+//! done. A hook that has to be shared while the Vm runs can be an `Arc<Mutex<H>>`, which then
+//! pays a lock per callback (this is how [crate::inspector::SharedInspector] follows steps).
+use std::{
+    any::Any,
+    fmt,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use crate::{
+    host::EcallRequest,
+    instructions::Instruction,
+    vm::{VMErrors, Vm},
+};
+
+/// Callbacks of the interpreter, see the module documentation
+#[allow(unused_variables)]
+pub trait VmHook {
+    /// Before the instruction at `vm.pc` executes, it has been fetched and charged for
+    fn before_step(&mut self, vm: &Vm, instruction: &Instruction) {}
+
+    /// After `instruction` executed, `vm.pc` is the next instruction then
+    fn after_step(&mut self, vm: &Vm, instruction: &Instruction) {}
+
+    /// A load instruction of `size` bytes at `address` read `value` (before sign extension), see
+    /// the module documentation for the memory ecalls read
+    fn on_memory_read(&mut self, address: u32, size: u8, value: u32) {}
+
+    /// A store instruction of `size` bytes of `value` at `address`, see the module documentation
+    /// for the memory ecalls write
+    fn on_memory_write(&mut self, address: u32, size: u8, value: u32) {}
+
+    /// The ecall is about to be fulfilled by the host, its inputs are in `vm`
+    fn on_ecall_enter(&mut self, vm: &Vm, request: &EcallRequest) {}
+
+    /// The host fulfilled the ecall, its outputs are in `vm`
+    fn on_ecall_exit(&mut self, vm: &Vm, request: &EcallRequest) {}
+
+    /// The Vm trapped with `error` at `vm.pc`
+    fn on_trap(&mut self, vm: &Vm, error: &VMErrors) {}
+}
+
+/// Forwards every callback to the shared hook, locking it each time
+impl<H: VmHook + ?Sized> VmHook for Arc<Mutex<H>> {
+    fn before_step(&mut self, vm: &Vm, instruction: &Instruction) {
+        lock(self).before_step(vm, instruction);
+    }
+
+    fn after_step(&mut self, vm: &Vm, instruction: &Instruction) {
+        lock(self).after_step(vm, instruction);
+    }
+
+    fn on_memory_read(&mut self, address: u32, size: u8, value: u32) {
+        lock(self).on_memory_read(address, size, value);
+    }
+
+    fn on_memory_write(&mut self, address: u32, size: u8, value: u32) {
+        lock(self).on_memory_write(address, size, value);
+    }
+
+    fn on_ecall_enter(&mut self, vm: &Vm, request: &EcallRequest) {
+        lock(self).on_ecall_enter(vm, request);
+    }
+
+    fn on_ecall_exit(&mut self, vm: &Vm, request: &EcallRequest) {
+        lock(self).on_ecall_exit(vm, request);
+    }
+
+    fn on_trap(&mut self, vm: &Vm, error: &VMErrors) {
+        lock(self).on_trap(vm, error);
+    }
+}
+
+/// A hook that panicked is still called, its state is its own business
+fn lock<H: ?Sized>(hook: &Mutex<H>) -> std::sync::MutexGuard<'_, H> {
+    hook.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// What a [Hook] needs from the hook it holds: cloning along with the Vm and downcasting
+trait InstalledHook: VmHook + Send {
+    fn clone_hook(&self) -> Box<dyn InstalledHook>;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<H: VmHook + Clone + Send + 'static> InstalledHook for H {
+    fn clone_hook(&self) -> Box<dyn InstalledHook> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// A [VmHook] installed on a Vm, owned by it. Cloning the Vm clones the hook.
+pub struct Hook(Box<dyn InstalledHook>);
+
+impl Hook {
+    pub fn new<H: VmHook + Clone + Send + 'static>(hook: H) -> Self {
+        Self(Box::new(hook))
+    }
+
+    /// The installed hook, `None` if it is not an `H`
+    pub fn get<H: 'static>(&self) -> Option<&H> {
+        self.0.as_any().downcast_ref()
+    }
+
+    /// The installed hook, `None` if it is not an `H`
+    pub fn get_mut<H: 'static>(&mut self) -> Option<&mut H> {
+        self.0.as_any_mut().downcast_mut()
+    }
+
+    /// Calls `f` with the hook of `vm`, if any, and the Vm itself.
+    /// The hook is out of the Vm meanwhile, so `f` can see both.
+    #[inline]
+    pub(crate) fn call(vm: &mut Vm, f: impl FnOnce(&mut dyn VmHook, &Vm)) {
+        if let Some(mut hook) = vm.hook.take() {
+            f(&mut *hook.0, vm);
+            vm.hook = Some(hook);
+        }
+    }
+}
+
+impl Clone for Hook {
+    fn clone(&self) -> Self {
+        Self(self.0.clone_hook())
+    }
+}
+
+impl fmt::Debug for Hook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Hook")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{context::Context, memory_map::PAGE_SIZE, utils::u32_vec_to_bytes};
+    use revm::{Context as EthContext, MainContext, database::CacheDB};
+    use riscv_evm_core::e_constants::RiscvEVMECalls;
+
+    #[derive(Default, Clone)]
+    struct Recorder {
+        steps: Vec<(u32, u32)>,
+        memory: Vec<(bool, u32, u8, u32)>,
+        ecalls: Vec<(RiscvEVMECalls, bool)>,
+        traps: Vec<u32>,
+    }
+
+    impl VmHook for Recorder {
+        fn before_step(&mut self, vm: &Vm, _instruction: &Instruction) {
+            self.steps.push((vm.pc, u32::MAX));
+        }
+
+        fn after_step(&mut self, vm: &Vm, _instruction: &Instruction) {
+            self.steps.last_mut().unwrap().1 = vm.pc;
+        }
+
+        fn on_memory_read(&mut self, address: u32, size: u8, value: u32) {
+            self.memory.push((false, address, size, value));
+        }
+
+        fn on_memory_write(&mut self, address: u32, size: u8, value: u32) {
+            self.memory.push((true, address, size, value));
+        }
+
+        fn on_ecall_enter(&mut self, _vm: &Vm, request: &EcallRequest) {
+            self.ecalls.push((request.ecall, false));
+        }
+
+        fn on_ecall_exit(&mut self, _vm: &Vm, request: &EcallRequest) {
+            self.ecalls.push((request.ecall, true));
+        }
+
+        fn on_trap(&mut self, vm: &Vm, error: &VMErrors) {
+            assert!(matches!(error, VMErrors::Breakpoint(_)));
+            self.traps.push(vm.pc);
+        }
+    }
+
+    #[test]
+    fn test_hook() {
+        let code = u32_vec_to_bytes(
+            &[
+                0x0C100F93, // addi t6, zero, 0xC1 (Sbrk)
+                0x00000073, // ecall
+                0x00c52023, // sw a2, 0(a0), a0 holds the break Sbrk returned
+                0x00052683, // lw a3, 0(a0)
+                0x00100073, // ebreak
+            ],
+            20,
+        );
+        let mut context = Context::new(EthContext::mainnet().with_db(CacheDB::default()));
+        let mut vm = Vm::from_bin_u8(code).unwrap();
+        vm.registers.write_reg(12, 7);
+        vm.hook = Some(Hook::new(Recorder::default()));
+
+        for _ in 0..4 {
+            vm.step(false, &mut context).unwrap();
+        }
+        assert!(vm.step(false, &mut context).is_err());
+
+        let recorder = vm.hook.as_ref().unwrap().get::<Recorder>().unwrap();
+        assert_eq!(
+            recorder.steps,
+            [(0, 4), (4, 8), (8, 12), (12, 16), (16, u32::MAX)]
+        );
+        assert_eq!(
+            recorder.memory,
+            [(true, PAGE_SIZE, 4, 7), (false, PAGE_SIZE, 4, 7)]
+        );
+        assert_eq!(
+            recorder.ecalls,
+            [(RiscvEVMECalls::Sbrk, false), (RiscvEVMECalls::Sbrk, true)]
+        );
+        assert_eq!(recorder.traps, [16]);
+    }
+}
@@ -0,0 +1,316 @@
+//! # Host
+//! Ecalls are a request/response protocol between the Vm and its host.
+//! The Vm stops on an ecall with an [EcallRequest] carrying the ecall inputs, decoded from the
+//! registers and the guest buffers they point to ([EcallArgs]). The host answers with an
+//! [EcallResponse], every register and memory write the ecall makes. The Vm then applies the
+//! response and carries on after the ecall, the host never touches the Vm.
+//!
+//! [Vm::run] drives the protocol synchronously with the [Context] as the host, see
+//! [crate::ecall_manager]. [Vm::run_resumable] hands the request to the caller instead, returning
+//! [Execution::Yield]. The caller fulfils it however it likes, from a remote database, a prover
+//! witness or across an `await`, calls and creates included, and continues with [Vm::resume].
+//!
+//! `Sbrk` and `DebugLog` only concern the Vm itself (its heap, its registers and memory), they
+//! are served by the Vm and never yield.
+use revm::primitives::{Address, B256, Bytes, U256};
+use riscv_evm_core::e_constants::*;
+
+use crate::{
+    context::Context,
+    utils::{bytes_to_u32, combine_u32_to_u64, read_guest_bytes, write_guest_bytes},
+    vm::{EXIT_REVERT, VMErrors, Vm},
+};
+
+/// An ecall the Vm is waiting on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EcallRequest {
+    pub ecall: RiscvEVMECalls,
+    /// Address of the `ecall` instruction, the pc stays on it until the Vm is resumed
+    pub pc: u32,
+    /// The inputs of the ecall
+    pub args: EcallArgs,
+}
+
+/// The inputs of an ecall, grouped by shape
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EcallArgs {
+    /// The ecall takes no inputs
+    None,
+    /// The account of `Balance`, `ExtCodeSize`, `ExtCodeHash` and `SelfDestruct`
+    Address(Address),
+    /// The calldata offset of `CallDataLoad` or the blob index of `BlobHash`
+    Index(u32),
+    /// The block number of `BlockHash`
+    Number(u64),
+    /// The storage slot of `SLoad`
+    Slot(U256),
+    /// The storage slot and value of `SStore`
+    Store { slot: U256, value: U256 },
+    /// A copy to guest memory, `CallDataCopy`, `CodeCopy`, `ReturnDataCopy`, and `ExtCodeCopy`
+    /// which also names the account
+    Copy {
+        address: Option<Address>,
+        dest_offset: u32,
+        offset: u32,
+        size: u32,
+    },
+    /// The guest buffer hashed by `Keccak256` or returned by `Return` and `Revert`
+    Bytes(Bytes),
+    /// `Log0` and `Log1`
+    Log { topics: Vec<B256>, data: Bytes },
+    /// `Call`, `CallCode`, `DelegateCall` and `StaticCall`, the value is zero for the ecalls that
+    /// do not take one
+    Call {
+        gas: U256,
+        address: Address,
+        value: U256,
+        input: Bytes,
+        return_offset: u32,
+        return_size: u32,
+    },
+    /// `Create`, and `Create2` with its salt
+    Create {
+        value: U256,
+        init_code: Bytes,
+        salt: Option<U256>,
+    },
+    /// The increment of `Sbrk`
+    Sbrk(i32),
+}
+
+/// The outcome of an ecall, applied to the Vm in order: gas, memory, return data, registers, halt
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EcallResponse {
+    /// Gas charged for the ecall, the [Context] host charges its meter as it goes and leaves it
+    /// at zero
+    pub gas: u64,
+    /// Guest buffers written, `(address, bytes)`, checked like any other store
+    pub memory: Vec<(u32, Vec<u8>)>,
+    /// Return data of a call, mapped at [crate::memory_map::RETURN_DATA_ADDRESS]
+    pub return_data: Option<Bytes>,
+    /// Registers written, `(register, value)`
+    pub registers: Vec<(u32, u32)>,
+    /// Whether the ecall ends the frame, like `Return` and `Revert` do
+    pub halt: bool,
+    /// Whether the frame ends reverting, with `halt`
+    pub revert: bool,
+}
+
+/// Why [Vm::run_resumable] or [Vm::resume] returned
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Execution {
+    /// The program halted
+    Halted,
+    /// The program is waiting on an ecall, continue with [Vm::resume]
+    Yield(EcallRequest),
+}
+
+/// Fulfils the ecalls of a Vm
+pub trait EcallHost {
+    /// Fulfils `request`
+    /// # Errors
+    /// When the ecall fails, the Vm traps with the error.
+    fn fulfil(&mut self, request: &EcallRequest) -> Result<EcallResponse, VMErrors>;
+}
+
+impl EcallRequest {
+    /// Decodes the ecall `vm` is on, its registers and the guest buffers they point to. The
+    /// registers are read from wherever the profile of `vm` passes them, see [crate::container].
+    /// # Errors
+    /// [VMErrors::EnvironmentError] for an unknown ecall, or a guest buffer error.
+    pub(crate) fn decode(vm: &Vm) -> Result<Self, VMErrors> {
+        let code = vm.ecall_reg(ECALL_CODE_REG)?;
+        let ecall = RiscvEVMECalls::from_u32(code).ok_or(VMErrors::EnvironmentError)?;
+        let reg = |register| vm.ecall_reg(register);
+        // the big-endian value of the registers starting at `first_register`
+        let be_bytes = |first_register: u32, value: &mut [u8]| -> Result<(), VMErrors> {
+            for (i, chunk) in value.chunks_exact_mut(4).enumerate() {
+                chunk.copy_from_slice(&reg(first_register + i as u32)?.to_be_bytes());
+            }
+            Ok(())
+        };
+        let address = |first_register| -> Result<Address, VMErrors> {
+            let mut address = [0u8; 20];
+            be_bytes(first_register, &mut address)?;
+            Ok(Address::new(address))
+        };
+        let word = |first_register| -> Result<[u8; 32], VMErrors> {
+            let mut word = [0u8; 32];
+            be_bytes(first_register, &mut word)?;
+            Ok(word)
+        };
+        let value = |first_register| word(first_register).map(U256::from_be_bytes);
+        let bytes = |offset, size| read_guest_bytes(vm, reg(offset)?, reg(size)?).map(Bytes::from);
+        let call = |call_value| -> Result<EcallArgs, VMErrors> {
+            Ok(EcallArgs::Call {
+                gas: value(CALL_INPUT_REGISTER_1)?,
+                address: address(CALL_INPUT_REGISTER_9)?,
+                value: call_value,
+                input: bytes(CALL_INPUT_REGISTER_22, CALL_INPUT_REGISTER_23)?,
+                return_offset: reg(CALL_INPUT_REGISTER_24)?,
+                return_size: reg(CALL_INPUT_REGISTER_25)?,
+            })
+        };
+        let copy =
+            |address, [dest_offset, offset, size]: [u32; 3]| -> Result<EcallArgs, VMErrors> {
+                Ok(EcallArgs::Copy {
+                    address,
+                    dest_offset: reg(dest_offset)?,
+                    offset: reg(offset)?,
+                    size: reg(size)?,
+                })
+            };
+
+        let args = match ecall {
+            RiscvEVMECalls::Keccak256 => {
+                EcallArgs::Bytes(bytes(KECCAK256_OFFSET_REGISTER, KECCAK256_SIZE_REGISTER)?)
+            }
+            RiscvEVMECalls::Return => {
+                EcallArgs::Bytes(bytes(RETURN_INPUT_REGISTER_1, RETURN_INPUT_REGISTER_2)?)
+            }
+            RiscvEVMECalls::Revert => {
+                EcallArgs::Bytes(bytes(REVERT_INPUT_REGISTER_1, REVERT_INPUT_REGISTER_2)?)
+            }
+            RiscvEVMECalls::Balance => EcallArgs::Address(address(BALANCE_INPUT_REGISTER_1)?),
+            RiscvEVMECalls::ExtCodeSize => {
+                EcallArgs::Address(address(EXT_CODE_SIZE_INPUT_REGISTER_1)?)
+            }
+            RiscvEVMECalls::ExtCodeHash => {
+                EcallArgs::Address(address(EXT_CODE_HASH_INPUT_REGISTER_1)?)
+            }
+            RiscvEVMECalls::SelfDestruct => {
+                EcallArgs::Address(address(SELFDESTRUCT_INPUT_REGISTER_1)?)
+            }
+            RiscvEVMECalls::CallDataLoad => EcallArgs::Index(reg(CALL_DATA_LOAD_INPUT_REGISTER)?),
+            RiscvEVMECalls::BlobHash => EcallArgs::Index(reg(BLOB_HASH_OUTPUT_REGISTER_1)?),
+            RiscvEVMECalls::BlockHash => EcallArgs::Number(combine_u32_to_u64(
+                reg(BLOCK_HASH_INPUT_REGISTER_1)?,
+                reg(BLOCK_HASH_INPUT_REGISTER_2)?,
+            )),
+            RiscvEVMECalls::SLoad => EcallArgs::Slot(value(SLOAD_INPUT_REGISTER_1)?),
+            RiscvEVMECalls::SStore => EcallArgs::Store {
+                slot: value(SSTORE_INPUT_REGISTER_1)?,
+                value: value(SSTORE_INPUT_REGISTER_9)?,
+            },
+            RiscvEVMECalls::CallDataCopy => copy(
+                None,
+                [
+                    CALL_DATA_COPY_INPUT_REGISTER_1,
+                    CALL_DATA_COPY_INPUT_REGISTER_2,
+                    CALL_DATA_COPY_INPUT_REGISTER_3,
+                ],
+            )?,
+            RiscvEVMECalls::CodeCopy => copy(
+                None,
+                [
+                    CODE_COPY_INPUT_REGISTER_1,
+                    CODE_COPY_INPUT_REGISTER_2,
+                    CODE_COPY_INPUT_REGISTER_3,
+                ],
+            )?,
+            RiscvEVMECalls::ReturnDataCopy => copy(
+                None,
+                [
+                    RETURN_DATA_COPY_INPUT_REGISTER_1,
+                    RETURN_DATA_COPY_INPUT_REGISTER_2,
+                    RETURN_DATA_COPY_INPUT_REGISTER_3,
+                ],
+            )?,
+            RiscvEVMECalls::ExtCodeCopy => copy(
+                Some(address(EXT_CODE_COPY_INPUT_REGISTER_1)?),
+                [
+                    EXT_CODE_COPY_INPUT_REGISTER_6,
+                    EXT_CODE_COPY_INPUT_REGISTER_7,
+                    EXT_CODE_COPY_INPUT_REGISTER_8,
+                ],
+            )?,
+            RiscvEVMECalls::Log0 => EcallArgs::Log {
+                topics: vec![],
+                data: bytes(LOG0_INPUT_REGISTER_1, LOG0_INPUT_REGISTER_2)?,
+            },
+            RiscvEVMECalls::Log1 => EcallArgs::Log {
+                topics: vec![B256::new(word(LOG1_INPUT_REGISTER_3)?)],
+                data: bytes(LOG1_INPUT_REGISTER_1, LOG1_INPUT_REGISTER_2)?,
+            },
+            RiscvEVMECalls::Call | RiscvEVMECalls::CallCode => {
+                call(value(CALL_INPUT_REGISTER_14)?)?
+            }
+            // these keep the value of the caller or transfer none
+            RiscvEVMECalls::DelegateCall | RiscvEVMECalls::StaticCall => call(U256::ZERO)?,
+            RiscvEVMECalls::Create => EcallArgs::Create {
+                value: value(CREATE_INPUT_REGISTER_3)?,
+                init_code: bytes(CREATE_INPUT_REGISTER_1, CREATE_INPUT_REGISTER_2)?,
+                salt: None,
+            },
+            RiscvEVMECalls::Create2 => EcallArgs::Create {
+                value: value(CREATE_2_INPUT_REGISTER_3)?,
+                init_code: bytes(CREATE_2_INPUT_REGISTER_1, CREATE_2_INPUT_REGISTER_2)?,
+                salt: Some(value(CREATE_2_INPUT_REGISTER_11)?),
+            },
+            RiscvEVMECalls::Sbrk => EcallArgs::Sbrk(reg(SBRK_INPUT_REGISTER)? as i32),
+            // `DebugLog` reads the guest only when a console is attached, see [Vm::debug_log]
+            _ => EcallArgs::None,
+        };
+
+        Ok(Self {
+            ecall,
+            pc: vm.pc,
+            args,
+        })
+    }
+}
+
+impl EcallResponse {
+    /// Writes `value` to `register`
+    pub fn write_reg(&mut self, register: u32, value: u32) {
+        self.registers.push((register, value));
+    }
+
+    /// Writes a 32 byte word (big-endian) to the 8 registers starting at `first_register`
+    pub fn write_word256(&mut self, first_register: u32, word: &[u8; 32]) {
+        self.write_bytes(first_register, word);
+    }
+
+    /// Writes a 20 byte address (big-endian) to the 5 registers starting at `first_register`
+    pub fn write_address(&mut self, first_register: u32, address: &[u8; 20]) {
+        self.write_bytes(first_register, address);
+    }
+
+    /// Writes `bytes` to guest memory at `address`
+    pub fn write_memory(&mut self, address: u32, bytes: &[u8]) {
+        self.memory.push((address, bytes.to_vec()));
+    }
+
+    fn write_bytes(&mut self, first_register: u32, bytes: &[u8]) {
+        for (i, chunk) in bytes.chunks_exact(4).enumerate() {
+            self.write_reg(first_register + i as u32, bytes_to_u32(chunk));
+        }
+    }
+
+    /// Applies the response to `vm`, charging its gas to `context`
+    /// # Errors
+    /// [VMErrors::OutOfGas], a guest buffer error or [VMErrors::InvalidRegister].
+    pub(crate) fn apply(&self, vm: &mut Vm, context: &mut Context) -> Result<(), VMErrors> {
+        context.charge_gas(self.gas)?;
+        for (address, bytes) in &self.memory {
+            write_guest_bytes(vm, *address, bytes)?;
+        }
+        if let Some(return_data) = &self.return_data {
+            vm.map_return_data(return_data)?;
+        }
+        for &(register, value) in &self.registers {
+            if register >= 32 {
+                return Err(VMErrors::InvalidRegister(register));
+            }
+            vm.write_ecall_reg(register, value)?;
+        }
+        if self.halt {
+            vm.running = false;
+            if self.revert {
+                vm.exit_code = EXIT_REVERT;
+            }
+        }
+
+        Ok(())
+    }
+}
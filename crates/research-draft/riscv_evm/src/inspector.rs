@@ -0,0 +1,133 @@
+//! # Inspector
+//! revm [Inspector] support, so existing tooling (call tracers, access-list inspectors, gas
+//! inspectors) can observe RISC-V execution.
+//! Attach one with [Context::with_inspector] and the frame machinery of [crate::ecall_manager]
+//! reports to it:
+//! - `call`/`call_end` around `Call`, `CallCode`, `DelegateCall` and `StaticCall` frames,
+//! - `create`/`create_end` around `Create` and `Create2` frames,
+//! - `log` for every `Log*` ecall,
+//! - `selfdestruct` for the `SelfDestruct` ecall.
+//!
+//! Like in revm, an inspector returning an outcome from `call`/`create` skips the frame, the
+//! frame runs with the inputs `call`/`create` left (calldata, target, code, caller, value, gas
+//! limit, init code), and the outcome left by `call_end`/`create_end` is what the caller sees:
+//! its gas is charged, and a failed outcome drops the state changes of the frame.
+//!
+//! The `step`/`step_end` callbacks of revm take an EVM interpreter. Their RISC-V counterparts are
+//! the [VmHook] callbacks, which see the pc and decoded instruction of every RISC-V step: an
+//! inspector that also implements [VmHook], attached with [SharedInspector::with_steps], is
+//! installed on the Vm of every frame. The top-level frame is run by the caller, the handler's
+//! `inspect` reports it the same way.
+use std::{
+    cell::RefCell,
+    fmt,
+    rc::Rc,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use revm::{
+    Inspector,
+    bytecode::Bytecode,
+    interpreter::{
+        Gas, InputsImpl, InstructionResult, Interpreter, InterpreterResult, SharedMemory,
+        interpreter::ExtBytecode,
+    },
+    primitives::Log,
+};
+
+use crate::{
+    context::{Context, EthContext},
+    hook::{Hook, VmHook},
+    vm::{EXIT_REVERT, EXIT_TRAP, Vm},
+};
+
+/// A revm [Inspector] attached to a [Context], shared by every frame of the transaction so the
+/// caller can keep a handle on it to read its results.
+#[derive(Clone)]
+pub struct SharedInspector {
+    inspector: Arc<Mutex<dyn Inspector<EthContext> + Send>>,
+    steps: Option<Hook>,
+}
+
+impl SharedInspector {
+    /// An inspector that only follows frames and logs
+    pub fn new<I: Inspector<EthContext> + Send + 'static>(inspector: Arc<Mutex<I>>) -> Self {
+        Self {
+            inspector,
+            steps: None,
+        }
+    }
+
+    /// An inspector that also follows every RISC-V step through its [VmHook] callbacks
+    pub fn with_steps<I: Inspector<EthContext> + VmHook + Send + 'static>(
+        inspector: Arc<Mutex<I>>,
+    ) -> Self {
+        Self {
+            steps: Some(Hook::new(inspector.clone())),
+            inspector,
+        }
+    }
+
+    /// The hook to install on the Vm of a frame, `None` when the inspector does not follow steps
+    pub fn step_hook(&self) -> Option<Hook> {
+        self.steps.clone()
+    }
+
+    /// Calls `f` with the inspector
+    pub fn with<R>(&self, f: impl FnOnce(&mut dyn Inspector<EthContext>) -> R) -> R {
+        let mut inspector = self
+            .inspector
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        f(&mut *inspector)
+    }
+
+    /// Reports a log emitted by the frame of `context`.
+    /// There is no EVM interpreter to hand over, the inspector gets a stand-in holding the frame
+    /// inputs and remaining gas.
+    pub(crate) fn log(&self, context: &mut Context, log: Log) {
+        let inputs = InputsImpl {
+            target_address: context.address,
+            caller_address: context.current_caller,
+            input: context.eth_context.tx.data.clone(),
+            call_value: context.eth_context.tx.value,
+        };
+        let mut interpreter = Interpreter::new(
+            Rc::new(RefCell::new(SharedMemory::new())),
+            ExtBytecode::new(Bytecode::new()),
+            inputs,
+            false,
+            false,
+            context.spec(),
+            context.gas.remaining(),
+        );
+        self.with(|inspector| inspector.log(&mut interpreter, &mut context.eth_context, log));
+    }
+}
+
+impl fmt::Debug for SharedInspector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedInspector")
+            .field("steps", &self.steps.is_some())
+            .finish()
+    }
+}
+
+/// The result of a frame that ran with `gas_limit` on the meter of `context`.
+/// Like an exceptional halt in the EVM, a trap (running out of gas included) uses up all of it.
+pub fn frame_result(vm: &Vm, context: &Context, gas_limit: u64) -> InterpreterResult {
+    let mut gas = Gas::new(gas_limit);
+    gas.set_spent(context.gas.spent());
+    gas.record_refund(context.gas.refunded());
+    let result = match vm.exit_code {
+        EXIT_REVERT => InstructionResult::Revert,
+        // a trap is the RISC-V counterpart of the EVM's invalid opcode
+        EXIT_TRAP => {
+            gas.spend_all();
+            InstructionResult::InvalidFEOpcode
+        }
+        _ => InstructionResult::Return,
+    };
+
+    InterpreterResult::new(result, context.return_data.clone(), gas)
+}
@@ -1,4 +1,10 @@
-use crate::vm::VMErrors;
+use crate::{
+    container::Profile,
+    disassembler::{abi_name, csr_name},
+    vm::VMErrors,
+};
+use riscv_evm_core::WORD_SIZE;
+use std::fmt;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct RType {
@@ -184,7 +190,69 @@ pub const JAL_CLASS: u32 = 0b1101111;
 pub const JALR_CLASS: u32 = 0b1100111;
 pub const UPPER_IMMEDIATE_CLASS: u32 = 0b0110111;
 pub const ENVIRONMENT_CLASS: u32 = 0b1110011;
+pub const MISC_MEMORY_CLASS: u32 = 0b0001111;
 pub const UPPER_IMMEDIATE_TO_PC_CLASS: u32 = 0b0010111;
+pub const IMMEDIATE_WORD_CLASS: u32 = 0b0011011;
+pub const REGISTER_WORD_CLASS: u32 = 0b0111011;
+
+// Optional bit-manipulation extensions (cargo features of the same name), the encodings of an
+// extension are only decoded when one of the extensions defining them is enabled
+const ZBA: bool = cfg!(feature = "zba");
+const ZBB: bool = cfg!(feature = "zbb");
+const ZBC: bool = cfg!(feature = "zbc");
+const ZBKB: bool = cfg!(feature = "zbkb");
+
+/// `cycle`, the read-only counter CSRs of Zicsr follow, the `h` ones hold the upper 32 bits
+pub const CSR_CYCLE: u32 = 0xc00;
+pub const CSR_TIME: u32 = 0xc01;
+pub const CSR_INSTRET: u32 = 0xc02;
+pub const CSR_CYCLEH: u32 = 0xc80;
+pub const CSR_TIMEH: u32 = 0xc81;
+pub const CSR_INSTRETH: u32 = 0xc82;
+
+/// `ecall`
+pub const ECALL: u32 = 0x0000_0073;
+/// `ebreak`
+pub const EBREAK: u32 = 0x0010_0073;
+/// `wfi`
+pub const WFI: u32 = 0x1050_0073;
+/// Size in bytes of an RV32C instruction, also the pc alignment once compressed code is allowed
+pub const COMPRESSED_INSTRUCTION_SIZE: u32 = 2;
+
+/// Size in bytes of the instruction whose lowest bits are `raw`: 4, or 2 for RV32C instructions
+#[inline]
+pub fn instruction_size(raw: u32) -> u32 {
+    if raw & 0b11 == 0b11 {
+        WORD_SIZE as u32
+    } else {
+        COMPRESSED_INSTRUCTION_SIZE
+    }
+}
+
+/// The 16-bit parcel at byte `offset` of `words`.
+/// Code words hold two parcels, the one at the lower address in the low half, which is how a
+/// little-endian toolchain lays out compressed code.
+#[inline]
+pub fn read_parcel(words: &[u32], offset: u32) -> Option<u16> {
+    let word = words.get((offset / WORD_SIZE as u32) as usize)?;
+    Some((word >> ((offset & 2) * 8)) as u16)
+}
+
+/// The raw instruction starting at byte `offset` of `words`, `None` when `offset` is not 16-bit
+/// aligned or the instruction does not fit.
+/// A 32-bit instruction at `offset % 4 == 2` spans two words, a compressed one only keeps its own
+/// 16 bits.
+#[inline]
+pub fn fetch_instruction(words: &[u32], offset: u32) -> Option<u32> {
+    if !offset.is_multiple_of(COMPRESSED_INSTRUCTION_SIZE) {
+        return None;
+    }
+    let low = read_parcel(words, offset)? as u32;
+    match instruction_size(low) {
+        COMPRESSED_INSTRUCTION_SIZE => Some(low),
+        _ => Some(low | (read_parcel(words, offset + 2)? as u32) << 16),
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct InstructionDecoder {
@@ -204,7 +272,8 @@ impl InstructionDecoder {
                     opcode,
                 });
             }
-            IMMEDIATE_CLASS | IMMEDIATE_LOAD_CLASS | JALR_CLASS | ENVIRONMENT_CLASS => {
+            IMMEDIATE_CLASS | IMMEDIATE_LOAD_CLASS | JALR_CLASS | ENVIRONMENT_CLASS
+            | MISC_MEMORY_CLASS => {
                 let decoded_instruction = DecodedInstruction::IType(IType::new(*instruction));
                 return Ok(Self {
                     decoded_instruction,
@@ -270,3 +339,1463 @@ impl InstructionDecoder {
         }
     }
 }
+
+/// A fully decoded and validated RV32IM instruction, or one of the optional Zba/Zbb/Zbc/Zbkb
+/// bit-manipulation instructions.
+/// This is what the Vm executes, an instruction is decoded once into this form and dispatched with a
+/// single `match`, register indexes are already extracted and immediates sign-extended.
+/// RV32C instructions decode to the RV32I instruction they expand to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    // RV32I register-register
+    Add {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Sub {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Sll {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Slt {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Sltu {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Xor {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Srl {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Sra {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Or {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    And {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    // RV32M
+    Mul {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Mulh {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Mulhsu {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Mulhu {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Div {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Divu {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Rem {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Remu {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    // Zba / Zbb / Zbc / Zbkb register-register
+    Sh1add {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Sh2add {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Sh3add {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Andn {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Orn {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Xnor {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Max {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Maxu {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Min {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Minu {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Rol {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Ror {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Clmul {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Clmulh {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Clmulr {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Pack {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Packh {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    // Zbb / Zbkb single operand
+    Clz {
+        rd: u32,
+        rs1: u32,
+    },
+    Ctz {
+        rd: u32,
+        rs1: u32,
+    },
+    Cpop {
+        rd: u32,
+        rs1: u32,
+    },
+    SextB {
+        rd: u32,
+        rs1: u32,
+    },
+    SextH {
+        rd: u32,
+        rs1: u32,
+    },
+    OrcB {
+        rd: u32,
+        rs1: u32,
+    },
+    Rev8 {
+        rd: u32,
+        rs1: u32,
+    },
+    Brev8 {
+        rd: u32,
+        rs1: u32,
+    },
+    Zip {
+        rd: u32,
+        rs1: u32,
+    },
+    Unzip {
+        rd: u32,
+        rs1: u32,
+    },
+    Rori {
+        rd: u32,
+        rs1: u32,
+        shamt: u32,
+    },
+    // RV64IM only (see [Instruction::decode64]), `*w` instructions work on the low 32 bits and
+    // sign-extend their result
+    Ld {
+        rd: u32,
+        rs1: u32,
+        imm: i32,
+    },
+    Lwu {
+        rd: u32,
+        rs1: u32,
+        imm: i32,
+    },
+    Sd {
+        rs1: u32,
+        rs2: u32,
+        imm: i32,
+    },
+    Addiw {
+        rd: u32,
+        rs1: u32,
+        imm: i32,
+    },
+    Slliw {
+        rd: u32,
+        rs1: u32,
+        shamt: u32,
+    },
+    Srliw {
+        rd: u32,
+        rs1: u32,
+        shamt: u32,
+    },
+    Sraiw {
+        rd: u32,
+        rs1: u32,
+        shamt: u32,
+    },
+    Addw {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Subw {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Sllw {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Srlw {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Sraw {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Mulw {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Divw {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Divuw {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Remw {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    Remuw {
+        rd: u32,
+        rs1: u32,
+        rs2: u32,
+    },
+    // RV32I register-immediate
+    Addi {
+        rd: u32,
+        rs1: u32,
+        imm: i32,
+    },
+    Slti {
+        rd: u32,
+        rs1: u32,
+        imm: i32,
+    },
+    Sltiu {
+        rd: u32,
+        rs1: u32,
+        imm: i32,
+    },
+    Xori {
+        rd: u32,
+        rs1: u32,
+        imm: i32,
+    },
+    Ori {
+        rd: u32,
+        rs1: u32,
+        imm: i32,
+    },
+    Andi {
+        rd: u32,
+        rs1: u32,
+        imm: i32,
+    },
+    Slli {
+        rd: u32,
+        rs1: u32,
+        shamt: u32,
+    },
+    Srli {
+        rd: u32,
+        rs1: u32,
+        shamt: u32,
+    },
+    Srai {
+        rd: u32,
+        rs1: u32,
+        shamt: u32,
+    },
+    // Loads
+    Lb {
+        rd: u32,
+        rs1: u32,
+        imm: i32,
+    },
+    Lh {
+        rd: u32,
+        rs1: u32,
+        imm: i32,
+    },
+    Lw {
+        rd: u32,
+        rs1: u32,
+        imm: i32,
+    },
+    Lbu {
+        rd: u32,
+        rs1: u32,
+        imm: i32,
+    },
+    Lhu {
+        rd: u32,
+        rs1: u32,
+        imm: i32,
+    },
+    // Stores
+    Sb {
+        rs1: u32,
+        rs2: u32,
+        imm: i32,
+    },
+    Sh {
+        rs1: u32,
+        rs2: u32,
+        imm: i32,
+    },
+    Sw {
+        rs1: u32,
+        rs2: u32,
+        imm: i32,
+    },
+    // Branches, `imm` is relative to the branch instruction
+    Beq {
+        rs1: u32,
+        rs2: u32,
+        imm: i32,
+    },
+    Bne {
+        rs1: u32,
+        rs2: u32,
+        imm: i32,
+    },
+    Blt {
+        rs1: u32,
+        rs2: u32,
+        imm: i32,
+    },
+    Bge {
+        rs1: u32,
+        rs2: u32,
+        imm: i32,
+    },
+    Bltu {
+        rs1: u32,
+        rs2: u32,
+        imm: i32,
+    },
+    Bgeu {
+        rs1: u32,
+        rs2: u32,
+        imm: i32,
+    },
+    // Upper immediates, `imm` already holds the value shifted into the upper 20 bits
+    Lui {
+        rd: u32,
+        imm: i32,
+    },
+    Auipc {
+        rd: u32,
+        imm: i32,
+    },
+    // Jumps
+    Jal {
+        rd: u32,
+        imm: i32,
+    },
+    Jalr {
+        rd: u32,
+        rs1: u32,
+        imm: i32,
+    },
+    // Environment
+    Ecall,
+    /// Stops at a breakpoint, see [VMErrors::Breakpoint]
+    Ebreak,
+    /// Traps, there is no interrupt to wait for
+    Wfi,
+    // Memory ordering, a single hart with no caches makes both no-ops, except that `fence.i`
+    // drops the pre-decoded program
+    Fence,
+    FenceI,
+    // Zicsr, only the read-only counters exist (see [CSR_CYCLE]), `uimm` is the 5-bit immediate
+    Csrrw {
+        rd: u32,
+        rs1: u32,
+        csr: u32,
+    },
+    Csrrs {
+        rd: u32,
+        rs1: u32,
+        csr: u32,
+    },
+    Csrrc {
+        rd: u32,
+        rs1: u32,
+        csr: u32,
+    },
+    Csrrwi {
+        rd: u32,
+        uimm: u32,
+        csr: u32,
+    },
+    Csrrsi {
+        rd: u32,
+        uimm: u32,
+        csr: u32,
+    },
+    Csrrci {
+        rd: u32,
+        uimm: u32,
+        csr: u32,
+    },
+}
+
+impl Instruction {
+    /// Decode and validate a raw instruction.
+    /// Unknown opcodes, funct3 and funct7 values are rejected here, so the Vm never has to.
+    pub fn decode(raw: u32) -> Result<Self, VMErrors> {
+        let decoded = InstructionDecoder::decode(&raw)?;
+
+        let instruction = match decoded.decoded_instruction {
+            DecodedInstruction::RType(r) => {
+                let (rd, rs1, rs2) = (r.rd as u32, r.rs1 as u32, r.rs2 as u32);
+                match (r.funct3, r.funct7) {
+                    (0b000, 0b0000000) => Self::Add { rd, rs1, rs2 },
+                    (0b000, 0b0100000) => Self::Sub { rd, rs1, rs2 },
+                    (0b001, 0b0000000) => Self::Sll { rd, rs1, rs2 },
+                    (0b010, 0b0000000) => Self::Slt { rd, rs1, rs2 },
+                    (0b011, 0b0000000) => Self::Sltu { rd, rs1, rs2 },
+                    (0b100, 0b0000000) => Self::Xor { rd, rs1, rs2 },
+                    (0b101, 0b0000000) => Self::Srl { rd, rs1, rs2 },
+                    (0b101, 0b0100000) => Self::Sra { rd, rs1, rs2 },
+                    (0b110, 0b0000000) => Self::Or { rd, rs1, rs2 },
+                    (0b111, 0b0000000) => Self::And { rd, rs1, rs2 },
+                    (0b000, 0b0000001) => Self::Mul { rd, rs1, rs2 },
+                    (0b001, 0b0000001) => Self::Mulh { rd, rs1, rs2 },
+                    (0b010, 0b0000001) => Self::Mulhsu { rd, rs1, rs2 },
+                    (0b011, 0b0000001) => Self::Mulhu { rd, rs1, rs2 },
+                    (0b100, 0b0000001) => Self::Div { rd, rs1, rs2 },
+                    (0b101, 0b0000001) => Self::Divu { rd, rs1, rs2 },
+                    (0b110, 0b0000001) => Self::Rem { rd, rs1, rs2 },
+                    (0b111, 0b0000001) => Self::Remu { rd, rs1, rs2 },
+                    (0b010, 0b0010000) if ZBA => Self::Sh1add { rd, rs1, rs2 },
+                    (0b100, 0b0010000) if ZBA => Self::Sh2add { rd, rs1, rs2 },
+                    (0b110, 0b0010000) if ZBA => Self::Sh3add { rd, rs1, rs2 },
+                    (0b111, 0b0100000) if ZBB || ZBKB => Self::Andn { rd, rs1, rs2 },
+                    (0b110, 0b0100000) if ZBB || ZBKB => Self::Orn { rd, rs1, rs2 },
+                    (0b100, 0b0100000) if ZBB || ZBKB => Self::Xnor { rd, rs1, rs2 },
+                    (0b110, 0b0000101) if ZBB => Self::Max { rd, rs1, rs2 },
+                    (0b111, 0b0000101) if ZBB => Self::Maxu { rd, rs1, rs2 },
+                    (0b100, 0b0000101) if ZBB => Self::Min { rd, rs1, rs2 },
+                    (0b101, 0b0000101) if ZBB => Self::Minu { rd, rs1, rs2 },
+                    (0b001, 0b0110000) if ZBB || ZBKB => Self::Rol { rd, rs1, rs2 },
+                    (0b101, 0b0110000) if ZBB || ZBKB => Self::Ror { rd, rs1, rs2 },
+                    (0b001, 0b0000101) if ZBC => Self::Clmul { rd, rs1, rs2 },
+                    (0b011, 0b0000101) if ZBC => Self::Clmulh { rd, rs1, rs2 },
+                    (0b010, 0b0000101) if ZBC => Self::Clmulr { rd, rs1, rs2 },
+                    // `zext.h` of Zbb is `pack rd, rs1, zero`
+                    (0b100, 0b0000100) if ZBKB || (ZBB && rs2 == 0) => Self::Pack { rd, rs1, rs2 },
+                    (0b111, 0b0000100) if ZBKB => Self::Packh { rd, rs1, rs2 },
+                    _ => return Err(VMErrors::InvalidFunct7(r.funct7)),
+                }
+            }
+            DecodedInstruction::IType(i) => {
+                let (rd, rs1, imm) = (i.rd as u32, i.rs1 as u32, i.imm);
+                match decoded.opcode {
+                    IMMEDIATE_CLASS => match i.funct3 {
+                        0b000 => Self::Addi { rd, rs1, imm },
+                        0b010 => Self::Slti { rd, rs1, imm },
+                        0b011 => Self::Sltiu { rd, rs1, imm },
+                        0b100 => Self::Xori { rd, rs1, imm },
+                        0b110 => Self::Ori { rd, rs1, imm },
+                        0b111 => Self::Andi { rd, rs1, imm },
+                        0b001 | 0b101 => {
+                            let shamt = i.metadata.imm_shift_amt;
+                            match (i.funct3, i.metadata.funct7) {
+                                (0b001, 0b0000000) => Self::Slli { rd, rs1, shamt },
+                                (0b101, 0b0000000) => Self::Srli { rd, rs1, shamt },
+                                (0b101, 0b0100000) => Self::Srai { rd, rs1, shamt },
+                                (0b101, 0b0110000) if ZBB || ZBKB => Self::Rori { rd, rs1, shamt },
+                                // single operand instructions use the shift amount as a selector
+                                (0b001, 0b0110000) if ZBB => match shamt {
+                                    0b00000 => Self::Clz { rd, rs1 },
+                                    0b00001 => Self::Ctz { rd, rs1 },
+                                    0b00010 => Self::Cpop { rd, rs1 },
+                                    0b00100 => Self::SextB { rd, rs1 },
+                                    0b00101 => Self::SextH { rd, rs1 },
+                                    _ => return Err(VMErrors::InvalidInstruction),
+                                },
+                                (0b101, 0b0010100) if ZBB && shamt == 0b00111 => {
+                                    Self::OrcB { rd, rs1 }
+                                }
+                                (0b101, 0b0110100) if (ZBB || ZBKB) && shamt == 0b11000 => {
+                                    Self::Rev8 { rd, rs1 }
+                                }
+                                (0b101, 0b0110100) if ZBKB && shamt == 0b00111 => {
+                                    Self::Brev8 { rd, rs1 }
+                                }
+                                (0b001, 0b0000100) if ZBKB && shamt == 0b01111 => {
+                                    Self::Zip { rd, rs1 }
+                                }
+                                (0b101, 0b0000100) if ZBKB && shamt == 0b01111 => {
+                                    Self::Unzip { rd, rs1 }
+                                }
+                                _ => return Err(VMErrors::InvalidFunct7(i.metadata.funct7)),
+                            }
+                        }
+                        _ => return Err(VMErrors::InvalidFunct3(i.funct3)),
+                    },
+                    IMMEDIATE_LOAD_CLASS => match i.funct3 {
+                        0b000 => Self::Lb { rd, rs1, imm },
+                        0b001 => Self::Lh { rd, rs1, imm },
+                        0b010 => Self::Lw { rd, rs1, imm },
+                        0b100 => Self::Lbu { rd, rs1, imm },
+                        0b101 => Self::Lhu { rd, rs1, imm },
+                        _ => return Err(VMErrors::InvalidFunct3(i.funct3)),
+                    },
+                    JALR_CLASS => match i.funct3 {
+                        0b000 => Self::Jalr { rd, rs1, imm },
+                        _ => return Err(VMErrors::InvalidFunct3(i.funct3)),
+                    },
+                    MISC_MEMORY_CLASS => match i.funct3 {
+                        0b000 => Self::Fence,
+                        0b001 => Self::FenceI,
+                        _ => return Err(VMErrors::InvalidFunct3(i.funct3)),
+                    },
+                    ENVIRONMENT_CLASS => {
+                        let csr = (imm as u32) & 0xfff;
+                        match i.funct3 {
+                            // the privileged instructions (`mret`, `sfence.vma`, ...) have no
+                            // meaning for a contract
+                            0b000 => match raw {
+                                ECALL => Self::Ecall,
+                                EBREAK => Self::Ebreak,
+                                WFI => Self::Wfi,
+                                _ => return Err(VMErrors::PrivilegedInstruction(raw)),
+                            },
+                            0b001 => Self::Csrrw { rd, rs1, csr },
+                            0b010 => Self::Csrrs { rd, rs1, csr },
+                            0b011 => Self::Csrrc { rd, rs1, csr },
+                            0b101 => Self::Csrrwi { rd, uimm: rs1, csr },
+                            0b110 => Self::Csrrsi { rd, uimm: rs1, csr },
+                            0b111 => Self::Csrrci { rd, uimm: rs1, csr },
+                            _ => return Err(VMErrors::InvalidFunct3(i.funct3)),
+                        }
+                    }
+                    _ => return Err(VMErrors::InvalidOpcode(decoded.opcode)),
+                }
+            }
+            DecodedInstruction::SType(s) => {
+                let (rs1, rs2, imm) = (s.rs1 as u32, s.rs2 as u32, s.imm);
+                match s.funct3 {
+                    0b000 => Self::Sb { rs1, rs2, imm },
+                    0b001 => Self::Sh { rs1, rs2, imm },
+                    0b010 => Self::Sw { rs1, rs2, imm },
+                    _ => return Err(VMErrors::InvalidFunct3(s.funct3)),
+                }
+            }
+            DecodedInstruction::BType(b) => {
+                let (rs1, rs2, imm) = (b.rs1 as u32, b.rs2 as u32, b.imm);
+                match b.funct3 {
+                    0b000 => Self::Beq { rs1, rs2, imm },
+                    0b001 => Self::Bne { rs1, rs2, imm },
+                    0b100 => Self::Blt { rs1, rs2, imm },
+                    0b101 => Self::Bge { rs1, rs2, imm },
+                    0b110 => Self::Bltu { rs1, rs2, imm },
+                    0b111 => Self::Bgeu { rs1, rs2, imm },
+                    _ => return Err(VMErrors::InvalidFunct3(b.funct3)),
+                }
+            }
+            DecodedInstruction::UType(u) => {
+                let (rd, imm) = (u.rd as u32, u.imm);
+                match decoded.opcode {
+                    UPPER_IMMEDIATE_CLASS => Self::Lui { rd, imm },
+                    _ => Self::Auipc { rd, imm },
+                }
+            }
+            DecodedInstruction::JType(j) => Self::Jal {
+                rd: j.rd as u32,
+                imm: j.imm,
+            },
+        };
+
+        Ok(instruction)
+    }
+
+    /// Decode and validate a raw RV64IM instruction.
+    /// Adds the 64-bit loads/stores, the `*w` word instructions and 6-bit shift amounts to what
+    /// [Instruction::decode] accepts, compressed and bit-manipulation instructions are not part of
+    /// the RV64 mode.
+    pub fn decode64(raw: u32) -> Result<Self, VMErrors> {
+        let (rd, rs1, rs2) = ((raw >> 7) & 0x1f, (raw >> 15) & 0x1f, (raw >> 20) & 0x1f);
+        let (funct3, funct7) = ((raw >> 12) & 0x7, raw >> 25);
+        let imm = (raw as i32) >> 20;
+
+        let instruction = match raw & 0x7f {
+            IMMEDIATE_LOAD_CLASS if funct3 == 0b011 => Self::Ld { rd, rs1, imm },
+            IMMEDIATE_LOAD_CLASS if funct3 == 0b110 => Self::Lwu { rd, rs1, imm },
+            STORE_CLASS if funct3 == 0b011 => Self::Sd {
+                rs1,
+                rs2,
+                imm: SType::new(raw).imm,
+            },
+            // shift amounts take 6 bits, leaving a 6-bit funct field
+            IMMEDIATE_CLASS if funct3 == 0b001 || funct3 == 0b101 => {
+                let shamt = (raw >> 20) & 0x3f;
+                match (funct3, raw >> 26) {
+                    (0b001, 0b000000) => Self::Slli { rd, rs1, shamt },
+                    (0b101, 0b000000) => Self::Srli { rd, rs1, shamt },
+                    (0b101, 0b010000) => Self::Srai { rd, rs1, shamt },
+                    _ => return Err(VMErrors::InvalidFunct7(funct7)),
+                }
+            }
+            IMMEDIATE_WORD_CLASS => match (funct3, funct7) {
+                (0b000, _) => Self::Addiw { rd, rs1, imm },
+                (0b001, 0b0000000) => Self::Slliw {
+                    rd,
+                    rs1,
+                    shamt: rs2,
+                },
+                (0b101, 0b0000000) => Self::Srliw {
+                    rd,
+                    rs1,
+                    shamt: rs2,
+                },
+                (0b101, 0b0100000) => Self::Sraiw {
+                    rd,
+                    rs1,
+                    shamt: rs2,
+                },
+                (0b001 | 0b101, _) => return Err(VMErrors::InvalidFunct7(funct7)),
+                _ => return Err(VMErrors::InvalidFunct3(funct3)),
+            },
+            REGISTER_WORD_CLASS => match (funct3, funct7) {
+                (0b000, 0b0000000) => Self::Addw { rd, rs1, rs2 },
+                (0b000, 0b0100000) => Self::Subw { rd, rs1, rs2 },
+                (0b001, 0b0000000) => Self::Sllw { rd, rs1, rs2 },
+                (0b101, 0b0000000) => Self::Srlw { rd, rs1, rs2 },
+                (0b101, 0b0100000) => Self::Sraw { rd, rs1, rs2 },
+                (0b000, 0b0000001) => Self::Mulw { rd, rs1, rs2 },
+                (0b100, 0b0000001) => Self::Divw { rd, rs1, rs2 },
+                (0b101, 0b0000001) => Self::Divuw { rd, rs1, rs2 },
+                (0b110, 0b0000001) => Self::Remw { rd, rs1, rs2 },
+                (0b111, 0b0000001) => Self::Remuw { rd, rs1, rs2 },
+                _ => return Err(VMErrors::InvalidFunct7(funct7)),
+            },
+            _ if instruction_size(raw) != WORD_SIZE as u32 => {
+                return Err(VMErrors::InvalidInstruction);
+            }
+            _ => Self::decode(raw)?,
+        };
+
+        Ok(instruction)
+    }
+
+    /// Decode a raw instruction of either size for `profile`, RV32E code may only name `x0`-`x15`
+    pub fn decode_for(raw: u32, profile: Profile) -> Result<Self, VMErrors> {
+        let instruction = Self::decode_any(raw)?;
+        match instruction
+            .registers()
+            .into_iter()
+            .find(|&register| register >= profile.register_count())
+        {
+            Some(register) => Err(VMErrors::InvalidRegister(register)),
+            None => Ok(instruction),
+        }
+    }
+
+    /// Decode a raw instruction of either size, `raw` holds a 16-bit compressed instruction in its
+    /// low half when its two lowest bits are not `0b11` (see [instruction_size])
+    pub fn decode_any(raw: u32) -> Result<Self, VMErrors> {
+        if instruction_size(raw) == COMPRESSED_INSTRUCTION_SIZE {
+            Self::decode_compressed(raw as u16)
+        } else {
+            Self::decode(raw)
+        }
+    }
+
+    /// Decode and validate a 16-bit RV32C instruction into the instruction it expands to.
+    /// Floating point loads/stores, reserved encodings and the RV64/RV128-only encodings are
+    /// rejected.
+    pub fn decode_compressed(parcel: u16) -> Result<Self, VMErrors> {
+        let raw = parcel as u32;
+        let bits = |hi: u32, lo: u32| (raw >> lo) & ((1 << (hi - lo + 1)) - 1);
+        // sign-extends the lowest `width` bits of `value`
+        let signed = |value: u32, width: u32| ((value << (32 - width)) as i32) >> (32 - width);
+        // 3-bit register fields only address x8-x15
+        let (rd_prime, rs1_prime) = (bits(4, 2) + 8, bits(9, 7) + 8);
+        let (rd, rs2) = (bits(11, 7), bits(6, 2));
+        let imm6 = signed(bits(12, 12) << 5 | bits(6, 2), 6);
+        let funct3 = bits(15, 13);
+
+        let instruction = match (raw & 0b11, funct3) {
+            // c.addi4spn
+            (0b00, 0b000) => {
+                let imm = bits(12, 11) << 4 | bits(10, 7) << 6 | bits(6, 6) << 2 | bits(5, 5) << 3;
+                if imm == 0 {
+                    return Err(VMErrors::InvalidInstruction);
+                }
+                Self::Addi {
+                    rd: rd_prime,
+                    rs1: 2,
+                    imm: imm as i32,
+                }
+            }
+            // c.lw / c.sw
+            (0b00, 0b010 | 0b110) => {
+                let imm = (bits(12, 10) << 3 | bits(6, 6) << 2 | bits(5, 5) << 6) as i32;
+                if funct3 == 0b010 {
+                    Self::Lw {
+                        rd: rd_prime,
+                        rs1: rs1_prime,
+                        imm,
+                    }
+                } else {
+                    Self::Sw {
+                        rs1: rs1_prime,
+                        rs2: rd_prime,
+                        imm,
+                    }
+                }
+            }
+            // c.addi (c.nop when rd is zero) / c.li
+            (0b01, 0b000) => Self::Addi {
+                rd,
+                rs1: rd,
+                imm: imm6,
+            },
+            (0b01, 0b010) => Self::Addi {
+                rd,
+                rs1: 0,
+                imm: imm6,
+            },
+            // c.jal / c.j
+            (0b01, 0b001 | 0b101) => {
+                let imm = bits(12, 12) << 11
+                    | bits(11, 11) << 4
+                    | bits(10, 9) << 8
+                    | bits(8, 8) << 10
+                    | bits(7, 7) << 6
+                    | bits(6, 6) << 7
+                    | bits(5, 3) << 1
+                    | bits(2, 2) << 5;
+                Self::Jal {
+                    rd: (funct3 == 0b001) as u32,
+                    imm: signed(imm, 12),
+                }
+            }
+            // c.addi16sp
+            (0b01, 0b011) if rd == 2 => {
+                let imm = bits(12, 12) << 9
+                    | bits(6, 6) << 4
+                    | bits(5, 5) << 6
+                    | bits(4, 3) << 7
+                    | bits(2, 2) << 5;
+                if imm == 0 {
+                    return Err(VMErrors::InvalidInstruction);
+                }
+                Self::Addi {
+                    rd: 2,
+                    rs1: 2,
+                    imm: signed(imm, 10),
+                }
+            }
+            // c.lui
+            (0b01, 0b011) => {
+                if imm6 == 0 {
+                    return Err(VMErrors::InvalidInstruction);
+                }
+                Self::Lui {
+                    rd,
+                    imm: imm6 << 12,
+                }
+            }
+            (0b01, 0b100) => {
+                let rd = rs1_prime;
+                match bits(11, 10) {
+                    // c.srli / c.srai, shift amounts of 32 and above are RV64 only
+                    0b00 | 0b01 if bits(12, 12) != 0 => return Err(VMErrors::InvalidInstruction),
+                    0b00 => Self::Srli {
+                        rd,
+                        rs1: rd,
+                        shamt: rs2,
+                    },
+                    0b01 => Self::Srai {
+                        rd,
+                        rs1: rd,
+                        shamt: rs2,
+                    },
+                    0b10 => Self::Andi {
+                        rd,
+                        rs1: rd,
+                        imm: imm6,
+                    },
+                    // c.subw / c.addw are RV64 only
+                    _ if bits(12, 12) != 0 => return Err(VMErrors::InvalidInstruction),
+                    _ => {
+                        let rs2 = rd_prime;
+                        match bits(6, 5) {
+                            0b00 => Self::Sub { rd, rs1: rd, rs2 },
+                            0b01 => Self::Xor { rd, rs1: rd, rs2 },
+                            0b10 => Self::Or { rd, rs1: rd, rs2 },
+                            _ => Self::And { rd, rs1: rd, rs2 },
+                        }
+                    }
+                }
+            }
+            // c.beqz / c.bnez
+            (0b01, 0b110 | 0b111) => {
+                let imm = bits(12, 12) << 8
+                    | bits(11, 10) << 3
+                    | bits(6, 5) << 6
+                    | bits(4, 3) << 1
+                    | bits(2, 2) << 5;
+                let (rs1, imm) = (rs1_prime, signed(imm, 9));
+                if funct3 == 0b110 {
+                    Self::Beq { rs1, rs2: 0, imm }
+                } else {
+                    Self::Bne { rs1, rs2: 0, imm }
+                }
+            }
+            // c.slli
+            (0b10, 0b000) => {
+                if bits(12, 12) != 0 {
+                    return Err(VMErrors::InvalidInstruction);
+                }
+                Self::Slli {
+                    rd,
+                    rs1: rd,
+                    shamt: rs2,
+                }
+            }
+            // c.lwsp
+            (0b10, 0b010) => {
+                if rd == 0 {
+                    return Err(VMErrors::InvalidInstruction);
+                }
+                Self::Lw {
+                    rd,
+                    rs1: 2,
+                    imm: (bits(12, 12) << 5 | bits(6, 4) << 2 | bits(3, 2) << 6) as i32,
+                }
+            }
+            (0b10, 0b100) => match (bits(12, 12), rd, rs2) {
+                // c.jr
+                (0, 0, 0) => return Err(VMErrors::InvalidInstruction),
+                (0, rs1, 0) => Self::Jalr { rd: 0, rs1, imm: 0 },
+                // c.mv
+                (0, rd, rs2) => Self::Add { rd, rs1: 0, rs2 },
+                // c.ebreak
+                (_, 0, 0) => Self::decode(EBREAK)?,
+                // c.jalr
+                (_, rs1, 0) => Self::Jalr { rd: 1, rs1, imm: 0 },
+                // c.add
+                (_, rd, rs2) => Self::Add { rd, rs1: rd, rs2 },
+            },
+            // c.swsp
+            (0b10, 0b110) => Self::Sw {
+                rs1: 2,
+                rs2,
+                imm: (bits(12, 9) << 2 | bits(8, 7) << 6) as i32,
+            },
+            _ => return Err(VMErrors::InvalidInstruction),
+        };
+
+        Ok(instruction)
+    }
+
+    /// The register this instruction writes, if any
+    pub fn rd(&self) -> Option<u32> {
+        match *self {
+            Self::Add { rd, .. }
+            | Self::Sub { rd, .. }
+            | Self::Sll { rd, .. }
+            | Self::Slt { rd, .. }
+            | Self::Sltu { rd, .. }
+            | Self::Xor { rd, .. }
+            | Self::Srl { rd, .. }
+            | Self::Sra { rd, .. }
+            | Self::Or { rd, .. }
+            | Self::And { rd, .. }
+            | Self::Mul { rd, .. }
+            | Self::Mulh { rd, .. }
+            | Self::Mulhsu { rd, .. }
+            | Self::Mulhu { rd, .. }
+            | Self::Div { rd, .. }
+            | Self::Divu { rd, .. }
+            | Self::Rem { rd, .. }
+            | Self::Remu { rd, .. }
+            | Self::Sh1add { rd, .. }
+            | Self::Sh2add { rd, .. }
+            | Self::Sh3add { rd, .. }
+            | Self::Andn { rd, .. }
+            | Self::Orn { rd, .. }
+            | Self::Xnor { rd, .. }
+            | Self::Max { rd, .. }
+            | Self::Maxu { rd, .. }
+            | Self::Min { rd, .. }
+            | Self::Minu { rd, .. }
+            | Self::Rol { rd, .. }
+            | Self::Ror { rd, .. }
+            | Self::Clmul { rd, .. }
+            | Self::Clmulh { rd, .. }
+            | Self::Clmulr { rd, .. }
+            | Self::Pack { rd, .. }
+            | Self::Packh { rd, .. }
+            | Self::Clz { rd, .. }
+            | Self::Ctz { rd, .. }
+            | Self::Cpop { rd, .. }
+            | Self::SextB { rd, .. }
+            | Self::SextH { rd, .. }
+            | Self::OrcB { rd, .. }
+            | Self::Rev8 { rd, .. }
+            | Self::Brev8 { rd, .. }
+            | Self::Zip { rd, .. }
+            | Self::Unzip { rd, .. }
+            | Self::Rori { rd, .. }
+            | Self::Ld { rd, .. }
+            | Self::Lwu { rd, .. }
+            | Self::Addiw { rd, .. }
+            | Self::Slliw { rd, .. }
+            | Self::Srliw { rd, .. }
+            | Self::Sraiw { rd, .. }
+            | Self::Addw { rd, .. }
+            | Self::Subw { rd, .. }
+            | Self::Sllw { rd, .. }
+            | Self::Srlw { rd, .. }
+            | Self::Sraw { rd, .. }
+            | Self::Mulw { rd, .. }
+            | Self::Divw { rd, .. }
+            | Self::Divuw { rd, .. }
+            | Self::Remw { rd, .. }
+            | Self::Remuw { rd, .. }
+            | Self::Addi { rd, .. }
+            | Self::Slti { rd, .. }
+            | Self::Sltiu { rd, .. }
+            | Self::Xori { rd, .. }
+            | Self::Ori { rd, .. }
+            | Self::Andi { rd, .. }
+            | Self::Slli { rd, .. }
+            | Self::Srli { rd, .. }
+            | Self::Srai { rd, .. }
+            | Self::Lb { rd, .. }
+            | Self::Lh { rd, .. }
+            | Self::Lw { rd, .. }
+            | Self::Lbu { rd, .. }
+            | Self::Lhu { rd, .. }
+            | Self::Lui { rd, .. }
+            | Self::Auipc { rd, .. }
+            | Self::Jal { rd, .. }
+            | Self::Jalr { rd, .. }
+            | Self::Csrrw { rd, .. }
+            | Self::Csrrs { rd, .. }
+            | Self::Csrrc { rd, .. }
+            | Self::Csrrwi { rd, .. }
+            | Self::Csrrsi { rd, .. }
+            | Self::Csrrci { rd, .. } => Some(rd),
+            Self::Sb { .. }
+            | Self::Sh { .. }
+            | Self::Sw { .. }
+            | Self::Sd { .. }
+            | Self::Beq { .. }
+            | Self::Bne { .. }
+            | Self::Blt { .. }
+            | Self::Bge { .. }
+            | Self::Bltu { .. }
+            | Self::Bgeu { .. }
+            | Self::Ecall
+            | Self::Ebreak
+            | Self::Wfi
+            | Self::Fence
+            | Self::FenceI => None,
+        }
+    }
+
+    /// The registers this instruction names, as `[rd, rs1, rs2]` with `zero` standing in for the
+    /// ones it does not have
+    pub fn registers(&self) -> [u32; 3] {
+        match *self {
+            Self::Add { rd, rs1, rs2 }
+            | Self::Sub { rd, rs1, rs2 }
+            | Self::Sll { rd, rs1, rs2 }
+            | Self::Slt { rd, rs1, rs2 }
+            | Self::Sltu { rd, rs1, rs2 }
+            | Self::Xor { rd, rs1, rs2 }
+            | Self::Srl { rd, rs1, rs2 }
+            | Self::Sra { rd, rs1, rs2 }
+            | Self::Or { rd, rs1, rs2 }
+            | Self::And { rd, rs1, rs2 }
+            | Self::Mul { rd, rs1, rs2 }
+            | Self::Mulh { rd, rs1, rs2 }
+            | Self::Mulhsu { rd, rs1, rs2 }
+            | Self::Mulhu { rd, rs1, rs2 }
+            | Self::Div { rd, rs1, rs2 }
+            | Self::Divu { rd, rs1, rs2 }
+            | Self::Rem { rd, rs1, rs2 }
+            | Self::Remu { rd, rs1, rs2 }
+            | Self::Sh1add { rd, rs1, rs2 }
+            | Self::Sh2add { rd, rs1, rs2 }
+            | Self::Sh3add { rd, rs1, rs2 }
+            | Self::Andn { rd, rs1, rs2 }
+            | Self::Orn { rd, rs1, rs2 }
+            | Self::Xnor { rd, rs1, rs2 }
+            | Self::Max { rd, rs1, rs2 }
+            | Self::Maxu { rd, rs1, rs2 }
+            | Self::Min { rd, rs1, rs2 }
+            | Self::Minu { rd, rs1, rs2 }
+            | Self::Rol { rd, rs1, rs2 }
+            | Self::Ror { rd, rs1, rs2 }
+            | Self::Clmul { rd, rs1, rs2 }
+            | Self::Clmulh { rd, rs1, rs2 }
+            | Self::Clmulr { rd, rs1, rs2 }
+            | Self::Pack { rd, rs1, rs2 }
+            | Self::Packh { rd, rs1, rs2 }
+            | Self::Addw { rd, rs1, rs2 }
+            | Self::Subw { rd, rs1, rs2 }
+            | Self::Sllw { rd, rs1, rs2 }
+            | Self::Srlw { rd, rs1, rs2 }
+            | Self::Sraw { rd, rs1, rs2 }
+            | Self::Mulw { rd, rs1, rs2 }
+            | Self::Divw { rd, rs1, rs2 }
+            | Self::Divuw { rd, rs1, rs2 }
+            | Self::Remw { rd, rs1, rs2 }
+            | Self::Remuw { rd, rs1, rs2 } => [rd, rs1, rs2],
+            Self::Addi { rd, rs1, .. }
+            | Self::Slti { rd, rs1, .. }
+            | Self::Sltiu { rd, rs1, .. }
+            | Self::Xori { rd, rs1, .. }
+            | Self::Ori { rd, rs1, .. }
+            | Self::Andi { rd, rs1, .. }
+            | Self::Addiw { rd, rs1, .. }
+            | Self::Slli { rd, rs1, .. }
+            | Self::Srli { rd, rs1, .. }
+            | Self::Srai { rd, rs1, .. }
+            | Self::Rori { rd, rs1, .. }
+            | Self::Slliw { rd, rs1, .. }
+            | Self::Srliw { rd, rs1, .. }
+            | Self::Sraiw { rd, rs1, .. }
+            | Self::Clz { rd, rs1 }
+            | Self::Ctz { rd, rs1 }
+            | Self::Cpop { rd, rs1 }
+            | Self::SextB { rd, rs1 }
+            | Self::SextH { rd, rs1 }
+            | Self::OrcB { rd, rs1 }
+            | Self::Rev8 { rd, rs1 }
+            | Self::Brev8 { rd, rs1 }
+            | Self::Zip { rd, rs1 }
+            | Self::Unzip { rd, rs1 }
+            | Self::Lb { rd, rs1, .. }
+            | Self::Lh { rd, rs1, .. }
+            | Self::Lw { rd, rs1, .. }
+            | Self::Lbu { rd, rs1, .. }
+            | Self::Lhu { rd, rs1, .. }
+            | Self::Ld { rd, rs1, .. }
+            | Self::Lwu { rd, rs1, .. }
+            | Self::Jalr { rd, rs1, .. }
+            | Self::Csrrw { rd, rs1, .. }
+            | Self::Csrrs { rd, rs1, .. }
+            | Self::Csrrc { rd, rs1, .. } => [rd, rs1, 0],
+            Self::Sb { rs1, rs2, .. }
+            | Self::Sh { rs1, rs2, .. }
+            | Self::Sw { rs1, rs2, .. }
+            | Self::Sd { rs1, rs2, .. }
+            | Self::Beq { rs1, rs2, .. }
+            | Self::Bne { rs1, rs2, .. }
+            | Self::Blt { rs1, rs2, .. }
+            | Self::Bge { rs1, rs2, .. }
+            | Self::Bltu { rs1, rs2, .. }
+            | Self::Bgeu { rs1, rs2, .. } => [0, rs1, rs2],
+            Self::Lui { rd, .. }
+            | Self::Auipc { rd, .. }
+            | Self::Jal { rd, .. }
+            | Self::Csrrwi { rd, .. }
+            | Self::Csrrsi { rd, .. }
+            | Self::Csrrci { rd, .. } => [rd, 0, 0],
+            Self::Ecall | Self::Ebreak | Self::Wfi | Self::Fence | Self::FenceI => [0, 0, 0],
+        }
+    }
+
+    /// The assembly mnemonic of this instruction
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Self::Add { .. } => "add",
+            Self::Sub { .. } => "sub",
+            Self::Sll { .. } => "sll",
+            Self::Slt { .. } => "slt",
+            Self::Sltu { .. } => "sltu",
+            Self::Xor { .. } => "xor",
+            Self::Srl { .. } => "srl",
+            Self::Sra { .. } => "sra",
+            Self::Or { .. } => "or",
+            Self::And { .. } => "and",
+            Self::Mul { .. } => "mul",
+            Self::Mulh { .. } => "mulh",
+            Self::Mulhsu { .. } => "mulhsu",
+            Self::Mulhu { .. } => "mulhu",
+            Self::Div { .. } => "div",
+            Self::Divu { .. } => "divu",
+            Self::Rem { .. } => "rem",
+            Self::Remu { .. } => "remu",
+            Self::Sh1add { .. } => "sh1add",
+            Self::Sh2add { .. } => "sh2add",
+            Self::Sh3add { .. } => "sh3add",
+            Self::Andn { .. } => "andn",
+            Self::Orn { .. } => "orn",
+            Self::Xnor { .. } => "xnor",
+            Self::Max { .. } => "max",
+            Self::Maxu { .. } => "maxu",
+            Self::Min { .. } => "min",
+            Self::Minu { .. } => "minu",
+            Self::Rol { .. } => "rol",
+            Self::Ror { .. } => "ror",
+            Self::Clmul { .. } => "clmul",
+            Self::Clmulh { .. } => "clmulh",
+            Self::Clmulr { .. } => "clmulr",
+            Self::Pack { .. } => "pack",
+            Self::Packh { .. } => "packh",
+            Self::Clz { .. } => "clz",
+            Self::Ctz { .. } => "ctz",
+            Self::Cpop { .. } => "cpop",
+            Self::SextB { .. } => "sext.b",
+            Self::SextH { .. } => "sext.h",
+            Self::OrcB { .. } => "orc.b",
+            Self::Rev8 { .. } => "rev8",
+            Self::Brev8 { .. } => "brev8",
+            Self::Zip { .. } => "zip",
+            Self::Unzip { .. } => "unzip",
+            Self::Rori { .. } => "rori",
+            Self::Ld { .. } => "ld",
+            Self::Lwu { .. } => "lwu",
+            Self::Sd { .. } => "sd",
+            Self::Addiw { .. } => "addiw",
+            Self::Slliw { .. } => "slliw",
+            Self::Srliw { .. } => "srliw",
+            Self::Sraiw { .. } => "sraiw",
+            Self::Addw { .. } => "addw",
+            Self::Subw { .. } => "subw",
+            Self::Sllw { .. } => "sllw",
+            Self::Srlw { .. } => "srlw",
+            Self::Sraw { .. } => "sraw",
+            Self::Mulw { .. } => "mulw",
+            Self::Divw { .. } => "divw",
+            Self::Divuw { .. } => "divuw",
+            Self::Remw { .. } => "remw",
+            Self::Remuw { .. } => "remuw",
+            Self::Addi { .. } => "addi",
+            Self::Slti { .. } => "slti",
+            Self::Sltiu { .. } => "sltiu",
+            Self::Xori { .. } => "xori",
+            Self::Ori { .. } => "ori",
+            Self::Andi { .. } => "andi",
+            Self::Slli { .. } => "slli",
+            Self::Srli { .. } => "srli",
+            Self::Srai { .. } => "srai",
+            Self::Lb { .. } => "lb",
+            Self::Lh { .. } => "lh",
+            Self::Lw { .. } => "lw",
+            Self::Lbu { .. } => "lbu",
+            Self::Lhu { .. } => "lhu",
+            Self::Sb { .. } => "sb",
+            Self::Sh { .. } => "sh",
+            Self::Sw { .. } => "sw",
+            Self::Beq { .. } => "beq",
+            Self::Bne { .. } => "bne",
+            Self::Blt { .. } => "blt",
+            Self::Bge { .. } => "bge",
+            Self::Bltu { .. } => "bltu",
+            Self::Bgeu { .. } => "bgeu",
+            Self::Lui { .. } => "lui",
+            Self::Auipc { .. } => "auipc",
+            Self::Jal { .. } => "jal",
+            Self::Jalr { .. } => "jalr",
+            Self::Ecall => "ecall",
+            Self::Ebreak => "ebreak",
+            Self::Wfi => "wfi",
+            Self::Fence => "fence",
+            Self::FenceI => "fence.i",
+            Self::Csrrw { .. } => "csrrw",
+            Self::Csrrs { .. } => "csrrs",
+            Self::Csrrc { .. } => "csrrc",
+            Self::Csrrwi { .. } => "csrrwi",
+            Self::Csrrsi { .. } => "csrrsi",
+            Self::Csrrci { .. } => "csrrci",
+        }
+    }
+}
+
+/// Renders the instruction as assembly with ABI register names, branch and jump offsets are printed
+/// relative to the instruction (see [crate::disassembler] for absolute targets and ecall names)
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mnemonic = self.mnemonic();
+        match *self {
+            Self::Add { rd, rs1, rs2 }
+            | Self::Sub { rd, rs1, rs2 }
+            | Self::Sll { rd, rs1, rs2 }
+            | Self::Slt { rd, rs1, rs2 }
+            | Self::Sltu { rd, rs1, rs2 }
+            | Self::Xor { rd, rs1, rs2 }
+            | Self::Srl { rd, rs1, rs2 }
+            | Self::Sra { rd, rs1, rs2 }
+            | Self::Or { rd, rs1, rs2 }
+            | Self::And { rd, rs1, rs2 }
+            | Self::Mul { rd, rs1, rs2 }
+            | Self::Mulh { rd, rs1, rs2 }
+            | Self::Mulhsu { rd, rs1, rs2 }
+            | Self::Mulhu { rd, rs1, rs2 }
+            | Self::Div { rd, rs1, rs2 }
+            | Self::Divu { rd, rs1, rs2 }
+            | Self::Rem { rd, rs1, rs2 }
+            | Self::Remu { rd, rs1, rs2 }
+            | Self::Sh1add { rd, rs1, rs2 }
+            | Self::Sh2add { rd, rs1, rs2 }
+            | Self::Sh3add { rd, rs1, rs2 }
+            | Self::Andn { rd, rs1, rs2 }
+            | Self::Orn { rd, rs1, rs2 }
+            | Self::Xnor { rd, rs1, rs2 }
+            | Self::Max { rd, rs1, rs2 }
+            | Self::Maxu { rd, rs1, rs2 }
+            | Self::Min { rd, rs1, rs2 }
+            | Self::Minu { rd, rs1, rs2 }
+            | Self::Rol { rd, rs1, rs2 }
+            | Self::Ror { rd, rs1, rs2 }
+            | Self::Clmul { rd, rs1, rs2 }
+            | Self::Clmulh { rd, rs1, rs2 }
+            | Self::Clmulr { rd, rs1, rs2 }
+            | Self::Pack { rd, rs1, rs2 }
+            | Self::Packh { rd, rs1, rs2 }
+            | Self::Addw { rd, rs1, rs2 }
+            | Self::Subw { rd, rs1, rs2 }
+            | Self::Sllw { rd, rs1, rs2 }
+            | Self::Srlw { rd, rs1, rs2 }
+            | Self::Sraw { rd, rs1, rs2 }
+            | Self::Mulw { rd, rs1, rs2 }
+            | Self::Divw { rd, rs1, rs2 }
+            | Self::Divuw { rd, rs1, rs2 }
+            | Self::Remw { rd, rs1, rs2 }
+            | Self::Remuw { rd, rs1, rs2 } => write!(
+                f,
+                "{} {}, {}, {}",
+                mnemonic,
+                abi_name(rd),
+                abi_name(rs1),
+                abi_name(rs2)
+            ),
+            Self::Addi { rd, rs1, imm }
+            | Self::Slti { rd, rs1, imm }
+            | Self::Sltiu { rd, rs1, imm }
+            | Self::Xori { rd, rs1, imm }
+            | Self::Ori { rd, rs1, imm }
+            | Self::Andi { rd, rs1, imm }
+            | Self::Addiw { rd, rs1, imm } => {
+                write!(
+                    f,
+                    "{} {}, {}, {}",
+                    mnemonic,
+                    abi_name(rd),
+                    abi_name(rs1),
+                    imm
+                )
+            }
+            Self::Slli { rd, rs1, shamt }
+            | Self::Srli { rd, rs1, shamt }
+            | Self::Srai { rd, rs1, shamt }
+            | Self::Rori { rd, rs1, shamt }
+            | Self::Slliw { rd, rs1, shamt }
+            | Self::Srliw { rd, rs1, shamt }
+            | Self::Sraiw { rd, rs1, shamt } => {
+                write!(
+                    f,
+                    "{} {}, {}, {}",
+                    mnemonic,
+                    abi_name(rd),
+                    abi_name(rs1),
+                    shamt
+                )
+            }
+            Self::Clz { rd, rs1 }
+            | Self::Ctz { rd, rs1 }
+            | Self::Cpop { rd, rs1 }
+            | Self::SextB { rd, rs1 }
+            | Self::SextH { rd, rs1 }
+            | Self::OrcB { rd, rs1 }
+            | Self::Rev8 { rd, rs1 }
+            | Self::Brev8 { rd, rs1 }
+            | Self::Zip { rd, rs1 }
+            | Self::Unzip { rd, rs1 } => {
+                write!(f, "{} {}, {}", mnemonic, abi_name(rd), abi_name(rs1))
+            }
+            Self::Lb { rd, rs1, imm }
+            | Self::Lh { rd, rs1, imm }
+            | Self::Lw { rd, rs1, imm }
+            | Self::Lbu { rd, rs1, imm }
+            | Self::Lhu { rd, rs1, imm }
+            | Self::Ld { rd, rs1, imm }
+            | Self::Lwu { rd, rs1, imm }
+            | Self::Jalr { rd, rs1, imm } => {
+                write!(
+                    f,
+                    "{} {}, {}({})",
+                    mnemonic,
+                    abi_name(rd),
+                    imm,
+                    abi_name(rs1)
+                )
+            }
+            Self::Sb { rs1, rs2, imm }
+            | Self::Sh { rs1, rs2, imm }
+            | Self::Sw { rs1, rs2, imm }
+            | Self::Sd { rs1, rs2, imm } => {
+                write!(
+                    f,
+                    "{} {}, {}({})",
+                    mnemonic,
+                    abi_name(rs2),
+                    imm,
+                    abi_name(rs1)
+                )
+            }
+            Self::Beq { rs1, rs2, imm }
+            | Self::Bne { rs1, rs2, imm }
+            | Self::Blt { rs1, rs2, imm }
+            | Self::Bge { rs1, rs2, imm }
+            | Self::Bltu { rs1, rs2, imm }
+            | Self::Bgeu { rs1, rs2, imm } => {
+                write!(
+                    f,
+                    "{} {}, {}, {}",
+                    mnemonic,
+                    abi_name(rs1),
+                    abi_name(rs2),
+                    imm
+                )
+            }
+            Self::Lui { rd, imm } | Self::Auipc { rd, imm } => {
+                write!(
+                    f,
+                    "{} {}, {:#x}",
+                    mnemonic,
+                    abi_name(rd),
+                    (imm as u32) >> 12
+                )
+            }
+            Self::Jal { rd, imm } => write!(f, "{} {}, {}", mnemonic, abi_name(rd), imm),
+            Self::Ecall | Self::Ebreak | Self::Wfi | Self::Fence | Self::FenceI => {
+                f.write_str(mnemonic)
+            }
+            Self::Csrrw { rd, rs1, csr }
+            | Self::Csrrs { rd, rs1, csr }
+            | Self::Csrrc { rd, rs1, csr } => {
+                write!(
+                    f,
+                    "{} {}, {}, {}",
+                    mnemonic,
+                    abi_name(rd),
+                    csr_name(csr),
+                    abi_name(rs1)
+                )
+            }
+            Self::Csrrwi { rd, uimm, csr }
+            | Self::Csrrsi { rd, uimm, csr }
+            | Self::Csrrci { rd, uimm, csr } => {
+                write!(
+                    f,
+                    "{} {}, {}, {}",
+                    mnemonic,
+                    abi_name(rd),
+                    csr_name(csr),
+                    uimm
+                )
+            }
+        }
+    }
+}
@@ -0,0 +1,933 @@
+//! # JIT
+//! Translates hot basic blocks of a [DecodedProgram] to native x86-64 code (`jit` feature).
+//! A block starting at a basic block leader is counted every time the interpreter reaches it and
+//! compiled once it ran [JIT_THRESHOLD] times, from then on `Vm::step` runs the whole block natively.
+//!
+//! Native code works on a copy of the Vm's registers (see [NativeBlock::run]) and covers the ALU instructions (RV32I and the
+//! multiplications of RV32M), `lui`/`auipc`, branches and `jal`. Loads and stores call back into
+//! the same helpers the interpreter uses. Everything else (ecalls, `jalr`, divisions, the
+//! bit-manipulation extensions) ends the block and is executed by the interpreter, so ecalls still
+//! reach the host, see [crate::host].
+//! A load or store that fails also leaves native code, right before the faulting instruction, and
+//! the interpreter executes it again to report the exact error.
+//!
+//! Gas is metered per block: the whole block is paid for before it runs, if there is not enough gas
+//! left the interpreter runs it instruction by instruction instead and fails at the exact
+//! instruction that runs out.
+//!
+//! Native code and the load/store helpers share the register copy through one raw pointer and the
+//! helpers only borrow the Vm for its memory, segments and hook, so no `&mut Vm` ever aliases a
+//! pointer native code holds. This is not checked under Miri: Miri cannot execute the mmap'd
+//! machine code nor the calls it makes into the helpers, the guest memory is a multi-GiB
+//! allocation it could not run through in reasonable time, and the `miri` component is not
+//! shipped for the stable toolchain this crate builds with.
+#[cfg(not(all(target_arch = "x86_64", unix)))]
+compile_error!("the `jit` feature only supports x86-64 unix targets");
+
+use std::{
+    fmt,
+    ptr::{self, NonNull},
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use hashbrown::HashMap;
+use riscv_evm_core::{MemoryChuckSize, WORD_SIZE};
+
+use crate::{
+    code_cache::DecodedProgram,
+    context::Context,
+    instructions::{Instruction, instruction_size},
+    utils::{load_from_memory, store_to_memory},
+    vm::{VMErrors, Vm},
+};
+
+/// Number of times the interpreter runs a block before it is compiled
+pub const JIT_THRESHOLD: u32 = 16;
+/// Longest run of instructions compiled into a single native block
+const MAX_BLOCK_INSTRUCTIONS: usize = 256;
+
+/// `extern "C" fn(registers, frame) -> (retired << 32) | next_pc`
+type BlockFn = unsafe extern "C" fn(*mut u32, *mut JitFrame) -> u64;
+
+/// What native code hands back to the load/store helpers.
+/// `registers` is the same pointer native code works on, `vm` is only used for memory, segments
+/// and the hook (its own registers are stale while the block runs).
+struct JitFrame {
+    vm: *mut Vm,
+    registers: *mut u32,
+}
+
+impl JitFrame {
+    /// # Safety
+    /// `registers` must point to the 32 live guest registers
+    unsafe fn read_reg(&self, reg: u32) -> u32 {
+        unsafe { *self.registers.add(reg as usize) }
+    }
+
+    /// # Safety
+    /// see [JitFrame::read_reg]
+    unsafe fn write_reg(&self, reg: u32, value: u32) {
+        if reg != 0 {
+            unsafe { *self.registers.add(reg as usize) = value };
+        }
+    }
+}
+
+enum BlockState {
+    /// Times the interpreter ran the block so far
+    Counting(u32),
+    Compiled(Arc<NativeBlock>),
+    /// The block starts with an instruction native code does not cover
+    Unsupported,
+}
+
+/// Native blocks of a [DecodedProgram] by start address, shared by every Vm running the program
+#[derive(Default)]
+pub struct JitBlocks {
+    blocks: Mutex<HashMap<u32, BlockState>>,
+}
+
+/// Native code is tied to the program it was compiled from, a cloned program starts over
+impl Clone for JitBlocks {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl fmt::Debug for JitBlocks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JitBlocks")
+            .field("compiled", &self.compiled())
+            .finish()
+    }
+}
+
+impl JitBlocks {
+    /// Number of blocks compiled to native code
+    pub fn compiled(&self) -> usize {
+        self.blocks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .values()
+            .filter(|state| matches!(state, BlockState::Compiled(_)))
+            .count()
+    }
+
+    /// Returns the native block starting at `pc`, compiling it once it is hot
+    fn lookup(&self, program: &DecodedProgram, pc: u32) -> Option<Arc<NativeBlock>> {
+        let mut blocks = self.blocks.lock().unwrap_or_else(PoisonError::into_inner);
+        let state = blocks.entry(pc).or_insert(BlockState::Counting(0));
+
+        match state {
+            BlockState::Compiled(block) => Some(block.clone()),
+            BlockState::Unsupported => None,
+            BlockState::Counting(count) if *count + 1 < JIT_THRESHOLD => {
+                *count += 1;
+                None
+            }
+            BlockState::Counting(_) => match compile_block(program, pc) {
+                Some(block) => {
+                    let block = Arc::new(block);
+                    *state = BlockState::Compiled(block.clone());
+                    Some(block)
+                }
+                None => {
+                    *state = BlockState::Unsupported;
+                    None
+                }
+            },
+        }
+    }
+}
+
+/// Runs the native block starting at the Vm's pc.
+/// Returns `None` when the interpreter has to execute the next instruction instead: the pc is not
+/// the start of a hot block, the block is not supported, or there is not enough gas for all of it.
+pub fn execute_block(
+    vm: &mut Vm,
+    program: &DecodedProgram,
+    context: &mut Context,
+) -> Option<Result<bool, VMErrors>> {
+    program.block_at(vm.pc)?;
+    let block = program.jit.lookup(program, vm.pc)?;
+
+    let cost = context.instruction_cost * block.instructions;
+    if context.gas.remaining() < cost {
+        return None;
+    }
+
+    let exit = block.run(vm);
+    let next_pc = exit as u32;
+    let retired = exit >> 32;
+    if retired == 0 {
+        // faulted on the first instruction
+        return None;
+    }
+
+    vm.pc = next_pc;
+    vm.instret += retired;
+    Some(
+        context
+            .charge_gas(context.instruction_cost * retired)
+            .map(|_| true),
+    )
+}
+
+/// A basic block compiled to native code
+pub struct NativeBlock {
+    code: ExecutableBuffer,
+    /// Number of instructions the block retires when it runs to the end
+    pub instructions: u64,
+}
+
+impl NativeBlock {
+    /// Runs the block on a copy of the registers that is written back afterwards (two 128-byte
+    /// copies per block), so the registers native code writes through a raw pointer are never part
+    /// of the `Vm` the helpers borrow.
+    fn run(&self, vm: &mut Vm) -> u64 {
+        let mut registers = vm.registers.clone();
+        let mut frame = JitFrame {
+            vm,
+            registers: registers.as_mut_ptr(),
+        };
+        // SAFETY: the buffer holds a complete function following the `BlockFn` ABI (see
+        // `compile_block`). Native code and the helpers reach the registers only through
+        // `frame.registers`, which outlives the call, and the Vm only through `frame.vm`.
+        let exit = unsafe {
+            let block: BlockFn = std::mem::transmute(self.code.ptr.as_ptr());
+            block(frame.registers, &mut frame)
+        };
+        vm.registers = registers;
+        exit
+    }
+}
+
+/// Read-only executable memory holding native code
+struct ExecutableBuffer {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+// SAFETY: the buffer is never written after it is made executable
+unsafe impl Send for ExecutableBuffer {}
+unsafe impl Sync for ExecutableBuffer {}
+
+impl ExecutableBuffer {
+    fn new(code: &[u8]) -> Option<Self> {
+        let len = code.len();
+        // SAFETY: a fresh private anonymous mapping, written while writable then switched to
+        // read + execute (never both writable and executable)
+        unsafe {
+            let ptr = libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            if ptr == libc::MAP_FAILED {
+                return None;
+            }
+            ptr::copy_nonoverlapping(code.as_ptr(), ptr.cast::<u8>(), len);
+            if libc::mprotect(ptr, len, libc::PROT_READ | libc::PROT_EXEC) != 0 {
+                libc::munmap(ptr, len);
+                return None;
+            }
+
+            Some(Self {
+                ptr: NonNull::new(ptr.cast())?,
+                len,
+            })
+        }
+    }
+}
+
+impl Drop for ExecutableBuffer {
+    fn drop(&mut self) {
+        // SAFETY: the mapping was created in `new` and is unmapped exactly once
+        unsafe {
+            libc::munmap(self.ptr.as_ptr().cast(), self.len);
+        }
+    }
+}
+
+/// Compiles the instructions starting at `start` up to (and including) the first branch or jump,
+/// or up to the first instruction native code does not cover.
+fn compile_block(program: &DecodedProgram, start: u32) -> Option<NativeBlock> {
+    let mut asm = Assembler::default();
+    asm.prologue();
+
+    let mut pc = start;
+    let mut retired = 0;
+    loop {
+        let Some((raw, Ok(instruction))) = program.fetch(pc) else {
+            asm.exit(pc, retired);
+            break;
+        };
+        if retired == MAX_BLOCK_INSTRUCTIONS as u32 {
+            asm.exit(pc, retired);
+            break;
+        }
+
+        let next_pc = pc.wrapping_add(instruction_size(raw));
+        if !asm.instruction(*instruction, pc, next_pc, retired) {
+            asm.exit(pc, retired);
+            break;
+        }
+        retired += 1;
+        if is_terminator(instruction) {
+            break;
+        }
+        pc = next_pc;
+    }
+
+    if retired == 0 {
+        return None;
+    }
+
+    Some(NativeBlock {
+        code: ExecutableBuffer::new(&asm.code)?,
+        instructions: retired as u64,
+    })
+}
+
+fn is_terminator(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::Beq { .. }
+            | Instruction::Bne { .. }
+            | Instruction::Blt { .. }
+            | Instruction::Bge { .. }
+            | Instruction::Bltu { .. }
+            | Instruction::Bgeu { .. }
+            | Instruction::Jal { .. }
+    )
+}
+
+fn load_kind(size: MemoryChuckSize, is_signed: bool) -> u32 {
+    let kind = match size {
+        MemoryChuckSize::BYTE => 0,
+        MemoryChuckSize::HalfWord => 1,
+        MemoryChuckSize::WordSize => 2,
+    };
+    kind | (is_signed as u32) << 2
+}
+
+fn chunk_of_kind(kind: u32) -> MemoryChuckSize {
+    match kind & 0b11 {
+        0 => MemoryChuckSize::BYTE,
+        1 => MemoryChuckSize::HalfWord,
+        _ => MemoryChuckSize::WordSize,
+    }
+}
+
+/// Load helper called from native code, `false` makes native code leave before the load
+extern "C" fn jit_load(frame: *mut JitFrame, rd: u32, rs1: u32, imm: i32, kind: u32) -> bool {
+    // SAFETY: `frame` is the frame handed to the block by `NativeBlock::run`, its Vm does not
+    // hold the registers (see `JitFrame`)
+    let frame = unsafe { &*frame };
+    let address = unsafe { frame.read_reg(rs1) }.wrapping_add(imm as u32);
+    let vm = unsafe { &mut *frame.vm };
+    match load_from_memory(vm, address, chunk_of_kind(kind), kind & 0b100 != 0) {
+        Ok(value) => {
+            unsafe { frame.write_reg(rd, value) };
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Store helper called from native code, `false` makes native code leave before the store
+extern "C" fn jit_store(frame: *mut JitFrame, rs1: u32, rs2: u32, imm: i32, kind: u32) -> bool {
+    // SAFETY: see `jit_load`
+    let frame = unsafe { &*frame };
+    let address = unsafe { frame.read_reg(rs1) }.wrapping_add(imm as u32);
+    let value = unsafe { frame.read_reg(rs2) };
+    let vm = unsafe { &mut *frame.vm };
+    if store_to_memory(vm, address, chunk_of_kind(kind), value).is_err() {
+        return false;
+    }
+
+    // a store into the code makes the rest of the native block stale, the interpreter repeats the
+    // (idempotent) store and carries on from memory
+    vm.invalidate_decoded(address, WORD_SIZE as u32);
+    vm.program.is_some()
+}
+
+/// Minimal x86-64 encoder.
+/// `rbx` holds the guest registers and `r12` the [JitFrame], `eax`, `ecx` and `edx` are scratch.
+#[derive(Default)]
+struct Assembler {
+    code: Vec<u8>,
+}
+
+impl Assembler {
+    fn emit(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    fn emit_u32(&mut self, value: u32) {
+        self.code.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn prologue(&mut self) {
+        self.emit(&[0x53]); // push rbx
+        self.emit(&[0x41, 0x54]); // push r12
+        self.emit(&[0x48, 0x83, 0xec, 0x08]); // sub rsp, 8 (keeps calls 16-byte aligned)
+        self.emit(&[0x48, 0x89, 0xfb]); // mov rbx, rdi
+        self.emit(&[0x49, 0x89, 0xf4]); // mov r12, rsi
+    }
+
+    fn epilogue(&mut self) {
+        self.emit(&[0x48, 0x83, 0xc4, 0x08]); // add rsp, 8
+        self.emit(&[0x41, 0x5c]); // pop r12
+        self.emit(&[0x5b]); // pop rbx
+        self.emit(&[0xc3]); // ret
+    }
+
+    /// `rax = (retired << 32) | eax` then return
+    fn return_eax(&mut self, retired: u32) {
+        self.emit(&[0xb9]); // mov ecx, retired
+        self.emit_u32(retired);
+        self.emit(&[0x48, 0xc1, 0xe1, 0x20]); // shl rcx, 32
+        self.emit(&[0x48, 0x09, 0xc8]); // or rax, rcx
+        self.epilogue();
+    }
+
+    /// Leave native code, continuing at `pc`
+    fn exit(&mut self, pc: u32, retired: u32) {
+        self.mov_eax(pc);
+        self.return_eax(retired);
+    }
+
+    fn mov_eax(&mut self, value: u32) {
+        self.emit(&[0xb8]);
+        self.emit_u32(value);
+    }
+
+    /// `eax = x{register}`
+    fn load_eax(&mut self, register: u32) {
+        match register {
+            0 => self.emit(&[0x31, 0xc0]),                       // xor eax, eax
+            _ => self.emit(&[0x8b, 0x43, (register * 4) as u8]), // mov eax, [rbx + 4 * register]
+        }
+    }
+
+    /// `ecx = x{register}`
+    fn load_ecx(&mut self, register: u32) {
+        match register {
+            0 => self.emit(&[0x31, 0xc9]),                       // xor ecx, ecx
+            _ => self.emit(&[0x8b, 0x4b, (register * 4) as u8]), // mov ecx, [rbx + 4 * register]
+        }
+    }
+
+    /// `x{register} = eax`, writes to `x0` are dropped
+    fn store_eax(&mut self, register: u32) {
+        if register != 0 {
+            self.emit(&[0x89, 0x43, (register * 4) as u8]); // mov [rbx + 4 * register], eax
+        }
+    }
+
+    /// `rd = rs1 <op> rs2` for a two byte `op eax, ecx` encoding
+    fn reg_op(&mut self, rd: u32, rs1: u32, rs2: u32, op: &[u8]) {
+        self.load_eax(rs1);
+        self.load_ecx(rs2);
+        self.emit(op);
+        self.store_eax(rd);
+    }
+
+    /// `rd = rs1 <op> imm` for a one byte `op eax, imm32` encoding
+    fn imm_op(&mut self, rd: u32, rs1: u32, imm: i32, op: u8) {
+        self.load_eax(rs1);
+        self.emit(&[op]);
+        self.emit_u32(imm as u32);
+        self.store_eax(rd);
+    }
+
+    fn shift_imm(&mut self, rd: u32, rs1: u32, shamt: u32, modrm: u8) {
+        self.load_eax(rs1);
+        self.emit(&[0xc1, modrm, shamt as u8]);
+        self.store_eax(rd);
+    }
+
+    /// `eax = flags match setcc`
+    fn set_eax(&mut self, setcc: u8) {
+        self.emit(&[0x0f, setcc, 0xc0]); // setcc al
+        self.emit(&[0x0f, 0xb6, 0xc0]); // movzx eax, al
+    }
+
+    /// Calls a load/store helper with `(vm, a, b, imm, kind)`, leaving native code when it fails
+    fn call_helper(&mut self, helper: usize, args: [u32; 4], pc: u32, retired: u32) {
+        self.emit(&[0x4c, 0x89, 0xe7]); // mov rdi, r12
+        self.emit(&[0xbe]); // mov esi, a
+        self.emit_u32(args[0]);
+        self.emit(&[0xba]); // mov edx, b
+        self.emit_u32(args[1]);
+        self.emit(&[0xb9]); // mov ecx, imm
+        self.emit_u32(args[2]);
+        self.emit(&[0x41, 0xb8]); // mov r8d, kind
+        self.emit_u32(args[3]);
+        self.emit(&[0x48, 0xb8]); // mov rax, helper
+        self.code.extend_from_slice(&(helper as u64).to_le_bytes());
+        self.emit(&[0xff, 0xd0]); // call rax
+        self.emit(&[0x84, 0xc0]); // test al, al
+
+        let mut fault = Assembler::default();
+        fault.exit(pc, retired);
+        self.emit(&[0x75, fault.code.len() as u8]); // jnz over the exit
+        self.emit(&fault.code);
+    }
+
+    /// Ends the block with a conditional branch, `cmovcc` picks the target when the condition holds
+    #[allow(clippy::too_many_arguments)]
+    fn branch(
+        &mut self,
+        rs1: u32,
+        rs2: u32,
+        cmovcc: u8,
+        pc: u32,
+        imm: i32,
+        next_pc: u32,
+        retired: u32,
+    ) {
+        self.load_eax(rs1);
+        self.load_ecx(rs2);
+        self.emit(&[0x39, 0xc8]); // cmp eax, ecx
+        self.mov_eax(next_pc);
+        self.emit(&[0xba]); // mov edx, target
+        self.emit_u32(pc.wrapping_add(imm as u32));
+        self.emit(&[0x0f, cmovcc, 0xc2]); // cmovcc eax, edx
+        self.return_eax(retired + 1);
+    }
+
+    /// Emits `instruction`, returns `false` when native code does not cover it
+    fn instruction(
+        &mut self,
+        instruction: Instruction,
+        pc: u32,
+        next_pc: u32,
+        retired: u32,
+    ) -> bool {
+        match instruction {
+            Instruction::Add { rd, rs1, rs2 } => self.reg_op(rd, rs1, rs2, &[0x01, 0xc8]),
+            Instruction::Sub { rd, rs1, rs2 } => self.reg_op(rd, rs1, rs2, &[0x29, 0xc8]),
+            Instruction::Xor { rd, rs1, rs2 } => self.reg_op(rd, rs1, rs2, &[0x31, 0xc8]),
+            Instruction::Or { rd, rs1, rs2 } => self.reg_op(rd, rs1, rs2, &[0x09, 0xc8]),
+            Instruction::And { rd, rs1, rs2 } => self.reg_op(rd, rs1, rs2, &[0x21, 0xc8]),
+            // x86 masks 32-bit shift counts to 5 bits, like RISC-V
+            Instruction::Sll { rd, rs1, rs2 } => self.reg_op(rd, rs1, rs2, &[0xd3, 0xe0]),
+            Instruction::Srl { rd, rs1, rs2 } => self.reg_op(rd, rs1, rs2, &[0xd3, 0xe8]),
+            Instruction::Sra { rd, rs1, rs2 } => self.reg_op(rd, rs1, rs2, &[0xd3, 0xf8]),
+            Instruction::Slt { rd, rs1, rs2 } => {
+                self.load_eax(rs1);
+                self.load_ecx(rs2);
+                self.emit(&[0x39, 0xc8]); // cmp eax, ecx
+                self.set_eax(0x9c); // setl
+                self.store_eax(rd);
+            }
+            Instruction::Sltu { rd, rs1, rs2 } => {
+                self.load_eax(rs1);
+                self.load_ecx(rs2);
+                self.emit(&[0x39, 0xc8]); // cmp eax, ecx
+                self.set_eax(0x92); // setb
+                self.store_eax(rd);
+            }
+            Instruction::Mul { rd, rs1, rs2 } => self.reg_op(rd, rs1, rs2, &[0x0f, 0xaf, 0xc1]),
+            Instruction::Mulh { rd, rs1, rs2 } => {
+                self.load_eax(rs1);
+                self.load_ecx(rs2);
+                self.emit(&[0x48, 0x63, 0xc0]); // movsxd rax, eax
+                self.emit(&[0x48, 0x63, 0xc9]); // movsxd rcx, ecx
+                self.emit(&[0x48, 0x0f, 0xaf, 0xc1]); // imul rax, rcx
+                self.emit(&[0x48, 0xc1, 0xe8, 0x20]); // shr rax, 32
+                self.store_eax(rd);
+            }
+            Instruction::Mulhsu { rd, rs1, rs2 } => {
+                self.load_eax(rs1);
+                self.load_ecx(rs2);
+                self.emit(&[0x48, 0x63, 0xc0]); // movsxd rax, eax
+                self.emit(&[0x48, 0x0f, 0xaf, 0xc1]); // imul rax, rcx
+                self.emit(&[0x48, 0xc1, 0xe8, 0x20]); // shr rax, 32
+                self.store_eax(rd);
+            }
+            Instruction::Mulhu { rd, rs1, rs2 } => {
+                // 32-bit loads zero-extend, the low 64 bits of the product are exact
+                self.load_eax(rs1);
+                self.load_ecx(rs2);
+                self.emit(&[0x48, 0x0f, 0xaf, 0xc1]); // imul rax, rcx
+                self.emit(&[0x48, 0xc1, 0xe8, 0x20]); // shr rax, 32
+                self.store_eax(rd);
+            }
+            Instruction::Addi { rd, rs1, imm } => self.imm_op(rd, rs1, imm, 0x05),
+            Instruction::Xori { rd, rs1, imm } => self.imm_op(rd, rs1, imm, 0x35),
+            Instruction::Ori { rd, rs1, imm } => self.imm_op(rd, rs1, imm, 0x0d),
+            Instruction::Andi { rd, rs1, imm } => self.imm_op(rd, rs1, imm, 0x25),
+            Instruction::Slti { rd, rs1, imm } => {
+                self.load_eax(rs1);
+                self.emit(&[0x3d]); // cmp eax, imm
+                self.emit_u32(imm as u32);
+                self.set_eax(0x9c); // setl
+                self.store_eax(rd);
+            }
+            Instruction::Sltiu { rd, rs1, imm } => {
+                self.load_eax(rs1);
+                self.emit(&[0x3d]); // cmp eax, imm
+                self.emit_u32(imm as u32);
+                self.set_eax(0x92); // setb
+                self.store_eax(rd);
+            }
+            Instruction::Slli { rd, rs1, shamt } => self.shift_imm(rd, rs1, shamt, 0xe0),
+            Instruction::Srli { rd, rs1, shamt } => self.shift_imm(rd, rs1, shamt, 0xe8),
+            Instruction::Srai { rd, rs1, shamt } => self.shift_imm(rd, rs1, shamt, 0xf8),
+            Instruction::Lui { rd, imm } => {
+                self.mov_eax(imm as u32);
+                self.store_eax(rd);
+            }
+            Instruction::Auipc { rd, imm } => {
+                self.mov_eax(pc.wrapping_add(imm as u32));
+                self.store_eax(rd);
+            }
+            Instruction::Lb { rd, rs1, imm } => {
+                self.load(rd, rs1, imm, MemoryChuckSize::BYTE, true, pc, retired)
+            }
+            Instruction::Lh { rd, rs1, imm } => {
+                self.load(rd, rs1, imm, MemoryChuckSize::HalfWord, true, pc, retired)
+            }
+            Instruction::Lw { rd, rs1, imm } => {
+                self.load(rd, rs1, imm, MemoryChuckSize::WordSize, false, pc, retired)
+            }
+            Instruction::Lbu { rd, rs1, imm } => {
+                self.load(rd, rs1, imm, MemoryChuckSize::BYTE, false, pc, retired)
+            }
+            Instruction::Lhu { rd, rs1, imm } => {
+                self.load(rd, rs1, imm, MemoryChuckSize::HalfWord, false, pc, retired)
+            }
+            Instruction::Sb { rs1, rs2, imm } => {
+                self.store(rs1, rs2, imm, MemoryChuckSize::BYTE, pc, retired)
+            }
+            Instruction::Sh { rs1, rs2, imm } => {
+                self.store(rs1, rs2, imm, MemoryChuckSize::HalfWord, pc, retired)
+            }
+            Instruction::Sw { rs1, rs2, imm } => {
+                self.store(rs1, rs2, imm, MemoryChuckSize::WordSize, pc, retired)
+            }
+            Instruction::Beq { rs1, rs2, imm } => {
+                self.branch(rs1, rs2, 0x44, pc, imm, next_pc, retired)
+            }
+            Instruction::Bne { rs1, rs2, imm } => {
+                self.branch(rs1, rs2, 0x45, pc, imm, next_pc, retired)
+            }
+            Instruction::Blt { rs1, rs2, imm } => {
+                self.branch(rs1, rs2, 0x4c, pc, imm, next_pc, retired)
+            }
+            Instruction::Bge { rs1, rs2, imm } => {
+                self.branch(rs1, rs2, 0x4d, pc, imm, next_pc, retired)
+            }
+            Instruction::Bltu { rs1, rs2, imm } => {
+                self.branch(rs1, rs2, 0x42, pc, imm, next_pc, retired)
+            }
+            Instruction::Bgeu { rs1, rs2, imm } => {
+                self.branch(rs1, rs2, 0x43, pc, imm, next_pc, retired)
+            }
+            Instruction::Jal { rd, imm } => {
+                self.mov_eax(next_pc);
+                self.store_eax(rd);
+                self.exit(pc.wrapping_add(imm as u32), retired + 1);
+            }
+            // x86 `div` traps on a zero divisor and on overflow, ecalls, traps and `fence.i` need
+            // the host
+            Instruction::Div { .. }
+            | Instruction::Divu { .. }
+            | Instruction::Rem { .. }
+            | Instruction::Remu { .. }
+            | Instruction::Jalr { .. }
+            | Instruction::Ecall
+            | Instruction::Ebreak
+            | Instruction::Wfi
+            | Instruction::FenceI => return false,
+            // a single hart has nothing to order
+            Instruction::Fence => {}
+            // counter reads need an exact instruction count
+            Instruction::Csrrw { .. }
+            | Instruction::Csrrs { .. }
+            | Instruction::Csrrc { .. }
+            | Instruction::Csrrwi { .. }
+            | Instruction::Csrrsi { .. }
+            | Instruction::Csrrci { .. } => return false,
+            // the optional bit-manipulation extensions are left to the interpreter
+            Instruction::Sh1add { .. }
+            | Instruction::Sh2add { .. }
+            | Instruction::Sh3add { .. }
+            | Instruction::Andn { .. }
+            | Instruction::Orn { .. }
+            | Instruction::Xnor { .. }
+            | Instruction::Max { .. }
+            | Instruction::Maxu { .. }
+            | Instruction::Min { .. }
+            | Instruction::Minu { .. }
+            | Instruction::Rol { .. }
+            | Instruction::Ror { .. }
+            | Instruction::Clmul { .. }
+            | Instruction::Clmulh { .. }
+            | Instruction::Clmulr { .. }
+            | Instruction::Pack { .. }
+            | Instruction::Packh { .. }
+            | Instruction::Clz { .. }
+            | Instruction::Ctz { .. }
+            | Instruction::Cpop { .. }
+            | Instruction::SextB { .. }
+            | Instruction::SextH { .. }
+            | Instruction::OrcB { .. }
+            | Instruction::Rev8 { .. }
+            | Instruction::Brev8 { .. }
+            | Instruction::Zip { .. }
+            | Instruction::Unzip { .. }
+            | Instruction::Rori { .. }
+            // RV64 instructions never reach a 32-bit program
+            | Instruction::Ld { .. }
+            | Instruction::Lwu { .. }
+            | Instruction::Sd { .. }
+            | Instruction::Addiw { .. }
+            | Instruction::Slliw { .. }
+            | Instruction::Srliw { .. }
+            | Instruction::Sraiw { .. }
+            | Instruction::Addw { .. }
+            | Instruction::Subw { .. }
+            | Instruction::Sllw { .. }
+            | Instruction::Srlw { .. }
+            | Instruction::Sraw { .. }
+            | Instruction::Mulw { .. }
+            | Instruction::Divw { .. }
+            | Instruction::Divuw { .. }
+            | Instruction::Remw { .. }
+            | Instruction::Remuw { .. } => return false,
+        }
+
+        true
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn load(
+        &mut self,
+        rd: u32,
+        rs1: u32,
+        imm: i32,
+        size: MemoryChuckSize,
+        is_signed: bool,
+        pc: u32,
+        retired: u32,
+    ) {
+        let args = [rd, rs1, imm as u32, load_kind(size, is_signed)];
+        self.call_helper(jit_load as *const () as usize, args, pc, retired);
+    }
+
+    fn store(
+        &mut self,
+        rs1: u32,
+        rs2: u32,
+        imm: i32,
+        size: MemoryChuckSize,
+        pc: u32,
+        retired: u32,
+    ) {
+        let args = [rs1, rs2, imm as u32, load_kind(size, false)];
+        self.call_helper(jit_store as *const () as usize, args, pc, retired);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use revm::{Context as EthContext, MainContext, database::CacheDB};
+
+    fn run_to(program: &Arc<DecodedProgram>, jit: bool, end: u32) -> (Vm, Context) {
+        let mut context = Context::new(EthContext::mainnet().with_db(CacheDB::default()));
+        context.jit = jit;
+        context.instruction_cost = 1;
+        let mut vm = Vm::from_program(program.clone());
+        vm.fuse_instructions = false;
+        while vm.pc != end {
+            vm.step(false, &mut context).unwrap();
+        }
+        (vm, context)
+    }
+
+    fn loop_program() -> Arc<DecodedProgram> {
+        Arc::new(DecodedProgram::new(
+            vec![
+                0x06400593, // 0x00: addi a1, zero, 100
+                0x10000613, // 0x04: addi a2, zero, 256
+                0x00a62023, // 0x08: sw a0, 0(a2)       <- loop
+                0x00062683, // 0x0c: lw a3, 0(a2)
+                0x00d50533, // 0x10: add a0, a0, a3
+                0x02b50733, // 0x14: mul a4, a0, a1
+                0x00351793, // 0x18: slli a5, a0, 3
+                0x00f7c733, // 0x1c: xor a4, a5, a5 (zero)
+                0xfff58593, // 0x20: addi a1, a1, -1
+                0x00153513, // 0x24: sltiu a0, a0, 1
+                0x00150513, // 0x28: addi a0, a0, 1
+                0xfc059ee3, // 0x2c: bne a1, zero, -36
+            ],
+            0,
+        ))
+    }
+
+    #[test]
+    fn test_jit_matches_interpreter() {
+        let program = loop_program();
+        let (jitted, jit_context) = run_to(&program, true, 0x30);
+        let (interpreted, context) = run_to(&program, false, 0x30);
+
+        assert_eq!(program.jit.compiled(), 1);
+        for register in 0..32 {
+            assert_eq!(
+                jitted.registers.read_reg(register),
+                interpreted.registers.read_reg(register),
+                "x{register}"
+            );
+        }
+        assert_eq!(jitted.instret, interpreted.instret);
+        assert_eq!(jit_context.gas.spent(), context.gas.spent());
+        assert_eq!(
+            jitted.memory.memory[256 / WORD_SIZE],
+            interpreted.memory.memory[256 / WORD_SIZE]
+        );
+    }
+
+    #[test]
+    fn test_jit_compressed_code() {
+        let program = Arc::new(DecodedProgram::new(
+            vec![
+                0x050d45d1, // 0x00: c.li a1, 20 ; 0x02: c.addi a0, 3 <- loop
+                0x063315fd, // 0x04: c.addi a1, -1 ; 0x06: add a2, a2, a0 (spans two words)
+                0xfde500a6, // 0x0a: c.bnez a1, -8
+                0x00010001, // 0x0c: c.nop ; 0x0e: c.nop
+            ],
+            0,
+        ));
+        let (jitted, _) = run_to(&program, true, 0x0c);
+        let (interpreted, _) = run_to(&program, false, 0x0c);
+
+        assert_eq!(program.jit.compiled(), 1);
+        assert_eq!(jitted.registers.read_reg(10), 60);
+        assert_eq!(jitted.registers.read_reg(12), 630);
+        assert_eq!(
+            jitted.registers.read_reg(12),
+            interpreted.registers.read_reg(12)
+        );
+        assert_eq!(jitted.instret, interpreted.instret);
+    }
+
+    #[test]
+    fn test_jit_falls_back_on_faulting_load() {
+        let program = Arc::new(DecodedProgram::new(
+            vec![
+                0x00150513, // 0x00: addi a0, a0, 1 <- loop
+                0x00555593, // 0x04: srli a1, a0, 5
+                0x0005a603, // 0x08: lw a2, 0(a1) (misaligned once a0 reaches 32)
+                0xfe000ae3, // 0x0c: beq zero, zero, -12
+            ],
+            0,
+        ));
+        let mut context = Context::new(EthContext::mainnet().with_db(CacheDB::default()));
+        let mut vm = Vm::from_program(program.clone());
+        vm.fuse_instructions = false;
+
+        let error = loop {
+            if let Err(error) = vm.step(false, &mut context) {
+                break error;
+            }
+        };
+
+        assert!(matches!(error, VMErrors::MemoryError));
+        assert_eq!(vm.pc, 0x08);
+        assert_eq!(vm.registers.read_reg(10), 32);
+        assert_eq!(vm.instret, 31 * 4 + 2);
+        assert_eq!(program.jit.compiled(), 1);
+    }
+
+    #[test]
+    fn test_jit_helpers_use_the_frame() {
+        let mut vm = Vm::from_program(loop_program());
+        let mut registers = vm.registers.clone();
+        let mut frame = JitFrame {
+            vm: &mut vm,
+            registers: registers.as_mut_ptr(),
+        };
+        // SAFETY: `registers` outlives the frame, as in `NativeBlock::run`
+        unsafe {
+            frame.write_reg(10, 0x100);
+            frame.write_reg(11, 0xdeadbeef);
+        }
+
+        let store = load_kind(MemoryChuckSize::WordSize, false);
+        assert!(jit_store(&mut frame, 10, 11, 4, store));
+        let load = load_kind(MemoryChuckSize::BYTE, true);
+        assert!(jit_load(&mut frame, 12, 10, 7, load));
+        assert!(jit_load(&mut frame, 0, 10, 4, load));
+        assert!(!jit_load(&mut frame, 12, 10, 1, store));
+
+        assert_eq!(registers.read_reg(12), 0xffffffef);
+        assert_eq!(registers.read_reg(0), 0);
+        assert_eq!(vm.memory.memory[0x104 / WORD_SIZE], 0xdeadbeef);
+        assert_eq!(vm.registers.read_reg(10), 0);
+    }
+
+    #[test]
+    fn test_jit_alu_edge_cases() {
+        let program = DecodedProgram::new(
+            vec![
+                0x02b51633, // mulh a2, a0, a1
+                0x02b526b3, // mulhsu a3, a0, a1
+                0x02b53733, // mulhu a4, a0, a1
+                0x00b527b3, // slt a5, a0, a1
+                0x00b53833, // sltu a6, a0, a1
+                0x40b558b3, // sra a7, a0, a1
+                0x00b55933, // srl s2, a0, a1
+                0x00b519b3, // sll s3, a0, a1
+                0xfff52a13, // slti s4, a0, -1
+                0xfff53a93, // sltiu s5, a0, -1
+                0x41f55b13, // srai s6, a0, 31
+                0x80054b93, // xori s7, a0, -2048
+                0x02b50c33, // mul s8, a0, a1
+                0x40b50cb3, // sub s9, a0, a1
+                0xfffffd37, // lui s10, 0xfffff
+                0x00001d97, // auipc s11, 0x1
+                0x00000013, // addi zero, zero, 0
+            ],
+            0x100,
+        );
+        let block = compile_block(&program, 0x100).unwrap();
+        let values = [0, 1, 31, 32, 0x7fffffff, 0x80000000, 0xffffffff, 0xdeadbeef];
+
+        for &a in &values {
+            for &b in &values {
+                let mut context = Context::new(EthContext::mainnet().with_db(CacheDB::default()));
+                let (mut interpreted, mut jitted) = (Vm::new(), Vm::new());
+                interpreted.pc = 0x100;
+                for vm in [&mut interpreted, &mut jitted] {
+                    vm.registers.write_reg(10, a);
+                    vm.registers.write_reg(11, b);
+                }
+                for (i, &raw) in program.code.iter().enumerate() {
+                    interpreted.memory.memory[0x40 + i] = raw;
+                }
+
+                for _ in 0..program.len() {
+                    interpreted.step(false, &mut context).unwrap();
+                }
+                let exit = block.run(&mut jitted);
+
+                assert_eq!(exit, (17 << 32) | 0x144);
+                for register in 0..32 {
+                    assert_eq!(
+                        jitted.registers.read_reg(register),
+                        interpreted.registers.read_reg(register),
+                        "x{register} for {a:#x}, {b:#x}"
+                    );
+                }
+            }
+        }
+    }
+}
@@ -1,8 +1,27 @@
+pub mod bitmanip;
+pub mod code_cache;
+pub mod container;
 pub mod context;
+pub mod debug_console;
+pub mod debug_info;
+pub mod disassembler;
 pub mod ecall_manager;
 pub mod elf_parser;
+pub mod fusion;
+pub mod gas;
+pub mod geth_trace;
+pub mod hook;
+pub mod host;
+pub mod inspector;
 pub mod instructions;
+#[cfg(feature = "jit")]
+pub mod jit;
+pub mod memory_map;
+pub mod segments;
+pub mod snapshot;
 pub mod test;
+pub mod trace;
 pub mod utils;
 pub mod vm;
+pub mod vm64;
 pub use riscv_evm_core;
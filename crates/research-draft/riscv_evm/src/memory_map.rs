@@ -0,0 +1,146 @@
+//! # Memory map
+//! Layout of the 32-bit guest address space, from the bottom up:
+//!
+//! | address                      | region                                                      |
+//! |------------------------------|-------------------------------------------------------------|
+//! | `0x0000_0000` (or ELF base)  | code (read + execute), then rodata and data for ELF images  |
+//! | [Heap::start]                | heap, from the first page after the image up to the break   |
+//! | [CALL_DATA_ADDRESS]          | calldata of the frame (read only), the break ends here      |
+//! | [RETURN_DATA_ADDRESS]        | return data of the last call (read only)                    |
+//! | [STACK_LIMIT]                | lowest stack address                                        |
+//! | [STACK_TOP]                  | initial `sp`, the stack grows down from here                |
+//!
+//! The heap grows with the `Sbrk` ecall, which backs the global allocator of contracts built with
+//! `alloc` (`riscv_evm_guest::BumpAllocator`). Every page the break reaches for the first time
+//! costs [crate::gas::HEAP_PAGE_COST], shrinking the heap refunds nothing and growing it again over
+//! pages already paid for is free.
+//!
+//! A frame starts with its calldata mapped at [CALL_DATA_ADDRESS], `a0` pointing at it and `a1`
+//! holding its length, so ABI arguments can be decoded in place with plain loads. After every
+//! call (`Call`, `CallCode`, `DelegateCall`, `StaticCall`) the callee's return data is mapped at
+//! [RETURN_DATA_ADDRESS] the same way, with its length in [RETURN_DATA_SIZE_REGISTER]. Both
+//! windows are [DATA_WINDOW_SIZE] bytes, bytes past the data read as zero like EVM calldata does.
+use riscv_evm_core::{MAXIMUM_GUEST_BUFFER_SIZE, MEMORY_PAGE_SIZE, Registers};
+use serde::{Deserialize, Serialize};
+
+use crate::gas::HEAP_PAGE_COST;
+
+/// Heap pages are priced and aligned to this size in bytes
+pub const PAGE_SIZE: u32 = MEMORY_PAGE_SIZE;
+/// Initial stack pointer, at the top of the address space (16-byte aligned as the ABI requires)
+pub const STACK_TOP: u32 = 0xffff_fff0;
+/// Lowest address of the stack region (just under 16 MiB of stack)
+pub const STACK_LIMIT: u32 = 0xff00_0000;
+/// Size of the calldata and return data windows, the largest buffer an ecall may move
+pub const DATA_WINDOW_SIZE: u32 = MAXIMUM_GUEST_BUFFER_SIZE;
+/// Return data of the last call, right below the stack
+pub const RETURN_DATA_ADDRESS: u32 = STACK_LIMIT - DATA_WINDOW_SIZE;
+/// Calldata of the frame, right below the return data
+pub const CALL_DATA_ADDRESS: u32 = RETURN_DATA_ADDRESS - DATA_WINDOW_SIZE;
+/// The break never grows past the calldata window
+pub const HEAP_LIMIT: u32 = CALL_DATA_ADDRESS;
+/// The ABI stack pointer register
+pub const SP: u32 = 2;
+/// `a0`, points at the calldata when a frame starts
+pub const CALL_DATA_ADDRESS_REGISTER: u32 = 10;
+/// `a1`, holds the calldata length when a frame starts
+pub const CALL_DATA_SIZE_REGISTER: u32 = 11;
+/// Holds the length of the mapped return data after a call, no call input uses it
+pub const RETURN_DATA_SIZE_REGISTER: u32 = 26;
+
+/// Registers a program starts with: all zero except `sp`, which points at [STACK_TOP]
+pub fn initial_registers() -> Registers {
+    let mut registers = Registers::new();
+    registers.write_reg(SP, STACK_TOP);
+    registers
+}
+
+/// The program break of a Vm
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Heap {
+    /// First address of the heap
+    pub start: u32,
+    /// The heap is `[start, brk)`
+    pub brk: u32,
+    /// End of the pages paid for so far
+    pub paid_end: u32,
+}
+
+impl Heap {
+    /// An empty heap starting at the first page boundary at or after `image_end`
+    pub fn new(image_end: u32) -> Self {
+        let start = image_end
+            .checked_next_multiple_of(PAGE_SIZE)
+            .unwrap_or(u32::MAX);
+        Self {
+            start,
+            brk: start,
+            paid_end: start,
+        }
+    }
+
+    /// The heap with its break moved by `increment` bytes and the gas to charge for the pages
+    /// reached for the first time. Nothing changes until the caller has charged the gas and
+    /// stores the new heap, the old break is still in `self.brk`.
+    /// `None` when the break would leave `[start, HEAP_LIMIT]`.
+    pub fn sbrk(&self, increment: i32) -> Option<(Heap, u64)> {
+        let brk = self.brk.checked_add_signed(increment)?;
+        if brk < self.start || brk > HEAP_LIMIT {
+            return None;
+        }
+
+        let end = brk.next_multiple_of(PAGE_SIZE);
+        let (paid_end, cost) = match end.checked_sub(self.paid_end) {
+            Some(grown) if grown > 0 => (end, u64::from(grown / PAGE_SIZE) * HEAP_PAGE_COST),
+            _ => (self.paid_end, 0),
+        };
+
+        Some((
+            Heap {
+                brk,
+                paid_end,
+                ..*self
+            },
+            cost,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Applies [Heap::sbrk] like the ecall does, returning the old break and the cost
+    fn sbrk(heap: &mut Heap, increment: i32) -> Option<(u32, u64)> {
+        let (moved, cost) = heap.sbrk(increment)?;
+        Some((std::mem::replace(heap, moved).brk, cost))
+    }
+
+    #[test]
+    fn test_heap_sbrk() {
+        let mut heap = Heap::new(0x1234);
+        assert_eq!(heap.start, 0x2000);
+
+        assert_eq!(sbrk(&mut heap, 0), Some((0x2000, 0)));
+        assert_eq!(sbrk(&mut heap, 16), Some((0x2000, HEAP_PAGE_COST)));
+        assert_eq!(
+            sbrk(&mut heap, PAGE_SIZE as i32),
+            Some((0x2010, HEAP_PAGE_COST))
+        );
+        // shrinking refunds nothing, growing back over paid pages is free
+        assert_eq!(sbrk(&mut heap, -(PAGE_SIZE as i32)), Some((0x3010, 0)));
+        assert_eq!(sbrk(&mut heap, PAGE_SIZE as i32), Some((0x2010, 0)));
+
+        // below the start, into the calldata or past the address space fails without moving the break
+        assert_eq!(sbrk(&mut heap, -0x2000), None);
+        let mut heap = Heap::new(HEAP_LIMIT - PAGE_SIZE);
+        assert_eq!(sbrk(&mut heap, PAGE_SIZE as i32 + 1), None);
+        assert_eq!(sbrk(&mut heap, i32::MIN), None);
+        assert_eq!(
+            sbrk(&mut heap, PAGE_SIZE as i32),
+            Some((HEAP_LIMIT - PAGE_SIZE, HEAP_PAGE_COST))
+        );
+        assert_eq!(heap.brk, HEAP_LIMIT);
+        assert_eq!(Heap::new(u32::MAX).sbrk(0), None);
+    }
+}
@@ -0,0 +1,260 @@
+//! # Segments
+//! Read/write/execute permissions of the guest address space.
+//! Loaded code is mapped read + execute, so a contract can neither overwrite its own instructions
+//! nor jump into data it wrote (W^X). The permissions come from the ELF program headers, or, for
+//! contract code, from the container: the code is a single read + execute segment at its base.
+//! Memory outside every segment (heap, stack, ecall buffers) is readable and writable but never
+//! executable. A [SegmentMap] without segments allows every access, for raw code loaded with
+//! [crate::vm::Vm::from_bin].
+//!
+//! Loads, stores, ecall buffers and instruction fetch are all checked, a violation is a
+//! [VMErrors::AccessViolation] trap.
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{elf_parser::Elf, vm::VMErrors};
+
+/// The kind of access made to guest memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Read => "read",
+            Self::Write => "write",
+            Self::Execute => "execute",
+        })
+    }
+}
+
+/// Access permissions of a segment
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Permissions {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl Permissions {
+    /// Code
+    pub const READ_EXECUTE: Self = Self {
+        read: true,
+        write: false,
+        execute: true,
+    };
+    /// Data, and memory outside every segment
+    pub const READ_WRITE: Self = Self {
+        read: true,
+        write: true,
+        execute: false,
+    };
+    /// Constants
+    pub const READ: Self = Self {
+        read: true,
+        write: false,
+        execute: false,
+    };
+
+    /// Whether `access` is allowed
+    pub fn allows(self, access: Access) -> bool {
+        match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+            Access::Execute => self.execute,
+        }
+    }
+}
+
+/// A range of guest memory with its permissions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Segment {
+    /// Address of the first byte
+    pub start: u32,
+    /// Size in bytes
+    pub size: u32,
+    pub permissions: Permissions,
+}
+
+impl Segment {
+    /// Whether `[address, address + size)` overlaps the segment
+    fn overlaps(&self, address: u32, size: u32) -> bool {
+        let end = self.start as u64 + self.size as u64;
+        (address as u64) < end && address as u64 + size as u64 > self.start as u64
+    }
+}
+
+/// The segments of the guest address space, see the module documentation
+#[derive(Debug, Clone, Default)]
+pub struct SegmentMap {
+    segments: Vec<Segment>,
+}
+
+impl SegmentMap {
+    /// A map with a single read + execute segment holding `size` bytes of code at `base`
+    pub fn code(base: u32, size: u32) -> Self {
+        let mut map = Self::default();
+        map.insert(Segment {
+            start: base,
+            size,
+            permissions: Permissions::READ_EXECUTE,
+        })
+        .expect("code segments are never writable");
+        map
+    }
+
+    /// The loadable segments of an ELF executable
+    /// # Errors
+    /// When a segment is both writable and executable.
+    pub fn from_elf(elf: &Elf) -> Result<Self, anyhow::Error> {
+        let mut map = Self::default();
+        for segment in &elf.segments {
+            map.insert(*segment)?;
+        }
+
+        Ok(map)
+    }
+
+    /// Adds a segment, writable segments can not be executable
+    /// # Errors
+    /// When the segment is both writable and executable.
+    pub fn insert(&mut self, segment: Segment) -> Result<(), anyhow::Error> {
+        if segment.permissions.write && segment.permissions.execute {
+            anyhow::bail!(
+                "segment at 0x{:08x} is both writable and executable",
+                segment.start
+            );
+        }
+
+        self.segments.push(segment);
+        Ok(())
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// End of the highest segment, 0 without segments
+    pub fn end(&self) -> u32 {
+        self.segments
+            .iter()
+            .map(|segment| segment.start.saturating_add(segment.size))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Whether no segment is mapped, every access is allowed then
+    pub fn is_unrestricted(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// Permissions at `address`
+    pub fn permissions(&self, address: u32) -> Permissions {
+        if self.is_unrestricted() {
+            return Permissions {
+                read: true,
+                write: true,
+                execute: true,
+            };
+        }
+
+        self.segments
+            .iter()
+            .find(|segment| segment.overlaps(address, 1))
+            .map_or(Permissions::READ_WRITE, |segment| segment.permissions)
+    }
+
+    /// Checks a single access at `address`
+    /// # Errors
+    /// [VMErrors::AccessViolation] when the access is not allowed.
+    #[inline]
+    pub fn check(&self, address: u32, access: Access) -> Result<(), VMErrors> {
+        if self.is_unrestricted() || self.permissions(address).allows(access) {
+            Ok(())
+        } else {
+            Err(VMErrors::AccessViolation(address, access))
+        }
+    }
+
+    /// Checks a data access (read or write) to `[address, address + size)`, for ecall buffers.
+    /// Memory outside every segment is always readable and writable, so only the segments the
+    /// range overlaps matter.
+    /// # Errors
+    /// [VMErrors::AccessViolation] with the first address of the first segment not allowing it.
+    pub fn check_range(&self, address: u32, size: u32, access: Access) -> Result<(), VMErrors> {
+        match self
+            .segments
+            .iter()
+            .find(|segment| segment.overlaps(address, size) && !segment.permissions.allows(access))
+        {
+            Some(segment) => Err(VMErrors::AccessViolation(
+                std::cmp::max(address, segment.start),
+                access,
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_segment_map() {
+        let unrestricted = SegmentMap::default();
+        assert!(unrestricted.check(0, Access::Write).is_ok());
+        assert!(unrestricted.check(0x1000, Access::Execute).is_ok());
+
+        let mut map = SegmentMap::code(0x100, 0x20);
+        map.insert(Segment {
+            start: 0x200,
+            size: 0x10,
+            permissions: Permissions::READ,
+        })
+        .unwrap();
+
+        assert!(map.check(0x100, Access::Execute).is_ok());
+        assert!(map.check(0x11c, Access::Read).is_ok());
+        assert!(matches!(
+            map.check(0x11c, Access::Write),
+            Err(VMErrors::AccessViolation(0x11c, Access::Write))
+        ));
+        // outside every segment is data
+        assert!(map.check(0x120, Access::Write).is_ok());
+        assert!(matches!(
+            map.check(0x120, Access::Execute),
+            Err(VMErrors::AccessViolation(0x120, Access::Execute))
+        ));
+        assert!(matches!(
+            map.check(0x204, Access::Write),
+            Err(VMErrors::AccessViolation(0x204, Access::Write))
+        ));
+
+        // a buffer reaching into the code from below
+        assert!(map.check_range(0xf0, 0x10, Access::Write).is_ok());
+        assert!(matches!(
+            map.check_range(0xf0, 0x11, Access::Write),
+            Err(VMErrors::AccessViolation(0x100, Access::Write))
+        ));
+        assert!(map.check_range(0xf0, 0x20, Access::Read).is_ok());
+
+        assert!(
+            map.insert(Segment {
+                start: 0x300,
+                size: 4,
+                permissions: Permissions {
+                    read: true,
+                    write: true,
+                    execute: true
+                }
+            })
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,262 @@
+//! # Snapshots
+//! [Vm::snapshot] captures a paused Vm, registers, pc, the memory pages written so far, segments,
+//! heap, counters, along with the gas meter and frame of its [Context], and [Vm::restore] puts
+//! them back, so execution can be resumed later, forked from an interesting state by a fuzzer, or
+//! replayed by a prover.
+//!
+//! A Vm that yielded on an ecall is snapshotted with the ecall pending, so the restored Vm expects
+//! the same [crate::host::EcallResponse].
+//!
+//! Snapshots serialize with serde, [Snapshot::to_bytes] produces a stable binary format (bincode,
+//! little-endian fixed-size integers) tagged with [SNAPSHOT_VERSION].
+//! The pre-decoded program, native blocks, tracer, hook and debug info are not part of a snapshot,
+//! a restored Vm decodes its code from memory.
+use revm::{interpreter::Gas, primitives::Address};
+use riscv_evm_core::{MEMORY_PAGE_SIZE, WORD_SIZE, e_constants::RiscvEVMECalls};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    container::Profile,
+    context::Context,
+    host::EcallRequest,
+    memory_map::Heap,
+    segments::{Segment, SegmentMap},
+    vm::Vm,
+};
+
+/// Version of the snapshot format, bumped whenever [Snapshot] changes
+pub const SNAPSHOT_VERSION: u32 = 2;
+
+/// A written memory page
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Page {
+    /// Page number, the page starts at `number * MEMORY_PAGE_SIZE`
+    pub number: u32,
+    pub words: Vec<u32>,
+}
+
+/// The gas meter of the frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GasSnapshot {
+    pub limit: u64,
+    pub spent: u64,
+    pub refunded: i64,
+}
+
+/// The frame the Vm runs in
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Frame {
+    pub address: [u8; 20],
+    pub caller: [u8; 20],
+    pub call_data: Vec<u8>,
+    pub return_data: Vec<u8>,
+}
+
+/// A paused Vm, see the module documentation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    pub registers: [u32; 32],
+    pub pc: u32,
+    pub running: bool,
+    pub exit_code: u32,
+    pub instret: u64,
+    pub profile: Profile,
+    pub segments: Vec<Segment>,
+    pub heap: Heap,
+    pub return_data_len: u32,
+    /// Whether the Vm yielded on the ecall at `pc`, see [crate::host]
+    pub pending_ecall: bool,
+    /// Pages written so far that are not all zero, in ascending order
+    pub pages: Vec<Page>,
+    pub gas: GasSnapshot,
+    pub frame: Frame,
+}
+
+impl Snapshot {
+    /// Captures `vm` and the gas meter and frame of `context`
+    pub fn capture(vm: &Vm, context: &Context) -> Self {
+        Self {
+            version: SNAPSHOT_VERSION,
+            registers: std::array::from_fn(|i| vm.registers.read_reg(i as u32)),
+            pc: vm.pc,
+            running: vm.running,
+            exit_code: vm.exit_code,
+            instret: vm.instret,
+            profile: vm.profile,
+            segments: vm.segments.segments().to_vec(),
+            heap: vm.heap,
+            return_data_len: vm.return_data_len,
+            pending_ecall: vm.pending_ecall.is_some(),
+            pages: vm
+                .memory
+                .dirty_pages()
+                .map(|number| (number, vm.memory.page(number)))
+                .filter(|(_, words)| words.iter().any(|&word| word != 0))
+                .map(|(number, words)| Page {
+                    number,
+                    words: words.to_vec(),
+                })
+                .collect(),
+            gas: GasSnapshot {
+                limit: context.gas.limit(),
+                spent: context.gas.spent(),
+                refunded: context.gas.refunded(),
+            },
+            frame: Frame {
+                address: context.address.0.0,
+                caller: context.current_caller.0.0,
+                call_data: context.eth_context.tx.data.to_vec(),
+                return_data: context.return_data.to_vec(),
+            },
+        }
+    }
+
+    /// Puts the snapshot back into `vm` and `context`, whatever they held before is replaced
+    /// # Errors
+    /// When the snapshot has another version or is malformed, `vm` and `context` are left
+    /// untouched then.
+    pub fn apply(&self, vm: &mut Vm, context: &mut Context) -> Result<(), anyhow::Error> {
+        if self.version != SNAPSHOT_VERSION {
+            anyhow::bail!(
+                "snapshot version {} is not supported, expected {SNAPSHOT_VERSION}",
+                self.version
+            );
+        }
+
+        let words_per_page = MEMORY_PAGE_SIZE as usize / WORD_SIZE;
+        let pages = 1u64 << (32 - MEMORY_PAGE_SIZE.trailing_zeros());
+        if let Some(page) = self
+            .pages
+            .iter()
+            .find(|page| page.words.len() != words_per_page || u64::from(page.number) >= pages)
+        {
+            anyhow::bail!("malformed snapshot page {}", page.number);
+        }
+
+        let code = self.registers[self.profile.ecall_code_register() as usize];
+        if self.pending_ecall && RiscvEVMECalls::from_u32(code).is_none() {
+            anyhow::bail!("malformed snapshot pending ecall 0x{code:x}");
+        }
+
+        let mut segments = SegmentMap::default();
+        for segment in &self.segments {
+            segments.insert(*segment)?;
+        }
+
+        vm.memory.clear();
+        for page in &self.pages {
+            let start = page.number as usize * words_per_page;
+            for (i, &word) in page.words.iter().enumerate() {
+                vm.memory.set_word(start + i, word);
+            }
+        }
+        for (i, &value) in self.registers.iter().enumerate() {
+            vm.registers.write_reg(i as u32, value);
+        }
+        vm.pc = self.pc;
+        vm.running = self.running;
+        vm.exit_code = self.exit_code;
+        vm.instret = self.instret;
+        vm.profile = self.profile;
+        vm.segments = segments;
+        vm.heap = self.heap;
+        vm.return_data_len = self.return_data_len;
+        vm.program = None;
+        // the inputs of the pending ecall are decoded again from the registers and memory
+        vm.pending_ecall = if self.pending_ecall {
+            let request = EcallRequest::decode(vm)
+                .map_err(|e| anyhow::anyhow!("malformed snapshot pending ecall: {e:?}"))?;
+            Some(request)
+        } else {
+            None
+        };
+
+        let mut gas = Gas::new(self.gas.limit);
+        gas.set_spent(self.gas.spent);
+        gas.set_refund(self.gas.refunded);
+        context.gas = gas;
+        context.address = Address::from(self.frame.address);
+        context.current_caller = Address::from(self.frame.caller);
+        let call_data = self.frame.call_data.clone();
+        context
+            .eth_context
+            .modify_tx(|tx| tx.data = call_data.into());
+        context.return_data = self.frame.return_data.clone().into();
+
+        Ok(())
+    }
+
+    /// Serializes the snapshot to its binary format
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("snapshots only hold plain data")
+    }
+
+    /// Deserializes a snapshot from its binary format
+    /// # Errors
+    /// When `bytes` is not a snapshot.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, anyhow::Error> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::code_cache::DecodedProgram;
+    use revm::{Context as EthContext, MainContext, database::CacheDB};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_snapshot_restore() {
+        let program = Arc::new(DecodedProgram::new(
+            vec![
+                0x00052603, // lw a2, 0(a0)
+                0x00160613, // addi a2, a2, 1
+                0x00c12023, // sw a2, 0(sp)
+                0x00012683, // lw a3, 0(sp)
+                0x00168693, // addi a3, a3, 1
+            ],
+            0,
+        ));
+        let mut context = Context::new(EthContext::mainnet().with_db(CacheDB::default()));
+        context.address = Address::from([0x42; 20]);
+        context.gas = Gas::new(1000);
+        context.charge_gas(10).unwrap();
+        let mut vm = Vm::from_program(program.clone());
+        vm.enter_frame(&[0, 0, 0, 41]).unwrap();
+        vm.heap = vm.heap.sbrk(100).unwrap().0;
+        for _ in 0..2 {
+            vm.step(false, &mut context).unwrap();
+        }
+
+        let snapshot = Snapshot::from_bytes(&vm.snapshot(&context).to_bytes()).unwrap();
+        assert_eq!(snapshot, vm.snapshot(&context));
+        assert!(snapshot.pages.len() >= 2);
+
+        for _ in 0..3 {
+            vm.step(false, &mut context).unwrap();
+        }
+
+        // a fresh Vm resumes from the snapshot and ends up in the same state
+        let mut restored = Vm::new();
+        let mut restored_context = Context::new(EthContext::mainnet().with_db(CacheDB::default()));
+        restored.restore(&snapshot, &mut restored_context).unwrap();
+        assert_eq!(restored_context.gas.spent(), 10);
+        assert_eq!(restored_context.address, context.address);
+        for _ in 0..3 {
+            restored.step(false, &mut restored_context).unwrap();
+        }
+        assert_eq!(restored.snapshot(&restored_context), vm.snapshot(&context));
+        assert_eq!(restored.registers.read_reg(13), 43);
+
+        // restoring over a Vm that ran further rewinds its memory too
+        vm.restore(&snapshot, &mut context).unwrap();
+        assert_eq!(vm.snapshot(&context), snapshot);
+
+        let mut other_version = snapshot.clone();
+        other_version.version += 1;
+        assert!(vm.restore(&other_version, &mut context).is_err());
+        assert!(Snapshot::from_bytes(&[1, 2, 3]).is_err());
+    }
+}
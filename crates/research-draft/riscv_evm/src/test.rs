@@ -1,23 +1,49 @@
 #[cfg(test)]
 mod tests {
     use crate::{
+        code_cache::DecodedProgram,
+        container::{Profile, with_container_header},
         context::Context,
         ecall_manager::process_ecall,
+        gas::HEAP_PAGE_COST,
+        hook::VmHook,
+        host::{EcallArgs, EcallHost, EcallRequest, EcallResponse, Execution},
+        inspector::SharedInspector,
+        instructions::Instruction,
+        memory_map::{
+            CALL_DATA_ADDRESS, HEAP_LIMIT, Heap, PAGE_SIZE, RETURN_DATA_ADDRESS,
+            RETURN_DATA_SIZE_REGISTER, SP, STACK_TOP,
+        },
+        segments::Access,
         utils::{
-            address_to_u32_vec, bytes_to_u32, split_u64_to_u32, u32_vec_to_address,
-            u32_vec_to_bytes,
+            address_to_u32_vec, bytes_to_u32, read_guest_bytes, read_word256, split_u64_to_u32,
+            u32_vec_to_address, u32_vec_to_bytes, write_address, write_guest_bytes, write_word256,
         },
-        vm::Vm,
+        vm::{VMErrors, Vm},
     };
     use revm::{
-        Context as RevmEthContext, DatabaseCommit, MainContext,
+        Context as RevmEthContext, DatabaseCommit, Inspector, MainContext,
         context::{ContextTr, JournalTr},
         database::{CacheDB, InMemoryDB},
-        primitives::{Address, TxKind, U256, keccak256},
+        handler::post_execution,
+        interpreter::{
+            CallInputs, CallOutcome, CallScheme, Gas, InstructionResult, Interpreter,
+            InterpreterResult,
+        },
+        primitives::{Address, Bytes, Log, TxKind, U256, hardfork::SpecId, keccak256},
         state::{AccountInfo, Bytecode},
     };
-    use riscv_evm_core::{MemoryChuckSize, Registers, e_constants::*, interfaces::MemoryInterface};
-    use std::str::FromStr;
+    use riscv_evm_core::{
+        MAXIMUM_GUEST_BUFFER_SIZE, MemoryChuckSize, Registers, e_constants::*,
+        interfaces::MemoryInterface,
+    };
+    use riscv_evm_guest::{BumpAllocator, ProgramBreak, SBRK};
+    use std::{
+        alloc::{GlobalAlloc, Layout},
+        cell::RefCell,
+        str::FromStr,
+        sync::{Arc, Mutex},
+    };
 
     // Helper function to create test VM and Context
     fn setup() -> (Vm, Context) {
@@ -134,6 +160,30 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_keccak256_out_of_bounds_buffer() {
+        let (mut vm, mut context) = setup();
+
+        // offset + size wraps around the address space
+        vm.registers.write_reg(ECALL_CODE_REG, 0x20); // Keccak256
+        vm.registers
+            .write_reg(KECCAK256_OFFSET_REGISTER, u32::MAX - 4);
+        vm.registers.write_reg(KECCAK256_SIZE_REGISTER, 32);
+        assert!(matches!(
+            process_ecall(&mut vm, &mut context),
+            Err(VMErrors::GuestBufferOutOfBounds(_, _))
+        ));
+
+        // size larger than the guest buffer cap
+        vm.registers.write_reg(KECCAK256_OFFSET_REGISTER, 0);
+        vm.registers
+            .write_reg(KECCAK256_SIZE_REGISTER, MAXIMUM_GUEST_BUFFER_SIZE + 1);
+        assert!(matches!(
+            process_ecall(&mut vm, &mut context),
+            Err(VMErrors::GuestBufferTooLarge(_))
+        ));
+    }
+
     #[test]
     fn test_address() {
         let (mut vm, mut context) = setup();
@@ -308,6 +358,83 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_sstore_gas_and_refund() {
+        let (mut vm, mut context) = setup();
+
+        let store = |vm: &mut Vm, context: &mut Context, value: u32| {
+            vm.registers.write_reg(ECALL_CODE_REG, 0x55); // SStore
+            vm.registers.write_reg(SSTORE_INPUT_REGISTER_8, 1);
+            vm.registers.write_reg(SSTORE_INPUT_REGISTER_16, value);
+            let spent = context.gas.spent();
+            process_ecall(vm, context).unwrap();
+            context.gas.spent() - spent
+        };
+
+        // 0 -> 5 on a cold slot: SSTORE_SET + COLD_SLOAD_COST
+        assert_eq!(store(&mut vm, &mut context, 5), 22100);
+        assert_eq!(context.gas.refunded(), 0);
+
+        // 5 -> 5 is a no-op write on a warm slot
+        assert_eq!(store(&mut vm, &mut context, 5), 100);
+        assert_eq!(context.gas.refunded(), 0);
+
+        // 5 -> 0 restores the original value, most of the set cost is refunded
+        assert_eq!(store(&mut vm, &mut context, 0), 100);
+        assert_eq!(context.gas.refunded(), 19900);
+
+        // EIP-3529 caps the final refund to a fifth of the gas spent
+        let spent = context.gas.spent();
+        post_execution::refund(SpecId::PRAGUE, &mut context.gas, 0);
+        assert_eq!(context.gas.refunded(), (spent / 5) as i64);
+    }
+
+    #[test]
+    fn test_gas_left() {
+        let (mut vm, mut context) = setup();
+        context.gas = Gas::new(1_000_000);
+        context.charge_gas(22100).unwrap();
+
+        vm.registers.write_reg(ECALL_CODE_REG, 0x5A); // Gas
+        process_ecall(&mut vm, &mut context).unwrap();
+        assert_eq!(
+            U256::from_be_bytes(read_word256(&vm, GAS_OUTPUT_REGISTER_1)),
+            U256::from(1_000_000 - 22100)
+        );
+    }
+
+    #[test]
+    fn test_sstore_sentry() {
+        let (mut vm, mut context) = setup();
+        context.gas = Gas::new(2300);
+
+        // EIP-2200: not more than the call stipend left
+        vm.registers.write_reg(ECALL_CODE_REG, 0x55); // SStore
+        assert!(matches!(
+            process_ecall(&mut vm, &mut context),
+            Err(VMErrors::OutOfGas)
+        ));
+        assert_eq!(context.gas.spent(), 0);
+
+        // past the sentry but short of the set cost, the write is undone
+        context.gas = Gas::new(3000);
+        vm.registers.write_reg(ECALL_CODE_REG, 0x55);
+        vm.registers.write_reg(SSTORE_INPUT_REGISTER_8, 1);
+        vm.registers.write_reg(SSTORE_INPUT_REGISTER_16, 5);
+        assert!(matches!(
+            process_ecall(&mut vm, &mut context),
+            Err(VMErrors::OutOfGas)
+        ));
+        let slot = context
+            .eth_context
+            .journal()
+            .sload(context.address, U256::from(1))
+            .unwrap();
+        assert_eq!(slot.data, U256::ZERO);
+        // the slot went back to cold too
+        assert!(slot.is_cold);
+    }
+
     #[test]
     fn test_return_operation() {
         let (mut vm, mut context) = setup();
@@ -347,7 +474,7 @@ mod tests {
         let (mut vm, mut context) = setup();
 
         // Set up invalid ECALL code
-        vm.registers.write_reg(ECALL_CODE_REG, 0xFF); // Invalid code
+        vm.registers.write_reg(ECALL_CODE_REG, 0xFE); // Invalid code, INVALID has no ecall
 
         // Process ECALL
         let result = process_ecall(&mut vm, &mut context);
@@ -420,6 +547,57 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_cold_warm_account_access() {
+        let (mut vm, mut context) = setup();
+        let cold_address = Address::from([0x11; 20]);
+        let listed_address = Address::from([0x22; 20]);
+
+        // Warmed the same way pre_execution::load_accounts handles the tx access list
+        context.eth_context.journal().warm_account(listed_address);
+
+        vm.registers.write_reg(ECALL_CODE_REG, 0x31); // Balance
+        let balance_of = |vm: &mut Vm, context: &mut Context, address: Address| {
+            for (i, &val) in address_to_u32_vec(&address.0).iter().enumerate() {
+                vm.registers
+                    .write_reg(BALANCE_INPUT_REGISTER_1 + i as u32, val);
+            }
+            let spent = context.gas.spent();
+            process_ecall(vm, context).unwrap();
+            context.gas.spent() - spent
+        };
+
+        // First access is cold, the next one is warm
+        assert_eq!(balance_of(&mut vm, &mut context, cold_address), 2600);
+        assert_eq!(balance_of(&mut vm, &mut context, cold_address), 100);
+
+        // Accounts in the access list start warm
+        assert_eq!(balance_of(&mut vm, &mut context, listed_address), 100);
+
+        // Storage slots follow the same rules
+        vm.registers.write_reg(ECALL_CODE_REG, 0x54); // SLoad
+        vm.registers.write_reg(SLOAD_INPUT_REGISTER_8, 7);
+        let spent = context.gas.spent();
+        process_ecall(&mut vm, &mut context).unwrap();
+        assert_eq!(context.gas.spent() - spent, 2100);
+
+        let spent = context.gas.spent();
+        process_ecall(&mut vm, &mut context).unwrap();
+        assert_eq!(context.gas.spent() - spent, 100);
+    }
+
+    #[test]
+    fn test_ecall_out_of_gas() {
+        let (mut vm, mut context) = setup();
+        context.gas = Gas::new(1000);
+
+        vm.registers.write_reg(ECALL_CODE_REG, 0x31); // Balance of a cold account
+        assert!(matches!(
+            process_ecall(&mut vm, &mut context),
+            Err(VMErrors::OutOfGas)
+        ));
+    }
+
     #[test]
     fn test_origin_and_caller() {
         let (mut vm, mut context) = setup_2();
@@ -989,8 +1167,11 @@ mod tests {
         context
             .eth_context
             .journal()
-            .load_account(context.address)
-            .unwrap();
+            .load_account(context.current_caller)
+            .unwrap()
+            .data
+            .info
+            .balance = U256::from(value);
 
         // Process Create ECALL (this would be complex to fully test)
         // In a real test we'd need to properly mock the creation process
@@ -1090,8 +1271,11 @@ mod tests {
         context
             .eth_context
             .journal()
-            .load_account(context.address)
-            .unwrap();
+            .load_account(context.current_caller)
+            .unwrap()
+            .data
+            .info
+            .balance = U256::from(value);
 
         // Process Create2 ECALL (this would be complex to fully test)
         // In a real test we'd need to properly mock the creation process
@@ -1183,8 +1367,11 @@ mod tests {
         context
             .eth_context
             .journal()
-            .load_account(context.address)
-            .unwrap();
+            .load_account(context.current_caller)
+            .unwrap()
+            .data
+            .info
+            .balance = U256::from(value);
 
         // Process Create ECALL (this would be complex to fully test)
         // In a real test we'd need to properly mock the creation process
@@ -1296,4 +1483,857 @@ mod tests {
             context.eth_context.journal().db().commit(i.state);
         }
     }
+
+    #[test]
+    fn test_debug_log() {
+        let (mut vm, context) = setup();
+        let mut context = context.with_debug_console();
+
+        let message = b"balance is low";
+        let offset = 200;
+        for (i, byte) in message.iter().enumerate() {
+            vm.memory
+                .write_mem(offset + i as u32, MemoryChuckSize::BYTE, *byte as u32);
+        }
+        vm.memory
+            .write_mem(300, MemoryChuckSize::WordSize, 0xDEADBEEF);
+
+        vm.registers.write_reg(ECALL_CODE_REG, 0xC0); // DebugLog
+        vm.registers.write_reg(DEBUG_LOG_INPUT_REGISTER_1, offset);
+        vm.registers
+            .write_reg(DEBUG_LOG_INPUT_REGISTER_2, message.len() as u32);
+        vm.registers.write_reg(
+            DEBUG_LOG_INPUT_REGISTER_3,
+            DEBUG_LOG_DUMP_REGISTERS | DEBUG_LOG_DUMP_MEMORY,
+        );
+        vm.registers.write_reg(DEBUG_LOG_INPUT_REGISTER_4, 300);
+        vm.registers.write_reg(DEBUG_LOG_INPUT_REGISTER_5, 4);
+
+        let result = process_ecall(&mut vm, &mut context);
+        assert!(result.unwrap().is_empty());
+
+        let console = context.debug_console.as_ref().unwrap();
+        assert_eq!(console.messages(), vec!["balance is low"]);
+
+        let entry = &console.entries[0];
+        assert_eq!(entry.address, context.address);
+        assert_eq!(entry.registers.unwrap()[ECALL_CODE_REG as usize], 0xC0);
+        assert_eq!(entry.memory, Some((300, vec![0xDE, 0xAD, 0xBE, 0xEF])));
+        assert!(console.to_string().contains("0000012c: de ad be ef"));
+    }
+
+    #[test]
+    fn test_debug_log_disabled() {
+        let (mut vm, mut context) = setup();
+
+        // Without a console the ecall does not even look at its buffer
+        vm.registers.write_reg(ECALL_CODE_REG, 0xC0); // DebugLog
+        vm.registers.write_reg(DEBUG_LOG_INPUT_REGISTER_1, u32::MAX);
+        vm.registers.write_reg(DEBUG_LOG_INPUT_REGISTER_2, u32::MAX);
+
+        let result = process_ecall(&mut vm, &mut context);
+        assert!(result.unwrap().is_empty());
+        assert!(context.debug_console.is_none());
+    }
+
+    #[test]
+    fn test_code_cache_reused_across_calls() {
+        let (_, mut context) = setup_2();
+
+        // addi t6, zero, 0xF3 (Return) ; ecall
+        let callee_code = u32_vec_to_bytes(&[0x0F300F93, 0x00000073], 8);
+        let callee = Address::from([0x42; 20]);
+        let mut db = InMemoryDB::default();
+        db.insert_account_info(
+            callee,
+            AccountInfo {
+                code: Some(Bytecode::new_legacy(callee_code.into())),
+                ..Default::default()
+            },
+        );
+        context.eth_context = RevmEthContext::mainnet().with_db(db);
+
+        let static_call = |context: &mut Context| {
+            let mut vm = Vm::new();
+            vm.registers.write_reg(ECALL_CODE_REG, 0xFA); // StaticCall
+            for (i, &val) in address_to_u32_vec(&callee.0).iter().enumerate() {
+                vm.registers
+                    .write_reg(CALL_INPUT_REGISTER_9 + i as u32, val);
+            }
+            process_ecall(&mut vm, context).unwrap();
+        };
+
+        static_call(&mut context);
+        static_call(&mut context);
+        {
+            let cache = context.code_cache.lock().unwrap();
+            assert_eq!((cache.len(), cache.misses, cache.hits), (1, 1, 1));
+        }
+
+        // another transaction handed the same cache does not decode the callee again
+        let mut next_context =
+            Context::new(context.eth_context.clone()).with_code_cache(context.code_cache.clone());
+        static_call(&mut next_context);
+        let cache = context.code_cache.lock().unwrap();
+        assert_eq!((cache.len(), cache.misses, cache.hits), (1, 1, 2));
+    }
+
+    // Runs the single instruction `instruction` with `a1 = rs1` and `a2 = rs2`, returns `a0`
+    fn run_instruction(instruction: u32, rs1: u32, rs2: u32) -> Result<u32, VMErrors> {
+        let (mut vm, mut context) = setup();
+        vm.memory
+            .write_mem(0, MemoryChuckSize::WordSize, instruction);
+        vm.registers.write_reg(11, rs1);
+        vm.registers.write_reg(12, rs2);
+
+        vm.step(false, &mut context)?;
+        assert_eq!(vm.pc, 4);
+        Ok(vm.registers.read_reg(10))
+    }
+
+    #[test]
+    #[cfg(feature = "zba")]
+    fn test_zba() {
+        assert_eq!(run_instruction(0x20c5a533, 5, 7).unwrap(), 17); // sh1add a0, a1, a2
+        assert_eq!(run_instruction(0x20c5c533, 5, 7).unwrap(), 27); // sh2add a0, a1, a2
+        assert_eq!(run_instruction(0x20c5e533, 5, 7).unwrap(), 47); // sh3add a0, a1, a2
+        assert_eq!(
+            run_instruction(0x20c5e533, 0x2000_0001, 1).unwrap(), // sh3add wraps
+            9
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "zbb")]
+    fn test_zbb() {
+        let cases = [
+            (0x40c5f533, 0xff00ff00, 0x0ff00ff0, 0xf000f000), // andn a0, a1, a2
+            (0x40c5e533, 0x00000000, 0xffff0000, 0x0000ffff), // orn a0, a1, a2
+            (0x40c5c533, 0xf0f0f0f0, 0xff00ff00, 0xf00ff00f), // xnor a0, a1, a2
+            (0x60059513, 0x00010000, 0, 15),                  // clz a0, a1
+            (0x60059513, 0x00000000, 0, 32),                  // clz a0, a1
+            (0x60159513, 0x00010000, 0, 16),                  // ctz a0, a1
+            (0x60159513, 0x00000000, 0, 32),                  // ctz a0, a1
+            (0x60259513, 0xf0f0f0f1, 0, 17),                  // cpop a0, a1
+            (0x0ac5e533, 0xffffffff, 1, 1),                   // max a0, a1, a2
+            (0x0ac5f533, 0xffffffff, 1, 0xffffffff),          // maxu a0, a1, a2
+            (0x0ac5c533, 0xffffffff, 1, 0xffffffff),          // min a0, a1, a2
+            (0x0ac5d533, 0xffffffff, 1, 1),                   // minu a0, a1, a2
+            (0x60459513, 0x00000080, 0, 0xffffff80),          // sext.b a0, a1
+            (0x60559513, 0x00008000, 0, 0xffff8000),          // sext.h a0, a1
+            (0x0805c533, 0x12345678, 0, 0x00005678),          // zext.h a0, a1
+            (0x60c59533, 0x80000001, 1, 0x00000003),          // rol a0, a1, a2
+            (0x60c5d533, 0x00000003, 33, 0x80000001),         // ror a0, a1, a2 (amount mod 32)
+            (0x6075d513, 0x00000080, 0, 0x00000001),          // rori a0, a1, 7
+            (0x2875d513, 0x00120300, 0, 0x00ffff00),          // orc.b a0, a1
+            (0x6985d513, 0x12345678, 0, 0x78563412),          // rev8 a0, a1
+        ];
+
+        for (instruction, rs1, rs2, expected) in cases {
+            assert_eq!(
+                run_instruction(instruction, rs1, rs2).unwrap(),
+                expected,
+                "{instruction:#010x}"
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "zbc")]
+    fn test_zbc() {
+        assert_eq!(run_instruction(0x0ac59533, 0b11, 0b11).unwrap(), 0b101); // clmul a0, a1, a2
+        assert_eq!(
+            run_instruction(0x0ac59533, 0x80000001, 0x80000001).unwrap(), // clmul a0, a1, a2
+            0x00000001
+        );
+        assert_eq!(
+            run_instruction(0x0ac5b533, 0x80000000, 0x80000000).unwrap(), // clmulh a0, a1, a2
+            0x40000000
+        );
+        assert_eq!(
+            run_instruction(0x0ac5a533, 0x80000000, 0x80000000).unwrap(), // clmulr a0, a1, a2
+            0x80000000
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "zbkb")]
+    fn test_zbkb() {
+        let cases = [
+            (0x08c5c533, 0x1234abcd, 0x5678ef01, 0xef01abcd), // pack a0, a1, a2
+            (0x08c5f533, 0x000012cd, 0x000034ef, 0x0000efcd), // packh a0, a1, a2
+            (0x6875d513, 0x01020380, 0, 0x8040c001),          // brev8 a0, a1
+            (0x08f59513, 0x0000ffff, 0, 0x55555555),          // zip a0, a1
+            (0x08f59513, 0xffff0000, 0, 0xaaaaaaaa),          // zip a0, a1
+            (0x08f5d513, 0x55555555, 0, 0x0000ffff),          // unzip a0, a1
+            (0x6985d513, 0x12345678, 0, 0x78563412),          // rev8 a0, a1
+            (0x40c5f533, 0xff00ff00, 0x0ff00ff0, 0xf000f000), // andn a0, a1, a2
+        ];
+
+        for (instruction, rs1, rs2, expected) in cases {
+            assert_eq!(
+                run_instruction(instruction, rs1, rs2).unwrap(),
+                expected,
+                "{instruction:#010x}"
+            );
+        }
+        assert_eq!(
+            crate::bitmanip::unzip(crate::bitmanip::zip(0x12345678)),
+            0x12345678
+        );
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "zbb", feature = "zbkb")))]
+    fn test_bitmanip_disabled() {
+        // rol and rev8 are plain RV32IM decode errors without the extensions
+        assert!(matches!(
+            run_instruction(0x60c59533, 1, 1),
+            Err(VMErrors::InvalidFunct7(0b0110000))
+        ));
+        assert!(matches!(
+            run_instruction(0x6985d513, 1, 0),
+            Err(VMErrors::InvalidFunct7(0b0110100))
+        ));
+    }
+
+    #[test]
+    fn test_rv32e_container() {
+        // add a0, a1, a6 ; ecall ; ecall
+        let code = u32_vec_to_bytes(&[0x01058533, 0x00000073, 0x00000073], 12);
+
+        // x16 is fine for RV32I, RV32E code only has x0-x15
+        let (_, mut context) = setup();
+        let mut vm = Vm::from_bin_u8(code.clone()).unwrap();
+        assert!(vm.step(false, &mut context).is_ok());
+        let mut vm = Vm::from_bin_u8(with_container_header(Profile::Rv32E, &code)).unwrap();
+        assert_eq!(vm.profile, Profile::Rv32E);
+        assert!(matches!(
+            vm.step(false, &mut context),
+            Err(VMErrors::InvalidRegister(16))
+        ));
+
+        // the ecall code goes in t0, a0-a5 are registers and the other ecall registers are
+        // words of the block t1 points at
+        let program =
+            DecodedProgram::from_bytes(&with_container_header(Profile::Rv32E, &code[4..]));
+        let mut vm = Vm::from_program(Arc::new(program));
+        let block = STACK_TOP - 128;
+        let word = |register: u32| block + 4 * register;
+        vm.registers.write_reg(RV32E_ECALL_BLOCK_REG, block);
+        vm.registers.write_reg(RV32E_ECALL_CODE_REG, 0x55); // SStore
+        vm.memory
+            .write_mem(word(SSTORE_INPUT_REGISTER_8), MemoryChuckSize::WordSize, 1);
+        vm.memory
+            .write_mem(word(SSTORE_INPUT_REGISTER_16), MemoryChuckSize::WordSize, 7);
+        vm.step(false, &mut context).unwrap();
+
+        vm.registers.write_reg(RV32E_ECALL_CODE_REG, 0x54); // SLoad
+        vm.step(false, &mut context).unwrap();
+        assert_eq!(
+            vm.memory
+                .read_mem(word(SLOAD_OUTPUT_REGISTER_8), MemoryChuckSize::WordSize),
+            Some(7)
+        );
+        // the Vm never touches registers RV32E code cannot name
+        for register in 16..32 {
+            assert_eq!(vm.registers.read_reg(register), 0);
+        }
+
+        // an ecall that only uses a0-a5 does not read the block
+        let program =
+            DecodedProgram::from_bytes(&with_container_header(Profile::Rv32E, &code[4..]));
+        let mut vm = Vm::from_program(Arc::new(program));
+        vm.registers.write_reg(RV32E_ECALL_BLOCK_REG, 1); // misaligned
+        vm.registers.write_reg(RV32E_ECALL_CODE_REG, 0xC1); // Sbrk
+        vm.registers.write_reg(SBRK_INPUT_REGISTER, 100);
+        vm.step(false, &mut context).unwrap();
+        assert_eq!(vm.registers.read_reg(SBRK_OUTPUT_REGISTER), vm.heap.start);
+        vm.registers.write_reg(RV32E_ECALL_CODE_REG, 0x54); // SLoad
+        assert!(matches!(
+            vm.step(false, &mut context),
+            Err(VMErrors::MemoryError)
+        ));
+    }
+
+    #[test]
+    fn test_sbrk() {
+        let (_, mut context) = setup();
+        // addi t6, zero, 0xC1 (Sbrk) ; ecall
+        let code = u32_vec_to_bytes(&[0x0C100F93, 0x00000073], 8);
+        let mut vm = Vm::from_bin_u8(code).unwrap();
+        assert_eq!(vm.registers.read_reg(SP), STACK_TOP);
+        assert_eq!(vm.heap.start, PAGE_SIZE);
+
+        // the first allocation pays for its page
+        vm.registers.write_reg(SBRK_INPUT_REGISTER, 100);
+        vm.step(false, &mut context).unwrap();
+        vm.step(false, &mut context).unwrap();
+        assert_eq!(vm.registers.read_reg(SBRK_OUTPUT_REGISTER), PAGE_SIZE);
+        assert_eq!(vm.heap.brk, PAGE_SIZE + 100);
+        // the stack is left where it was
+        assert_eq!(vm.registers.read_reg(SP), STACK_TOP);
+        assert_eq!(context.gas.spent(), HEAP_PAGE_COST);
+
+        // the break is usable memory
+        vm.memory
+            .write_mem(PAGE_SIZE + 96, MemoryChuckSize::WordSize, 0xDEADBEEF);
+
+        // growing within the paid page is free, two more pages cost two pages
+        vm.registers.write_reg(SBRK_INPUT_REGISTER, 2 * PAGE_SIZE);
+        process_ecall(&mut vm, &mut context).unwrap();
+        assert_eq!(vm.registers.read_reg(SBRK_OUTPUT_REGISTER), PAGE_SIZE + 100);
+        assert_eq!(context.gas.spent(), 3 * HEAP_PAGE_COST);
+
+        // a heap shrinking below its start, or growing into the calldata, fails with -1 and keeps
+        // the break
+        vm.registers
+            .write_reg(SBRK_INPUT_REGISTER, -(4 * PAGE_SIZE as i32) as u32);
+        process_ecall(&mut vm, &mut context).unwrap();
+        assert_eq!(vm.registers.read_reg(SBRK_OUTPUT_REGISTER), u32::MAX);
+        assert_eq!(vm.heap.brk, 3 * PAGE_SIZE + 100);
+        vm.heap = Heap::new(HEAP_LIMIT - PAGE_SIZE);
+        vm.registers.write_reg(SBRK_INPUT_REGISTER, PAGE_SIZE + 1);
+        process_ecall(&mut vm, &mut context).unwrap();
+        assert_eq!(vm.registers.read_reg(SBRK_OUTPUT_REGISTER), u32::MAX);
+        vm.heap = Heap::new(PAGE_SIZE);
+
+        // running out of gas for the pages traps
+        context.gas = Gas::new(HEAP_PAGE_COST);
+        vm.registers.write_reg(SBRK_INPUT_REGISTER, 2 * PAGE_SIZE);
+        assert!(matches!(
+            process_ecall(&mut vm, &mut context),
+            Err(VMErrors::OutOfGas)
+        ));
+        // and leaves the break where it was
+        assert_eq!(vm.heap, Heap::new(PAGE_SIZE));
+    }
+
+    /// The break of a Vm, moved by running its `Sbrk` ecall
+    struct VmBreak(RefCell<(Vm, Context)>);
+
+    impl ProgramBreak for VmBreak {
+        fn sbrk(&self, increment: i32) -> Option<usize> {
+            let (vm, context) = &mut *self.0.borrow_mut();
+            vm.registers.write_reg(ECALL_CODE_REG, SBRK);
+            vm.registers
+                .write_reg(SBRK_INPUT_REGISTER, increment as u32);
+            process_ecall(vm, context).unwrap();
+            let previous = vm.registers.read_reg(SBRK_OUTPUT_REGISTER);
+            (previous != u32::MAX).then_some(previous as usize)
+        }
+    }
+
+    #[test]
+    fn test_guest_allocator() {
+        assert_eq!(RiscvEVMECalls::from_u32(SBRK), Some(RiscvEVMECalls::Sbrk));
+        let (_, context) = setup();
+        // a one-word image, the heap starts on the page after it
+        let vm = Vm::from_bin_u8(u32_vec_to_bytes(&[0x00000073], 4)).unwrap();
+        let allocator = BumpAllocator::with_break(VmBreak(RefCell::new((vm, context))));
+        let alloc = |size, align| unsafe {
+            allocator.alloc(Layout::from_size_align(size, align).unwrap()) as usize as u32
+        };
+
+        // allocations start at the heap and are carved out in order, aligned
+        assert_eq!(alloc(100, 8), PAGE_SIZE);
+        let block = alloc(PAGE_SIZE as usize, 16);
+        assert_eq!(block, PAGE_SIZE + 112);
+        {
+            let (vm, context) = &mut *allocator_break(&allocator);
+            assert_eq!(vm.heap.brk, block + PAGE_SIZE);
+            assert_eq!(context.gas.spent(), 2 * HEAP_PAGE_COST);
+            // the memory is the guest's to use
+            write_guest_bytes(vm, block, &[1, 2, 3, 4]).unwrap();
+            assert_eq!(read_guest_bytes(vm, block, 4).unwrap(), [1, 2, 3, 4]);
+        }
+
+        // a heap that cannot grow that far fails the allocation and keeps the break
+        assert_eq!(alloc(HEAP_LIMIT as usize, 1), 0);
+        assert_eq!(alloc(4, 4), block + PAGE_SIZE);
+    }
+
+    fn allocator_break(allocator: &BumpAllocator<VmBreak>) -> std::cell::RefMut<'_, (Vm, Context)> {
+        allocator.program_break().0.borrow_mut()
+    }
+
+    #[test]
+    fn test_call_data_and_return_data_mapped() {
+        let (_, mut context) = setup();
+        // lw a2, 0(a0) ; lw a3, 4(a0) ; sw a2, 0(a0)
+        let program = DecodedProgram::new(vec![0x00052603, 0x00452683, 0x00c52023], 0);
+        let mut vm = Vm::from_program(Arc::new(program));
+        vm.enter_frame(&[1, 2, 3, 4, 5]).unwrap();
+        assert_eq!(vm.registers.read_reg(10), CALL_DATA_ADDRESS);
+        assert_eq!(vm.registers.read_reg(11), 5);
+
+        // calldata is read in place, zero padded, and can not be written
+        vm.step(false, &mut context).unwrap();
+        vm.step(false, &mut context).unwrap();
+        assert_eq!(vm.registers.read_reg(12), 0x01020304);
+        assert_eq!(vm.registers.read_reg(13), 0x05000000);
+        assert!(matches!(
+            vm.step(false, &mut context),
+            Err(VMErrors::AccessViolation(CALL_DATA_ADDRESS, Access::Write))
+        ));
+
+        // a shorter return data clears what is left of the previous one
+        vm.map_return_data(&[0xaa; 8]).unwrap();
+        vm.map_return_data(&[0xbb; 2]).unwrap();
+        assert_eq!(vm.registers.read_reg(RETURN_DATA_SIZE_REGISTER), 2);
+        assert_eq!(
+            read_guest_bytes(&vm, RETURN_DATA_ADDRESS, 8).unwrap(),
+            [0xbb, 0xbb, 0, 0, 0, 0, 0, 0]
+        );
+        assert!(matches!(
+            write_guest_bytes(&mut vm, RETURN_DATA_ADDRESS, &[0]),
+            Err(VMErrors::AccessViolation(
+                RETURN_DATA_ADDRESS,
+                Access::Write
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_call_maps_callee_return_data() {
+        let (_, mut context) = setup_2();
+
+        // the callee echoes its calldata straight from the calldata window:
+        // mv ra, a0 ; mv sp, a1 ; addi t6, zero, 0xF3 (Return) ; ecall
+        let callee_code = u32_vec_to_bytes(&[0x00050093, 0x00058113, 0x0F300F93, 0x00000073], 16);
+        let callee = Address::from([0x42; 20]);
+        let mut db = InMemoryDB::default();
+        db.insert_account_info(
+            callee,
+            AccountInfo {
+                code: Some(Bytecode::new_legacy(callee_code.into())),
+                ..Default::default()
+            },
+        );
+        context.eth_context = RevmEthContext::mainnet().with_db(db);
+
+        let mut vm = Vm::new();
+        let call_data = [0xde, 0xad, 0xbe, 0xef, 0x01];
+        write_guest_bytes(&mut vm, 0x100, &call_data).unwrap();
+        vm.registers.write_reg(ECALL_CODE_REG, 0xFA); // StaticCall
+        for (i, &val) in address_to_u32_vec(&callee.0).iter().enumerate() {
+            vm.registers
+                .write_reg(CALL_INPUT_REGISTER_9 + i as u32, val);
+        }
+        vm.registers.write_reg(CALL_INPUT_REGISTER_22, 0x100);
+        vm.registers
+            .write_reg(CALL_INPUT_REGISTER_23, call_data.len() as u32);
+        process_ecall(&mut vm, &mut context).unwrap();
+
+        assert_eq!(
+            vm.registers.read_reg(RETURN_DATA_SIZE_REGISTER),
+            call_data.len() as u32
+        );
+        assert_eq!(
+            read_guest_bytes(&vm, RETURN_DATA_ADDRESS, 8).unwrap(),
+            [0xde, 0xad, 0xbe, 0xef, 0x01, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn test_call_forwards_requested_gas() {
+        // the callee reads a cold slot (2100) and returns:
+        // li ra, 0 ; addi t6, zero, 0x54 (SLoad) ; ecall ; li sp, 0 ;
+        // addi t6, zero, 0xF3 (Return) ; ecall
+        let callee_code = u32_vec_to_bytes(
+            &[
+                0x00000093, 0x05400F93, 0x00000073, 0x00000113, 0x0F300F93, 0x00000073,
+            ],
+            24,
+        );
+        let callee = Address::from([0x42; 20]);
+
+        // the gas the caller spent on a call asking for `requested` gas, sending `value`
+        let spent = |gas_left: u64, requested: u64, value: u64| {
+            let (_, mut context) = setup_2();
+            let mut db = InMemoryDB::default();
+            db.insert_account_info(
+                callee,
+                AccountInfo {
+                    code: Some(Bytecode::new_legacy(callee_code.clone().into())),
+                    ..Default::default()
+                },
+            );
+            context.eth_context = RevmEthContext::mainnet().with_db(db);
+            context
+                .eth_context
+                .journal()
+                .load_account(context.address)
+                .unwrap()
+                .data
+                .info
+                .balance = U256::from(value);
+            context.gas = Gas::new(gas_left);
+
+            let mut vm = Vm::new();
+            vm.registers.write_reg(ECALL_CODE_REG, 0xF1); // Call
+            write_word256(
+                &mut vm,
+                CALL_INPUT_REGISTER_1,
+                &U256::from(requested).to_be_bytes(),
+            );
+            write_address(&mut vm, CALL_INPUT_REGISTER_9, &callee.0);
+            write_word256(
+                &mut vm,
+                CALL_INPUT_REGISTER_14,
+                &U256::from(value).to_be_bytes(),
+            );
+            process_ecall(&mut vm, &mut context).unwrap();
+            context.gas.spent()
+        };
+
+        // 2600 for the cold callee, then only what the callee used of the 5000 it was given
+        assert_eq!(spent(100_000, 5000, 0), 2600 + 2100);
+        // a callee given less than it needs runs out of gas and uses up all it was given
+        assert_eq!(spent(100_000, 1000, 0), 2600 + 1000);
+        // at most all but one 64th of the 640 left after the call cost is forwarded
+        assert_eq!(spent(2600 + 640, 100_000, 0), 2600 + 630);
+        // a value transfer (9000) comes with the 2300 stipend, enough for the callee even when
+        // the guest asks for no gas, and what the callee did not use of it goes back
+        assert_eq!(spent(100_000, 0, 1), 2600 + 9000 + 2100 - 2300);
+    }
+
+    #[test]
+    fn test_resumable_ecalls() {
+        let (_, mut context) = setup();
+        // addi t6, zero, 0x30 (Address) ; ecall ; li sp, 0 ; addi t6, zero, 0xF3 (Return) ; ecall
+        let code = u32_vec_to_bytes(
+            &[0x03000F93, 0x00000073, 0x00000113, 0x0F300F93, 0x00000073],
+            20,
+        );
+        let mut vm = Vm::from_bin_u8(code).unwrap();
+
+        let Ok(Execution::Yield(request)) = vm.run_resumable(&mut context) else {
+            panic!("the Vm should yield on the first ecall");
+        };
+        assert_eq!(request.ecall, RiscvEVMECalls::Address);
+        assert_eq!((request.pc, vm.pc), (4, 4));
+        let snapshot = vm.snapshot(&context);
+
+        // the host answers with data only
+        let response = EcallResponse {
+            gas: 2,
+            registers: vec![(ADDRESS_REGISTER_1, 0x1234)],
+            ..Default::default()
+        };
+        let Ok(Execution::Yield(request)) = vm.resume(response, &mut context) else {
+            panic!("the Vm should yield on the second ecall");
+        };
+        assert_eq!(request.ecall, RiscvEVMECalls::Return);
+        assert_eq!(request.pc, 16);
+        assert_eq!(vm.registers.read_reg(ADDRESS_REGISTER_1), 0x1234);
+        assert_eq!(context.gas.spent(), 2);
+
+        let response = EcallResponse {
+            halt: true,
+            ..Default::default()
+        };
+        assert!(matches!(
+            vm.resume(response, &mut context),
+            Ok(Execution::Halted)
+        ));
+        assert!(matches!(
+            vm.resume(EcallResponse::default(), &mut context),
+            Err(VMErrors::NoPendingEcall)
+        ));
+
+        // a snapshot keeps the ecall pending, the synchronous host can fulfil it too
+        let mut restored = Vm::new();
+        let (_, mut restored_context) = setup();
+        restored.restore(&snapshot, &mut restored_context).unwrap();
+        assert_eq!(restored.pending_ecall, Some(request_at(4)));
+        let response = restored_context.fulfil(&request_at(4)).unwrap();
+        let Ok(Execution::Yield(request)) = restored.resume(response, &mut restored_context) else {
+            panic!("the restored Vm should yield on the second ecall");
+        };
+        assert_eq!(request.pc, 16);
+        assert_eq!(
+            restored.registers.read_reg(ADDRESS_REGISTER_1),
+            address_to_u32_vec(&context.address.0)[0]
+        );
+    }
+
+    #[test]
+    fn test_resumable_call() {
+        let (_, mut context) = setup();
+        // addi t6, zero, 0xFA (StaticCall) ; ecall ; li ra, 0 ; li sp, 0 ;
+        // addi t6, zero, 0xF3 (Return) ; ecall
+        let code = u32_vec_to_bytes(
+            &[
+                0x0FA00F93, 0x00000073, 0x00000093, 0x00000113, 0x0F300F93, 0x00000073,
+            ],
+            24,
+        );
+        let mut vm = Vm::from_bin_u8(code).unwrap();
+        let callee = Address::from([0x42; 20]);
+        let call_data = [0xde, 0xad, 0xbe, 0xef, 0x01];
+        write_guest_bytes(&mut vm, 0x100, &call_data).unwrap();
+        write_address(&mut vm, CALL_INPUT_REGISTER_9, &callee.0);
+        vm.registers.write_reg(CALL_INPUT_REGISTER_22, 0x100);
+        vm.registers
+            .write_reg(CALL_INPUT_REGISTER_23, call_data.len() as u32);
+        vm.registers.write_reg(CALL_INPUT_REGISTER_24, 0x200);
+        vm.registers.write_reg(CALL_INPUT_REGISTER_25, 2);
+
+        // the request carries everything the host needs to run the call itself
+        let Ok(Execution::Yield(request)) = vm.run_resumable(&mut context) else {
+            panic!("the Vm should yield on the call");
+        };
+        assert_eq!(request.ecall, RiscvEVMECalls::StaticCall);
+        let EcallArgs::Call {
+            address,
+            value,
+            input,
+            return_offset,
+            return_size,
+            ..
+        } = request.args
+        else {
+            panic!("the call inputs should be decoded");
+        };
+        assert_eq!((address, value), (callee, U256::ZERO));
+        assert_eq!(input, Bytes::from(call_data.to_vec()));
+        assert_eq!((return_offset, return_size), (0x200, 2));
+
+        let output = [0xca, 0xfe, 0xba, 0xbe];
+        let response = EcallResponse {
+            gas: 700,
+            memory: vec![(0x200, output[..2].to_vec())],
+            return_data: Some(Bytes::from(output.to_vec())),
+            ..Default::default()
+        };
+        let Ok(Execution::Yield(request)) = vm.resume(response, &mut context) else {
+            panic!("the Vm should yield on the return");
+        };
+        assert_eq!(request.ecall, RiscvEVMECalls::Return);
+        assert_eq!(read_guest_bytes(&vm, 0x200, 4).unwrap(), [0xca, 0xfe, 0, 0]);
+        assert_eq!(
+            read_guest_bytes(&vm, RETURN_DATA_ADDRESS, 4).unwrap(),
+            output
+        );
+        assert_eq!(vm.registers.read_reg(RETURN_DATA_SIZE_REGISTER), 4);
+        assert_eq!(context.gas.spent(), 700);
+    }
+
+    fn request_at(pc: u32) -> EcallRequest {
+        EcallRequest {
+            ecall: RiscvEVMECalls::Address,
+            pc,
+            args: EcallArgs::None,
+        }
+    }
+
+    #[derive(Default)]
+    struct CallRecorder {
+        /// answer every call with this output instead of running it
+        answer: Option<Bytes>,
+        /// run every call with this calldata instead
+        input: Option<Bytes>,
+        calls: Vec<(CallScheme, Address, Bytes)>,
+        call_ends: Vec<(InstructionResult, Bytes)>,
+        logs: Vec<Log>,
+        steps: Vec<u32>,
+        selfdestructs: Vec<(Address, Address, U256)>,
+    }
+
+    impl Inspector<crate::context::EthContext> for CallRecorder {
+        fn call(
+            &mut self,
+            _context: &mut crate::context::EthContext,
+            inputs: &mut CallInputs,
+        ) -> Option<CallOutcome> {
+            self.calls
+                .push((inputs.scheme, inputs.target_address, inputs.input.clone()));
+            if let Some(input) = &self.input {
+                inputs.input = input.clone();
+            }
+            self.answer.clone().map(|output| {
+                let result = InterpreterResult::new(InstructionResult::Return, output, Gas::new(0));
+                CallOutcome::new(result, inputs.return_memory_offset.clone())
+            })
+        }
+
+        fn call_end(
+            &mut self,
+            _context: &mut crate::context::EthContext,
+            _inputs: &CallInputs,
+            outcome: &mut CallOutcome,
+        ) {
+            self.call_ends
+                .push((outcome.result.result, outcome.result.output.clone()));
+        }
+
+        fn log(
+            &mut self,
+            _interp: &mut Interpreter,
+            _context: &mut crate::context::EthContext,
+            log: Log,
+        ) {
+            self.logs.push(log);
+        }
+
+        fn selfdestruct(&mut self, contract: Address, target: Address, value: U256) {
+            self.selfdestructs.push((contract, target, value));
+        }
+    }
+
+    impl VmHook for CallRecorder {
+        fn before_step(&mut self, vm: &Vm, _instruction: &Instruction) {
+            self.steps.push(vm.pc);
+        }
+    }
+
+    #[test]
+    fn test_inspector() {
+        let (_, mut context) = setup_2();
+
+        // the callee echoes its calldata:
+        // mv ra, a0 ; mv sp, a1 ; addi t6, zero, 0xF3 (Return) ; ecall
+        let callee_code = u32_vec_to_bytes(&[0x00050093, 0x00058113, 0x0F300F93, 0x00000073], 16);
+        let callee = Address::from([0x42; 20]);
+        let mut db = InMemoryDB::default();
+        db.insert_account_info(
+            callee,
+            AccountInfo {
+                code: Some(Bytecode::new_legacy(callee_code.into())),
+                ..Default::default()
+            },
+        );
+        context.eth_context = RevmEthContext::mainnet().with_db(db);
+        let recorder = Arc::new(Mutex::new(CallRecorder::default()));
+        let mut context = context.with_inspector(SharedInspector::with_steps(recorder.clone()));
+
+        let mut vm = Vm::new();
+        let call_data = [0xde, 0xad, 0xbe, 0xef];
+        write_guest_bytes(&mut vm, 0x100, &call_data).unwrap();
+        let static_call = |vm: &mut Vm, context: &mut Context| {
+            vm.registers.write_reg(ECALL_CODE_REG, 0xFA); // StaticCall
+            for (i, &val) in address_to_u32_vec(&callee.0).iter().enumerate() {
+                vm.registers
+                    .write_reg(CALL_INPUT_REGISTER_9 + i as u32, val);
+            }
+            vm.registers.write_reg(CALL_INPUT_REGISTER_22, 0x100);
+            vm.registers.write_reg(CALL_INPUT_REGISTER_23, 4);
+            process_ecall(vm, context).unwrap();
+        };
+        static_call(&mut vm, &mut context);
+
+        {
+            let recorder = recorder.lock().unwrap();
+            assert_eq!(
+                recorder.calls,
+                [(CallScheme::StaticCall, callee, Bytes::from(call_data))]
+            );
+            assert_eq!(
+                recorder.call_ends,
+                [(InstructionResult::Return, Bytes::from(call_data))]
+            );
+            // the callee's steps, RISC-V pcs included
+            assert_eq!(recorder.steps, [0, 4, 8, 12]);
+        }
+
+        // an inspector answering the call skips the callee
+        recorder.lock().unwrap().answer = Some(Bytes::from_static(&[0x99]));
+        static_call(&mut vm, &mut context);
+        {
+            let recorder = recorder.lock().unwrap();
+            assert_eq!(recorder.calls.len(), 2);
+            assert_eq!(recorder.call_ends[1].1, Bytes::from_static(&[0x99]));
+            assert_eq!(recorder.steps.len(), 4);
+        }
+        assert_eq!(vm.registers.read_reg(RETURN_DATA_SIZE_REGISTER), 1);
+
+        // the callee runs with the calldata the inspector put in
+        {
+            let mut recorder = recorder.lock().unwrap();
+            recorder.answer = None;
+            recorder.input = Some(Bytes::from_static(&[0x01, 0x02]));
+        }
+        static_call(&mut vm, &mut context);
+        assert_eq!(
+            recorder.lock().unwrap().call_ends[2].1,
+            Bytes::from_static(&[0x01, 0x02])
+        );
+        assert_eq!(
+            read_guest_bytes(&vm, RETURN_DATA_ADDRESS, 2).unwrap(),
+            [1, 2]
+        );
+
+        // logs are reported too
+        vm.registers.write_reg(ECALL_CODE_REG, 0xA0); // Log0
+        vm.registers.write_reg(LOG0_INPUT_REGISTER_1, 0x100);
+        vm.registers.write_reg(LOG0_INPUT_REGISTER_2, 4);
+        process_ecall(&mut vm, &mut context).unwrap();
+        let recorder = recorder.lock().unwrap();
+        assert_eq!(recorder.logs.len(), 1);
+        assert_eq!(recorder.logs[0].data.data, Bytes::from(call_data));
+    }
+
+    #[test]
+    fn test_selfdestruct() {
+        let (mut vm, context) = setup();
+        let recorder = Arc::new(Mutex::new(CallRecorder::default()));
+        let mut context = context.with_inspector(SharedInspector::new(recorder.clone()));
+        let mut db = InMemoryDB::default();
+        db.insert_account_info(
+            context.address,
+            AccountInfo {
+                balance: U256::from(1000),
+                ..Default::default()
+            },
+        );
+        context.eth_context = RevmEthContext::mainnet().with_db(db);
+        let target = Address::from([0x42; 20]);
+
+        vm.running = true;
+        vm.registers.write_reg(ECALL_CODE_REG, 0xFF); // SelfDestruct
+        write_address(&mut vm, SELFDESTRUCT_INPUT_REGISTER_1, &target.0);
+        process_ecall(&mut vm, &mut context).unwrap();
+
+        // halts, paying 5000 + 2600 for the cold target + 25000 for sending value to a new account
+        assert!(!vm.running);
+        assert_eq!(context.gas.spent(), 32600);
+        let balance = |context: &mut Context, address| {
+            context
+                .eth_context
+                .journal()
+                .load_account(address)
+                .unwrap()
+                .data
+                .info
+                .balance
+        };
+        assert_eq!(balance(&mut context, target), U256::from(1000));
+        let contract = context.address;
+        assert_eq!(balance(&mut context, contract), U256::ZERO);
+        assert_eq!(
+            recorder.lock().unwrap().selfdestructs,
+            [(contract, target, U256::from(1000))]
+        );
+
+        // the balance stays put when the ecall can not be paid for
+        let (mut vm, mut context) = setup();
+        context.eth_context = RevmEthContext::mainnet().with_db(InMemoryDB::default());
+        context.gas = Gas::new(5000);
+        vm.registers.write_reg(ECALL_CODE_REG, 0xFF);
+        write_address(&mut vm, SELFDESTRUCT_INPUT_REGISTER_1, &target.0);
+        context
+            .eth_context
+            .journal()
+            .load_account(context.address)
+            .unwrap()
+            .data
+            .info
+            .balance = U256::from(1000);
+        assert!(matches!(
+            process_ecall(&mut vm, &mut context),
+            Err(VMErrors::OutOfGas)
+        ));
+        assert_eq!(balance(&mut context, contract), U256::from(1000));
+    }
 }
@@ -0,0 +1,447 @@
+//! Opt-in execution trace recorder.
+//! Attach a [Tracer] to [Vm::tracer](crate::vm::Vm) and every step records the pc, the raw instruction,
+//! the registers it wrote, the memory it touched and the ecall it made (if any).
+//! A trace can be written as JSON Lines (one step per line) or as a compact binary stream, and read back
+//! from the binary format to replay or diff two runs.
+use std::io::{self, Read, Write};
+
+use crate::instructions::{ECALL, Instruction};
+use riscv_evm_core::{
+    MemoryChuckSize, Registers, e_constants::ECALL_CODE_REG, interfaces::MemoryInterface,
+};
+
+/// Magic bytes at the start of a binary trace
+pub const TRACE_MAGIC: [u8; 4] = *b"RVTR";
+/// Version of the binary trace format
+pub const TRACE_VERSION: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterWrite {
+    pub register: u8,
+    pub value: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub address: u32,
+    /// Access width in bytes (1, 2 or 4)
+    pub size: u8,
+    pub is_write: bool,
+    /// The value loaded, or the value stored
+    pub value: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EcallTrace {
+    pub code: u32,
+    /// x1 - x30 when the ecall was made
+    pub arguments: Vec<u32>,
+    /// The registers the ecall wrote
+    pub results: Vec<RegisterWrite>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceStep {
+    pub pc: u32,
+    pub instruction: u32,
+    pub registers_written: Vec<RegisterWrite>,
+    pub memory: Vec<MemoryAccess>,
+    pub ecall: Option<EcallTrace>,
+}
+
+/// State captured before a step executes, used to compute the step's diff afterwards
+#[derive(Debug, Clone)]
+pub struct PendingStep {
+    pc: u32,
+    instruction: u32,
+    registers: Registers,
+    memory: Option<(u32, MemoryChuckSize, bool)>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Tracer {
+    pub steps: Vec<TraceStep>,
+}
+
+impl Tracer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Captures the state needed to trace the instruction at `pc`, called before it executes
+    pub fn before_step(&self, pc: u32, instruction: u32, registers: &Registers) -> PendingStep {
+        PendingStep {
+            pc,
+            instruction,
+            registers: registers.clone(),
+            memory: memory_access_of(instruction, registers),
+        }
+    }
+
+    /// Records the step once it has executed (successfully or not)
+    pub fn after_step(
+        &mut self,
+        pending: PendingStep,
+        registers: &Registers,
+        memory: &impl MemoryInterface,
+    ) {
+        let registers_written: Vec<RegisterWrite> = (1..32)
+            .filter(|&i| pending.registers.read_reg(i) != registers.read_reg(i))
+            .map(|i| RegisterWrite {
+                register: i as u8,
+                value: registers.read_reg(i),
+            })
+            .collect();
+
+        let memory = pending
+            .memory
+            .map(|(address, chunk, is_write)| {
+                let size = chunk_size_in_bytes(&chunk);
+                MemoryAccess {
+                    address,
+                    size,
+                    is_write,
+                    // a misaligned access traps before touching memory, so there is no value to show
+                    value: if address % size as u32 == 0 {
+                        memory.read_mem(address, chunk).unwrap_or_default()
+                    } else {
+                        0
+                    },
+                }
+            })
+            .into_iter()
+            .collect();
+
+        let ecall = (pending.instruction == ECALL).then(|| EcallTrace {
+            code: pending.registers.read_reg(ECALL_CODE_REG),
+            arguments: (1..ECALL_CODE_REG)
+                .map(|i| pending.registers.read_reg(i))
+                .collect(),
+            results: registers_written.clone(),
+        });
+
+        self.steps.push(TraceStep {
+            pc: pending.pc,
+            instruction: pending.instruction,
+            registers_written,
+            memory,
+            ecall,
+        });
+    }
+
+    /// Writes the trace as JSON Lines, one step per line
+    pub fn write_json_lines(&self, writer: &mut impl Write) -> io::Result<()> {
+        for step in &self.steps {
+            writeln!(writer, "{}", step.to_json())?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the trace in the compact binary format, all integers are little-endian
+    pub fn write_binary(&self, writer: &mut impl Write) -> io::Result<()> {
+        writer.write_all(&TRACE_MAGIC)?;
+        writer.write_all(&[TRACE_VERSION])?;
+        writer.write_all(&(self.steps.len() as u64).to_le_bytes())?;
+
+        for step in &self.steps {
+            writer.write_all(&step.pc.to_le_bytes())?;
+            writer.write_all(&step.instruction.to_le_bytes())?;
+            write_register_writes(writer, &step.registers_written)?;
+
+            writer.write_all(&[step.memory.len() as u8])?;
+            for access in &step.memory {
+                writer.write_all(&access.address.to_le_bytes())?;
+                writer.write_all(&[access.size, access.is_write as u8])?;
+                writer.write_all(&access.value.to_le_bytes())?;
+            }
+
+            match &step.ecall {
+                Some(ecall) => {
+                    writer.write_all(&[1])?;
+                    writer.write_all(&ecall.code.to_le_bytes())?;
+                    writer.write_all(&[ecall.arguments.len() as u8])?;
+                    for argument in &ecall.arguments {
+                        writer.write_all(&argument.to_le_bytes())?;
+                    }
+                    write_register_writes(writer, &ecall.results)?;
+                }
+                None => writer.write_all(&[0])?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads a trace written by [Tracer::write_binary]
+    pub fn read_binary(reader: &mut impl Read) -> io::Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != TRACE_MAGIC || read_u8(reader)? != TRACE_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a trace file",
+            ));
+        }
+
+        let mut count = [0u8; 8];
+        reader.read_exact(&mut count)?;
+        let count = u64::from_le_bytes(count);
+
+        let mut steps = Vec::new();
+        for _ in 0..count {
+            let pc = read_u32(reader)?;
+            let instruction = read_u32(reader)?;
+            let registers_written = read_register_writes(reader)?;
+
+            let mut memory = Vec::new();
+            for _ in 0..read_u8(reader)? {
+                let address = read_u32(reader)?;
+                let size = read_u8(reader)?;
+                let is_write = read_u8(reader)? != 0;
+                let value = read_u32(reader)?;
+                memory.push(MemoryAccess {
+                    address,
+                    size,
+                    is_write,
+                    value,
+                });
+            }
+
+            let ecall = if read_u8(reader)? != 0 {
+                let code = read_u32(reader)?;
+                let mut arguments = Vec::new();
+                for _ in 0..read_u8(reader)? {
+                    arguments.push(read_u32(reader)?);
+                }
+                let results = read_register_writes(reader)?;
+                Some(EcallTrace {
+                    code,
+                    arguments,
+                    results,
+                })
+            } else {
+                None
+            };
+
+            steps.push(TraceStep {
+                pc,
+                instruction,
+                registers_written,
+                memory,
+                ecall,
+            });
+        }
+
+        Ok(Self { steps })
+    }
+
+    /// Returns the index of the first step where the two traces differ, `None` if they are identical
+    pub fn first_divergence(&self, other: &Tracer) -> Option<usize> {
+        let common = std::cmp::min(self.steps.len(), other.steps.len());
+        (0..common)
+            .find(|&i| self.steps[i] != other.steps[i])
+            .or((self.steps.len() != other.steps.len()).then_some(common))
+    }
+}
+
+impl TraceStep {
+    pub fn to_json(&self) -> String {
+        let registers = register_writes_to_json(&self.registers_written);
+        let memory = self
+            .memory
+            .iter()
+            .map(|m| {
+                format!(
+                    "{{\"address\":{},\"size\":{},\"write\":{},\"value\":{}}}",
+                    m.address, m.size, m.is_write, m.value
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        let ecall = match &self.ecall {
+            Some(ecall) => format!(
+                "{{\"code\":{},\"args\":[{}],\"results\":{}}}",
+                ecall.code,
+                ecall
+                    .arguments
+                    .iter()
+                    .map(|a| a.to_string())
+                    .collect::<Vec<_>>()
+                    .join(","),
+                register_writes_to_json(&ecall.results)
+            ),
+            None => String::from("null"),
+        };
+
+        format!(
+            "{{\"pc\":{},\"insn\":{},\"regs\":{},\"mem\":[{}],\"ecall\":{}}}",
+            self.pc, self.instruction, registers, memory, ecall
+        )
+    }
+}
+
+/// Works out the memory access a load or store will make, using the registers before it executes
+fn memory_access_of(
+    instruction: u32,
+    registers: &Registers,
+) -> Option<(u32, MemoryChuckSize, bool)> {
+    let (rs1, imm, chunk, is_write) = match Instruction::decode_any(instruction).ok()? {
+        Instruction::Lb { rs1, imm, .. } | Instruction::Lbu { rs1, imm, .. } => {
+            (rs1, imm, MemoryChuckSize::BYTE, false)
+        }
+        Instruction::Lh { rs1, imm, .. } | Instruction::Lhu { rs1, imm, .. } => {
+            (rs1, imm, MemoryChuckSize::HalfWord, false)
+        }
+        Instruction::Lw { rs1, imm, .. } => (rs1, imm, MemoryChuckSize::WordSize, false),
+        Instruction::Sb { rs1, imm, .. } => (rs1, imm, MemoryChuckSize::BYTE, true),
+        Instruction::Sh { rs1, imm, .. } => (rs1, imm, MemoryChuckSize::HalfWord, true),
+        Instruction::Sw { rs1, imm, .. } => (rs1, imm, MemoryChuckSize::WordSize, true),
+        _ => return None,
+    };
+
+    let address = registers.read_reg(rs1).wrapping_add(imm as u32);
+
+    Some((address, chunk, is_write))
+}
+
+pub(crate) fn chunk_size_in_bytes(chunk: &MemoryChuckSize) -> u8 {
+    match chunk {
+        MemoryChuckSize::BYTE => 1,
+        MemoryChuckSize::HalfWord => 2,
+        MemoryChuckSize::WordSize => 4,
+    }
+}
+
+fn register_writes_to_json(writes: &[RegisterWrite]) -> String {
+    let writes = writes
+        .iter()
+        .map(|w| format!("[{},{}]", w.register, w.value))
+        .collect::<Vec<_>>()
+        .join(",");
+    format!("[{}]", writes)
+}
+
+fn write_register_writes(writer: &mut impl Write, writes: &[RegisterWrite]) -> io::Result<()> {
+    writer.write_all(&[writes.len() as u8])?;
+    for write in writes {
+        writer.write_all(&[write.register])?;
+        writer.write_all(&write.value.to_le_bytes())?;
+    }
+
+    Ok(())
+}
+
+fn read_register_writes(reader: &mut impl Read) -> io::Result<Vec<RegisterWrite>> {
+    let mut writes = Vec::new();
+    for _ in 0..read_u8(reader)? {
+        let register = read_u8(reader)?;
+        let value = read_u32(reader)?;
+        writes.push(RegisterWrite { register, value });
+    }
+
+    Ok(writes)
+}
+
+fn read_u8(reader: &mut impl Read) -> io::Result<u8> {
+    let mut byte = [0u8; 1];
+    reader.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{context::Context, vm::Vm};
+    use revm::{Context as EthContext, MainContext, database::CacheDB};
+
+    fn traced_run() -> Tracer {
+        let code: Vec<u32> = vec![
+            0x00500093, // addi x1, x0, 5
+            0x04102023, // sw x1, 64(x0)
+            0x04002103, // lw x2, 64(x0)
+            0x00000f93, // addi x31, x0, 0 (ecall code for an unknown ecall)
+            0x00000073, // ecall
+        ];
+        let eth_context = EthContext::mainnet().with_db(CacheDB::default());
+        let mut context = Context::new(eth_context);
+        let mut vm = Vm::from_bin(code).unwrap();
+        vm.tracer = Some(Tracer::new());
+        vm.run(false, &mut context);
+
+        vm.tracer.unwrap()
+    }
+
+    #[test]
+    fn test_trace_records_steps() {
+        let tracer = traced_run();
+        let steps = &tracer.steps;
+        assert_eq!(steps.len(), 5);
+
+        assert_eq!(steps[0].pc, 0);
+        assert_eq!(steps[0].instruction, 0x00500093);
+        assert_eq!(
+            steps[0].registers_written,
+            vec![RegisterWrite {
+                register: 1,
+                value: 5
+            }]
+        );
+        assert!(steps[0].memory.is_empty());
+
+        assert_eq!(
+            steps[1].memory,
+            vec![MemoryAccess {
+                address: 64,
+                size: 4,
+                is_write: true,
+                value: 5
+            }]
+        );
+        assert!(steps[1].registers_written.is_empty());
+
+        assert_eq!(steps[2].memory[0].value, 5);
+        assert!(!steps[2].memory[0].is_write);
+        assert_eq!(steps[2].registers_written[0].register, 2);
+
+        // the failed ecall is still recorded with its arguments
+        let ecall = steps[4].ecall.as_ref().unwrap();
+        assert_eq!(ecall.code, 0);
+        assert_eq!(ecall.arguments.len(), 30);
+        assert_eq!(ecall.arguments[0], 5);
+    }
+
+    #[test]
+    fn test_trace_serialization() {
+        let tracer = traced_run();
+
+        let mut json = Vec::new();
+        tracer.write_json_lines(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert_eq!(json.lines().count(), tracer.steps.len());
+        assert_eq!(
+            json.lines().next().unwrap(),
+            "{\"pc\":0,\"insn\":5243027,\"regs\":[[1,5]],\"mem\":[],\"ecall\":null}"
+        );
+
+        let mut binary = Vec::new();
+        tracer.write_binary(&mut binary).unwrap();
+        let decoded = Tracer::read_binary(&mut binary.as_slice()).unwrap();
+        assert_eq!(decoded.steps, tracer.steps);
+        assert_eq!(decoded.first_divergence(&tracer), None);
+
+        let mut other = decoded.clone();
+        other.steps[3].pc = 100;
+        assert_eq!(other.first_divergence(&tracer), Some(3));
+        other.steps.truncate(3);
+        assert_eq!(other.first_divergence(&tracer), Some(3));
+
+        assert!(Tracer::read_binary(&mut &b"nope"[..]).is_err());
+    }
+}
@@ -1,26 +1,58 @@
-use crate::vm::{VMErrors, Vm};
-use riscv_evm_core::{MemoryChuckSize, interfaces::MemoryInterface};
+use crate::{
+    hook::Hook,
+    segments::Access,
+    trace::chunk_size_in_bytes,
+    vm::{VMErrors, Vm},
+};
+use riscv_evm_core::{MAXIMUM_GUEST_BUFFER_SIZE, MemoryChuckSize, interfaces::MemoryInterface};
 
 pub fn process_load_to_reg(
     vm: &mut Vm,
-    decoded_instruction: &crate::instructions::IType,
+    rd: u32,
+    rs1: u32,
+    imm: i32,
     mem_chuck_size: MemoryChuckSize,
     is_signed: bool,
 ) -> Result<(), VMErrors> {
-    let addr = vm
-        .registers
-        .read_reg(decoded_instruction.rs1 as u32)
-        .wrapping_add(decoded_instruction.imm as u32);
+    let addr = vm.registers.read_reg(rs1).wrapping_add(imm as u32);
+    let load_data = load_from_memory(vm, addr, mem_chuck_size, is_signed)?;
+    vm.registers.write_reg(rd, load_data);
 
-    let align_mask = match mem_chuck_size {
+    Ok(())
+}
+
+pub fn process_store_to_memory(
+    vm: &mut Vm,
+    rs1: u32,
+    rs2: u32,
+    imm: i32,
+    mem_chuck_size: MemoryChuckSize,
+) -> Result<(), VMErrors> {
+    let addr = vm.registers.read_reg(rs1).wrapping_add(imm as u32);
+    let data_to_store = vm.registers.read_reg(rs2);
+    store_to_memory(vm, addr, mem_chuck_size, data_to_store)
+}
+
+fn align_mask(mem_chuck_size: &MemoryChuckSize) -> u32 {
+    match mem_chuck_size {
         MemoryChuckSize::BYTE => 0x0,
         MemoryChuckSize::HalfWord => 0x1,
         MemoryChuckSize::WordSize => 0x3,
-    };
+    }
+}
 
-    if (addr & align_mask) != 0x0 {
+/// Loads `addr` (sign extended when `is_signed`), checking alignment and the segment permissions.
+/// Only touches the memory, segments and hook of the Vm, never its registers.
+pub fn load_from_memory(
+    vm: &mut Vm,
+    addr: u32,
+    mem_chuck_size: MemoryChuckSize,
+    is_signed: bool,
+) -> Result<u32, VMErrors> {
+    if (addr & align_mask(&mem_chuck_size)) != 0x0 {
         return Err(VMErrors::MemoryError);
     }
+    vm.segments.check(addr, Access::Read)?;
 
     let mut load_data = match vm.memory.read_mem(addr, mem_chuck_size.clone()) {
         Some(d) => d,
@@ -29,6 +61,9 @@ pub fn process_load_to_reg(
         }
     };
 
+    let size = chunk_size_in_bytes(&mem_chuck_size);
+    Hook::call(vm, |hook, _| hook.on_memory_read(addr, size, load_data));
+
     if is_signed {
         load_data = (match mem_chuck_size {
             MemoryChuckSize::BYTE => (load_data as i8) as i32,
@@ -37,32 +72,21 @@ pub fn process_load_to_reg(
         }) as u32;
     }
 
-    vm.registers
-        .write_reg(decoded_instruction.rd as u32, load_data);
-
-    Ok(())
+    Ok(load_data)
 }
 
-pub fn process_store_to_memory(
+/// Stores `data_to_store` at `addr`, checking alignment and the segment permissions.
+/// Only touches the memory, segments and hook of the Vm, never its registers.
+pub fn store_to_memory(
     vm: &mut Vm,
-    decoded_instruction: &crate::instructions::SType,
+    addr: u32,
     mem_chuck_size: MemoryChuckSize,
+    data_to_store: u32,
 ) -> Result<(), VMErrors> {
-    let addr = vm
-        .registers
-        .read_reg(decoded_instruction.rs1 as u32)
-        .wrapping_add(decoded_instruction.imm as u32);
-    let data_to_store = vm.registers.read_reg(decoded_instruction.rs2 as u32);
-
-    let align_mask = match mem_chuck_size {
-        MemoryChuckSize::BYTE => 0x0,
-        MemoryChuckSize::HalfWord => 0x1,
-        MemoryChuckSize::WordSize => 0x3,
-    };
-
-    if (addr & align_mask) != 0x0 {
+    if (addr & align_mask(&mem_chuck_size)) != 0x0 {
         return Err(VMErrors::MemoryError);
     }
+    vm.segments.check(addr, Access::Write)?;
 
     if !vm
         .memory
@@ -71,9 +95,150 @@ pub fn process_store_to_memory(
         return Err(VMErrors::MemoryStoreError);
     }
 
+    let size = chunk_size_in_bytes(&mem_chuck_size);
+    Hook::call(vm, |hook, _| {
+        hook.on_memory_write(addr, size, data_to_store)
+    });
+
     Ok(())
 }
 
+/// Returns the (exclusive) end address of a guest buffer, making sure the buffer is not larger than
+/// [MAXIMUM_GUEST_BUFFER_SIZE] and that `offset + size` does not wrap around the address space
+fn guest_buffer_end(offset: u32, size: u32) -> Result<u32, VMErrors> {
+    if size > MAXIMUM_GUEST_BUFFER_SIZE {
+        return Err(VMErrors::GuestBufferTooLarge(size));
+    }
+
+    offset
+        .checked_add(size)
+        .ok_or(VMErrors::GuestBufferOutOfBounds(offset, size))
+}
+
+/// Reads `size` bytes of guest memory starting at `offset`.
+/// The bytes are copied a word at a time, the whole range is validated before anything is read.
+/// Like the other guest buffer helpers it does not call the memory callbacks of the hook, see
+/// [crate::hook].
+pub fn read_guest_bytes(vm: &Vm, offset: u32, size: u32) -> Result<Vec<u8>, VMErrors> {
+    let end = guest_buffer_end(offset, size)?;
+    if size != 0 && ((end - 1) >> 2) as usize >= vm.memory.memory.len() {
+        return Err(VMErrors::GuestBufferOutOfBounds(offset, size));
+    }
+    vm.segments.check_range(offset, size, Access::Read)?;
+
+    let mut data = Vec::with_capacity(size as usize);
+    let mut addr = offset;
+    while addr < end {
+        let word = vm.memory.memory[(addr >> 2) as usize].to_be_bytes();
+        let start = (addr & 0x3) as usize;
+        let len = std::cmp::min(4 - start, (end - addr) as usize);
+
+        data.extend_from_slice(&word[start..start + len]);
+        addr += len as u32;
+    }
+
+    Ok(data)
+}
+
+/// Writes `data` to guest memory starting at `offset`.
+/// The whole range is validated before anything is written, so a failed write never leaves memory
+/// partially updated.
+pub fn write_guest_bytes(vm: &mut Vm, offset: u32, data: &[u8]) -> Result<(), VMErrors> {
+    let size = u32::try_from(data.len()).map_err(|_| VMErrors::GuestBufferTooLarge(u32::MAX))?;
+    vm.segments.check_range(offset, size, Access::Write)?;
+    map_guest_bytes(vm, offset, data)
+}
+
+/// Writes `data` to guest memory starting at `offset` regardless of the segment permissions, for
+/// the host filling read-only regions (see [crate::memory_map]).
+/// Like [write_guest_bytes] nothing is written unless the whole range is valid.
+pub fn map_guest_bytes(vm: &mut Vm, offset: u32, data: &[u8]) -> Result<(), VMErrors> {
+    let size = u32::try_from(data.len()).map_err(|_| VMErrors::GuestBufferTooLarge(u32::MAX))?;
+    let end = guest_buffer_end(offset, size)?;
+    if size != 0 && ((end - 1) >> 2) as usize >= vm.memory.memory.len() {
+        return Err(VMErrors::GuestBufferOutOfBounds(offset, size));
+    }
+    vm.invalidate_decoded(offset, size);
+
+    let mut addr = offset;
+    let mut written = 0;
+    while addr < end {
+        let word_addr = (addr >> 2) as usize;
+        let mut word = vm.memory.memory[word_addr].to_be_bytes();
+        let start = (addr & 0x3) as usize;
+        let len = std::cmp::min(4 - start, (end - addr) as usize);
+
+        word[start..start + len].copy_from_slice(&data[written..written + len]);
+        vm.memory.set_word(word_addr, u32::from_be_bytes(word));
+        addr += len as u32;
+        written += len;
+    }
+
+    Ok(())
+}
+
+/// Copies `size` bytes of `source` starting at `offset`, bytes past the end of `source` are zero
+/// (this is how the EVM treats calldata, code and return data).
+pub fn copy_padded(source: &[u8], offset: u32, size: u32) -> Result<Vec<u8>, VMErrors> {
+    if size > MAXIMUM_GUEST_BUFFER_SIZE {
+        return Err(VMErrors::GuestBufferTooLarge(size));
+    }
+
+    let mut data = vec![0u8; size as usize];
+    let start = std::cmp::min(offset as usize, source.len());
+    let end = std::cmp::min(
+        (offset as usize).saturating_add(size as usize),
+        source.len(),
+    );
+    data[..end - start].copy_from_slice(&source[start..end]);
+
+    Ok(data)
+}
+
+/// Reads a 256 bit word (big-endian) from the 8 registers starting at `first_register`
+pub fn read_word256(vm: &Vm, first_register: u32) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    for (i, chunk) in word.chunks_exact_mut(4).enumerate() {
+        chunk.copy_from_slice(
+            &vm.registers
+                .read_reg(first_register + i as u32)
+                .to_be_bytes(),
+        );
+    }
+
+    word
+}
+
+/// Writes a 256 bit word (big-endian) to the 8 registers starting at `first_register`
+pub fn write_word256(vm: &mut Vm, first_register: u32, word: &[u8; 32]) {
+    for (i, chunk) in word.chunks_exact(4).enumerate() {
+        vm.registers
+            .write_reg(first_register + i as u32, bytes_to_u32(chunk));
+    }
+}
+
+/// Reads a 20 byte address (big-endian) from the 5 registers starting at `first_register`
+pub fn read_address(vm: &Vm, first_register: u32) -> [u8; 20] {
+    let mut address = [0u8; 20];
+    for (i, chunk) in address.chunks_exact_mut(4).enumerate() {
+        chunk.copy_from_slice(
+            &vm.registers
+                .read_reg(first_register + i as u32)
+                .to_be_bytes(),
+        );
+    }
+
+    address
+}
+
+/// Writes a 20 byte address (big-endian) to the 5 registers starting at `first_register`
+pub fn write_address(vm: &mut Vm, first_register: u32, address: &[u8; 20]) {
+    for (i, chunk) in address.chunks_exact(4).enumerate() {
+        vm.registers
+            .write_reg(first_register + i as u32, bytes_to_u32(chunk));
+    }
+}
+
 // Function to convert a slice of 4 bytes to a u32 (big-endian)
 pub fn bytes_to_u32(bytes: &[u8]) -> u32 {
     ((bytes[0] as u32) << 24)
@@ -649,4 +814,83 @@ mod test {
 
         assert_eq!(roundtrip, large_vector);
     }
+
+    #[test]
+    fn test_copy_padded() {
+        let source = [0x11, 0x22, 0x33, 0x44];
+
+        // Fully inside the source
+        assert_eq!(copy_padded(&source, 1, 2).unwrap(), vec![0x22, 0x33]);
+
+        // Running past the end is zero padded
+        assert_eq!(
+            copy_padded(&source, 2, 4).unwrap(),
+            vec![0x33, 0x44, 0x00, 0x00]
+        );
+
+        // Offsets far past the end do not overflow
+        assert_eq!(copy_padded(&source, u32::MAX, 2).unwrap(), vec![0, 0]);
+
+        // Buffers larger than the cap are rejected
+        assert!(matches!(
+            copy_padded(&source, 0, MAXIMUM_GUEST_BUFFER_SIZE + 1),
+            Err(VMErrors::GuestBufferTooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn test_guest_bytes_roundtrip() {
+        let mut vm = Vm::new();
+        let data = [0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF, 0x01];
+
+        // Unaligned write spanning three words
+        write_guest_bytes(&mut vm, 3, &data).unwrap();
+        assert_eq!(read_guest_bytes(&vm, 3, data.len() as u32).unwrap(), data);
+
+        // The neighbouring bytes are left untouched
+        assert_eq!(vm.memory.read_mem(2, MemoryChuckSize::BYTE).unwrap(), 0);
+        assert_eq!(vm.memory.read_mem(10, MemoryChuckSize::BYTE).unwrap(), 0);
+        assert_eq!(vm.memory.read_mem(3, MemoryChuckSize::BYTE).unwrap(), 0xAA);
+        assert_eq!(vm.memory.read_mem(9, MemoryChuckSize::BYTE).unwrap(), 0x01);
+    }
+
+    #[test]
+    fn test_guest_bytes_out_of_bounds() {
+        let mut vm = Vm::new();
+
+        // offset + size wraps around the address space
+        assert!(matches!(
+            read_guest_bytes(&vm, u32::MAX - 1, 4),
+            Err(VMErrors::GuestBufferOutOfBounds(_, _))
+        ));
+        assert!(matches!(
+            write_guest_bytes(&mut vm, u32::MAX, &[1, 2]),
+            Err(VMErrors::GuestBufferOutOfBounds(_, _))
+        ));
+
+        // Buffers larger than the cap are rejected before touching memory
+        assert!(matches!(
+            read_guest_bytes(&vm, 0, MAXIMUM_GUEST_BUFFER_SIZE + 1),
+            Err(VMErrors::GuestBufferTooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn test_word256_registers_roundtrip() {
+        let mut vm = Vm::new();
+        let mut word = [0u8; 32];
+        for (i, byte) in word.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+
+        write_word256(&mut vm, 1, &word);
+        assert_eq!(vm.registers.read_reg(1), 0x00010203);
+        assert_eq!(vm.registers.read_reg(8), 0x1C1D1E1F);
+        assert_eq!(read_word256(&vm, 1), word);
+
+        let mut address = [0u8; 20];
+        address.copy_from_slice(&word[..20]);
+        write_address(&mut vm, 9, &address);
+        assert_eq!(read_address(&vm, 9), address);
+    }
 }
@@ -1,17 +1,45 @@
 //! This mod holds all the necessary structs and functions to emulate a RISC-V CPU.
 use crate::{
+    bitmanip,
+    code_cache::DecodedProgram,
+    container::{EcallLocation, Profile, split_container},
     context::Context,
-    ecall_manager::process_ecall,
+    debug_console::DebugLogEntry,
+    debug_info::{DebugInfo, SourceLocation},
     elf_parser::Elf,
-    instructions::InstructionDecoder,
-    utils::{bytes_to_u32_vec, process_load_to_reg, process_store_to_memory},
+    fusion::FusedOp,
+    hook::Hook,
+    host::{EcallArgs, EcallHost, EcallRequest, EcallResponse, Execution},
+    instructions::{
+        CSR_CYCLE, CSR_CYCLEH, CSR_INSTRET, CSR_INSTRETH, CSR_TIME, CSR_TIMEH, Instruction, WFI,
+        fetch_instruction, instruction_size,
+    },
+    memory_map::{
+        CALL_DATA_ADDRESS, CALL_DATA_ADDRESS_REGISTER, CALL_DATA_SIZE_REGISTER, DATA_WINDOW_SIZE,
+        Heap, RETURN_DATA_ADDRESS, RETURN_DATA_SIZE_REGISTER, initial_registers,
+    },
+    segments::{Access, Permissions, Segment, SegmentMap},
+    snapshot::Snapshot,
+    trace::Tracer,
+    utils::{
+        bytes_to_u32_vec, map_guest_bytes, process_load_to_reg, process_store_to_memory,
+        read_guest_bytes,
+    },
 };
 use riscv_evm_core::{
-    Memory, MemoryChuckSize, Registers, interfaces::MemoryInterface, sign_extend_u32,
+    Memory, MemoryChuckSize, Registers, WORD_SIZE,
+    e_constants::{
+        DEBUG_LOG_DUMP_MEMORY, DEBUG_LOG_DUMP_REGISTERS, DEBUG_LOG_INPUT_REGISTER_1,
+        DEBUG_LOG_INPUT_REGISTER_2, DEBUG_LOG_INPUT_REGISTER_3, DEBUG_LOG_INPUT_REGISTER_4,
+        DEBUG_LOG_INPUT_REGISTER_5, RV32E_ECALL_BLOCK_REG, RiscvEVMECalls, SBRK_OUTPUT_REGISTER,
+    },
+    interfaces::MemoryInterface,
+    sign_extend_u32,
 };
 use std::{
     fs::File,
     io::{BufReader, Read},
+    sync::Arc,
 };
 
 #[derive(Debug, Clone)]
@@ -25,6 +53,15 @@ pub enum VMErrors {
     MemoryStoreError,
     InvalidFunct7(u32),
     InvalidFunct3(u32),
+    /// The instruction names a register its [crate::container::Profile] does not have
+    InvalidRegister(u32),
+    /// The CSR does not exist or is read-only and was written
+    InvalidCsr(u32),
+    /// An `ebreak` at this address, the pc is left on it so a debugger can inspect the state and
+    /// resume past it
+    Breakpoint(u32),
+    /// `wfi` or a privileged instruction, with its encoding
+    PrivilegedInstruction(u32),
     EnvirmentCallErrorWithDetail(String),
     VMAccountLoadFailed,
     VMCreateError(u32),
@@ -32,26 +69,86 @@ pub enum VMErrors {
     SLoadError(String),
     SStoreError(String),
     CodeLoadError(String),
+    GuestBufferOutOfBounds(u32, u32),
+    GuestBufferTooLarge(u32),
+    /// An access the segment at this address does not allow, see [crate::segments]
+    AccessViolation(u32, Access),
+    /// [Vm::resume] was called while no ecall is pending
+    NoPendingEcall,
+    OutOfGas,
 }
 
+/// [Vm::exit_code] of a frame that ended with the `Revert` ecall
+pub const EXIT_REVERT: u32 = 1;
+/// [Vm::exit_code] of a frame [Vm::run] stopped on a trap
+pub const EXIT_TRAP: u32 = 2;
+
 #[derive(Debug, Clone)]
 pub struct Vm {
     pub registers: Registers,
     pub memory: Memory,
     pub pc: u32,
     pub running: bool,
+    /// 0 while running and after `Return`, otherwise [EXIT_REVERT] or [EXIT_TRAP]
     pub exit_code: u32,
+    /// Opt-in execution trace recorder, `None` unless tracing was requested
+    pub tracer: Option<Tracer>,
+    /// Opt-in instrumentation, see [crate::hook]
+    pub hook: Option<Hook>,
+    /// Function names and source lines of the loaded ELF for trap and revert reports, see
+    /// [crate::debug_info]
+    pub debug_info: Option<Arc<DebugInfo>>,
+    /// Pre-decoded form of the loaded code, instructions outside of it (or after it was
+    /// overwritten) are decoded from memory as they are fetched
+    pub program: Option<Arc<DecodedProgram>>,
+    /// Dispatch fused instruction pairs of the pre-decoded program as one op, see [crate::fusion]
+    pub fuse_instructions: bool,
+    /// Number of instructions retired, a fused op retires both of its instructions
+    pub instret: u64,
+    /// The base ISA the code runs with, see [crate::container]
+    pub profile: Profile,
+    /// Permissions of the address space, see [crate::segments]
+    pub segments: SegmentMap,
+    /// The program break moved by the `Sbrk` ecall, see [crate::memory_map]
+    pub heap: Heap,
+    /// Length of the return data mapped at [RETURN_DATA_ADDRESS]
+    pub return_data_len: u32,
+    /// The ecall the Vm yielded on and waits to be resumed from, see [crate::host]
+    pub pending_ecall: Option<EcallRequest>,
+    /// Hand ecalls to the caller instead of the [Context], set while [Vm::run_resumable] runs
+    yield_ecalls: bool,
+}
+
+/// Reads the file at `path`
+fn read_file(path: String) -> Result<Vec<u8>, anyhow::Error> {
+    let mut file = BufReader::new(File::open(path)?);
+    let mut buf = vec![];
+    file.read_to_end(&mut buf)?;
+    Ok(buf)
 }
 
 impl Vm {
     /// Create a new Vm.
+    /// Every Vm starts with `sp` at the top of the stack, see [crate::memory_map].
     pub fn new() -> Self {
         Self {
-            registers: Registers::new(),
+            registers: initial_registers(),
             memory: Memory::new(),
             pc: 0,
             running: false,
             exit_code: 0,
+            tracer: None,
+            hook: None,
+            debug_info: None,
+            program: None,
+            fuse_instructions: true,
+            instret: 0,
+            profile: Profile::Rv32I,
+            segments: SegmentMap::default(),
+            heap: Heap::default(),
+            return_data_len: 0,
+            pending_ecall: None,
+            yield_ecalls: false,
         }
     }
 
@@ -59,44 +156,190 @@ impl Vm {
     /// # Errors
     /// This function may return an error if the ELF is not valid.
     pub fn from_bin_elf(path: String) -> Result<Self, anyhow::Error> {
-        let mut file = BufReader::new(File::open(path)?);
-        let mut buf = vec![];
-        file.read_to_end(&mut buf).unwrap();
+        Self::from_elf(Elf::decode(&read_file(path)?)?)
+    }
+
+    /// Create a new Vm from a binary ELF file, keeping its symbol table and line table so traps
+    /// and reverts are reported with a backtrace (see [crate::debug_info]).
+    /// # Errors
+    /// This function may return an error if the ELF or its debug info is not valid.
+    pub fn from_bin_elf_with_debug_info(path: String) -> Result<Self, anyhow::Error> {
+        Self::from_elf(Elf::decode_with_debug_info(&read_file(path)?)?)
+    }
 
-        let program_elf_decoded = Elf::decode(&buf)?;
+    fn from_elf(program_elf_decoded: Elf) -> Result<Self, anyhow::Error> {
+        if program_elf_decoded.is_64bit {
+            anyhow::bail!("must be a 32-bit elf, RV64 executables run on crate::vm64::Vm64");
+        }
+        let segments = SegmentMap::from_elf(&program_elf_decoded)?;
 
         Ok(Self {
-            registers: Registers::new(),
-            memory: Memory::new_with_load_program(
-                &program_elf_decoded.instructions,
-                program_elf_decoded.pc_base,
-            ),
+            registers: initial_registers(),
+            memory: program_elf_decoded.memory(),
             pc: program_elf_decoded.pc_start,
             running: false,
             exit_code: 0,
+            tracer: None,
+            hook: None,
+            debug_info: program_elf_decoded.debug_info,
+            program: None,
+            fuse_instructions: true,
+            instret: 0,
+            profile: Profile::Rv32I,
+            heap: Heap::new(segments.end()),
+            return_data_len: 0,
+            pending_ecall: None,
+            yield_ecalls: false,
+            segments,
         })
     }
 
     pub fn from_bin(instructions: Vec<u32>) -> Result<Self, anyhow::Error> {
         Ok(Self {
-            registers: Registers::new(),
+            registers: initial_registers(),
             memory: Memory::new_with_load_program(&instructions, 0),
             pc: 0,
             running: false,
             exit_code: 0,
+            tracer: None,
+            hook: None,
+            debug_info: None,
+            program: None,
+            fuse_instructions: true,
+            instret: 0,
+            profile: Profile::Rv32I,
+            segments: SegmentMap::default(),
+            heap: Heap::new((instructions.len() * WORD_SIZE) as u32),
+            return_data_len: 0,
+            pending_ecall: None,
+            yield_ecalls: false,
         })
     }
 
+    /// Create a new Vm from contract bytes, honouring their container header (see
+    /// [crate::container]). The code is mapped read + execute.
     pub fn from_bin_u8(instructions: Vec<u8>) -> Result<Self, anyhow::Error> {
+        let (profile, code) = split_container(&instructions);
+        let code = bytes_to_u32_vec(code);
+        let segments = SegmentMap::code(0, (code.len() * WORD_SIZE) as u32);
         Ok(Self {
-            registers: Registers::new(),
-            memory: Memory::new_with_load_program(&bytes_to_u32_vec(&instructions), 0),
+            registers: initial_registers(),
+            memory: Memory::new_with_load_program(&code, 0),
             pc: 0,
             running: false,
             exit_code: 0,
+            tracer: None,
+            hook: None,
+            debug_info: None,
+            program: None,
+            fuse_instructions: true,
+            instret: 0,
+            profile,
+            heap: Heap::new(segments.end()),
+            return_data_len: 0,
+            pending_ecall: None,
+            yield_ecalls: false,
+            segments,
         })
     }
 
+    /// Create a new Vm running a pre-decoded program, see [crate::code_cache::CodeCache].
+    /// The code is mapped read + execute.
+    pub fn from_program(program: Arc<DecodedProgram>) -> Self {
+        let segments = SegmentMap::code(program.base, (program.code.len() * WORD_SIZE) as u32);
+        Self {
+            profile: program.profile,
+            registers: initial_registers(),
+            memory: Memory::new_with_load_program(&program.code, program.base),
+            pc: program.base,
+            running: false,
+            exit_code: 0,
+            tracer: None,
+            hook: None,
+            debug_info: None,
+            program: Some(program),
+            fuse_instructions: true,
+            instret: 0,
+            heap: Heap::new(segments.end()),
+            return_data_len: 0,
+            pending_ecall: None,
+            yield_ecalls: false,
+            segments,
+        }
+    }
+
+    /// Drops the pre-decoded program when `[address, address + size)` overwrites part of it, so
+    /// self-modifying code is decoded again from memory.
+    pub fn invalidate_decoded(&mut self, address: u32, size: u32) {
+        if self
+            .program
+            .as_ref()
+            .is_some_and(|program| program.overlaps(address, size))
+        {
+            self.program = None;
+        }
+    }
+
+    /// Maps the calldata of the frame about to run read-only at [CALL_DATA_ADDRESS], with `a0`
+    /// pointing at it and `a1` holding its length, and reserves the read-only return data window.
+    /// Called once when a frame starts, see [crate::memory_map].
+    /// # Errors
+    /// [VMErrors::GuestBufferTooLarge] when the calldata does not fit its window.
+    pub fn enter_frame(&mut self, call_data: &[u8]) -> Result<(), VMErrors> {
+        map_guest_bytes(self, CALL_DATA_ADDRESS, call_data)?;
+        // raw code without segments stays unrestricted
+        if !self.segments.is_unrestricted() {
+            for start in [CALL_DATA_ADDRESS, RETURN_DATA_ADDRESS] {
+                self.segments
+                    .insert(Segment {
+                        start,
+                        size: DATA_WINDOW_SIZE,
+                        permissions: Permissions::READ,
+                    })
+                    .expect("read-only segments are never executable");
+            }
+        }
+
+        self.registers
+            .write_reg(CALL_DATA_ADDRESS_REGISTER, CALL_DATA_ADDRESS);
+        self.registers
+            .write_reg(CALL_DATA_SIZE_REGISTER, call_data.len() as u32);
+        Ok(())
+    }
+
+    /// Maps the return data of the call that just returned at [RETURN_DATA_ADDRESS] in place of
+    /// the previous one, its length goes to [RETURN_DATA_SIZE_REGISTER].
+    /// # Errors
+    /// [VMErrors::GuestBufferTooLarge] when the return data does not fit its window.
+    pub fn map_return_data(&mut self, return_data: &[u8]) -> Result<(), VMErrors> {
+        let size = return_data.len() as u32;
+        // whatever is left of the previous return data reads as zero again
+        let stale = vec![0; self.return_data_len.saturating_sub(size) as usize];
+        map_guest_bytes(self, RETURN_DATA_ADDRESS, return_data)?;
+        map_guest_bytes(self, RETURN_DATA_ADDRESS + size, &stale)?;
+
+        self.return_data_len = size;
+        self.registers.write_reg(RETURN_DATA_SIZE_REGISTER, size);
+        Ok(())
+    }
+
+    /// Captures the Vm along with the gas meter and frame of `context`, see [crate::snapshot]
+    pub fn snapshot(&self, context: &Context) -> Snapshot {
+        Snapshot::capture(self, context)
+    }
+
+    /// Resumes from `snapshot`, replacing the state of the Vm and the gas meter and frame of
+    /// `context`
+    /// # Errors
+    /// When the snapshot has another version or is malformed.
+    pub fn restore(
+        &mut self,
+        snapshot: &Snapshot,
+        context: &mut Context,
+    ) -> Result<(), anyhow::Error> {
+        snapshot.apply(self, context)
+    }
+
     /// Step the Vm.
     /// This function will execute the instruction at the current program counter.
     /// If the instruction is a branch, the program counter will be updated accordingly.
@@ -104,645 +347,745 @@ impl Vm {
     /// If the instruction is a syscall, the program will be halted.
     /// If the instruction is a halt, the program will be halted.
     pub fn step(&mut self, debug_mode: bool, context: &mut Context) -> Result<bool, VMErrors> {
-        // Fetch the instruction from memory
-        let instruction = self
-            .memory
-            .read_mem(self.pc, MemoryChuckSize::WordSize)
-            .ok_or(VMErrors::InvalidMemoryAccess)?;
+        let result = self.step_instruction(debug_mode, context);
+        if let Err(error) = &result {
+            Hook::call(self, |hook, vm| hook.on_trap(vm, error));
+        }
+
+        result
+    }
+
+    fn step_instruction(
+        &mut self,
+        debug_mode: bool,
+        context: &mut Context,
+    ) -> Result<bool, VMErrors> {
+        // native blocks and fused ops are skipped when every instruction has to be observed on its own
+        #[cfg(feature = "jit")]
+        if context.jit
+            && !debug_mode
+            && self.tracer.is_none()
+            && self.hook.is_none()
+            && let Some(program) = self.program.clone()
+            && let Some(result) = crate::jit::execute_block(self, &program, context)
+        {
+            return result;
+        }
 
-        // Decode the instruction
-        let decoded_instruction = InstructionDecoder::decode(&instruction)?;
+        if self.fuse_instructions
+            && !debug_mode
+            && self.tracer.is_none()
+            && self.hook.is_none()
+            && let Some(op) = self
+                .program
+                .as_deref()
+                .and_then(|program| program.fused_at(self.pc))
+        {
+            context.charge_gas(2 * context.instruction_cost)?;
+            self.execute_fused(op);
+            self.instret += 2;
+            return Ok(true);
+        }
+
+        // Fetch the instruction, pre-decoded programs skip both the memory read and the decoding
+        let (instruction, decoded_instruction) = match self
+            .program
+            .as_deref()
+            .and_then(|program| program.fetch(self.pc))
+        {
+            Some((instruction, decoded)) => (instruction, decoded.clone()?),
+            None => {
+                self.segments.check(self.pc, Access::Execute)?;
+                let instruction = fetch_instruction(&self.memory.memory, self.pc)
+                    .ok_or(VMErrors::InvalidMemoryAccess)?;
+                (
+                    instruction,
+                    Instruction::decode_for(instruction, self.profile)?,
+                )
+            }
+        };
 
         if debug_mode {
-            println!("{}", decoded_instruction.to_string());
-        }
-
-        // Execute the instruction
-        match decoded_instruction.decoded_instruction {
-            crate::instructions::DecodedInstruction::RType(rtype) => {
-                match rtype.funct3 {
-                    0b000 => {
-                        // Funct3 for add, sub, mul
-                        match rtype.funct7 {
-                            0b0000000 => {
-                                // Funct7 for add
-                                let rs1 = self.registers.read_reg(rtype.rs1 as u32);
-                                let rs2 = self.registers.read_reg(rtype.rs2 as u32);
-                                let rd = rs1.wrapping_add(rs2);
-                                self.registers.write_reg(rtype.rd as u32, rd);
-                                self.pc += 4;
-                                Ok(true)
-                            }
-                            0b0100000 => {
-                                // Funct7 for sub
-                                let rs1 = self.registers.read_reg(rtype.rs1 as u32);
-                                let rs2 = self.registers.read_reg(rtype.rs2 as u32);
-                                let rd = rs1.wrapping_sub(rs2);
-                                self.registers.write_reg(rtype.rd as u32, rd);
-                                self.pc += 4;
-                                Ok(true)
-                            }
-                            0b0000001 => {
-                                // Funct7 for mul
-                                let rs1 = self.registers.read_reg(rtype.rs1 as u32);
-                                let rs2 = self.registers.read_reg(rtype.rs2 as u32);
-                                let rd = rs1.wrapping_mul(rs2);
-                                self.registers.write_reg(rtype.rd as u32, rd);
-                                self.pc += 4;
-                                Ok(true)
-                            }
-                            _ => return Err(VMErrors::InvalidFunct7(rtype.funct7)),
-                        }
-                    }
-                    0b001 => {
-                        // Funct3 for sll, mulh
-                        match rtype.funct7 {
-                            0b0000000 => {
-                                // Funct7 for sll
-                                let rs1 = self.registers.read_reg(rtype.rs1 as u32);
-                                let rs2 = self.registers.read_reg(rtype.rs2 as u32);
-                                let rd = rs1.wrapping_shl(rs2);
-                                self.registers.write_reg(rtype.rd as u32, rd);
-                                self.pc += 4;
-                                Ok(true)
-                            }
-                            0b0000001 => {
-                                // Funct7 for mulh
-                                let rs1 =
-                                    sign_extend_u32(self.registers.read_reg(rtype.rs1 as u32));
-                                let rs2 =
-                                    sign_extend_u32(self.registers.read_reg(rtype.rs2 as u32));
-                                let rd = (rs1.wrapping_mul(rs2) >> 32) as u32;
-                                self.registers.write_reg(rtype.rd as u32, rd);
-                                self.pc += 4;
-                                Ok(true)
-                            }
-                            _ => return Err(VMErrors::InvalidFunct7(rtype.funct7)),
-                        }
-                    }
-                    0b010 => {
-                        // Funct3 for slt, mulhsu
-                        match rtype.funct7 {
-                            0b0000000 => {
-                                // Funct7 for slt
-                                let rs1 = self.registers.read_reg(rtype.rs1 as u32) as i32;
-                                let rs2 = self.registers.read_reg(rtype.rs2 as u32) as i32;
-                                let rd = if rs1 < rs2 { 1 } else { 0 };
-                                self.registers.write_reg(rtype.rd as u32, rd);
-                                self.pc += 4;
-                                Ok(true)
-                            }
-                            0b0000001 => {
-                                // Funct7 for mulhsu
-                                let rs1 =
-                                    sign_extend_u32(self.registers.read_reg(rtype.rs1 as u32));
-                                let rs2 = self.registers.read_reg(rtype.rs2 as u32) as i64;
-                                let rd = (rs1.wrapping_mul(rs2) >> 32) as u32;
-                                self.registers.write_reg(rtype.rd as u32, rd);
-                                self.pc += 4;
-                                Ok(true)
-                            }
-                            _ => return Err(VMErrors::InvalidFunct7(rtype.funct7)),
-                        }
-                    }
-                    0b011 => {
-                        // Funct3 for sltu, mulhu
-                        match rtype.funct7 {
-                            0b0000000 => {
-                                // Funct7 for sltu
-                                let rs1 = self.registers.read_reg(rtype.rs1 as u32);
-                                let rs2 = self.registers.read_reg(rtype.rs2 as u32);
-                                let rd = if rs1 < rs2 { 1 } else { 0 };
-                                self.registers.write_reg(rtype.rd as u32, rd);
-                                self.pc += 4;
-                                Ok(true)
-                            }
-                            0b0000001 => {
-                                // Funct7 for mulhu
-                                let rs1 = self.registers.read_reg(rtype.rs1 as u32) as u64;
-                                let rs2 = self.registers.read_reg(rtype.rs2 as u32) as u64;
-                                let rd = (rs1.wrapping_mul(rs2) >> 32) as u32;
-                                self.registers.write_reg(rtype.rd as u32, rd);
-                                self.pc += 4;
-                                Ok(true)
-                            }
-                            _ => return Err(VMErrors::InvalidFunct7(rtype.funct7)),
-                        }
-                    }
-                    0b100 => {
-                        // Funct3 for xor, div
-                        match rtype.funct7 {
-                            0b0000000 => {
-                                // Funct7 for xor
-                                let rs1 = self.registers.read_reg(rtype.rs1 as u32);
-                                let rs2 = self.registers.read_reg(rtype.rs2 as u32);
-                                let rd = rs1 ^ rs2;
-                                self.registers.write_reg(rtype.rd as u32, rd);
-                                self.pc += 4;
-                                Ok(true)
-                            }
-                            0b0000001 => {
-                                // Funct7 for div
-                                let rs1 = self.registers.read_reg(rtype.rs1 as u32) as i32;
-                                let rs2 = self.registers.read_reg(rtype.rs2 as u32) as i32;
-                                let rd = if rs2 != 0 {
-                                    rs1.wrapping_div(rs2) as u32
-                                } else {
-                                    u32::MAX
-                                };
-                                self.registers.write_reg(rtype.rd as u32, rd);
-                                self.pc += 4;
-                                Ok(true)
-                            }
-                            _ => return Err(VMErrors::InvalidFunct7(rtype.funct7)),
-                        }
-                    }
-                    0b101 => {
-                        // Funct3 for srl, sra, divu
-                        match rtype.funct7 {
-                            0b0000000 => {
-                                // Funct7 for srl
-                                let rs1 = self.registers.read_reg(rtype.rs1 as u32);
-                                let rs2 = self.registers.read_reg(rtype.rs2 as u32);
-                                let rd = rs1.wrapping_shr(rs2);
-                                self.registers.write_reg(rtype.rd as u32, rd);
-                                self.pc += 4;
-                                Ok(true)
-                            }
-                            0b0000001 => {
-                                // Funct7 for divu
-                                let rs1 = self.registers.read_reg(rtype.rs1 as u32);
-                                let rs2 = self.registers.read_reg(rtype.rs2 as u32);
-                                let rd = if rs2 != 0 { rs1 / rs2 } else { u32::MAX };
-                                self.registers.write_reg(rtype.rd as u32, rd);
-                                self.pc += 4;
-                                Ok(true)
-                            }
-                            0b0100000 => {
-                                // Funct7 for sra
-                                let rs1 = self.registers.read_reg(rtype.rs1 as u32) as i32;
-                                let rs2 = self.registers.read_reg(rtype.rs2 as u32) as u32;
-                                let rd = rs1.wrapping_shr(rs2) as u32;
-                                self.registers.write_reg(rtype.rd as u32, rd);
-                                self.pc += 4;
-                                Ok(true)
-                            }
-                            _ => return Err(VMErrors::InvalidFunct7(rtype.funct7)),
-                        }
-                    }
-                    0b110 => {
-                        // Funct3 for or, rem
-                        match rtype.funct7 {
-                            0b0000000 => {
-                                // Funct7 for or
-                                let rs1 = self.registers.read_reg(rtype.rs1 as u32);
-                                let rs2 = self.registers.read_reg(rtype.rs2 as u32);
-                                let rd = rs1 | rs2;
-                                self.registers.write_reg(rtype.rd as u32, rd);
-                                self.pc += 4;
-                                Ok(true)
-                            }
-                            0b0000001 => {
-                                // Funct7 for rem
-                                let rs1 = self.registers.read_reg(rtype.rs1 as u32) as i32;
-                                let rs2 = self.registers.read_reg(rtype.rs2 as u32) as i32;
-                                let rd = if rs2 != 0 {
-                                    rs1.wrapping_rem(rs2) as u32
-                                } else {
-                                    rs1 as u32
-                                };
-                                self.registers.write_reg(rtype.rd as u32, rd);
-                                self.pc += 4;
-                                Ok(true)
-                            }
-                            _ => return Err(VMErrors::InvalidFunct7(rtype.funct7)),
-                        }
-                    }
-                    0b111 => {
-                        // Funct3 for and, remu
-                        match rtype.funct7 {
-                            0b0000000 => {
-                                // Funct7 for and
-                                let rs1 = self.registers.read_reg(rtype.rs1 as u32);
-                                let rs2 = self.registers.read_reg(rtype.rs2 as u32);
-                                let rd = rs1 & rs2;
-                                self.registers.write_reg(rtype.rd as u32, rd);
-                                self.pc += 4;
-                                Ok(true)
-                            }
-                            0b0000001 => {
-                                // Funct7 for remu
-                                let rs1 = self.registers.read_reg(rtype.rs1 as u32);
-                                let rs2 = self.registers.read_reg(rtype.rs2 as u32);
-                                let rd = if rs2 != 0 { rs1 % rs2 } else { rs1 };
-                                self.registers.write_reg(rtype.rd as u32, rd);
-                                self.pc += 4;
-                                Ok(true)
-                            }
-                            _ => return Err(VMErrors::InvalidFunct7(rtype.funct7)),
-                        }
-                    }
-                    _ => return Err(VMErrors::InvalidFunct3(rtype.funct3)),
-                }
+            println!("{decoded_instruction}");
+        }
+
+        context.charge_gas(context.instruction_cost)?;
+
+        let pending_trace = self
+            .tracer
+            .as_ref()
+            .map(|tracer| tracer.before_step(self.pc, instruction, &self.registers));
+
+        Hook::call(self, |hook, vm| hook.before_step(vm, &decoded_instruction));
+
+        let result = self.execute(decoded_instruction, instruction_size(instruction), context);
+        if result.is_ok() {
+            self.instret += 1;
+            Hook::call(self, |hook, vm| hook.after_step(vm, &decoded_instruction));
+        }
+
+        if let (Some(pending), Some(tracer)) = (pending_trace, self.tracer.as_mut()) {
+            tracer.after_step(pending, &self.registers, &self.memory);
+        }
+
+        result
+    }
+
+    /// Execute a decoded instruction of `size` bytes.
+    /// Every instruction is dispatched from this single `match`, decoding and validation already
+    /// happened in [Instruction::decode_any].
+    fn execute(
+        &mut self,
+        instruction: Instruction,
+        size: u32,
+        context: &mut Context,
+    ) -> Result<bool, VMErrors> {
+        let next_pc = self.pc.wrapping_add(size);
+        // straight-line instructions fall through to `next_pc`, control flow sets the pc itself
+        match instruction {
+            Instruction::Add { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, u32::wrapping_add),
+            Instruction::Sub { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, u32::wrapping_sub),
+            Instruction::Sll { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, u32::wrapping_shl),
+            Instruction::Slt { rd, rs1, rs2 } => {
+                self.op_reg(rd, rs1, rs2, |a, b| ((a as i32) < (b as i32)) as u32)
             }
-            crate::instructions::DecodedInstruction::IType(itype) => {
-                match decoded_instruction.opcode {
-                    0b0010011 => {
-                        // Funct3 for addi, slti, sltiu, xori, ori, andi
-                        match itype.funct3 {
-                            0b000 => {
-                                // Funct3 for addi
-                                let rs1 = self.registers.read_reg(itype.rs1 as u32);
-                                let imm = itype.imm as u32;
-                                let rd = rs1.wrapping_add(imm);
-                                self.registers.write_reg(itype.rd as u32, rd);
-                                self.pc += 4;
-                                Ok(true)
-                            }
-                            0b001 => {
-                                // Funct3 for slli
-                                let rs1 = self.registers.read_reg(itype.rs1 as u32);
-                                let imm = itype.metadata.imm_shift_amt;
-                                let rd = rs1.wrapping_shl(imm);
-                                self.registers.write_reg(itype.rd as u32, rd);
-                                self.pc += 4;
-                                Ok(true)
-                            }
-                            0b010 => {
-                                // Funct3 for slti
-                                let rs1 = self.registers.read_reg(itype.rs1 as u32) as i32;
-                                let imm = itype.imm as i32;
-                                let rd = if rs1 < imm { 1 } else { 0 };
-                                self.registers.write_reg(itype.rd as u32, rd);
-                                self.pc += 4;
-                                Ok(true)
-                            }
-                            0b011 => {
-                                // Funct3 for sltiu
-                                let rs1 = self.registers.read_reg(itype.rs1 as u32);
-                                let imm = itype.imm as u32;
-                                let rd = if rs1 < imm { 1 } else { 0 };
-                                self.registers.write_reg(itype.rd as u32, rd);
-                                self.pc += 4;
-                                Ok(true)
-                            }
-                            0b100 => {
-                                // Funct3 for xori
-                                let rs1 = self.registers.read_reg(itype.rs1 as u32);
-                                let imm = itype.imm as u32;
-                                let rd = rs1 ^ imm;
-                                self.registers.write_reg(itype.rd as u32, rd);
-                                self.pc += 4;
-                                Ok(true)
-                            }
-                            0b101 => {
-                                // Funct3 for srli, srai
-                                match itype.metadata.funct7 {
-                                    0b0000000 => {
-                                        // Funct7 for srli
-                                        let rs1 = self.registers.read_reg(itype.rs1 as u32);
-                                        let imm = itype.metadata.imm_shift_amt;
-                                        let rd = rs1.wrapping_shr(imm);
-                                        self.registers.write_reg(itype.rd as u32, rd);
-                                        self.pc += 4;
-                                        Ok(true)
-                                    }
-                                    0b0100000 => {
-                                        // Funct7 for srai
-                                        let rs1 = self.registers.read_reg(itype.rs1 as u32) as i32;
-                                        let imm = itype.metadata.imm_shift_amt;
-                                        let rd = rs1.wrapping_shr(imm) as u32;
-                                        self.registers.write_reg(itype.rd as u32, rd);
-                                        self.pc += 4;
-                                        Ok(true)
-                                    }
-                                    _ => {
-                                        return Err(VMErrors::InvalidFunct7(itype.metadata.funct7));
-                                    }
-                                }
-                            }
-                            0b110 => {
-                                // Funct3 for ori
-                                let rs1 = self.registers.read_reg(itype.rs1 as u32);
-                                let imm = itype.imm as u32;
-                                let rd = rs1 | imm;
-                                self.registers.write_reg(itype.rd as u32, rd);
-                                self.pc += 4;
-                                Ok(true)
-                            }
-                            0b111 => {
-                                // Funct3 for andi
-                                let rs1 = self.registers.read_reg(itype.rs1 as u32);
-                                let imm = itype.imm as u32;
-                                let rd = rs1 & imm;
-                                self.registers.write_reg(itype.rd as u32, rd);
-                                self.pc += 4;
-                                Ok(true)
-                            }
-                            _ => return Err(VMErrors::InvalidFunct3(itype.funct3)),
-                        }
-                    }
-                    0b0000011 => {
-                        // Funct3 for lb, lh, lw, lbu, lhu
-                        match itype.funct3 {
-                            0b000 => {
-                                // Funct3 for lb
-                                match process_load_to_reg(self, &itype, MemoryChuckSize::BYTE, true)
-                                {
-                                    Ok(_) => {
-                                        self.pc += 4;
-                                        Ok(true)
-                                    }
-                                    Err(e) => Err(e),
-                                }
-                            }
-                            0b001 => {
-                                // Funct3 for lh
-                                match process_load_to_reg(
-                                    self,
-                                    &itype,
-                                    MemoryChuckSize::HalfWord,
-                                    true,
-                                ) {
-                                    Ok(_) => {
-                                        self.pc += 4;
-                                        Ok(true)
-                                    }
-                                    Err(e) => Err(e),
-                                }
-                            }
-                            0b010 => {
-                                // Funct3 for lw
-                                match process_load_to_reg(
-                                    self,
-                                    &itype,
-                                    MemoryChuckSize::WordSize,
-                                    false,
-                                ) {
-                                    Ok(_) => {
-                                        self.pc += 4;
-                                        Ok(true)
-                                    }
-                                    Err(e) => Err(e),
-                                }
-                            }
-                            0b100 => {
-                                // Funct3 for lbu
-                                match process_load_to_reg(
-                                    self,
-                                    &itype,
-                                    MemoryChuckSize::BYTE,
-                                    false,
-                                ) {
-                                    Ok(_) => {
-                                        self.pc += 4;
-                                        Ok(true)
-                                    }
-                                    Err(e) => Err(e),
-                                }
-                            }
-                            0b101 => {
-                                // Funct3 for lhu
-                                match process_load_to_reg(
-                                    self,
-                                    &itype,
-                                    MemoryChuckSize::HalfWord,
-                                    false,
-                                ) {
-                                    Ok(_) => {
-                                        self.pc += 4;
-                                        Ok(true)
-                                    }
-                                    Err(e) => Err(e),
-                                }
-                            }
-                            _ => return Err(VMErrors::InvalidFunct3(itype.funct3)),
-                        }
-                    }
-                    0b1100111 => {
-                        // Funct3 for jalr
-                        match itype.funct3 {
-                            0b000 => {
-                                // Funct3 for jalr
-                                let rs1 = self.registers.read_reg(itype.rs1 as u32);
-                                let imm = itype.imm as u32;
-                                let mut dest_addr = rs1.wrapping_add(imm);
-
-                                // see that dest_addr is even
-                                dest_addr &= 0xfffffffe;
-                                self.registers.write_reg(itype.rd as u32, self.pc + 4);
-                                self.pc = dest_addr;
-                                Ok(true)
-                            }
-                            _ => return Err(VMErrors::InvalidFunct3(itype.funct3)),
-                        }
-                    }
-                    0b1110011 => {
-                        process_ecall(self, context)?;
-                        self.pc += 4;
-                        Ok(true)
-                    }
-                    _ => return Err(VMErrors::InvalidOpcode(decoded_instruction.opcode)),
+            Instruction::Sltu { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, |a, b| (a < b) as u32),
+            Instruction::Xor { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, |a, b| a ^ b),
+            Instruction::Srl { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, u32::wrapping_shr),
+            Instruction::Sra { rd, rs1, rs2 } => {
+                self.op_reg(rd, rs1, rs2, |a, b| (a as i32).wrapping_shr(b) as u32)
+            }
+            Instruction::Or { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, |a, b| a | b),
+            Instruction::And { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, |a, b| a & b),
+            Instruction::Mul { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, u32::wrapping_mul),
+            Instruction::Mulh { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, |a, b| {
+                (sign_extend_u32(a).wrapping_mul(sign_extend_u32(b)) >> 32) as u32
+            }),
+            Instruction::Mulhsu { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, |a, b| {
+                (sign_extend_u32(a).wrapping_mul(i64::from(b)) >> 32) as u32
+            }),
+            Instruction::Mulhu { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, |a, b| {
+                (u64::from(a).wrapping_mul(u64::from(b)) >> 32) as u32
+            }),
+            Instruction::Div { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, |a, b| {
+                if b != 0 {
+                    (a as i32).wrapping_div(b as i32) as u32
+                } else {
+                    u32::MAX
                 }
+            }),
+            Instruction::Divu { rd, rs1, rs2 } => {
+                self.op_reg(rd, rs1, rs2, |a, b| a.checked_div(b).unwrap_or(u32::MAX))
             }
-            crate::instructions::DecodedInstruction::SType(stype) => {
-                match stype.funct3 {
-                    0b000 => {
-                        // Funct3 for sb
-                        match process_store_to_memory(self, &stype, MemoryChuckSize::BYTE) {
-                            Ok(_) => {
-                                self.pc += 4;
-                                Ok(true)
-                            }
-                            Err(e) => Err(e),
-                        }
-                    }
-                    0b001 => {
-                        // Funct3 for sh
-                        match process_store_to_memory(self, &stype, MemoryChuckSize::HalfWord) {
-                            Ok(_) => {
-                                self.pc += 4;
-                                Ok(true)
-                            }
-                            Err(e) => Err(e),
-                        }
-                    }
-                    0b010 => {
-                        // Funct3 for sw
-                        match process_store_to_memory(self, &stype, MemoryChuckSize::WordSize) {
-                            Ok(_) => {
-                                self.pc += 4;
-                                Ok(true)
-                            }
-                            Err(e) => Err(e),
-                        }
-                    }
-                    _ => return Err(VMErrors::InvalidFunct3(stype.funct3)),
+            Instruction::Rem { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, |a, b| {
+                if b != 0 {
+                    (a as i32).wrapping_rem(b as i32) as u32
+                } else {
+                    a
                 }
+            }),
+            Instruction::Remu { rd, rs1, rs2 } => {
+                self.op_reg(rd, rs1, rs2, |a, b| a.checked_rem(b).unwrap_or(a))
             }
-            crate::instructions::DecodedInstruction::BType(btype) => {
-                match btype.funct3 {
-                    0b000 => {
-                        // Funct3 for beq
-                        let rs1 = self.registers.read_reg(btype.rs1 as u32);
-                        let rs2 = self.registers.read_reg(btype.rs2 as u32);
-
-                        if rs1 == rs2 {
-                            let target = self.pc.wrapping_add(btype.imm as u32);
-                            self.pc = target;
-                        } else {
-                            self.pc += 4;
-                        }
-
-                        Ok(true)
-                    }
-                    0b001 => {
-                        // Funct3 for bne
-                        let rs1 = self.registers.read_reg(btype.rs1 as u32);
-                        let rs2 = self.registers.read_reg(btype.rs2 as u32);
-
-                        if rs1 != rs2 {
-                            let target = self.pc.wrapping_add(btype.imm as u32);
-                            self.pc = target;
-                        } else {
-                            self.pc += 4;
-                        }
-
-                        Ok(true)
-                    }
-                    0b100 => {
-                        // Funct3 for blt
-                        let rs1 = self.registers.read_reg(btype.rs1 as u32) as i32;
-                        let rs2 = self.registers.read_reg(btype.rs2 as u32) as i32;
-
-                        if rs1 < rs2 {
-                            let target = self.pc.wrapping_add(btype.imm as u32);
-                            self.pc = target;
-                        } else {
-                            self.pc += 4;
-                        }
-
-                        Ok(true)
-                    }
-                    0b101 => {
-                        // Funct3 for bge
-                        let rs1 = self.registers.read_reg(btype.rs1 as u32) as i32;
-                        let rs2 = self.registers.read_reg(btype.rs2 as u32) as i32;
-
-                        if rs1 >= rs2 {
-                            let target = self.pc.wrapping_add(btype.imm as u32);
-                            self.pc = target;
-                        } else {
-                            self.pc += 4;
-                        }
-
-                        Ok(true)
-                    }
-                    0b110 => {
-                        // Funct3 for bltu
-                        let rs1 = self.registers.read_reg(btype.rs1 as u32);
-                        let rs2 = self.registers.read_reg(btype.rs2 as u32);
-
-                        if rs1 < rs2 {
-                            let target = self.pc.wrapping_add(btype.imm as u32);
-                            self.pc = target;
-                        } else {
-                            self.pc += 4;
-                        }
-
-                        Ok(true)
-                    }
-                    0b111 => {
-                        // Funct3 for bgeu
-                        let rs1 = self.registers.read_reg(btype.rs1 as u32);
-                        let rs2 = self.registers.read_reg(btype.rs2 as u32);
-
-                        if rs1 >= rs2 {
-                            let target = self.pc.wrapping_add(btype.imm as u32);
-                            self.pc = target;
-                        } else {
-                            self.pc += 4;
-                        }
-
-                        Ok(true)
+            Instruction::Sh1add { rd, rs1, rs2 } => {
+                self.op_reg(rd, rs1, rs2, |a, b| (a << 1).wrapping_add(b))
+            }
+            Instruction::Sh2add { rd, rs1, rs2 } => {
+                self.op_reg(rd, rs1, rs2, |a, b| (a << 2).wrapping_add(b))
+            }
+            Instruction::Sh3add { rd, rs1, rs2 } => {
+                self.op_reg(rd, rs1, rs2, |a, b| (a << 3).wrapping_add(b))
+            }
+            Instruction::Andn { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, |a, b| a & !b),
+            Instruction::Orn { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, |a, b| a | !b),
+            Instruction::Xnor { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, |a, b| !(a ^ b)),
+            Instruction::Max { rd, rs1, rs2 } => {
+                self.op_reg(rd, rs1, rs2, |a, b| (a as i32).max(b as i32) as u32)
+            }
+            Instruction::Maxu { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, u32::max),
+            Instruction::Min { rd, rs1, rs2 } => {
+                self.op_reg(rd, rs1, rs2, |a, b| (a as i32).min(b as i32) as u32)
+            }
+            Instruction::Minu { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, u32::min),
+            Instruction::Rol { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, u32::rotate_left),
+            Instruction::Ror { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, u32::rotate_right),
+            Instruction::Clmul { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, bitmanip::clmul),
+            Instruction::Clmulh { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, bitmanip::clmulh),
+            Instruction::Clmulr { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, bitmanip::clmulr),
+            Instruction::Pack { rd, rs1, rs2 } => {
+                self.op_reg(rd, rs1, rs2, |a, b| (a & 0xffff) | (b << 16))
+            }
+            Instruction::Packh { rd, rs1, rs2 } => {
+                self.op_reg(rd, rs1, rs2, |a, b| (a & 0xff) | ((b & 0xff) << 8))
+            }
+            Instruction::Clz { rd, rs1 } => self.op_imm(rd, rs1, 0, |a, _| a.leading_zeros()),
+            Instruction::Ctz { rd, rs1 } => self.op_imm(rd, rs1, 0, |a, _| a.trailing_zeros()),
+            Instruction::Cpop { rd, rs1 } => self.op_imm(rd, rs1, 0, |a, _| a.count_ones()),
+            Instruction::SextB { rd, rs1 } => self.op_imm(rd, rs1, 0, |a, _| a as i8 as u32),
+            Instruction::SextH { rd, rs1 } => self.op_imm(rd, rs1, 0, |a, _| a as i16 as u32),
+            Instruction::OrcB { rd, rs1 } => self.op_imm(rd, rs1, 0, |a, _| bitmanip::orc_b(a)),
+            Instruction::Rev8 { rd, rs1 } => self.op_imm(rd, rs1, 0, |a, _| a.swap_bytes()),
+            Instruction::Brev8 { rd, rs1 } => self.op_imm(rd, rs1, 0, |a, _| bitmanip::brev8(a)),
+            Instruction::Zip { rd, rs1 } => self.op_imm(rd, rs1, 0, |a, _| bitmanip::zip(a)),
+            Instruction::Unzip { rd, rs1 } => self.op_imm(rd, rs1, 0, |a, _| bitmanip::unzip(a)),
+            Instruction::Rori { rd, rs1, shamt } => self.op_imm(rd, rs1, shamt, u32::rotate_right),
+            Instruction::Addi { rd, rs1, imm } => {
+                self.op_imm(rd, rs1, imm as u32, u32::wrapping_add)
+            }
+            Instruction::Slti { rd, rs1, imm } => {
+                self.op_imm(rd, rs1, imm as u32, |a, b| ((a as i32) < (b as i32)) as u32)
+            }
+            Instruction::Sltiu { rd, rs1, imm } => {
+                self.op_imm(rd, rs1, imm as u32, |a, b| (a < b) as u32)
+            }
+            Instruction::Xori { rd, rs1, imm } => self.op_imm(rd, rs1, imm as u32, |a, b| a ^ b),
+            Instruction::Ori { rd, rs1, imm } => self.op_imm(rd, rs1, imm as u32, |a, b| a | b),
+            Instruction::Andi { rd, rs1, imm } => self.op_imm(rd, rs1, imm as u32, |a, b| a & b),
+            Instruction::Slli { rd, rs1, shamt } => self.op_imm(rd, rs1, shamt, u32::wrapping_shl),
+            Instruction::Srli { rd, rs1, shamt } => self.op_imm(rd, rs1, shamt, u32::wrapping_shr),
+            Instruction::Srai { rd, rs1, shamt } => {
+                self.op_imm(rd, rs1, shamt, |a, b| (a as i32).wrapping_shr(b) as u32)
+            }
+            Instruction::Lb { rd, rs1, imm } => {
+                self.load(rd, rs1, imm, MemoryChuckSize::BYTE, true)?
+            }
+            Instruction::Lh { rd, rs1, imm } => {
+                self.load(rd, rs1, imm, MemoryChuckSize::HalfWord, true)?
+            }
+            Instruction::Lw { rd, rs1, imm } => {
+                self.load(rd, rs1, imm, MemoryChuckSize::WordSize, false)?
+            }
+            Instruction::Lbu { rd, rs1, imm } => {
+                self.load(rd, rs1, imm, MemoryChuckSize::BYTE, false)?
+            }
+            Instruction::Lhu { rd, rs1, imm } => {
+                self.load(rd, rs1, imm, MemoryChuckSize::HalfWord, false)?
+            }
+            Instruction::Sb { rs1, rs2, imm } => {
+                self.store(rs1, rs2, imm, MemoryChuckSize::BYTE)?
+            }
+            Instruction::Sh { rs1, rs2, imm } => {
+                self.store(rs1, rs2, imm, MemoryChuckSize::HalfWord)?
+            }
+            Instruction::Sw { rs1, rs2, imm } => {
+                self.store(rs1, rs2, imm, MemoryChuckSize::WordSize)?
+            }
+            Instruction::Beq { rs1, rs2, imm } => {
+                return self.branch(rs1, rs2, imm, next_pc, |a, b| a == b);
+            }
+            Instruction::Bne { rs1, rs2, imm } => {
+                return self.branch(rs1, rs2, imm, next_pc, |a, b| a != b);
+            }
+            Instruction::Blt { rs1, rs2, imm } => {
+                return self.branch(rs1, rs2, imm, next_pc, |a, b| (a as i32) < (b as i32));
+            }
+            Instruction::Bge { rs1, rs2, imm } => {
+                return self.branch(rs1, rs2, imm, next_pc, |a, b| (a as i32) >= (b as i32));
+            }
+            Instruction::Bltu { rs1, rs2, imm } => {
+                return self.branch(rs1, rs2, imm, next_pc, |a, b| a < b);
+            }
+            Instruction::Bgeu { rs1, rs2, imm } => {
+                return self.branch(rs1, rs2, imm, next_pc, |a, b| a >= b);
+            }
+            Instruction::Lui { rd, imm } => {
+                self.registers.write_reg(rd, imm as u32);
+            }
+            Instruction::Auipc { rd, imm } => {
+                self.registers
+                    .write_reg(rd, self.pc.wrapping_add(imm as u32));
+            }
+            Instruction::Jal { rd, imm } => {
+                // the target is relative to the jal itself, not to the next instruction
+                self.registers.write_reg(rd, next_pc);
+                self.pc = self.pc.wrapping_add(imm as u32);
+                return Ok(true);
+            }
+            Instruction::Jalr { rd, rs1, imm } => {
+                // the target address always has its lowest bit cleared
+                let dest_addr = self.registers.read_reg(rs1).wrapping_add(imm as u32) & !1;
+                self.registers.write_reg(rd, next_pc);
+                self.pc = dest_addr;
+                return Ok(true);
+            }
+            Instruction::Ecall => {
+                let request = self.begin_ecall()?;
+                let response = match self.local_ecall(&request, context) {
+                    Some(response) => response,
+                    None if self.yield_ecalls => {
+                        // the pc stays on the ecall until the caller resumes the Vm
+                        self.pending_ecall = Some(request);
+                        return Ok(true);
                     }
-                    _ => return Err(VMErrors::InvalidFunct3(btype.funct3)),
+                    None => context.fulfil(&request),
+                };
+                self.end_ecall(&request, response, context)?;
+            }
+            Instruction::Ebreak => return Err(VMErrors::Breakpoint(self.pc)),
+            Instruction::Wfi => return Err(VMErrors::PrivilegedInstruction(WFI)),
+            Instruction::Fence => {}
+            Instruction::FenceI => {
+                // the code may have been written to, it is decoded again from memory from now on
+                self.program = None;
+            }
+            // `csrr` (`csrrs`/`csrrc` with nothing to set or clear) is the only way to use the
+            // read-only counters, everything else is a write and traps
+            Instruction::Csrrs { rd, rs1: 0, csr }
+            | Instruction::Csrrc { rd, rs1: 0, csr }
+            | Instruction::Csrrsi { rd, uimm: 0, csr }
+            | Instruction::Csrrci { rd, uimm: 0, csr } => {
+                let value = self.read_csr(csr)?;
+                self.registers.write_reg(rd, value);
+            }
+            Instruction::Csrrw { csr, .. }
+            | Instruction::Csrrs { csr, .. }
+            | Instruction::Csrrc { csr, .. }
+            | Instruction::Csrrwi { csr, .. }
+            | Instruction::Csrrsi { csr, .. }
+            | Instruction::Csrrci { csr, .. } => {
+                return Err(VMErrors::InvalidCsr(csr));
+            }
+            // only decoded for RV64, see [crate::vm64]
+            Instruction::Ld { .. }
+            | Instruction::Lwu { .. }
+            | Instruction::Sd { .. }
+            | Instruction::Addiw { .. }
+            | Instruction::Slliw { .. }
+            | Instruction::Srliw { .. }
+            | Instruction::Sraiw { .. }
+            | Instruction::Addw { .. }
+            | Instruction::Subw { .. }
+            | Instruction::Sllw { .. }
+            | Instruction::Srlw { .. }
+            | Instruction::Sraw { .. }
+            | Instruction::Mulw { .. }
+            | Instruction::Divw { .. }
+            | Instruction::Divuw { .. }
+            | Instruction::Remw { .. }
+            | Instruction::Remuw { .. } => {
+                return Err(VMErrors::InvalidInstruction);
+            }
+        }
+
+        self.pc = next_pc;
+        Ok(true)
+    }
+
+    /// Reads the counter CSR `csr`, any other CSR traps.
+    /// Every instruction takes one cycle and `time` never advances, so the counters only depend on
+    /// the instructions executed.
+    fn read_csr(&self, csr: u32) -> Result<u32, VMErrors> {
+        match csr {
+            CSR_CYCLE | CSR_INSTRET => Ok(self.instret as u32),
+            CSR_CYCLEH | CSR_INSTRETH => Ok((self.instret >> 32) as u32),
+            CSR_TIME | CSR_TIMEH => Ok(0),
+            _ => Err(VMErrors::InvalidCsr(csr)),
+        }
+    }
+
+    /// Decodes the ecall the pc is on
+    fn begin_ecall(&mut self) -> Result<EcallRequest, VMErrors> {
+        let request = EcallRequest::decode(self)?;
+        Hook::call(self, |hook, vm| hook.on_ecall_enter(vm, &request));
+        Ok(request)
+    }
+
+    /// Reads the ecall register `register` of the RV32I ecall layout, from wherever the profile
+    /// of the Vm passes it (see [crate::container])
+    pub(crate) fn ecall_reg(&self, register: u32) -> Result<u32, VMErrors> {
+        match self.profile.ecall_location(register) {
+            EcallLocation::Register(register) => Ok(self.registers.read_reg(register)),
+            EcallLocation::Block(offset) => {
+                let address = self.ecall_block_address(offset)?;
+                self.segments.check(address, Access::Read)?;
+                self.memory
+                    .read_mem(address, MemoryChuckSize::WordSize)
+                    .ok_or(VMErrors::MemoryLoadError)
+            }
+        }
+    }
+
+    /// Writes the ecall register `register` of the RV32I ecall layout, see [Vm::ecall_reg]
+    pub(crate) fn write_ecall_reg(&mut self, register: u32, value: u32) -> Result<(), VMErrors> {
+        match self.profile.ecall_location(register) {
+            EcallLocation::Register(register) => self.registers.write_reg(register, value),
+            EcallLocation::Block(offset) => {
+                let address = self.ecall_block_address(offset)?;
+                self.segments.check(address, Access::Write)?;
+                if !self
+                    .memory
+                    .write_mem(address, MemoryChuckSize::WordSize, value)
+                {
+                    return Err(VMErrors::MemoryStoreError);
                 }
             }
-            crate::instructions::DecodedInstruction::UType(utype) => {
-                match decoded_instruction.opcode {
-                    0b0110111 => {
-                        // Funct3 for lui
-                        let imm = utype.imm as u32;
-                        self.registers.write_reg(utype.rd as u32, imm);
-                        self.pc += 4;
-                        Ok(true)
+        }
+        Ok(())
+    }
+
+    /// The address of the word at `offset` in the RV32E ecall block, which must be word-aligned
+    fn ecall_block_address(&self, offset: u32) -> Result<u32, VMErrors> {
+        let address = self
+            .registers
+            .read_reg(RV32E_ECALL_BLOCK_REG)
+            .checked_add(offset)
+            .ok_or(VMErrors::MemoryError)?;
+        if address % WORD_SIZE as u32 != 0 {
+            return Err(VMErrors::MemoryError);
+        }
+        Ok(address)
+    }
+
+    /// Serves the ecalls that only concern the Vm, `None` for the ones that go to the host
+    pub(crate) fn local_ecall(
+        &mut self,
+        request: &EcallRequest,
+        context: &mut Context,
+    ) -> Option<Result<EcallResponse, VMErrors>> {
+        match (request.ecall, &request.args) {
+            (RiscvEVMECalls::Sbrk, &EcallArgs::Sbrk(increment)) => {
+                Some(self.sbrk(increment, context))
+            }
+            (RiscvEVMECalls::DebugLog, _) => Some(self.debug_log(context)),
+            _ => None,
+        }
+    }
+
+    /// Moves the program break, the old break comes back like sbrk(2) does and u32::MAX (-1)
+    /// tells the allocator the heap is exhausted
+    fn sbrk(&mut self, increment: i32, context: &mut Context) -> Result<EcallResponse, VMErrors> {
+        let previous = match self.heap.sbrk(increment) {
+            // the break only moves once its pages are paid for
+            Some((heap, cost)) => {
+                context.charge_gas(cost)?;
+                std::mem::replace(&mut self.heap, heap).brk
+            }
+            None => u32::MAX,
+        };
+
+        let mut response = EcallResponse::default();
+        response.write_reg(SBRK_OUTPUT_REGISTER, previous);
+        Ok(response)
+    }
+
+    /// Pushes a message to the debug console of `context`.
+    /// Free in production, nothing is read from the guest unless a console is attached.
+    fn debug_log(&self, context: &mut Context) -> Result<EcallResponse, VMErrors> {
+        let Some(console) = context.debug_console.as_mut() else {
+            return Ok(EcallResponse::default());
+        };
+
+        let offset = self.ecall_reg(DEBUG_LOG_INPUT_REGISTER_1)?;
+        let size = self.ecall_reg(DEBUG_LOG_INPUT_REGISTER_2)?;
+        let flags = self.ecall_reg(DEBUG_LOG_INPUT_REGISTER_3)?;
+
+        let message = String::from_utf8_lossy(&read_guest_bytes(self, offset, size)?).into_owned();
+
+        let registers = if flags & DEBUG_LOG_DUMP_REGISTERS != 0 {
+            let mut registers = [0u32; 32];
+            for (i, register) in registers.iter_mut().enumerate() {
+                *register = self.registers.read_reg(i as u32);
+            }
+            Some(registers)
+        } else {
+            None
+        };
+
+        let memory = if flags & DEBUG_LOG_DUMP_MEMORY != 0 {
+            let dump_offset = self.ecall_reg(DEBUG_LOG_INPUT_REGISTER_4)?;
+            let dump_size = self.ecall_reg(DEBUG_LOG_INPUT_REGISTER_5)?;
+            Some((dump_offset, read_guest_bytes(self, dump_offset, dump_size)?))
+        } else {
+            None
+        };
+
+        console.push(DebugLogEntry {
+            address: context.address,
+            message,
+            registers,
+            memory,
+        });
+
+        Ok(EcallResponse::default())
+    }
+
+    /// Applies the host's response to the ecall
+    fn end_ecall(
+        &mut self,
+        request: &EcallRequest,
+        response: Result<EcallResponse, VMErrors>,
+        context: &mut Context,
+    ) -> Result<(), VMErrors> {
+        let result = response.and_then(|response| response.apply(self, context));
+        if result.is_ok() {
+            Hook::call(self, |hook, vm| hook.on_ecall_exit(vm, request));
+        }
+        result
+    }
+
+    /// Execute a fused op, with the same effect as executing its two instructions in order
+    fn execute_fused(&mut self, op: FusedOp) {
+        match op {
+            FusedOp::LoadImmediate { rd, value } => {
+                self.registers.write_reg(rd, value);
+                self.pc += 8;
+            }
+            FusedOp::FarJump { rd, hi, link, lo } => {
+                self.registers
+                    .write_reg(rd, self.pc.wrapping_add(hi as u32));
+                let dest_addr = self.registers.read_reg(rd).wrapping_add(lo as u32) & !1;
+                self.registers.write_reg(link, self.pc + 8);
+                self.pc = dest_addr;
+            }
+            FusedOp::ShiftAdd {
+                tmp,
+                rs1,
+                shamt,
+                rd,
+                add_rs1,
+                add_rs2,
+            } => {
+                let shifted = self.registers.read_reg(rs1).wrapping_shl(shamt);
+                self.registers.write_reg(tmp, shifted);
+                let sum = self
+                    .registers
+                    .read_reg(add_rs1)
+                    .wrapping_add(self.registers.read_reg(add_rs2));
+                self.registers.write_reg(rd, sum);
+                self.pc += 8;
+            }
+            FusedOp::CompareBranch {
+                compare,
+                rd,
+                branch_if_set,
+                imm,
+            } => {
+                let is_set = match compare {
+                    Instruction::Slt { rs1, rs2, .. } => {
+                        (self.registers.read_reg(rs1) as i32)
+                            < (self.registers.read_reg(rs2) as i32)
                     }
-                    0b0010111 => {
-                        // Funct3 for auipc
-                        let imm = utype.imm as u32;
-                        let pc = self.pc;
-                        self.registers
-                            .write_reg(utype.rd as u32, pc.wrapping_add(imm));
-                        self.pc += 4;
-                        Ok(true)
+                    Instruction::Sltu { rs1, rs2, .. } => {
+                        self.registers.read_reg(rs1) < self.registers.read_reg(rs2)
                     }
-                    _ => return Err(VMErrors::InvalidOpcode(decoded_instruction.opcode)),
-                }
-            }
-            crate::instructions::DecodedInstruction::JType(jtype) => {
-                match decoded_instruction.opcode {
-                    0b1101111 => {
-                        // Funct3 for jal
-                        self.pc += 4;
-                        self.registers.write_reg(jtype.rd as u32, self.pc);
-                        self.pc += jtype.imm as u32 - 4; // self.pc += imm "not" self.pc = self.pc + 4 + imm
-                        Ok(true)
+                    Instruction::Slti { rs1, imm, .. } => {
+                        (self.registers.read_reg(rs1) as i32) < imm
                     }
-                    _ => return Err(VMErrors::InvalidOpcode(decoded_instruction.opcode)),
+                    Instruction::Sltiu { rs1, imm, .. } => {
+                        self.registers.read_reg(rs1) < imm as u32
+                    }
+                    _ => unreachable!("only comparisons are fused with a branch"),
+                };
+                self.registers.write_reg(rd, is_set as u32);
+
+                // the branch reads `rd` back, which is always 0 when `rd` is `zero`
+                let branch_pc = self.pc + 4;
+                if (self.registers.read_reg(rd) != 0) == branch_if_set {
+                    self.pc = branch_pc.wrapping_add(imm as u32);
+                } else {
+                    self.pc = branch_pc + 4;
                 }
             }
         }
     }
 
+    /// `rd = op(rs1, rs2)`
+    #[inline(always)]
+    fn op_reg(&mut self, rd: u32, rs1: u32, rs2: u32, op: impl FnOnce(u32, u32) -> u32) {
+        let rs2 = self.registers.read_reg(rs2);
+        self.op_imm(rd, rs1, rs2, op)
+    }
+
+    /// `rd = op(rs1, imm)`
+    #[inline(always)]
+    fn op_imm(&mut self, rd: u32, rs1: u32, imm: u32, op: impl FnOnce(u32, u32) -> u32) {
+        let rs1 = self.registers.read_reg(rs1);
+        self.registers.write_reg(rd, op(rs1, imm));
+    }
+
+    #[inline(always)]
+    fn load(
+        &mut self,
+        rd: u32,
+        rs1: u32,
+        imm: i32,
+        size: MemoryChuckSize,
+        is_signed: bool,
+    ) -> Result<(), VMErrors> {
+        process_load_to_reg(self, rd, rs1, imm, size, is_signed)
+    }
+
+    #[inline(always)]
+    fn store(
+        &mut self,
+        rs1: u32,
+        rs2: u32,
+        imm: i32,
+        size: MemoryChuckSize,
+    ) -> Result<(), VMErrors> {
+        process_store_to_memory(self, rs1, rs2, imm, size)?;
+        if self.program.is_some() {
+            let address = self.registers.read_reg(rs1).wrapping_add(imm as u32);
+            self.invalidate_decoded(address, WORD_SIZE as u32);
+        }
+        Ok(())
+    }
+
+    /// Jump `imm` bytes away from the branch when `condition(rs1, rs2)` holds, fall through to
+    /// `next_pc` otherwise
+    #[inline(always)]
+    fn branch(
+        &mut self,
+        rs1: u32,
+        rs2: u32,
+        imm: i32,
+        next_pc: u32,
+        condition: impl FnOnce(u32, u32) -> bool,
+    ) -> Result<bool, VMErrors> {
+        let rs1 = self.registers.read_reg(rs1);
+        let rs2 = self.registers.read_reg(rs2);
+
+        if condition(rs1, rs2) {
+            self.pc = self.pc.wrapping_add(imm as u32);
+        } else {
+            self.pc = next_pc;
+        }
+
+        Ok(true)
+    }
+
     /// Run the Vm.
-    /// This function will run the Vm until it halts.
+    /// This function will run the Vm until it halts, with the [Context] fulfilling every ecall
+    /// the Vm yields on (see [crate::host]).
     /// The Vm will halt if the program counter is out of bounds or if the instruction is a halt.
     pub fn run(&mut self, debug_mode: bool, context: &mut Context) {
-        let mut count = 0;
+        let start = self.instret;
         self.running = true;
+        let mut execution = self.run_until_yield(debug_mode, Some(start), context);
+        while let Ok(Execution::Yield(request)) = &execution {
+            let response = context.fulfil(request);
+            execution = self.resume_with(response, debug_mode, Some(start), context);
+        }
+        if let Err(e) = execution {
+            match e {
+                VMErrors::Breakpoint(address) => {
+                    eprintln!("Breakpoint at pc: {:x}", address);
+                }
+                _ => {
+                    eprintln!("Error at pc: {:x} - error: {:?}", self.pc, e);
+                }
+            }
+            self.print_backtrace(self.pc);
+            self.exit_code = EXIT_TRAP;
+            self.running = false;
+        }
+        if self.exit_code == EXIT_REVERT && self.debug_info.is_some() {
+            // the pc moved past the `Revert` ecall
+            let pc = self.pc.wrapping_sub(WORD_SIZE as u32);
+            eprintln!("Revert at pc: {:x}", pc);
+            self.print_backtrace(pc);
+        }
+    }
+
+    /// Where the Vm is in the source, innermost frame first, `None` without debug info (see
+    /// [crate::debug_info])
+    pub fn backtrace(&self) -> Option<Vec<SourceLocation>> {
+        Some(self.debug_info.as_ref()?.backtrace(self, self.pc))
+    }
+
+    fn print_backtrace(&self, pc: u32) {
+        let Some(debug_info) = &self.debug_info else {
+            return;
+        };
+        for (i, frame) in debug_info.backtrace(self, pc).iter().enumerate() {
+            eprintln!("  {i}: {frame}");
+        }
+    }
+
+    /// Run the Vm until it halts or reaches an ecall, which is handed to the caller as
+    /// [Execution::Yield] instead of being processed, see [crate::host].
+    /// # Errors
+    /// When the Vm traps.
+    pub fn run_resumable(&mut self, context: &mut Context) -> Result<Execution, VMErrors> {
+        self.running = true;
+        self.run_until_yield(false, None, context)
+    }
+
+    /// Applies the response to the pending ecall and runs on like [Vm::run_resumable] does
+    /// # Errors
+    /// [VMErrors::NoPendingEcall] when the Vm did not yield, or when the Vm traps.
+    pub fn resume(
+        &mut self,
+        response: EcallResponse,
+        context: &mut Context,
+    ) -> Result<Execution, VMErrors> {
+        self.resume_with(Ok(response), false, None, context)
+    }
+
+    /// Ends the pending ecall with `response`, a failed ecall traps on the ecall itself
+    fn resume_with(
+        &mut self,
+        response: Result<EcallResponse, VMErrors>,
+        debug_mode: bool,
+        start: Option<u64>,
+        context: &mut Context,
+    ) -> Result<Execution, VMErrors> {
+        let request = self.pending_ecall.take().ok_or(VMErrors::NoPendingEcall)?;
+        if let Err(error) = self.end_ecall(&request, response, context) {
+            Hook::call(self, |hook, vm| hook.on_trap(vm, &error));
+            return Err(error);
+        }
+        self.pc = request.pc.wrapping_add(4);
+        self.run_until_yield(debug_mode, start, context)
+    }
+
+    /// Steps until the Vm halts or yields. [Vm::run] passes the instret it started from, the Vm
+    /// stops 100 instructions after it.
+    fn run_until_yield(
+        &mut self,
+        debug_mode: bool,
+        start: Option<u64>,
+        context: &mut Context,
+    ) -> Result<Execution, VMErrors> {
+        self.yield_ecalls = true;
+        let mut result = Ok(Execution::Halted);
         while self.running {
             match self.step(debug_mode, context) {
-                Ok(true) => {
-                    count += 1;
-                    if count > 100 {
-                        self.running = false;
-                    } else {
-                        continue;
-                    }
-                }
+                Ok(true) => {}
                 Ok(false) => break,
                 Err(e) => {
-                    match e {
-                        _ => {
-                            eprintln!("Error at pc: {:x} - error: {:?}", self.pc, e);
-                        }
-                    }
-                    self.running = false;
+                    result = Err(e);
+                    break;
                 }
             }
+            if let Some(request) = &self.pending_ecall {
+                result = Ok(Execution::Yield(request.clone()));
+                break;
+            }
+            if let Some(start) = start
+                && self.instret - start > 100
+            {
+                self.running = false;
+            }
         }
+        self.yield_ecalls = false;
+        result
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::Vm;
+    use super::{VMErrors, Vm};
     use crate::{
+        code_cache::DecodedProgram,
         context::Context,
-        utils::{bytes_to_u32_vec, u32_vec_to_bytes},
+        elf_parser::Elf,
+        instructions::Instruction,
+        segments::{Access, SegmentMap},
+        utils::{bytes_to_u32_vec, read_guest_bytes, u32_vec_to_bytes, write_guest_bytes},
     };
     use revm::{Context as EthContext, MainContext, database::CacheDB};
+    use std::sync::Arc;
 
     #[test]
     fn test_vm_run() {
@@ -756,6 +1099,53 @@ mod test {
         vm.run(true, &mut context);
     }
 
+    /// An ELF32 RISC-V executable with `code` at 0x1000 and a read only `data` segment at 0x2000
+    /// that is followed by a page of bss
+    fn elf32(code: &[u32], data: &[u32]) -> Vec<u8> {
+        let mut elf = vec![0x7f, b'E', b'L', b'F', 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        elf.extend(2u16.to_le_bytes()); // ET_EXEC
+        elf.extend(243u16.to_le_bytes()); // EM_RISCV
+        for field in [1u32, 0x1000, 52, 0, 0] {
+            elf.extend(field.to_le_bytes());
+        }
+        for half in [52u16, 32, 2, 40, 0, 0] {
+            elf.extend(half.to_le_bytes());
+        }
+
+        let code_size = (code.len() * 4) as u32;
+        let data_size = (data.len() * 4) as u32;
+        for field in [1, 116, 0x1000, 0x1000, code_size, code_size, 5, 4] {
+            elf.extend(u32::to_le_bytes(field));
+        }
+        let data_offset = 116 + code_size;
+        for field in [1, data_offset, 0x2000, 0x2000, data_size, 0x1000, 4, 4] {
+            elf.extend(u32::to_le_bytes(field));
+        }
+        elf.extend(code.iter().chain(data).flat_map(|word| word.to_le_bytes()));
+        elf
+    }
+
+    #[test]
+    fn test_from_elf_loads_data() {
+        let code = [
+            0x00002537, // lui a0, 0x2
+            0x00052583, // lw a1, 0(a0)
+            0x00452603, // lw a2, 4(a0)
+        ];
+        let elf = Elf::decode(&elf32(&code, &[0x12345678, 0x9abcdef0])).unwrap();
+        let mut vm = Vm::from_elf(elf).unwrap();
+        assert_eq!(vm.pc, 0x1000);
+        // the heap starts after the bss
+        assert_eq!(vm.heap.start, 0x3000);
+
+        let mut context = Context::new(EthContext::mainnet().with_db(CacheDB::default()));
+        for _ in 0..3 {
+            vm.step(false, &mut context).unwrap();
+        }
+        assert_eq!(vm.registers.read_reg(11), 0x12345678);
+        assert_eq!(vm.registers.read_reg(12), 0x9abcdef0);
+    }
+
     #[test]
     fn test_vm_run_with_u8() {
         let code: Vec<u32> = vec![
@@ -806,4 +1196,376 @@ mod test {
             vm.run(true, &mut context);
         }
     }
+
+    #[test]
+    fn test_decode_instruction() {
+        assert_eq!(
+            Instruction::decode(0x00c58533).unwrap(),
+            Instruction::Add {
+                rd: 10,
+                rs1: 11,
+                rs2: 12
+            }
+        );
+        assert_eq!(
+            Instruction::decode(0xff010113).unwrap(),
+            Instruction::Addi {
+                rd: 2,
+                rs1: 2,
+                imm: -16
+            }
+        );
+        assert_eq!(
+            Instruction::decode(0x40355513).unwrap(),
+            Instruction::Srai {
+                rd: 10,
+                rs1: 10,
+                shamt: 3
+            }
+        );
+        assert_eq!(
+            Instruction::decode(0xfeb50ee3).unwrap(),
+            Instruction::Beq {
+                rs1: 10,
+                rs2: 11,
+                imm: -4
+            }
+        );
+        assert_eq!(Instruction::decode(0x00000073).unwrap(), Instruction::Ecall);
+        assert_eq!(
+            Instruction::decode(0xff010113).unwrap().to_string(),
+            "addi sp, sp, -16"
+        );
+
+        // slli with a funct7 other than 0 is not RV32I
+        assert!(matches!(
+            Instruction::decode(0x40351513),
+            Err(VMErrors::InvalidFunct7(0b0100000))
+        ));
+        // ld is RV64 only
+        assert!(matches!(
+            Instruction::decode(0x00003003),
+            Err(VMErrors::InvalidFunct3(0b011))
+        ));
+    }
+
+    #[test]
+    fn test_decode_compressed_instruction() {
+        let cases = [
+            (0x0808, "addi a0, sp, 16"),
+            (0x414c, "lw a1, 4(a0)"),
+            (0xc60c, "sw a1, 8(a2)"),
+            (0x0001, "addi zero, zero, 0"),
+            (0x1575, "addi a0, a0, -3"),
+            (0x2005, "jal ra, 32"),
+            (0x57fd, "addi a5, zero, -1"),
+            (0x7139, "addi sp, sp, -64"),
+            (0x76fd, "lui a3, 0xfffff"),
+            (0x810d, "srli a0, a0, 3"),
+            (0x85fd, "srai a1, a1, 31"),
+            (0x9861, "andi s0, s0, -8"),
+            (0x8d0d, "sub a0, a0, a1"),
+            (0x8d2d, "xor a0, a0, a1"),
+            (0x8d4d, "or a0, a0, a1"),
+            (0x8d6d, "and a0, a0, a1"),
+            (0xbfed, "jal zero, -6"),
+            (0xc501, "beq a0, zero, 8"),
+            (0xfdf5, "bne a1, zero, -4"),
+            (0x050a, "slli a0, a0, 2"),
+            (0x40b2, "lw ra, 12(sp)"),
+            (0x8082, "jalr zero, 0(ra)"),
+            (0x852e, "add a0, zero, a1"),
+            (0x9782, "jalr ra, 0(a5)"),
+            (0x952e, "add a0, a0, a1"),
+            (0xc606, "sw ra, 12(sp)"),
+        ];
+        for (parcel, text) in cases {
+            assert_eq!(
+                Instruction::decode_compressed(parcel).unwrap().to_string(),
+                text,
+                "{parcel:#06x}"
+            );
+            assert_eq!(
+                Instruction::decode_any(parcel as u32).unwrap(),
+                Instruction::decode_compressed(parcel).unwrap()
+            );
+        }
+
+        // the all-zero parcel, c.flw and the RV64 c.subw are not RV32C
+        for parcel in [0x0000, 0x6100, 0x9d0d] {
+            assert!(
+                Instruction::decode_compressed(parcel).is_err(),
+                "{parcel:#06x}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_vm_run_compressed() {
+        let code: Vec<u32> = vec![
+            0x05934515, // 0x00: c.li a0, 5 ; 0x02: addi a1, zero, 7 (spans two words)
+            0x952e0070, // 0x06: c.add a0, a1
+            0x05052019, // 0x08: c.jal 6 ; 0x0a: c.addi a0, 1 (skipped)
+            0x06330001, // 0x0c: c.nop ; 0x0e: add a2, a0, ra (spans two words)
+            0xe2110015, // 0x12: c.bnez a2, 4
+            0x00010001, // 0x14: c.nop ; 0x16: c.nop
+        ];
+        let eth_context = EthContext::mainnet().with_db(CacheDB::default());
+        let mut context = Context::new(eth_context);
+        let bytes = u32_vec_to_bytes(&code, code.len() * 4);
+        let mut from_memory = Vm::from_bin(code.clone()).unwrap();
+        let mut from_program = Vm::from_program(Arc::new(DecodedProgram::new(code, 0)));
+        let mut from_bytes = Vm::from_bin_u8(bytes.clone()).unwrap();
+        let mut from_program_bytes = Vm::from_program(Arc::new(DecodedProgram::from_bytes(&bytes)));
+
+        for vm in [
+            &mut from_memory,
+            &mut from_program,
+            &mut from_bytes,
+            &mut from_program_bytes,
+        ] {
+            for _ in 0..6 {
+                vm.step(false, &mut context).unwrap();
+            }
+
+            assert_eq!(vm.pc, 0x16);
+            assert_eq!(vm.registers.read_reg(10), 12);
+            assert_eq!(vm.registers.read_reg(1), 0x0a);
+            assert_eq!(vm.registers.read_reg(12), 22);
+        }
+    }
+
+    #[test]
+    fn test_jal_backwards() {
+        let code: Vec<u32> = vec![
+            0x00150513, // addi a0, a0, 1
+            0xffdff06f, // jal zero, -4
+        ];
+        let eth_context = EthContext::mainnet().with_db(CacheDB::default());
+        let mut context = Context::new(eth_context);
+        let mut vm = Vm::from_bin(code).unwrap();
+
+        for _ in 0..4 {
+            vm.step(false, &mut context).unwrap();
+        }
+
+        assert_eq!(vm.registers.read_reg(10), 2);
+        assert_eq!(vm.pc, 0);
+    }
+
+    #[test]
+    fn test_self_modifying_code_invalidates_program() {
+        let program = DecodedProgram::new(
+            vec![
+                0x00b02423, // sw a1, 8(zero)
+                0x00150513, // addi a0, a0, 1
+                0x00150513, // addi a0, a0, 1 (overwritten)
+            ],
+            0,
+        );
+        let eth_context = EthContext::mainnet().with_db(CacheDB::default());
+        let mut context = Context::new(eth_context);
+        let mut vm = Vm::from_program(Arc::new(program));
+        // the code is write-protected unless the segments are lifted
+        vm.segments = SegmentMap::default();
+        vm.registers.write_reg(11, 0x00550513); // addi a0, a0, 5
+
+        for _ in 0..3 {
+            vm.step(false, &mut context).unwrap();
+        }
+
+        assert!(vm.program.is_none());
+        assert_eq!(vm.registers.read_reg(10), 6);
+    }
+
+    #[test]
+    fn test_code_is_write_protected_and_data_not_executable() {
+        let program = Arc::new(DecodedProgram::new(
+            vec![
+                0x00b02423, // 0x00: sw a1, 8(zero)
+                0x00802603, // 0x04: lw a2, 8(zero)
+                0x10b02023, // 0x08: sw a1, 0x100(zero)
+                0x10000067, // 0x0c: jalr zero, 0x100(zero)
+            ],
+            0,
+        ));
+        let eth_context = EthContext::mainnet().with_db(CacheDB::default());
+        let mut context = Context::new(eth_context);
+        let mut vm = Vm::from_program(program.clone());
+        vm.registers.write_reg(11, 0x00550513); // addi a0, a0, 5
+
+        // the store into the code traps and leaves it untouched
+        assert!(matches!(
+            vm.step(false, &mut context),
+            Err(VMErrors::AccessViolation(8, Access::Write))
+        ));
+        assert_eq!(vm.pc, 0);
+        assert_eq!(vm.memory.memory[2], 0x10b02023);
+        assert!(vm.program.is_some());
+
+        // code can still be read, data written, but not executed
+        vm.pc = 4;
+        for _ in 0..3 {
+            vm.step(false, &mut context).unwrap();
+        }
+        assert_eq!(vm.registers.read_reg(12), 0x10b02023);
+        assert_eq!(vm.pc, 0x100);
+        assert!(matches!(
+            vm.step(false, &mut context),
+            Err(VMErrors::AccessViolation(0x100, Access::Execute))
+        ));
+
+        // ecalls can not copy into the code either
+        assert!(matches!(
+            write_guest_bytes(&mut vm, 0x0e, &[0; 4]),
+            Err(VMErrors::AccessViolation(0x0e, Access::Write))
+        ));
+        assert!(read_guest_bytes(&vm, 0x0e, 4).is_ok());
+        assert!(write_guest_bytes(&mut vm, 0x10, &[0; 4]).is_ok());
+    }
+
+    #[test]
+    fn test_fused_ops_match_unfused_execution() {
+        let code = vec![
+            0x12345537, // 0x00: lui a0, 0x12345
+            0xfff50513, // 0x04: addi a0, a0, -1
+            0x00251593, // 0x08: slli a1, a0, 2
+            0x00a585b3, // 0x0c: add a1, a1, a0
+            0x00b53633, // 0x10: sltu a2, a0, a1
+            0x00061463, // 0x14: bne a2, zero, 8
+            0x00100693, // 0x18: addi a3, zero, 1 (skipped)
+            0x00000297, // 0x1c: auipc t0, 0
+            0x00c280e7, // 0x20: jalr ra, 12(t0)
+            0x00200693, // 0x24: addi a3, zero, 2 (skipped)
+            0x00300713, // 0x28: addi a4, zero, 3
+        ];
+        let program = Arc::new(DecodedProgram::new(code, 0));
+
+        let run = |fuse_instructions: bool| {
+            let eth_context = EthContext::mainnet().with_db(CacheDB::default());
+            let mut context = Context::new(eth_context);
+            let mut vm = Vm::from_program(program.clone());
+            vm.fuse_instructions = fuse_instructions;
+
+            let mut steps = 0;
+            while vm.pc != 0x2c {
+                vm.step(false, &mut context).unwrap();
+                steps += 1;
+            }
+            (vm, steps)
+        };
+        let (fused, fused_steps) = run(true);
+        let (unfused, unfused_steps) = run(false);
+
+        assert_eq!((fused_steps, unfused_steps), (5, 9));
+        assert_eq!((fused.instret, unfused.instret), (9, 9));
+        for register in 0..32 {
+            assert_eq!(
+                fused.registers.read_reg(register),
+                unfused.registers.read_reg(register)
+            );
+        }
+        assert_eq!(fused.registers.read_reg(10), 0x12344fff);
+        assert_eq!(fused.registers.read_reg(1), 0x24);
+        assert_eq!(fused.registers.read_reg(13), 0);
+        assert_eq!(fused.registers.read_reg(14), 3);
+    }
+
+    #[test]
+    fn test_csr_counters() {
+        let code: Vec<u32> = vec![
+            0x00100593, // addi a1, zero, 1
+            0x00158593, // addi a1, a1, 1
+            0xc0202573, // csrr a0, instret
+            0xc0002673, // csrr a2, cycle
+            0xc01026f3, // csrr a3, time
+            0xc8202773, // csrr a4, instreth
+            0xc00067f3, // csrrsi a5, cycle, 0
+        ];
+        let eth_context = EthContext::mainnet().with_db(CacheDB::default());
+        let mut context = Context::new(eth_context);
+        let mut vm = Vm::from_bin(code).unwrap();
+        vm.registers.write_reg(13, 7);
+        for _ in 0..7 {
+            vm.step(false, &mut context).unwrap();
+        }
+
+        // the counters hold the instructions retired before the reading one
+        assert_eq!(vm.registers.read_reg(10), 2);
+        assert_eq!(vm.registers.read_reg(12), 3);
+        assert_eq!(vm.registers.read_reg(13), 0);
+        assert_eq!(vm.registers.read_reg(14), 0);
+        assert_eq!(vm.registers.read_reg(15), 6);
+        assert_eq!(
+            Instruction::decode(0xc0202573).unwrap().to_string(),
+            "csrrs a0, instret, zero"
+        );
+
+        // writes and any other CSR trap
+        for (instruction, csr) in [
+            (0xc0059073, 0xc00), // csrw cycle, a1
+            (0x30002573, 0x300), // csrr a0, mstatus
+            (0xc025a573, 0xc02), // csrrs a0, instret, a1
+        ] {
+            let mut vm = Vm::from_bin(vec![instruction]).unwrap();
+            vm.registers.write_reg(11, 1);
+            assert!(matches!(
+                vm.step(false, &mut context),
+                Err(VMErrors::InvalidCsr(found)) if found == csr
+            ));
+        }
+    }
+
+    #[test]
+    fn test_fence_ebreak_and_privileged_instructions() {
+        assert_eq!(Instruction::decode(0x0ff0000f).unwrap(), Instruction::Fence);
+        assert_eq!(
+            Instruction::decode(0x0000100f).unwrap(),
+            Instruction::FenceI
+        );
+        assert_eq!(
+            Instruction::decode(0x00100073).unwrap(),
+            Instruction::Ebreak
+        );
+        assert_eq!(
+            Instruction::decode_compressed(0x9002).unwrap(),
+            Instruction::Ebreak
+        );
+        assert_eq!(Instruction::decode(0x10500073).unwrap(), Instruction::Wfi);
+        // mret
+        assert!(matches!(
+            Instruction::decode(0x30200073),
+            Err(VMErrors::PrivilegedInstruction(0x30200073))
+        ));
+
+        let code: Vec<u32> = vec![
+            0x0ff0000f, // fence
+            0x0000100f, // fence.i
+            0x00100073, // ebreak
+            0x10500073, // wfi
+        ];
+        let eth_context = EthContext::mainnet().with_db(CacheDB::default());
+        let mut context = Context::new(eth_context);
+        let mut vm = Vm::from_program(Arc::new(DecodedProgram::new(code, 0)));
+
+        vm.step(false, &mut context).unwrap();
+        assert!(vm.program.is_some());
+        // fence.i drops the pre-decoded program
+        vm.step(false, &mut context).unwrap();
+        assert!(vm.program.is_none());
+        assert_eq!(vm.pc, 8);
+
+        // the breakpoint leaves the pc on the ebreak
+        assert!(matches!(
+            vm.step(false, &mut context),
+            Err(VMErrors::Breakpoint(8))
+        ));
+        assert_eq!((vm.pc, vm.instret), (8, 2));
+
+        vm.pc += 4;
+        assert!(matches!(
+            vm.step(false, &mut context),
+            Err(VMErrors::PrivilegedInstruction(0x10500073))
+        ));
+    }
 }
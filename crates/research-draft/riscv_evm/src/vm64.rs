@@ -0,0 +1,664 @@
+//! # RV64IM
+//! A 64-bit variant of the [Vm] running RV64IM code, where a 256-bit EVM word takes four
+//! registers instead of eight and 64-bit arithmetic is a single instruction.
+//! [Vm64] has its own 64-bit registers and pc, and wraps a 32-bit [Vm] for everything else: the
+//! memory, the halting state and the ecalls.
+//! Ecalls keep the RV32 register ABI, every register carries one 32-bit limb in its low half.
+//! Registers written by an ecall come back sign-extended, the way RV64 holds 32-bit values.
+//! Addresses must fit in 32 bits, the memory is the same 4 GiB as for RV32, and a doubleword is
+//! stored as two words with the low word first.
+//! Pre-decoded programs, fusion, the JIT and tracing only exist for the 32-bit [Vm].
+use crate::{
+    context::Context,
+    ecall_manager::process_ecall,
+    elf_parser::Elf,
+    instructions::{Instruction, WFI},
+    memory_map::{Heap, SP},
+    segments::{Access, SegmentMap},
+    vm::{VMErrors, Vm},
+};
+use riscv_evm_core::{MemoryChuckSize, Registers64, WORD_SIZE, interfaces::MemoryInterface};
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+};
+
+#[derive(Debug, Clone)]
+pub struct Vm64 {
+    pub registers: Registers64,
+    pub pc: u64,
+    /// The 32-bit Vm owning the memory and the halting state, ecalls run against it
+    pub vm: Vm,
+    /// Number of instructions retired
+    pub instret: u64,
+}
+
+/// Sign-extends a 32-bit result to 64 bits, as every `*w` instruction does
+fn sign_extend_word(value: u32) -> u64 {
+    value as i32 as i64 as u64
+}
+
+impl Vm64 {
+    /// Create a new Vm64.
+    pub fn new() -> Self {
+        Self::with_vm(Vm::new())
+    }
+
+    /// The 64-bit registers start like the Vm's, with `sp` at the top of the stack
+    fn with_vm(vm: Vm) -> Self {
+        let mut registers = Registers64::new();
+        registers.write_reg(SP, u64::from(vm.registers.read_reg(SP)));
+        Self {
+            registers,
+            pc: u64::from(vm.pc),
+            vm,
+            instret: 0,
+        }
+    }
+
+    /// Create a new Vm64 from an ELF64 RISC-V executable.
+    /// # Errors
+    /// This function may return an error if the ELF is not valid or not a 64-bit one.
+    pub fn from_bin_elf(path: String) -> Result<Self, anyhow::Error> {
+        let mut file = BufReader::new(File::open(path)?);
+        let mut buf = vec![];
+        file.read_to_end(&mut buf)?;
+
+        let program_elf_decoded = Elf::decode(&buf)?;
+        if !program_elf_decoded.is_64bit {
+            anyhow::bail!("must be a 64-bit elf");
+        }
+
+        let mut vm = Vm::new();
+        vm.segments = SegmentMap::from_elf(&program_elf_decoded)?;
+        vm.heap = Heap::new(vm.segments.end());
+        vm.memory = program_elf_decoded.memory();
+        vm.pc = program_elf_decoded.pc_start;
+
+        Ok(Self::with_vm(vm))
+    }
+
+    pub fn from_bin(instructions: Vec<u32>) -> Result<Self, anyhow::Error> {
+        Ok(Self::with_vm(Vm::from_bin(instructions)?))
+    }
+
+    /// Step the Vm64, executing the instruction at the current program counter.
+    pub fn step(&mut self, debug_mode: bool, context: &mut Context) -> Result<bool, VMErrors> {
+        let address = Self::address(self.pc, MemoryChuckSize::WordSize)?;
+        self.vm.segments.check(address, Access::Execute)?;
+        let raw = self
+            .vm
+            .memory
+            .read_mem(address, MemoryChuckSize::WordSize)
+            .ok_or(VMErrors::InvalidMemoryAccess)?;
+        let instruction = Instruction::decode64(raw)?;
+
+        if debug_mode {
+            println!("{instruction}");
+        }
+
+        context.charge_gas(context.instruction_cost)?;
+
+        self.execute(instruction, context)?;
+        self.instret += 1;
+        Ok(true)
+    }
+
+    /// Execute a decoded RV64IM instruction
+    fn execute(&mut self, instruction: Instruction, context: &mut Context) -> Result<(), VMErrors> {
+        let next_pc = self.pc.wrapping_add(WORD_SIZE as u64);
+        // straight-line instructions fall through to `next_pc`, control flow sets the pc itself
+        match instruction {
+            Instruction::Add { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, u64::wrapping_add),
+            Instruction::Sub { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, u64::wrapping_sub),
+            Instruction::Sll { rd, rs1, rs2 } => {
+                self.op_reg(rd, rs1, rs2, |a, b| a.wrapping_shl(b as u32))
+            }
+            Instruction::Slt { rd, rs1, rs2 } => {
+                self.op_reg(rd, rs1, rs2, |a, b| ((a as i64) < (b as i64)) as u64)
+            }
+            Instruction::Sltu { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, |a, b| (a < b) as u64),
+            Instruction::Xor { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, |a, b| a ^ b),
+            Instruction::Srl { rd, rs1, rs2 } => {
+                self.op_reg(rd, rs1, rs2, |a, b| a.wrapping_shr(b as u32))
+            }
+            Instruction::Sra { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, |a, b| {
+                (a as i64).wrapping_shr(b as u32) as u64
+            }),
+            Instruction::Or { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, |a, b| a | b),
+            Instruction::And { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, |a, b| a & b),
+            Instruction::Mul { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, u64::wrapping_mul),
+            Instruction::Mulh { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, |a, b| {
+                ((a as i64 as i128 * b as i64 as i128) >> 64) as u64
+            }),
+            Instruction::Mulhsu { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, |a, b| {
+                ((a as i64 as i128).wrapping_mul(i128::from(b)) >> 64) as u64
+            }),
+            Instruction::Mulhu { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, |a, b| {
+                ((u128::from(a) * u128::from(b)) >> 64) as u64
+            }),
+            Instruction::Div { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, |a, b| {
+                if b != 0 {
+                    (a as i64).wrapping_div(b as i64) as u64
+                } else {
+                    u64::MAX
+                }
+            }),
+            Instruction::Divu { rd, rs1, rs2 } => {
+                self.op_reg(rd, rs1, rs2, |a, b| a.checked_div(b).unwrap_or(u64::MAX))
+            }
+            Instruction::Rem { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, |a, b| {
+                if b != 0 {
+                    (a as i64).wrapping_rem(b as i64) as u64
+                } else {
+                    a
+                }
+            }),
+            Instruction::Remu { rd, rs1, rs2 } => {
+                self.op_reg(rd, rs1, rs2, |a, b| a.checked_rem(b).unwrap_or(a))
+            }
+            Instruction::Addi { rd, rs1, imm } => self.op_imm(rd, rs1, imm, u64::wrapping_add),
+            Instruction::Slti { rd, rs1, imm } => {
+                self.op_imm(rd, rs1, imm, |a, b| ((a as i64) < (b as i64)) as u64)
+            }
+            Instruction::Sltiu { rd, rs1, imm } => self.op_imm(rd, rs1, imm, |a, b| (a < b) as u64),
+            Instruction::Xori { rd, rs1, imm } => self.op_imm(rd, rs1, imm, |a, b| a ^ b),
+            Instruction::Ori { rd, rs1, imm } => self.op_imm(rd, rs1, imm, |a, b| a | b),
+            Instruction::Andi { rd, rs1, imm } => self.op_imm(rd, rs1, imm, |a, b| a & b),
+            Instruction::Slli { rd, rs1, shamt } => {
+                self.op_imm(rd, rs1, shamt as i32, |a, b| a << b)
+            }
+            Instruction::Srli { rd, rs1, shamt } => {
+                self.op_imm(rd, rs1, shamt as i32, |a, b| a >> b)
+            }
+            Instruction::Srai { rd, rs1, shamt } => {
+                self.op_imm(rd, rs1, shamt as i32, |a, b| ((a as i64) >> b) as u64)
+            }
+            Instruction::Addiw { rd, rs1, imm } => self.op_imm(rd, rs1, imm, |a, b| {
+                sign_extend_word((a as u32).wrapping_add(b as u32))
+            }),
+            Instruction::Slliw { rd, rs1, shamt } => self.op_imm(rd, rs1, shamt as i32, |a, b| {
+                sign_extend_word((a as u32) << b)
+            }),
+            Instruction::Srliw { rd, rs1, shamt } => self.op_imm(rd, rs1, shamt as i32, |a, b| {
+                sign_extend_word((a as u32) >> b)
+            }),
+            Instruction::Sraiw { rd, rs1, shamt } => self.op_imm(rd, rs1, shamt as i32, |a, b| {
+                sign_extend_word(((a as i32) >> b) as u32)
+            }),
+            Instruction::Addw { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, |a, b| {
+                sign_extend_word((a as u32).wrapping_add(b as u32))
+            }),
+            Instruction::Subw { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, |a, b| {
+                sign_extend_word((a as u32).wrapping_sub(b as u32))
+            }),
+            Instruction::Sllw { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, |a, b| {
+                sign_extend_word((a as u32).wrapping_shl(b as u32))
+            }),
+            Instruction::Srlw { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, |a, b| {
+                sign_extend_word((a as u32).wrapping_shr(b as u32))
+            }),
+            Instruction::Sraw { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, |a, b| {
+                sign_extend_word((a as i32).wrapping_shr(b as u32) as u32)
+            }),
+            Instruction::Mulw { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, |a, b| {
+                sign_extend_word((a as u32).wrapping_mul(b as u32))
+            }),
+            Instruction::Divw { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, |a, b| {
+                if b as u32 != 0 {
+                    sign_extend_word((a as i32).wrapping_div(b as i32) as u32)
+                } else {
+                    u64::MAX
+                }
+            }),
+            Instruction::Divuw { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, |a, b| {
+                sign_extend_word((a as u32).checked_div(b as u32).unwrap_or(u32::MAX))
+            }),
+            Instruction::Remw { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, |a, b| {
+                if b as u32 != 0 {
+                    sign_extend_word((a as i32).wrapping_rem(b as i32) as u32)
+                } else {
+                    sign_extend_word(a as u32)
+                }
+            }),
+            Instruction::Remuw { rd, rs1, rs2 } => self.op_reg(rd, rs1, rs2, |a, b| {
+                sign_extend_word((a as u32).checked_rem(b as u32).unwrap_or(a as u32))
+            }),
+            Instruction::Lb { rd, rs1, imm } => {
+                let value = self.load(rs1, imm, MemoryChuckSize::BYTE)?;
+                self.registers.write_reg(rd, value as i8 as u64);
+            }
+            Instruction::Lh { rd, rs1, imm } => {
+                let value = self.load(rs1, imm, MemoryChuckSize::HalfWord)?;
+                self.registers.write_reg(rd, value as i16 as u64);
+            }
+            Instruction::Lw { rd, rs1, imm } => {
+                let value = self.load(rs1, imm, MemoryChuckSize::WordSize)?;
+                self.registers.write_reg(rd, sign_extend_word(value));
+            }
+            Instruction::Lbu { rd, rs1, imm } => {
+                let value = self.load(rs1, imm, MemoryChuckSize::BYTE)?;
+                self.registers.write_reg(rd, u64::from(value));
+            }
+            Instruction::Lhu { rd, rs1, imm } => {
+                let value = self.load(rs1, imm, MemoryChuckSize::HalfWord)?;
+                self.registers.write_reg(rd, u64::from(value));
+            }
+            Instruction::Lwu { rd, rs1, imm } => {
+                let value = self.load(rs1, imm, MemoryChuckSize::WordSize)?;
+                self.registers.write_reg(rd, u64::from(value));
+            }
+            Instruction::Ld { rd, rs1, imm } => {
+                // a doubleword must be 8-byte aligned, which also keeps its high word in range
+                if !self.effective_address(rs1, imm).is_multiple_of(8) {
+                    return Err(VMErrors::MemoryError);
+                }
+                let low = self.load(rs1, imm, MemoryChuckSize::WordSize)?;
+                let high = self.load(rs1, imm.wrapping_add(4), MemoryChuckSize::WordSize)?;
+                self.registers
+                    .write_reg(rd, u64::from(low) | u64::from(high) << 32);
+            }
+            Instruction::Sb { rs1, rs2, imm } => {
+                self.store(rs1, imm, MemoryChuckSize::BYTE, rs2)?
+            }
+            Instruction::Sh { rs1, rs2, imm } => {
+                self.store(rs1, imm, MemoryChuckSize::HalfWord, rs2)?
+            }
+            Instruction::Sw { rs1, rs2, imm } => {
+                self.store(rs1, imm, MemoryChuckSize::WordSize, rs2)?
+            }
+            Instruction::Sd { rs1, rs2, imm } => {
+                if !self.effective_address(rs1, imm).is_multiple_of(8) {
+                    return Err(VMErrors::MemoryError);
+                }
+                let value = self.registers.read_reg(rs2);
+                self.write(rs1, imm, MemoryChuckSize::WordSize, value as u32)?;
+                self.write(
+                    rs1,
+                    imm.wrapping_add(4),
+                    MemoryChuckSize::WordSize,
+                    (value >> 32) as u32,
+                )?;
+            }
+            Instruction::Beq { rs1, rs2, imm } => {
+                return self.branch(rs1, rs2, imm, next_pc, |a, b| a == b);
+            }
+            Instruction::Bne { rs1, rs2, imm } => {
+                return self.branch(rs1, rs2, imm, next_pc, |a, b| a != b);
+            }
+            Instruction::Blt { rs1, rs2, imm } => {
+                return self.branch(rs1, rs2, imm, next_pc, |a, b| (a as i64) < (b as i64));
+            }
+            Instruction::Bge { rs1, rs2, imm } => {
+                return self.branch(rs1, rs2, imm, next_pc, |a, b| (a as i64) >= (b as i64));
+            }
+            Instruction::Bltu { rs1, rs2, imm } => {
+                return self.branch(rs1, rs2, imm, next_pc, |a, b| a < b);
+            }
+            Instruction::Bgeu { rs1, rs2, imm } => {
+                return self.branch(rs1, rs2, imm, next_pc, |a, b| a >= b);
+            }
+            Instruction::Lui { rd, imm } => {
+                self.registers.write_reg(rd, imm as i64 as u64);
+            }
+            Instruction::Auipc { rd, imm } => {
+                self.registers
+                    .write_reg(rd, self.pc.wrapping_add(imm as i64 as u64));
+            }
+            Instruction::Jal { rd, imm } => {
+                // the target is relative to the jal itself, not to the next instruction
+                self.registers.write_reg(rd, next_pc);
+                self.pc = self.pc.wrapping_add(imm as i64 as u64);
+                return Ok(());
+            }
+            Instruction::Jalr { rd, rs1, imm } => {
+                // the target address always has its lowest bit cleared
+                let dest_addr = self.effective_address(rs1, imm) & !1;
+                self.registers.write_reg(rd, next_pc);
+                self.pc = dest_addr;
+                return Ok(());
+            }
+            Instruction::Ecall => {
+                self.ecall(context)?;
+            }
+            Instruction::Ebreak => return Err(VMErrors::Breakpoint(self.pc as u32)),
+            Instruction::Wfi => return Err(VMErrors::PrivilegedInstruction(WFI)),
+            // there is no pre-decoded program to drop
+            Instruction::Fence | Instruction::FenceI => {}
+            // the bit-manipulation extensions and the counter CSRs are only supported for RV32
+            _ => return Err(VMErrors::InvalidInstruction),
+        }
+
+        self.pc = next_pc;
+        Ok(())
+    }
+
+    /// Runs an ecall on the wrapped [Vm], which sees the low 32 bits of every register.
+    /// Registers the ecall changed are written back sign-extended, the others keep their upper
+    /// half.
+    fn ecall(&mut self, context: &mut Context) -> Result<(), VMErrors> {
+        for reg in 1..32 {
+            self.vm
+                .registers
+                .write_reg(reg, self.registers.read_reg(reg) as u32);
+        }
+        self.vm.pc = self.pc as u32;
+
+        process_ecall(&mut self.vm, context)?;
+
+        for reg in 1..32 {
+            let value = self.vm.registers.read_reg(reg);
+            if value != self.registers.read_reg(reg) as u32 {
+                self.registers.write_reg(reg, sign_extend_word(value));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// `rd = op(rs1, rs2)`
+    #[inline(always)]
+    fn op_reg(&mut self, rd: u32, rs1: u32, rs2: u32, op: impl FnOnce(u64, u64) -> u64) {
+        let rs1 = self.registers.read_reg(rs1);
+        let rs2 = self.registers.read_reg(rs2);
+        self.registers.write_reg(rd, op(rs1, rs2));
+    }
+
+    /// `rd = op(rs1, imm)`, with `imm` sign-extended to 64 bits
+    #[inline(always)]
+    fn op_imm(&mut self, rd: u32, rs1: u32, imm: i32, op: impl FnOnce(u64, u64) -> u64) {
+        let rs1 = self.registers.read_reg(rs1);
+        self.registers.write_reg(rd, op(rs1, imm as i64 as u64));
+    }
+
+    /// `rs1 + imm`
+    fn effective_address(&self, rs1: u32, imm: i32) -> u64 {
+        self.registers.read_reg(rs1).wrapping_add(imm as i64 as u64)
+    }
+
+    /// The 32-bit memory address of `address`, which must be below 4 GiB and aligned to `size`
+    fn address(address: u64, size: MemoryChuckSize) -> Result<u32, VMErrors> {
+        let address = u32::try_from(address).map_err(|_| VMErrors::InvalidMemoryAccess)?;
+        let align_mask = match size {
+            MemoryChuckSize::BYTE => 0x0,
+            MemoryChuckSize::HalfWord => 0x1,
+            MemoryChuckSize::WordSize => 0x3,
+        };
+
+        if (address & align_mask) != 0x0 {
+            return Err(VMErrors::MemoryError);
+        }
+
+        Ok(address)
+    }
+
+    fn load(&self, rs1: u32, imm: i32, size: MemoryChuckSize) -> Result<u32, VMErrors> {
+        let address = Self::address(self.effective_address(rs1, imm), size.clone())?;
+        self.vm.segments.check(address, Access::Read)?;
+        self.vm
+            .memory
+            .read_mem(address, size)
+            .ok_or(VMErrors::MemoryLoadError)
+    }
+
+    fn store(
+        &mut self,
+        rs1: u32,
+        imm: i32,
+        size: MemoryChuckSize,
+        rs2: u32,
+    ) -> Result<(), VMErrors> {
+        let value = self.registers.read_reg(rs2) as u32;
+        self.write(rs1, imm, size, value)
+    }
+
+    fn write(
+        &mut self,
+        rs1: u32,
+        imm: i32,
+        size: MemoryChuckSize,
+        value: u32,
+    ) -> Result<(), VMErrors> {
+        let address = Self::address(self.effective_address(rs1, imm), size.clone())?;
+        self.vm.segments.check(address, Access::Write)?;
+        if !self.vm.memory.write_mem(address, size, value) {
+            return Err(VMErrors::MemoryStoreError);
+        }
+
+        Ok(())
+    }
+
+    /// Jump `imm` bytes away from the branch when `condition(rs1, rs2)` holds, fall through to
+    /// `next_pc` otherwise
+    #[inline(always)]
+    fn branch(
+        &mut self,
+        rs1: u32,
+        rs2: u32,
+        imm: i32,
+        next_pc: u64,
+        condition: impl FnOnce(u64, u64) -> bool,
+    ) -> Result<(), VMErrors> {
+        let rs1 = self.registers.read_reg(rs1);
+        let rs2 = self.registers.read_reg(rs2);
+
+        if condition(rs1, rs2) {
+            self.pc = self.pc.wrapping_add(imm as i64 as u64);
+        } else {
+            self.pc = next_pc;
+        }
+
+        Ok(())
+    }
+
+    /// Run the Vm64 until it halts, with the same instruction limit as [Vm::run].
+    pub fn run(&mut self, debug_mode: bool, context: &mut Context) {
+        let start = self.instret;
+        self.vm.running = true;
+        while self.vm.running {
+            match self.step(debug_mode, context) {
+                Ok(true) => {
+                    if self.instret - start > 100 {
+                        self.vm.running = false;
+                    }
+                }
+                Ok(false) => break,
+                Err(e) => {
+                    eprintln!("Error at pc: {:x} - error: {:?}", self.pc, e);
+                    self.vm.running = false;
+                }
+            }
+        }
+    }
+}
+
+impl Default for Vm64 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Vm64;
+    use crate::{
+        context::Context,
+        elf_parser::Elf,
+        instructions::Instruction,
+        segments::{Access, Permissions, Segment},
+        vm::VMErrors,
+    };
+    use revm::{Context as EthContext, MainContext, database::CacheDB, primitives::Address};
+    use riscv_evm_core::e_constants::ECALL_CODE_REG;
+
+    fn context() -> Context {
+        Context::new(EthContext::mainnet().with_db(CacheDB::default()))
+    }
+
+    fn run(code: Vec<u32>) -> Vm64 {
+        let mut context = context();
+        let mut vm = Vm64::from_bin(code.clone()).unwrap();
+        for _ in &code {
+            vm.step(false, &mut context).unwrap();
+        }
+        vm
+    }
+
+    /// A minimal ELF64 RISC-V executable with `code` as its only segment, loaded at `base`
+    fn elf64(code: &[u32], base: u64) -> Vec<u8> {
+        let mut elf = vec![0x7f, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        elf.extend(2u16.to_le_bytes()); // ET_EXEC
+        elf.extend(243u16.to_le_bytes()); // EM_RISCV
+        elf.extend(1u32.to_le_bytes());
+        elf.extend(base.to_le_bytes()); // entry
+        elf.extend(64u64.to_le_bytes()); // program headers right after this header
+        elf.extend(0u64.to_le_bytes()); // no section headers
+        elf.extend(0u32.to_le_bytes());
+        for half in [64u16, 56, 1, 64, 0, 0] {
+            elf.extend(half.to_le_bytes());
+        }
+
+        let size = (code.len() * 4) as u64;
+        elf.extend(1u32.to_le_bytes()); // PT_LOAD
+        elf.extend(5u32.to_le_bytes()); // R + X
+        for field in [120, base, base, size, size, 4] {
+            elf.extend(u64::to_le_bytes(field));
+        }
+        elf.extend(code.iter().flat_map(|word| word.to_le_bytes()));
+        elf
+    }
+
+    #[test]
+    fn test_decode64() {
+        assert_eq!(
+            Instruction::decode64(0x04003803).unwrap(),
+            Instruction::Ld {
+                rd: 16,
+                rs1: 0,
+                imm: 64
+            }
+        );
+        assert_eq!(
+            Instruction::decode64(0x02059613).unwrap(),
+            Instruction::Slli {
+                rd: 12,
+                rs1: 11,
+                shamt: 32
+            }
+        );
+        assert_eq!(
+            Instruction::decode64(0x00b6873b).unwrap().to_string(),
+            "addw a4, a3, a1"
+        );
+        // compressed code is not supported for RV64
+        assert!(matches!(
+            Instruction::decode64(0x0505),
+            Err(VMErrors::InvalidInstruction)
+        ));
+    }
+
+    #[test]
+    fn test_rv64_arithmetic_and_memory() {
+        let vm = run(vec![
+            0xfff00593, // addi a1, zero, -1
+            0x02059613, // slli a2, a1, 32
+            0x0015d693, // srli a3, a1, 1
+            0x00b6873b, // addw a4, a3, a1
+            0x00b687b3, // add a5, a3, a1
+            0x04c03023, // sd a2, 64(zero)
+            0x04003803, // ld a6, 64(zero)
+            0x04402883, // lw a7, 68(zero)
+            0x04406283, // lwu t0, 68(zero)
+            0x02b5b333, // mulhu t1, a1, a1
+            0x02b643bb, // divw t2, a2, a1
+            0x0205fe3b, // remuw t3, a1, zero
+            0x4045de9b, // sraiw t4, a1, 4
+            0x80000537, // lui a0, 0x80000
+        ]);
+
+        let reg = |reg| vm.registers.read_reg(reg);
+        assert_eq!(reg(11), u64::MAX);
+        assert_eq!(reg(12), 0xffff_ffff_0000_0000);
+        assert_eq!(reg(13), 0x7fff_ffff_ffff_ffff);
+        assert_eq!(reg(14), 0xffff_ffff_ffff_fffe);
+        assert_eq!(reg(15), 0x7fff_ffff_ffff_fffe);
+        assert_eq!(reg(16), 0xffff_ffff_0000_0000);
+        assert_eq!(reg(17), u64::MAX);
+        assert_eq!(reg(5), 0xffff_ffff);
+        assert_eq!(reg(6), 0xffff_ffff_ffff_fffe);
+        assert_eq!(reg(7), 0);
+        assert_eq!(reg(28), u64::MAX);
+        assert_eq!(reg(29), u64::MAX);
+        assert_eq!(reg(10), 0xffff_ffff_8000_0000);
+        assert_eq!(vm.pc, 14 * 4);
+        assert_eq!(vm.instret, 14);
+    }
+
+    #[test]
+    fn test_rv64_invalid_accesses() {
+        let mut context = context();
+
+        // ld a6, 68(zero) is not 8-byte aligned
+        let mut vm = Vm64::from_bin(vec![0x04403803]).unwrap();
+        assert!(matches!(
+            vm.step(false, &mut context),
+            Err(VMErrors::MemoryError)
+        ));
+
+        // ld a6, 0(a1) with a1 above 4 GiB
+        let mut vm = Vm64::from_bin(vec![0x0005b803]).unwrap();
+        vm.registers.write_reg(11, 1 << 32);
+        assert!(matches!(
+            vm.step(false, &mut context),
+            Err(VMErrors::InvalidMemoryAccess)
+        ));
+    }
+
+    #[test]
+    fn test_rv64_ecall_sign_extends_results() {
+        let mut context = context();
+        context.address = Address::from([
+            0xff, 0xff, 0xff, 0xff, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, 4,
+        ]);
+
+        let mut vm = Vm64::from_bin(vec![0x00000073]).unwrap(); // ecall
+        vm.registers.write_reg(ECALL_CODE_REG, 0x30); // Address
+        vm.registers.write_reg(6, 0xdead_beef_0000_0000);
+        vm.step(false, &mut context).unwrap();
+
+        assert_eq!(vm.registers.read_reg(1), u64::MAX);
+        assert_eq!(vm.registers.read_reg(2), 1);
+        assert_eq!(vm.registers.read_reg(5), 4);
+        // registers the ecall leaves alone keep their upper half
+        assert_eq!(vm.registers.read_reg(6), 0xdead_beef_0000_0000);
+        assert_eq!(vm.pc, 4);
+    }
+
+    #[test]
+    fn test_elf64() {
+        let code = [0x02059613, 0x00000073];
+        let elf = Elf::decode(&elf64(&code, 0x1000)).unwrap();
+        assert!(elf.is_64bit);
+        assert_eq!(elf.pc_start, 0x1000);
+        assert_eq!(elf.pc_base, 0x1000);
+        assert_eq!(elf.instructions, code);
+        assert_eq!(
+            elf.segments,
+            [Segment {
+                start: 0x1000,
+                size: 8,
+                permissions: Permissions::READ_EXECUTE
+            }]
+        );
+
+        let path = std::env::temp_dir().join(format!("riscv_evm_vm64_{}.elf", std::process::id()));
+        std::fs::write(&path, elf64(&code, 0x1000)).unwrap();
+        let path = path.to_string_lossy().to_string();
+        assert!(crate::vm::Vm::from_bin_elf(path.clone()).is_err());
+        let vm = Vm64::from_bin_elf(path.clone()).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(vm.pc, 0x1000);
+        assert!(vm.vm.segments.check(0x1004, Access::Write).is_err());
+        assert!(vm.vm.segments.check(0x1008, Access::Execute).is_err());
+    }
+}
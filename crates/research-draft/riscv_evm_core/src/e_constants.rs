@@ -1,3 +1,4 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RiscvEVMECalls {
     /// [offset, size] -> hash
     Keccak256,
@@ -84,10 +85,19 @@ pub enum RiscvEVMECalls {
     StaticCall,
     /// Halt execution reverting state changes but returning data and remaining gas [offset, size]
     Revert,
+    /// Halt execution sending the balance of the current contract to an account, the contract
+    /// is only deleted when it was created in the same transaction (EIP-6780) [address]
+    SelfDestruct,
     /// Loads a word (32-bytes) from storage
     SLoad,
     /// Stores a word (32-bytes) from storage
     SStore,
+    /// Writes a UTF-8 message (and optional register/memory dumps) to the host debug console,
+    /// this is a no-op unless the debug console is enabled [offset, size, flags, dumpOffset, dumpSize]
+    DebugLog,
+    /// Moves the program break by a signed increment, returning the previous break (or `u32::MAX`
+    /// when the heap can not grow that far), new heap pages are paid for [increment] -> oldBreak
+    Sbrk,
 }
 
 impl RiscvEVMECalls {
@@ -135,8 +145,11 @@ impl RiscvEVMECalls {
             0xF5 => Some(Self::Create2),
             0xFA => Some(Self::StaticCall),
             0xFD => Some(Self::Revert),
+            0xFF => Some(Self::SelfDestruct),
             0x54 => Some(Self::SLoad),
             0x55 => Some(Self::SStore),
+            0xC0 => Some(Self::DebugLog),
+            0xC1 => Some(Self::Sbrk),
             _ => None,
         }
     }
@@ -146,6 +159,10 @@ impl RiscvEVMECalls {
 // ECALL Constants
 //==========================
 pub const ECALL_CODE_REG: u32 = 31;
+/// RV32E code passes the ecall code in `t0`
+pub const RV32E_ECALL_CODE_REG: u32 = 5;
+/// RV32E code points `t1` at the ecall block, which holds the ecall registers other than `a0`-`a5`
+pub const RV32E_ECALL_BLOCK_REG: u32 = 6;
 
 // Keccak256
 pub const KECCAK256_OFFSET_REGISTER: u32 = 1;
@@ -497,6 +514,13 @@ pub const CREATE_2_OUTPUT_REGISTER_5: u32 = 23;
 pub const REVERT_INPUT_REGISTER_1: u32 = 1;
 pub const REVERT_INPUT_REGISTER_2: u32 = 2;
 
+// SelfDestruct
+pub const SELFDESTRUCT_INPUT_REGISTER_1: u32 = 1;
+pub const SELFDESTRUCT_INPUT_REGISTER_2: u32 = 2;
+pub const SELFDESTRUCT_INPUT_REGISTER_3: u32 = 3;
+pub const SELFDESTRUCT_INPUT_REGISTER_4: u32 = 4;
+pub const SELFDESTRUCT_INPUT_REGISTER_5: u32 = 5;
+
 // SLoad
 pub const SLOAD_INPUT_REGISTER_1: u32 = 1;
 pub const SLOAD_INPUT_REGISTER_2: u32 = 2;
@@ -533,3 +557,19 @@ pub const SSTORE_INPUT_REGISTER_13: u32 = 13;
 pub const SSTORE_INPUT_REGISTER_14: u32 = 14;
 pub const SSTORE_INPUT_REGISTER_15: u32 = 15;
 pub const SSTORE_INPUT_REGISTER_16: u32 = 16;
+
+// DebugLog
+pub const DEBUG_LOG_INPUT_REGISTER_1: u32 = 1;
+pub const DEBUG_LOG_INPUT_REGISTER_2: u32 = 2;
+pub const DEBUG_LOG_INPUT_REGISTER_3: u32 = 3;
+pub const DEBUG_LOG_INPUT_REGISTER_4: u32 = 4;
+pub const DEBUG_LOG_INPUT_REGISTER_5: u32 = 5;
+
+/// Set in the DebugLog flags register to dump all the registers along with the message
+pub const DEBUG_LOG_DUMP_REGISTERS: u32 = 1 << 0;
+/// Set in the DebugLog flags register to hex dump the memory range [dumpOffset, dumpOffset + dumpSize)
+pub const DEBUG_LOG_DUMP_MEMORY: u32 = 1 << 1;
+
+// Sbrk, `a0` in and out like sbrk(2) in the C ABI, so the call leaves `ra` and `sp` alone
+pub const SBRK_INPUT_REGISTER: u32 = 10;
+pub const SBRK_OUTPUT_REGISTER: u32 = 10;
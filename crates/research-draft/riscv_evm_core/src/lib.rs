@@ -10,6 +10,12 @@ pub const MAXIMUM_MEMORY_SIZE: u32 = u32::MAX;
 pub const HALF_WORD: usize = 2;
 /// This is the size of a byte in the VM
 pub const BYTE: usize = 1;
+/// This is the largest buffer an ecall is allowed to move between guest memory and the host
+pub const MAXIMUM_GUEST_BUFFER_SIZE: u32 = 1 << 24;
+/// This is the size of a memory page in bytes, the granularity writes are tracked at
+pub const MEMORY_PAGE_SIZE: u32 = 4096;
+/// This is the number of pages in the 32-bit address space
+const MEMORY_PAGES: usize = 1 << (32 - MEMORY_PAGE_SIZE.trailing_zeros());
 
 /// This defines the different chuck of memory that can be read or written to
 #[derive(Debug, Clone)]
@@ -22,6 +28,9 @@ pub enum MemoryChuckSize {
 #[derive(Debug, Clone)]
 pub struct Memory {
     pub memory: Vec<u32>,
+    /// One bit per page written since the memory was created or cleared, writes made directly to
+    /// `memory` are not tracked
+    dirty: Vec<u64>,
 }
 
 #[derive(Debug, Clone)]
@@ -29,6 +38,12 @@ pub struct Registers {
     data: [u32; 32],
 }
 
+/// The 64-bit registers of an RV64 hart
+#[derive(Debug, Clone, Default)]
+pub struct Registers64 {
+    data: [u64; 32],
+}
+
 impl MemoryInterface for Memory {
     // fn read_mem(&self, addr: u32, size: MemoryChuckSize) -> Option<u32> {
     //     // Calculate a mask and shift to apply to a 32-bit word to get the required data
@@ -168,6 +183,7 @@ impl MemoryInterface for Memory {
                 self.memory[word_addr as usize] = value;
             }
         }
+        self.mark_dirty(addr);
 
         true
     }
@@ -189,12 +205,37 @@ impl Registers {
 
         self.data[reg as usize] = value;
     }
+
+    /// Raw pointer to the 32 registers, for native code that reads and writes them in place.
+    /// Anything writing through it must leave `x0` untouched.
+    pub fn as_mut_ptr(&mut self) -> *mut u32 {
+        self.data.as_mut_ptr()
+    }
+}
+
+impl Registers64 {
+    pub fn new() -> Self {
+        Registers64 { data: [0; 32] }
+    }
+
+    pub fn read_reg(&self, reg: u32) -> u64 {
+        self.data[reg as usize]
+    }
+
+    pub fn write_reg(&mut self, reg: u32, value: u64) {
+        if reg == 0 {
+            return;
+        }
+
+        self.data[reg as usize] = value;
+    }
 }
 
 impl Memory {
     pub fn new() -> Self {
         Memory {
             memory: vec![0; MAXIMUM_MEMORY_SIZE as usize],
+            dirty: vec![0; MEMORY_PAGES / 64],
         }
     }
 
@@ -202,11 +243,48 @@ impl Memory {
         let mut addr = (base_addr >> 2) as usize;
 
         for word in program {
-            self.memory[addr] = *word;
+            self.set_word(addr, *word);
             addr += 1;
         }
     }
 
+    /// Writes the word at word index `word_addr`, tracking the page it is in
+    pub fn set_word(&mut self, word_addr: usize, value: u32) {
+        self.memory[word_addr] = value;
+        self.mark_dirty((word_addr as u32) << 2);
+    }
+
+    fn mark_dirty(&mut self, addr: u32) {
+        let page = (addr / MEMORY_PAGE_SIZE) as usize;
+        self.dirty[page / 64] |= 1 << (page % 64);
+    }
+
+    /// The numbers of the pages written so far, in ascending order
+    pub fn dirty_pages(&self) -> impl Iterator<Item = u32> + '_ {
+        self.dirty.iter().enumerate().flat_map(|(i, &bits)| {
+            (0..64)
+                .filter(move |bit| bits & (1 << bit) != 0)
+                .map(move |bit| (i * 64 + bit) as u32)
+        })
+    }
+
+    /// The words of page number `page`
+    pub fn page(&self, page: u32) -> &[u32] {
+        let words = (MEMORY_PAGE_SIZE as usize) / WORD_SIZE;
+        let start = page as usize * words;
+        &self.memory[start..start + words]
+    }
+
+    /// Zeroes every page written so far, leaving the memory as it was created
+    pub fn clear(&mut self) {
+        let words = (MEMORY_PAGE_SIZE as usize) / WORD_SIZE;
+        for page in self.dirty_pages().collect::<Vec<_>>() {
+            let start = page as usize * words;
+            self.memory[start..start + words].fill(0);
+        }
+        self.dirty.fill(0);
+    }
+
     pub fn new_with_load_program(program: &Vec<u32>, base_addr: u32) -> Self {
         let mut memory = Memory::new();
         memory.load_program(program, base_addr);
@@ -223,6 +301,19 @@ pub fn sign_extend_u32(x: u32) -> i64 {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_dirty_pages() {
+        let mut memory = Memory::new();
+        memory.load_program(&vec![1, 2], 0x1ffc);
+        memory.write_mem(0xffff_fffc, MemoryChuckSize::BYTE, 7);
+        assert_eq!(memory.dirty_pages().collect::<Vec<_>>(), [1, 2, 0xfffff]);
+        assert_eq!(memory.page(2)[0], 2);
+
+        memory.clear();
+        assert_eq!(memory.dirty_pages().count(), 0);
+        assert_eq!(memory.read_mem(0x2000, MemoryChuckSize::WordSize), Some(0));
+    }
+
     #[test]
     fn test_memory_read() {
         // Create a memory instance with our test values
@@ -0,0 +1,132 @@
+//! # Guest runtime
+//! What contracts written in Rust and built for a bare RISC-V target (e.g.
+//! `riscv32im-unknown-none-elf`) link against to run in the RISC-V EVM. For now this is the global
+//! allocator, so contracts can use `alloc`:
+//!
+//! ```ignore
+//! #[global_allocator]
+//! static ALLOCATOR: riscv_evm_guest::BumpAllocator = riscv_evm_guest::BumpAllocator::new();
+//! ```
+//!
+//! The heap it allocates from is the one the `Sbrk` ecall grows, see `riscv_evm::memory_map`.
+#![no_std]
+
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    cell::Cell,
+    ptr,
+};
+
+/// Code of the `Sbrk` ecall
+pub const SBRK: u32 = 0xC1;
+
+/// Moves the program break of the guest
+pub trait ProgramBreak {
+    /// Moves the break by `increment` bytes and returns the old break, `None` when the heap cannot
+    /// move that far (`Sbrk` returning -1)
+    fn sbrk(&self, increment: i32) -> Option<usize>;
+}
+
+/// The break of the Vm the guest runs in, moved with the `Sbrk` ecall
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sbrk;
+
+impl ProgramBreak for Sbrk {
+    #[cfg(target_arch = "riscv32")]
+    fn sbrk(&self, increment: i32) -> Option<usize> {
+        let previous: u32;
+        // SAFETY: `Sbrk` only writes `a0`, and only moves the end of the heap
+        unsafe {
+            #[cfg(not(target_feature = "e"))]
+            core::arch::asm!("ecall", in("t6") SBRK, inlateout("a0") increment => previous);
+            // RV32E code passes the ecall code in t0
+            #[cfg(target_feature = "e")]
+            core::arch::asm!("ecall", in("t0") SBRK, inlateout("a0") increment => previous);
+        }
+        (previous != u32::MAX).then_some(previous as usize)
+    }
+
+    /// There is no Vm to ask outside of a RISC-V guest, so there is no heap either
+    #[cfg(not(target_arch = "riscv32"))]
+    fn sbrk(&self, _increment: i32) -> Option<usize> {
+        None
+    }
+}
+
+/// A bump allocator over the heap: allocations are carved out of it in order, moving the break
+/// up when the next one does not fit. Freed memory is never reused, a contract runs for one frame
+/// and every heap page it reaches is paid for once anyway (see `riscv_evm::gas::HEAP_PAGE_COST`).
+#[derive(Debug)]
+pub struct BumpAllocator<B = Sbrk> {
+    program_break: B,
+    /// The next free byte, `None` until the first allocation asks where the heap starts
+    next: Cell<Option<usize>>,
+    /// The break, nothing but this allocator moves it
+    end: Cell<usize>,
+}
+
+// SAFETY: a guest runs on a single hart, nothing ever allocates concurrently
+unsafe impl<B: Sync> Sync for BumpAllocator<B> {}
+
+impl BumpAllocator {
+    /// An allocator over the heap of the Vm
+    pub const fn new() -> Self {
+        Self::with_break(Sbrk)
+    }
+}
+
+impl Default for BumpAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<B> BumpAllocator<B> {
+    /// An allocator over the heap `program_break` moves
+    pub const fn with_break(program_break: B) -> Self {
+        Self {
+            program_break,
+            next: Cell::new(None),
+            end: Cell::new(0),
+        }
+    }
+}
+
+impl<B: ProgramBreak> BumpAllocator<B> {
+    /// What moves the break
+    pub fn program_break(&self) -> &B {
+        &self.program_break
+    }
+
+    /// The address of a new block of `layout`, `None` when the heap is exhausted
+    fn bump(&self, layout: Layout) -> Option<usize> {
+        let next = match self.next.get() {
+            Some(next) => next,
+            None => {
+                let start = self.program_break.sbrk(0)?;
+                self.end.set(start);
+                start
+            }
+        };
+        let start = next.checked_next_multiple_of(layout.align())?;
+        let new_next = start.checked_add(layout.size())?;
+        if new_next > self.end.get() {
+            let increment = i32::try_from(new_next - self.end.get()).ok()?;
+            self.program_break.sbrk(increment)?;
+            self.end.set(new_next);
+        }
+        self.next.set(Some(new_next));
+        Some(start)
+    }
+}
+
+unsafe impl<B: ProgramBreak> GlobalAlloc for BumpAllocator<B> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        match self.bump(layout) {
+            Some(address) => address as *mut u8,
+            None => ptr::null_mut(),
+        }
+    }
+
+    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {}
+}
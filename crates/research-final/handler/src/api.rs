@@ -1,11 +1,17 @@
-use primitives::RiscvEVM;
 use revm::{
-    Database, DatabaseCommit,
+    DatabaseCommit,
     context::{
-        Block, ContextSetters, ContextTr, JournalOutput, JournalTr, Transaction,
-        result::{EVMError, ExecutionResult, HaltReason, InvalidTransaction, ResultAndState},
+        Block, ContextSetters, ContextTr, Transaction,
+        result::{ExecutionResult, HaltReason, ResultAndState},
     },
 };
+use riscv_evm::{context::EthContext, inspector::SharedInspector};
+
+use crate::{
+    frame::{RiscvFrameError, RiscvInspector},
+    handler::{Handler, RiscvHandler},
+    main_builder::MainnetRiscvEVM,
+};
 
 /// Execute EVM transactions. Main trait for transaction execution.
 pub trait ExecuteEvm {
@@ -49,23 +55,42 @@ pub trait ExecuteCommitEvm: ExecuteEvm {
     }
 }
 
-impl<CTX, P> ExecuteEvm for RiscvEVM<CTX, P>
-where
-    CTX: ContextTr<Journal: JournalTr<FinalOutput = JournalOutput>> + ContextSetters,
-{
-    type Output = Result<
-        ResultAndState<HaltReason>,
-        EVMError<<CTX::Db as Database>::Error, InvalidTransaction>,
-    >;
+/// Inspect EVM transactions, the counterpart of revm's `InspectEvm`.
+/// The frames report to the inspector the EVM was built with whenever they run, so
+/// `inspect_replay` is `replay` under the name revm tooling expects.
+pub trait InspectEvm: ExecuteEvm {
+    /// The inspector type.
+    type Inspector;
+
+    /// Set the inspector.
+    fn set_inspector(&mut self, inspector: Self::Inspector);
+
+    /// Inspect the transaction that is set in the context.
+    fn inspect_replay(&mut self) -> Self::Output;
+
+    /// Inspect the given transaction.
+    fn inspect_with_tx(&mut self, tx: Self::Tx) -> Self::Output {
+        self.set_tx(tx);
+        self.inspect_replay()
+    }
 
-    type Tx = <CTX as ContextTr>::Tx;
+    /// Inspect the given transaction with the given inspector.
+    fn inspect(&mut self, tx: Self::Tx, inspector: Self::Inspector) -> Self::Output {
+        self.set_tx(tx);
+        self.set_inspector(inspector);
+        self.inspect_replay()
+    }
+}
 
-    type Block = <CTX as ContextTr>::Block;
+impl<I: RiscvInspector> ExecuteEvm for MainnetRiscvEVM<EthContext, I> {
+    type Output = Result<ResultAndState<HaltReason>, RiscvFrameError>;
+
+    type Tx = <EthContext as ContextTr>::Tx;
+
+    type Block = <EthContext as ContextTr>::Block;
 
     fn replay(&mut self) -> Self::Output {
-        // let mut t = MainnetHandler::<_, _, EthFrame<_, _, _>>::default();
-        // t.run(self)
-        todo!()
+        RiscvHandler::<I>::default().run(self)
     }
 
     fn set_tx(&mut self, tx: Self::Tx) {
@@ -77,15 +102,8 @@ where
     }
 }
 
-impl<CTX, P> ExecuteCommitEvm for RiscvEVM<CTX, P>
-where
-    CTX: ContextTr<Journal: JournalTr<FinalOutput = JournalOutput>, Db: DatabaseCommit>
-        + ContextSetters,
-{
-    type CommitOutput = Result<
-        ExecutionResult<HaltReason>,
-        EVMError<<CTX::Db as Database>::Error, InvalidTransaction>,
-    >;
+impl<I: RiscvInspector> ExecuteCommitEvm for MainnetRiscvEVM<EthContext, I> {
+    type CommitOutput = Result<ExecutionResult<HaltReason>, RiscvFrameError>;
 
     fn replay_commit(&mut self) -> Self::CommitOutput {
         self.replay().map(|r| {
@@ -94,3 +112,15 @@ where
         })
     }
 }
+
+impl InspectEvm for MainnetRiscvEVM<EthContext, SharedInspector> {
+    type Inspector = SharedInspector;
+
+    fn set_inspector(&mut self, inspector: Self::Inspector) {
+        self.inspector = inspector;
+    }
+
+    fn inspect_replay(&mut self) -> Self::Output {
+        self.replay()
+    }
+}
@@ -0,0 +1,475 @@
+//! The frame the handler runs the RISC-V contracts in.
+//! Nested calls and creates are run by the ecalls of the Vm itself (see
+//! [riscv_evm::ecall_manager]), so the handler only ever sees the top-level frame: it runs the
+//! whole call tree against the journal of the EVM under a checkpoint, committed on success and
+//! reverted otherwise.
+//!
+//! The inspector of the EVM, if any, sees the top-level frame the way revm reports it: `call` or
+//! `create` may change the inputs or answer for the frame, and the outcome left by `call_end` or
+//! `create_end` is the result of the transaction. It follows the nested frames through the
+//! ecalls, see [riscv_evm::inspector].
+use std::{convert::Infallible, marker::PhantomData, sync::Arc};
+
+use revm::{
+    context::{
+        ContextTr, JournalTr,
+        journaled_state::JournalCheckpoint,
+        result::{EVMError, FromStringError, InvalidTransaction},
+    },
+    database::CacheDB,
+    handler::{Frame, FrameInitOrResult, FrameOrResult, FrameResult, ItemOrResult},
+    interpreter::{
+        CallInputs, CallOutcome, CreateInputs, CreateOutcome, FrameInput, Gas, InstructionResult,
+        InterpreterResult,
+    },
+    primitives::{Address, Bytes, keccak256},
+    state::Bytecode,
+};
+use riscv_evm::{
+    code_cache::DecodedProgram,
+    context::{Context as RiscvContext, EthContext},
+    ecall_manager::run_frame,
+    inspector::{SharedInspector, frame_result},
+    vm::VMErrors,
+};
+
+use crate::main_builder::MainnetRiscvEVM;
+
+pub type RiscvFrameError = EVMError<Infallible, InvalidTransaction>;
+
+/// What a [MainnetRiscvEVM] holds as its inspector: `()` for none, or a [SharedInspector]
+pub trait RiscvInspector {
+    /// The inspector to report the frames to
+    fn shared_inspector(&self) -> Option<SharedInspector>;
+}
+
+impl RiscvInspector for () {
+    fn shared_inspector(&self) -> Option<SharedInspector> {
+        None
+    }
+}
+
+impl RiscvInspector for SharedInspector {
+    fn shared_inspector(&self) -> Option<SharedInspector> {
+        Some(self.clone())
+    }
+}
+
+/// A frame waiting to be run by [Frame::run]
+#[derive(Debug)]
+pub struct RiscvFrame<I = ()> {
+    pub input: FrameInput,
+    inspector: PhantomData<I>,
+}
+
+impl<I: RiscvInspector> Frame for RiscvFrame<I> {
+    type Evm = MainnetRiscvEVM<EthContext, I>;
+    type FrameInit = FrameInput;
+    type FrameResult = FrameResult;
+    type Error = RiscvFrameError;
+
+    fn init_first(
+        _evm: &mut Self::Evm,
+        frame_input: Self::FrameInit,
+    ) -> Result<FrameOrResult<Self>, Self::Error> {
+        Ok(ItemOrResult::Item(Self {
+            input: frame_input,
+            inspector: PhantomData,
+        }))
+    }
+
+    fn init(
+        &self,
+        evm: &mut Self::Evm,
+        frame_input: Self::FrameInit,
+    ) -> Result<FrameOrResult<Self>, Self::Error> {
+        Self::init_first(evm, frame_input)
+    }
+
+    fn run(&mut self, evm: &mut Self::Evm) -> Result<FrameInitOrResult<Self>, Self::Error> {
+        let result = match &self.input {
+            FrameInput::Call(inputs) => FrameResult::Call(call(evm, (**inputs).clone())?),
+            FrameInput::Create(inputs) => FrameResult::Create(create(evm, (**inputs).clone())?),
+            FrameInput::EOFCreate(_) => {
+                return Err(Self::Error::from_string(
+                    "EOF contracts are not supported by the RISC-V VM".into(),
+                ));
+            }
+        };
+        Ok(ItemOrResult::Result(result))
+    }
+
+    fn return_result(
+        &mut self,
+        _evm: &mut Self::Evm,
+        _result: Self::FrameResult,
+    ) -> Result<(), Self::Error> {
+        // `run` never asks for a sub-frame, the ecalls run them
+        Ok(())
+    }
+}
+
+/// Runs `run` on a [RiscvContext] over the context of `evm`, which is moved out for the time of
+/// the frame and put back after, so the frame runs against the journal of `evm` itself
+fn with_frame_context<I, T>(
+    evm: &mut MainnetRiscvEVM<EthContext, I>,
+    gas_limit: u64,
+    inspector: Option<SharedInspector>,
+    run: impl FnOnce(&mut RiscvContext) -> T,
+) -> T {
+    let spec = evm.context.cfg.spec;
+    let eth_context =
+        std::mem::replace(&mut evm.context, EthContext::new(CacheDB::default(), spec));
+    let mut context = RiscvContext::new(eth_context);
+    context.gas = Gas::new(gas_limit);
+    context.inspector = inspector;
+    let output = run(&mut context);
+    evm.context = context.eth_context;
+    output
+}
+
+/// Runs `program` in `context` and returns its result, with the refunds of the SSTOREs it ran
+fn run_program(
+    context: &mut RiscvContext,
+    program: Arc<DecodedProgram>,
+    gas_limit: u64,
+) -> Result<InterpreterResult, RiscvFrameError> {
+    // an account without code accepts the call
+    if program.is_empty() {
+        return Ok(InterpreterResult::new(
+            InstructionResult::Stop,
+            Bytes::new(),
+            Gas::new(gas_limit),
+        ));
+    }
+
+    let vm = run_frame(program, context).map_err(vm_error)?;
+    Ok(frame_result(&vm, context, gas_limit))
+}
+
+/// Ends the frame started at `checkpoint`, keeping its changes if it succeeded and reverting
+/// them otherwise
+fn settle<I>(
+    evm: &mut MainnetRiscvEVM<EthContext, I>,
+    result: &InterpreterResult,
+    checkpoint: JournalCheckpoint,
+) {
+    if result.is_ok() {
+        evm.context.journal().checkpoint_commit();
+    } else {
+        evm.context.journal().checkpoint_revert(checkpoint);
+    }
+}
+
+fn call<I: RiscvInspector>(
+    evm: &mut MainnetRiscvEVM<EthContext, I>,
+    mut inputs: CallInputs,
+) -> Result<CallOutcome, RiscvFrameError> {
+    let inspector = evm.inspector.shared_inspector();
+    let answer = inspector
+        .as_ref()
+        .and_then(|inspector| inspector.with(|i| i.call(&mut evm.context, &mut inputs)));
+
+    let checkpoint = evm.context.journal().checkpoint();
+    let mut outcome = match answer {
+        Some(outcome) => outcome,
+        None => match run_call(evm, &inputs, inspector.clone()) {
+            Ok(result) => CallOutcome::new(result, inputs.return_memory_offset.clone()),
+            Err(error) => {
+                evm.context.journal().checkpoint_revert(checkpoint);
+                return Err(error);
+            }
+        },
+    };
+    if let Some(inspector) = &inspector {
+        inspector.with(|i| i.call_end(&mut evm.context, &inputs, &mut outcome));
+    }
+
+    settle(evm, &outcome.result, checkpoint);
+    Ok(outcome)
+}
+
+/// Moves the value of the call of `inputs` and runs the code of its bytecode address
+fn run_call<I>(
+    evm: &mut MainnetRiscvEVM<EthContext, I>,
+    inputs: &CallInputs,
+    inspector: Option<SharedInspector>,
+) -> Result<InterpreterResult, RiscvFrameError> {
+    let journal = evm.context.journal();
+    journal.load_account(inputs.caller)?;
+    journal.load_account(inputs.target_address)?;
+    if let Some(error) =
+        journal.transfer(inputs.caller, inputs.target_address, inputs.call_value())?
+    {
+        return Ok(InterpreterResult::new(
+            error.into(),
+            Bytes::new(),
+            Gas::new(inputs.gas_limit),
+        ));
+    }
+
+    with_frame_context(evm, inputs.gas_limit, inspector, |context| {
+        context.address = inputs.target_address;
+        context.current_caller = inputs.caller;
+        let data = std::mem::replace(&mut context.eth_context.tx.data, inputs.input.clone());
+        let program = context.load_program(inputs.bytecode_address);
+        let result = run_program(context, program, inputs.gas_limit);
+        context.eth_context.tx.data = data;
+        result
+    })
+}
+
+fn create<I: RiscvInspector>(
+    evm: &mut MainnetRiscvEVM<EthContext, I>,
+    mut inputs: CreateInputs,
+) -> Result<CreateOutcome, RiscvFrameError> {
+    let inspector = evm.inspector.shared_inspector();
+    let answer = inspector
+        .as_ref()
+        .and_then(|inspector| inspector.with(|i| i.create(&mut evm.context, &mut inputs)));
+
+    let (checkpoint, mut outcome) = match answer {
+        Some(outcome) => (evm.context.journal().checkpoint(), outcome),
+        None => run_create(evm, &inputs, inspector.clone())?,
+    };
+    if let Some(inspector) = &inspector {
+        inspector.with(|i| i.create_end(&mut evm.context, &inputs, &mut outcome));
+    }
+
+    if outcome.result.is_ok()
+        && let Some(address) = outcome.address
+    {
+        let journal = evm.context.journal();
+        journal.set_code(address, Bytecode::new_legacy(outcome.result.output.clone()));
+        journal.inc_account_nonce(address)?;
+    }
+    settle(evm, &outcome.result, checkpoint);
+    Ok(outcome)
+}
+
+/// Bumps the nonce of the creator, which stays bumped whether or not the init code succeeds,
+/// then moves the value and runs the init code of `inputs` under a checkpoint
+fn run_create<I>(
+    evm: &mut MainnetRiscvEVM<EthContext, I>,
+    inputs: &CreateInputs,
+    inspector: Option<SharedInspector>,
+) -> Result<(JournalCheckpoint, CreateOutcome), RiscvFrameError> {
+    let journal = evm.context.journal();
+    journal.load_account(inputs.caller)?;
+    let nonce = journal.inc_account_nonce(inputs.caller)?;
+    let checkpoint = journal.checkpoint();
+    let Some(nonce) = nonce else {
+        let result = InterpreterResult::new(
+            InstructionResult::Return,
+            Bytes::new(),
+            Gas::new(inputs.gas_limit),
+        );
+        return Ok((checkpoint, CreateOutcome::new(result, None)));
+    };
+    let address = inputs.created_address(nonce - 1);
+
+    match run_init_code(evm, inputs, address, inspector) {
+        Ok(outcome) => Ok((checkpoint, outcome)),
+        Err(error) => {
+            evm.context.journal().checkpoint_revert(checkpoint);
+            Err(error)
+        }
+    }
+}
+
+/// Moves the value of the create of `inputs` to `address` and runs the init code there
+fn run_init_code<I>(
+    evm: &mut MainnetRiscvEVM<EthContext, I>,
+    inputs: &CreateInputs,
+    address: Address,
+    inspector: Option<SharedInspector>,
+) -> Result<CreateOutcome, RiscvFrameError> {
+    let journal = evm.context.journal();
+    journal.load_account(address)?;
+    if let Some(error) = journal.transfer(inputs.caller, address, inputs.value)? {
+        let result = InterpreterResult::new(error.into(), Bytes::new(), Gas::new(inputs.gas_limit));
+        return Ok(CreateOutcome::new(result, None));
+    }
+
+    let result = with_frame_context(evm, inputs.gas_limit, inspector, |context| {
+        context.address = address;
+        context.current_caller = inputs.caller;
+        let program = context.decoded_program(keccak256(&inputs.init_code), &inputs.init_code);
+        run_program(context, program, inputs.gas_limit)
+    })?;
+    Ok(CreateOutcome::new(result, Some(address)))
+}
+
+fn vm_error(error: VMErrors) -> RiscvFrameError {
+    RiscvFrameError::from_string(format!("{error:?}"))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use revm::{
+        Context, Inspector, MainContext,
+        context::{
+            TxEnv,
+            transaction::{AccessList, AccessListItem},
+        },
+        database::{CacheDB, EmptyDB},
+        interpreter::{CallInputs, CallOutcome, InstructionResult},
+        primitives::{Address, B256, TxKind, U256},
+        state::{AccountInfo, Bytecode},
+    };
+    use riscv_evm::{context::EthContext, inspector::SharedInspector, utils::u32_vec_to_bytes};
+
+    use crate::{
+        api::{ExecuteEvm, InspectEvm},
+        main_builder::MainBuilder,
+    };
+
+    const CONTRACT: Address = Address::new([0x42; 20]);
+
+    /// Clears slot 0, then returns nothing
+    const CLEAR_SLOT: [u32; 6] = [
+        0x00000113, // addi sp, zero, 0
+        0x00000513, // addi a0, zero, 0
+        0x05500F93, // addi t6, zero, 0x55 (SStore slot 0 = 0)
+        0x00000073, // ecall
+        0x0F300F93, // addi t6, zero, 0xF3 (Return)
+        0x00000073, // ecall
+    ];
+
+    /// A database holding `code` at [CONTRACT], with 1 in its slot 0
+    fn deploy(code: &[u32]) -> CacheDB<EmptyDB> {
+        let bytecode = Bytecode::new_legacy(u32_vec_to_bytes(code, code.len() * 4).into());
+        let mut db = CacheDB::default();
+        db.insert_account_info(
+            CONTRACT,
+            AccountInfo {
+                code_hash: bytecode.hash_slow(),
+                code: Some(bytecode),
+                ..Default::default()
+            },
+        );
+        db.insert_account_storage(CONTRACT, U256::ZERO, U256::from(1))
+            .unwrap();
+        db
+    }
+
+    #[test]
+    fn test_clear_slot_refund() {
+        let db = deploy(&CLEAR_SLOT);
+
+        let mut evm = Context::mainnet()
+            .modify_tx_chained(|tx| tx.kind = TxKind::Call(CONTRACT))
+            .with_db(db)
+            .build_mainnet_with_riscv_evm();
+        let output = evm.replay().unwrap();
+
+        assert!(output.result.is_success());
+        // 21000 intrinsic + 5000 for the cold SSTORE, less the 4800 clearing refund
+        assert_eq!(output.result.gas_used(), 21200);
+        assert_eq!(
+            output.state[&CONTRACT].storage[&U256::ZERO].present_value,
+            U256::ZERO
+        );
+    }
+
+    #[test]
+    fn test_access_list_warms_frame() {
+        let code = [
+            0x00000113, // addi sp, zero, 0
+            0x0A000F93, // addi t6, zero, 0xA0 (Log0)
+            0x00000073, // ecall
+            0x00100093, // addi ra, zero, 1
+            0x03100F93, // addi t6, zero, 0x31 (Balance of 0x00000001000..)
+            0x00000073, // ecall
+            0x00000093, // addi ra, zero, 0
+            0x05400F93, // addi t6, zero, 0x54 (SLoad slot 0)
+            0x00000073, // ecall
+            0x0F300F93, // addi t6, zero, 0xF3 (Return)
+            0x00000073, // ecall
+        ];
+        let mut account = Address::ZERO;
+        account.0[3] = 1;
+
+        let gas_used = |access_list: AccessList| {
+            let mut evm = Context::mainnet()
+                .modify_tx_chained(|tx| {
+                    tx.kind = TxKind::Call(CONTRACT);
+                    tx.access_list = access_list;
+                })
+                .with_db(deploy(&code))
+                .build_mainnet_with_riscv_evm();
+            let output = evm.replay().unwrap();
+            assert!(output.result.is_success());
+            output.result.gas_used()
+        };
+
+        // 21000 intrinsic + 2600 for the cold account + 2100 for the cold slot
+        assert_eq!(gas_used(AccessList::default()), 25700);
+        // 21000 intrinsic + 2 * 2400 for the addresses + 1900 for the slot, 100 for each access
+        let access_list = AccessList(vec![
+            AccessListItem {
+                address: account,
+                storage_keys: vec![],
+            },
+            AccessListItem {
+                address: CONTRACT,
+                storage_keys: vec![B256::ZERO],
+            },
+        ]);
+        assert_eq!(gas_used(access_list), 27900);
+    }
+
+    #[derive(Default)]
+    struct Reverter {
+        calls: Vec<Address>,
+        call_ends: Vec<InstructionResult>,
+    }
+
+    impl Inspector<EthContext> for Reverter {
+        fn call(
+            &mut self,
+            _context: &mut EthContext,
+            inputs: &mut CallInputs,
+        ) -> Option<CallOutcome> {
+            self.calls.push(inputs.target_address);
+            None
+        }
+
+        fn call_end(
+            &mut self,
+            _context: &mut EthContext,
+            _inputs: &CallInputs,
+            outcome: &mut CallOutcome,
+        ) {
+            self.call_ends.push(outcome.result.result);
+            outcome.result.result = InstructionResult::Revert;
+        }
+    }
+
+    #[test]
+    fn test_inspect() {
+        let reverter = Arc::new(Mutex::new(Reverter::default()));
+        let mut evm = Context::mainnet()
+            .with_db(deploy(&CLEAR_SLOT))
+            .build_mainnet_with_riscv_evm_and_inspector(SharedInspector::new(reverter.clone()));
+        let tx = TxEnv {
+            kind: TxKind::Call(CONTRACT),
+            ..Default::default()
+        };
+        let output = evm.inspect_with_tx(tx).unwrap();
+
+        // the inspector saw the top-level frame succeed, and its revert is the result
+        let reverter = reverter.lock().unwrap();
+        assert_eq!(reverter.calls, [CONTRACT]);
+        assert_eq!(reverter.call_ends, [InstructionResult::Return]);
+        assert!(!output.result.is_success());
+        // so the slot keeps its value
+        assert!(
+            output.state.get(&CONTRACT).is_none_or(|account| {
+                account.storage[&U256::ZERO].present_value == U256::from(1)
+            })
+        );
+    }
+}
@@ -1,11 +1,14 @@
 //! This is a handler implementation pulled from the revm implementation and modified to accommodate this experiment
+use std::marker::PhantomData;
+
 use primitives::RiscvEvmTr;
 use revm::{
     Database,
     context::{
         Cfg, ContextTr, JournalOutput, JournalTr, Transaction,
         result::{
-            FromStringError, HaltReasonTr, InvalidHeader, InvalidTransaction, ResultAndState,
+            FromStringError, HaltReason, HaltReasonTr, InvalidHeader, InvalidTransaction,
+            ResultAndState,
         },
     },
     context_interface::context::ContextError,
@@ -15,8 +18,14 @@ use revm::{
     },
     interpreter::{FrameInput, Gas, InitialAndFloorGas},
 };
+use riscv_evm::context::EthContext;
 
-use crate::{execution, pre_execution};
+use crate::{
+    execution,
+    frame::{RiscvFrame, RiscvFrameError, RiscvInspector},
+    main_builder::MainnetRiscvEVM,
+    pre_execution,
+};
 
 pub trait EvmTrError<EVM: RiscvEvmTr>:
     From<InvalidTransaction>
@@ -388,3 +397,20 @@ pub trait Handler<CTX: ContextTr> {
         Err(error)
     }
 }
+
+/// The handler running transactions on the RISC-V VM, see [RiscvFrame]
+#[derive(Debug)]
+pub struct RiscvHandler<I = ()>(PhantomData<I>);
+
+impl<I> Default for RiscvHandler<I> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<I: RiscvInspector> Handler<EthContext> for RiscvHandler<I> {
+    type RiscvEVM = MainnetRiscvEVM<EthContext, I>;
+    type Frame = RiscvFrame<I>;
+    type HaltReason = HaltReason;
+    type Error = RiscvFrameError;
+}
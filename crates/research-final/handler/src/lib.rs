@@ -1,6 +1,7 @@
 //! The role of this lib is similar to the mainnet_builder_hanlder in REVM, this would be responsible for building the RiscvEVM structure and binding a Context to it
 pub mod api;
 pub mod execution;
+pub mod frame;
 pub mod handler;
 pub mod main_builder;
 pub mod post_execution;
@@ -5,12 +5,17 @@ use revm::{
     handler::EthPrecompiles,
 };
 
-pub type MainnetRiscvEVM<CTX> = RiscvEVM<CTX, EthPrecompiles>;
+pub type MainnetRiscvEVM<CTX, I = ()> = RiscvEVM<CTX, EthPrecompiles, I>;
 
 pub trait MainBuilder: Sized {
     type Context;
 
     fn build_mainnet_with_riscv_evm(self) -> MainnetRiscvEVM<Self::Context>;
+
+    fn build_mainnet_with_riscv_evm_and_inspector<I>(
+        self,
+        inspector: I,
+    ) -> MainnetRiscvEVM<Self::Context, I>;
 }
 
 impl<BLOCK, TX, CFG, DB, JOURNAL, CHAIN> MainBuilder for Context<BLOCK, TX, CFG, DB, JOURNAL, CHAIN>
@@ -24,9 +29,17 @@ where
     type Context = Self;
 
     fn build_mainnet_with_riscv_evm(self) -> MainnetRiscvEVM<Self::Context> {
+        self.build_mainnet_with_riscv_evm_and_inspector(())
+    }
+
+    fn build_mainnet_with_riscv_evm_and_inspector<I>(
+        self,
+        inspector: I,
+    ) -> MainnetRiscvEVM<Self::Context, I> {
         RiscvEVM {
             context: self,
             precompiles: EthPrecompiles::default(),
+            inspector,
         }
     }
 }
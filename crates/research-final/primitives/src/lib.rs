@@ -20,12 +20,14 @@ pub trait RiscvEvmTr {
 }
 
 #[derive(Debug)]
-pub struct RiscvEVM<Context, P> {
+pub struct RiscvEVM<Context, P, I = ()> {
     pub context: Context,
     pub precompiles: P,
+    /// The inspector following the transactions, `()` for none
+    pub inspector: I,
 }
 
-impl<CTX, P> RiscvEvmTr for RiscvEVM<CTX, P>
+impl<CTX, P, I> RiscvEvmTr for RiscvEVM<CTX, P, I>
 where
     CTX: ContextTr,
     P: PrecompileProvider<CTX>,